@@ -3,6 +3,7 @@
 //! It currently support simulation of layer 2 (data link) at most,
 //! the first layer (physical) cannot be simulated.
 
+
 pub mod proto;
 pub mod net;
 pub mod node;
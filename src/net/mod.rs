@@ -0,0 +1,922 @@
+//! This module contains all primitive structures for
+//! network simulation.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::any::{TypeId, Any};
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::proto::{EthFrame, PppFrame};
+
+mod wireless;
+pub use wireless::*;
+
+
+/// A handle to a node.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    index: usize,
+}
+
+/// A handle to a point-to-point link, returned when linking nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LinkId {
+    index: usize,
+}
+
+/// A handle to a multi-access link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BusHandle {
+    index: usize,
+}
+
+/// Internally used to represent different sides of a point-to-point link,
+/// or a member of a multi-access link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum LinkSide {
+    Side0,
+    Side1,
+    Member(usize),
+}
+
+/// A handle to a link, used internally by nodes to keep tracks
+/// of links to them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawLinkHandle {
+    index: usize,
+    side: LinkSide,
+    ty: TypeId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinkHandle<T> {
+    index: usize,
+    side: LinkSide,
+    _phantom: PhantomData<*const T>,
+}
+
+impl RawLinkHandle {
+    
+    pub fn new_pair<T: 'static>(index: usize) -> (Self, Self) {
+        let ty = TypeId::of::<T>();
+        (
+            Self { index, side: LinkSide::Side0, ty },
+            Self { index, side: LinkSide::Side1, ty },
+        )
+    }
+
+    /// Create the handle of the given member of a multi-access link.
+    pub fn new_member<T: 'static>(index: usize, member: usize) -> Self {
+        Self { index, side: LinkSide::Member(member), ty: TypeId::of::<T>() }
+    }
+
+    /// Return `true` if you can cast this raw link handle to the
+    /// given `T`-typed link handle.
+    pub fn is<T: 'static>(&self) -> bool {
+        self.ty == TypeId::of::<T>()
+    }
+
+    /// Cast this raw link handle, if possible, to the given 
+    /// `T`-typed link handle. To be further used as 
+    pub fn cast<T: 'static>(&self) -> Option<LinkHandle<T>> {
+        if self.ty == TypeId::of::<T>() {
+            Some(LinkHandle { 
+                index: self.index, 
+                side: self.side,
+                _phantom: PhantomData
+            })
+        } else {
+            None
+        }
+    }
+
+}
+
+
+/// This structure defines a network of nodes. These nodes can
+/// be later connected together between their interfaces.
+pub struct Network {
+    /// List of nodes contained in this network.
+    nodes: Vec<Box<dyn Node>>,
+    /// List of links, the type is dynamically allocated but should
+    /// always be a concrete derivation of `LinkQueues<T>` or `BusQueues<T>`.
+    queues: Vec<Box<dyn Medium>>,
+    /// List of listeners for packets.
+    listeners: Vec<Box<dyn UntypedListener>>,
+    /// The simulated clock, this is the number of ticks since
+    /// the creation of the network.
+    time: u64,
+}
+
+impl Network {
+
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            queues: Vec::new(),
+            listeners: Vec::new(),
+            time: 0,
+        }
+    }
+
+    /// Get the current time of the simulated clock, in ticks.
+    #[inline]
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Add a new node to the network, its handle is returned and 
+    /// can be later used to link nodes.
+    pub fn push(&mut self, node: impl Node + 'static) -> NodeHandle {
+        let index = self.nodes.len();
+        self.nodes.push(Box::new(node));
+        NodeHandle { index }
+    }
+
+    /// Link two nodes with a link.
+    pub fn link<T: 'static>(&mut self, 
+        node_0: NodeHandle, iface_0: usize, 
+        node_1: NodeHandle, iface_1: usize,
+    ) -> LinkId {
+
+        let index = self.queues.len();
+        
+        let (
+            handle_0, 
+            handle_1
+        ) = RawLinkHandle::new_pair::<T>(index);
+
+        if !self.nodes.get_mut(node_0.index).unwrap().link(iface_0, handle_0) {
+            panic!()
+        }
+        
+        if !self.nodes.get_mut(node_1.index).unwrap().link(iface_1, handle_1) {
+            panic!()
+        }
+
+        self.queues.push(Box::new(LinkQueues::<T> {
+            queue_0: Vec::new(),
+            queue_1: Vec::new(),
+            node_0,
+            node_1,
+            up: true,
+            max_size: None,
+            drops: Vec::new(),
+        }));
+
+        LinkId { index }
+
+    }
+
+    /// Set a point-to-point link up or down, data sent on a link that
+    /// is down is lost, and data in transit is discarded.
+    pub fn set_link_up(&mut self, link: LinkId, up: bool) {
+        self.queues.get_mut(link.index)
+            .expect("invalid link")
+            .set_up(up);
+    }
+
+    /// Set the maximum size of data sent on a point-to-point link, bigger
+    /// data is dropped, or `None` to accept any size.
+    /// 
+    /// # Panics
+    /// 
+    /// If the link doesn't carry data of type `T`.
+    pub fn set_link_max_size<T: DataSize + 'static>(&mut self, link: LinkId, max_size: Option<usize>) {
+        let queues = self.queues.get_mut(link.index)
+            .expect("invalid link")
+            .as_any_mut()
+            .downcast_mut::<LinkQueues<T>>()
+            .expect("incoherent link type");
+        queues.max_size = max_size.map(|max_size| (max_size, T::size as fn(&T) -> usize));
+    }
+
+    /// Get the data dropped by a point-to-point link and the reason.
+    pub fn link_drops(&self, link: LinkId) -> &[LinkDrop] {
+        self.queues.get(link.index)
+            .and_then(|queues| queues.drops())
+            .expect("invalid link")
+    }
+
+    /// Take the data drops recorded by a point-to-point link.
+    pub fn take_link_drops(&mut self, link: LinkId) -> Vec<LinkDrop> {
+        self.queues.get_mut(link.index)
+            .and_then(|queues| queues.drops_mut())
+            .map(std::mem::take)
+            .expect("invalid link")
+    }
+
+    /// Link many nodes with a multi-access link, every data sent by a
+    /// node is received by all other ones. By default the medium is 
+    /// ideal and doesn't model collisions.
+    pub fn bus<T: Clone + 'static>(&mut self, members: &[(NodeHandle, usize)]) -> BusHandle {
+
+        let index = self.queues.len();
+
+        for (member, &(node, iface)) in members.iter().enumerate() {
+            let handle = RawLinkHandle::new_member::<T>(index, member);
+            if !self.nodes.get_mut(node.index).unwrap().link(iface, handle) {
+                panic!()
+            }
+        }
+
+        self.queues.push(Box::new(BusQueues::<T> {
+            members: members.iter().map(|&(node, _)| BusMember {
+                node,
+                tx: Vec::new(),
+                rx: Vec::new(),
+                attempts: 0,
+                backoff_until: 0,
+            }).collect(),
+            max_size: None,
+            drops: Vec::new(),
+            state: BusState {
+                csma_cd: false,
+                rng: XorShift::new(index as u64),
+                stats: BusStats::default(),
+            },
+        }));
+
+        BusHandle { index }
+
+    }
+
+    /// Enable or disable CSMA/CD modelling on a multi-access link. When
+    /// enabled, only one data can be transmitted on the medium per tick,
+    /// simultaneous transmissions collide and are retried after a random
+    /// binary exponential backoff.
+    pub fn set_bus_csma_cd(&mut self, bus: BusHandle, enabled: bool) {
+        self.bus_state_mut(bus).csma_cd = enabled;
+    }
+
+    /// Set the maximum size of data sent on a multi-access link, bigger
+    /// data is dropped, or `None` to accept any size.
+    /// 
+    /// # Panics
+    /// 
+    /// If the link doesn't carry data of type `T`.
+    pub fn set_bus_max_size<T: DataSize + 'static>(&mut self, bus: BusHandle, max_size: Option<usize>) {
+        let queues = self.queues.get_mut(bus.index)
+            .expect("invalid bus")
+            .as_any_mut()
+            .downcast_mut::<BusQueues<T>>()
+            .expect("incoherent link type");
+        queues.max_size = max_size.map(|max_size| (max_size, T::size as fn(&T) -> usize));
+    }
+
+    /// Get the data dropped by a multi-access link and the reason.
+    pub fn bus_drops(&self, bus: BusHandle) -> &[LinkDrop] {
+        self.queues.get(bus.index)
+            .and_then(|queues| queues.drops())
+            .expect("invalid bus")
+    }
+
+    /// Get the statistics of a multi-access link.
+    pub fn bus_stats(&self, bus: BusHandle) -> BusStats {
+        self.queues.get(bus.index)
+            .and_then(|queues| queues.bus_state())
+            .expect("invalid bus")
+            .stats
+    }
+
+    fn bus_state_mut(&mut self, bus: BusHandle) -> &mut BusState {
+        self.queues.get_mut(bus.index)
+            .and_then(|queues| queues.bus_state_mut())
+            .expect("invalid bus")
+    }
+
+    /// Tick each node in the network.
+    pub fn tick(&mut self) {
+
+        for node in &mut self.nodes {
+
+            let node = &mut **node;
+
+            let mut links = Links {
+                queues: &mut self.queues,
+                listeners: &mut self.listeners,
+                time: self.time,
+            };
+
+            node.tick(&mut links);
+
+        }
+
+        for queues in &mut self.queues {
+            queues.tick(self.time);
+        }
+
+        self.time += 1;
+
+    }
+
+    /// Subscribe with a listener for specific data transfers.
+    pub fn subscribe<L: Listener + 'static>(&mut self, listener: L) {
+        self.listeners.push(Box::new(listener))
+    }
+
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Internal trait implemented by all kinds of link queues.
+trait Medium {
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Called after all nodes have been ticked.
+    fn tick(&mut self, _time: u64) {}
+
+    /// Set the medium up or down.
+    fn set_up(&mut self, _up: bool) {}
+
+    /// Get the drops recorded by a point-to-point link, if this is one.
+    fn drops(&self) -> Option<&Vec<LinkDrop>> {
+        None
+    }
+
+    fn drops_mut(&mut self) -> Option<&mut Vec<LinkDrop>> {
+        None
+    }
+
+    /// Get the state of a multi-access link, if this is one.
+    fn bus_state(&self) -> Option<&BusState> {
+        None
+    }
+
+    fn bus_state_mut(&mut self) -> Option<&mut BusState> {
+        None
+    }
+
+    /// Get the state of a wireless medium, if this is one.
+    fn wireless_state(&self) -> Option<&WirelessState> {
+        None
+    }
+
+    fn wireless_state_mut(&mut self) -> Option<&mut WirelessState> {
+        None
+    }
+
+}
+
+/// Queue of data and the node that sent them.
+type Queue<T> = Vec<(NodeHandle, Box<T>)>;
+
+/// Maximum size of data on a link and the function giving the size.
+type MaxSize<T> = Option<(usize, fn(&T) -> usize)>;
+
+/// Data carried by links that have a size, used to enforce the maximum
+/// size of data on links.
+pub trait DataSize {
+
+    /// Size of the data in bytes.
+    fn size(&self) -> usize;
+
+}
+
+impl DataSize for EthFrame {
+    fn size(&self) -> usize {
+        EthFrame::size(self)
+    }
+}
+
+impl DataSize for PppFrame {
+    fn size(&self) -> usize {
+        PppFrame::size(self)
+    }
+}
+
+/// A data dropped by a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkDrop {
+    /// Time of the simulated clock when the data was dropped.
+    pub time: u64,
+    /// The node that sent the data.
+    pub node: NodeHandle,
+    pub reason: LinkDropReason,
+}
+
+/// Reason of a data drop on a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkDropReason {
+    /// The link was down.
+    LinkDown,
+    /// The data was bigger than the maximum size of the link.
+    Oversized {
+        size: usize,
+        max_size: usize,
+    },
+}
+
+/// A structure defining an absolute 
+struct LinkQueues<T> {
+    /// Messages to be transfered to the first node of this link.
+    queue_0: Queue<T>,
+    /// Messages to be transfered to the second node of this link.
+    queue_1: Queue<T>,
+    node_0: NodeHandle,
+    node_1: NodeHandle,
+    up: bool,
+    max_size: MaxSize<T>,
+    /// Data dropped when sent.
+    drops: Vec<LinkDrop>,
+}
+
+impl<T: 'static> Medium for LinkQueues<T> {
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn set_up(&mut self, up: bool) {
+        self.up = up;
+        if !up {
+            self.queue_0.clear();
+            self.queue_1.clear();
+        }
+    }
+
+    fn drops(&self) -> Option<&Vec<LinkDrop>> {
+        Some(&self.drops)
+    }
+
+    fn drops_mut(&mut self) -> Option<&mut Vec<LinkDrop>> {
+        Some(&mut self.drops)
+    }
+
+}
+
+/// Queues of a multi-access link.
+struct BusQueues<T> {
+    members: Vec<BusMember<T>>,
+    max_size: MaxSize<T>,
+    /// Data dropped when sent.
+    drops: Vec<LinkDrop>,
+    state: BusState,
+}
+
+struct BusMember<T> {
+    node: NodeHandle,
+    /// Data waiting to be transmitted on the medium.
+    tx: Queue<T>,
+    /// Data received from the medium.
+    rx: Queue<T>,
+    /// Number of collisions for the data being transmitted.
+    attempts: u32,
+    /// Time before which this member will not try to transmit.
+    backoff_until: u64,
+}
+
+/// Untyped state of a multi-access link.
+struct BusState {
+    csma_cd: bool,
+    /// Random generator used for backoff.
+    rng: XorShift,
+    stats: BusStats,
+}
+
+/// Statistics of a multi-access link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BusStats {
+    /// Number of data successfully transmitted on the medium.
+    pub transmitted: u64,
+    /// Number of collisions.
+    pub collisions: u64,
+    /// Number of data dropped after too many collisions.
+    pub dropped: u64,
+}
+
+/// Maximum number of collisions before a data is dropped.
+const BUS_MAX_ATTEMPTS: u32 = 16;
+/// Maximum backoff exponent.
+const BUS_MAX_BACKOFF_EXP: u32 = 10;
+
+/// A small deterministic random generator, used by media that need
+/// randomness, so that simulations are reproducible.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(0x2545_F491_4F6C_DD1D ^ seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Get a random number in `[0, 1)`.
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+}
+
+impl<T: Clone> BusQueues<T> {
+
+    /// Transmit the first pending data of the given member to all others.
+    fn transmit(&mut self, index: usize) {
+        let (src, data) = self.members[index].tx.remove(0);
+        for (other_index, other) in self.members.iter_mut().enumerate() {
+            if other_index != index {
+                other.rx.push((src, data.clone()));
+            }
+        }
+        self.state.stats.transmitted += 1;
+    }
+
+}
+
+impl<T: Clone + 'static> Medium for BusQueues<T> {
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn tick(&mut self, time: u64) {
+
+        if !self.state.csma_cd {
+            for index in 0..self.members.len() {
+                while !self.members[index].tx.is_empty() {
+                    self.transmit(index);
+                }
+            }
+            return;
+        }
+
+        let ready: Vec<usize> = self.members.iter()
+            .enumerate()
+            .filter(|(_, member)| !member.tx.is_empty() && member.backoff_until <= time)
+            .map(|(index, _)| index)
+            .collect();
+
+        match ready[..] {
+            [] => {}
+            [index] => {
+                self.transmit(index);
+                self.members[index].attempts = 0;
+            }
+            _ => {
+                self.state.stats.collisions += 1;
+                for index in ready {
+                    let random = self.state.rng.next_u64();
+                    let member = &mut self.members[index];
+                    member.attempts += 1;
+                    if member.attempts > BUS_MAX_ATTEMPTS {
+                        member.tx.remove(0);
+                        member.attempts = 0;
+                        self.state.stats.dropped += 1;
+                    } else {
+                        let slots = 1u64 << member.attempts.min(BUS_MAX_BACKOFF_EXP);
+                        member.backoff_until = time + 1 + random % slots;
+                    }
+                }
+            }
+        }
+
+    }
+
+    fn drops(&self) -> Option<&Vec<LinkDrop>> {
+        Some(&self.drops)
+    }
+
+    fn drops_mut(&mut self) -> Option<&mut Vec<LinkDrop>> {
+        Some(&mut self.drops)
+    }
+
+    fn bus_state(&self) -> Option<&BusState> {
+        Some(&self.state)
+    }
+
+    fn bus_state_mut(&mut self) -> Option<&mut BusState> {
+        Some(&mut self.state)
+    }
+
+}
+
+/// Temporary object given when ticking nodes, used to receive and send
+/// data on link.
+pub struct Links<'a> {
+    queues: &'a mut Vec<Box<dyn Medium>>,
+    listeners: &'a mut Vec<Box<dyn UntypedListener>>,
+    time: u64,
+}
+
+impl<'a> Links<'a> {
+
+    /// Get the current time of the simulated clock, in ticks.
+    #[inline]
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn get<T: 'static>(&mut self, link: &LinkHandle<T>) -> Link<'_, T> {
+
+        let queues_raw = self.queues.get_mut(link.index)
+            .expect("invalid link")
+            .as_any_mut();
+
+        if let LinkSide::Member(member) = link.side {
+            let (tx, rx, node, max_size, drops) = if queues_raw.is::<BusQueues<T>>() {
+                let queues = queues_raw.downcast_mut::<BusQueues<T>>().unwrap();
+                let member = &mut queues.members[member];
+                (&mut member.tx, &mut member.rx, member.node, queues.max_size, Some(&mut queues.drops))
+            } else {
+                let (tx, rx, node) = queues_raw.downcast_mut::<WirelessQueues<T>>()
+                    .expect("incoherent link type")
+                    .member_mut(member);
+                (tx, rx, node, None, None)
+            };
+            return Link {
+                tx,
+                rx,
+                node,
+                up: true,
+                max_size,
+                drops,
+                listeners: self.listeners,
+                time: self.time,
+            };
+        }
+
+        let queues = queues_raw.downcast_mut::<LinkQueues<T>>()
+            .expect("incoherent link type");
+
+        match link.side {
+            LinkSide::Side0 => Link {
+                tx: &mut queues.queue_0,
+                rx: &mut queues.queue_1,
+                node: queues.node_0,
+                up: queues.up,
+                max_size: queues.max_size,
+                drops: Some(&mut queues.drops),
+                listeners: self.listeners,
+                time: self.time,
+            },
+            _ => Link {
+                tx: &mut queues.queue_1,
+                rx: &mut queues.queue_0,
+                node: queues.node_1,
+                up: queues.up,
+                max_size: queues.max_size,
+                drops: Some(&mut queues.drops),
+                listeners: self.listeners,
+                time: self.time,
+            },
+        }
+
+    }
+
+}
+
+/// Temporary object returned by `Links` and used send and receive packets 
+/// of the given type in the link.
+pub struct Link<'a, T> {
+    tx: &'a mut Queue<T>,
+    rx: &'a mut Queue<T>,
+    /// The node owning this side of the link.
+    node: NodeHandle,
+    /// False if the link is down.
+    up: bool,
+    max_size: MaxSize<T>,
+    /// Where drops are recorded, if the link records them.
+    drops: Option<&'a mut Vec<LinkDrop>>,
+    listeners: &'a mut Vec<Box<dyn UntypedListener>>,
+    time: u64,
+}
+
+impl<'a, T: 'static> Link<'a, T> {
+
+    /// Get the current time of the simulated clock, in ticks.
+    #[inline]
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Return `true` if the link is up, this can be used by nodes to
+    /// detect a loss of carrier.
+    #[inline]
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+
+    pub fn send(&mut self, data: Box<T>) {
+        let reason = match self.max_size {
+            _ if !self.up => LinkDropReason::LinkDown,
+            Some((max_size, size_fn)) if size_fn(&data) > max_size => {
+                LinkDropReason::Oversized { size: size_fn(&data), max_size }
+            }
+            _ => {
+                self.tx.push((self.node, data));
+                return;
+            }
+        };
+        if let Some(drops) = &mut self.drops {
+            drops.push(LinkDrop { time: self.time, node: self.node, reason });
+        }
+    }
+
+    pub fn recv(&mut self) -> Option<Box<T>> {
+
+        if self.rx.is_empty() {
+            return None;
+        }
+
+        let (src, data) = self.rx.remove(0);
+
+        for listener in &mut self.listeners[..] {
+            listener.event(src, self.node, &*data);
+        }
+
+        Some(data)
+
+    }
+
+} 
+
+/// Node that can be linked to other ones and ticked by the network controller.
+pub trait Node {
+
+    /// Called to link this node to a given link through the given interface.
+    /// This function returns `true` when the link was successful.
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool;
+
+    /// Tick the node to process their links.
+    fn tick(&mut self, links: &mut Links);
+    
+}
+
+/// A listener to track packets sent on links.
+pub trait Listener {
+
+    /// data type that you want to capture on the link. 
+    type Data;
+
+    /// Called when an event of this type is transfered on a link.
+    /// A data is considered transfered when actually received by
+    /// an end.
+    fn event(&mut self, src: NodeHandle, dst: NodeHandle, data: &Self::Data);
+
+}
+
+/// Internally used to store dynamically dispatched listeners.
+trait UntypedListener {
+
+    /// This event only triggers when the given dynamic type is
+    /// valid for this listener.
+    fn event(&mut self, src: NodeHandle, dst: NodeHandle, data: &dyn Any);
+
+}
+
+impl<L> UntypedListener for L
+where
+    L: Listener,
+    L::Data: 'static
+{
+    fn event(&mut self, src: NodeHandle, dst: NodeHandle, data: &dyn Any) {
+        if let Some(data) = data.downcast_ref::<L::Data>() {
+            Listener::event(self, src, dst, data);
+        }
+    }
+}
+
+/// A basic `Listener` implementation that tracks a specific 
+/// data type. You can associate node handles to a host name
+/// in order to have better debugging.
+pub struct DebugListener<T> {
+    node_names: HashMap<NodeHandle, String>,
+    _phantom: PhantomData<*const T>,
+}
+
+impl<T> DebugListener<T> {
+
+    pub fn new() -> Self {
+        Self {
+            node_names: HashMap::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn name(&mut self, node: NodeHandle, name: impl ToString) {
+        self.node_names.insert(node, name.to_string());
+    }
+
+}
+
+impl<T> Default for DebugListener<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> Listener for DebugListener<T> {
+    type Data = T;
+    fn event(&mut self, src: NodeHandle, dst: NodeHandle, data: &Self::Data) {
+        match (self.node_names.get(&src), self.node_names.get(&dst)) {
+            (Some(src), Some(dst)) => println!("[{src} -> {dst}] {data:?}"),
+            (None, Some(dst)) => println!("[{src:?} -> {dst}] {data:?}"),
+            (Some(src), None) => println!("[{src} -> {dst:?}] {data:?}"),
+            (None, None) => println!("[{src:?} -> {dst:?}] {data:?}"),
+        }
+    }
+}
+
+
+/// A wrapper for node that can be mutably shared.
+pub struct RcNode<N: Node> {
+    inner: Rc<RefCell<N>>,
+}
+
+impl<N: Node> RcNode<N> {
+
+    #[inline]
+    pub fn new(node: N) -> Self {
+        Self { inner: Rc::new(RefCell::new(node))}
+    }
+
+    #[inline]
+    pub fn borrow_mut(&self) -> RefMut<'_, N> {
+        self.inner.borrow_mut()
+    }
+
+}
+
+impl<N: Node> Clone for RcNode<N> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self { inner: Rc::clone(&self.inner) }
+    }
+}
+
+impl<N: Node> Node for RcNode<N> {
+
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
+        self.inner.borrow_mut().link(iface, link)
+    }
+
+    fn tick(&mut self, links: &mut Links) {
+        self.inner.borrow_mut().tick(links)
+    }
+
+}
+
+
+/// A wrapper for node that can be mutably shared between threads.
+pub struct ArcNode<N: Node> {
+    inner: Arc<Mutex<N>>,
+}
+
+impl<N: Node> ArcNode<N> {
+
+    #[inline]
+    pub fn new(node: N) -> Self {
+        Self { inner: Arc::new(Mutex::new(node))}
+    }
+
+    #[inline]
+    pub fn borrow_mut(&self) -> MutexGuard<'_, N> {
+        self.inner.lock().unwrap()
+    }
+
+}
+
+impl<N: Node> Clone for ArcNode<N> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self { inner: Arc::clone(&self.inner) }
+    }
+}
+
+impl<N: Node> Node for ArcNode<N> {
+
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
+        self.inner.lock().unwrap().link(iface, link)
+    }
+
+    fn tick(&mut self, links: &mut Links) {
+        self.inner.lock().unwrap().tick(links)
+    }
+
+}
+
+
+// Debug impls
+
+impl fmt::Debug for NodeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NodeHandle")
+            .field(&self.index)
+            .finish()
+    }
+}
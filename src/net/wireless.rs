@@ -0,0 +1,342 @@
+//! Wireless medium, a multi-access link where nodes have positions
+//! and the delivery depends on the distance between them.
+
+use std::any::Any;
+
+use super::{Network, NodeHandle, RawLinkHandle, Medium, Queue, XorShift};
+
+
+/// Minimum contention window, in ticks.
+const CW_MIN: u64 = 4;
+/// Maximum contention window, in ticks.
+const CW_MAX: u64 = 256;
+/// Maximum number of retries before a data is dropped.
+const MAX_RETRIES: u32 = 7;
+
+
+/// A handle to a wireless medium.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WirelessHandle {
+    index: usize,
+}
+
+/// A path loss model, giving the probability for a data to be received
+/// depending on the distance between the sender and the receiver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathLoss {
+    /// Data is always received within the range, and never beyond.
+    UnitDisk {
+        range: f64,
+    },
+    /// Log-distance path loss model, the received power in dBm is
+    /// `tx_power - reference_loss - 10 * exponent * log10(distance)`,
+    /// distance being in meters. The delivery probability is 50% when
+    /// the received power equals the sensitivity, and increases with
+    /// the margin.
+    LogDistance {
+        tx_power: f64,
+        reference_loss: f64,
+        exponent: f64,
+        sensitivity: f64,
+    },
+}
+
+impl PathLoss {
+
+    /// Probability for a data to be received at the given distance.
+    pub fn delivery_probability(&self, distance: f64) -> f64 {
+        match *self {
+            PathLoss::UnitDisk { range } => {
+                if distance <= range { 1.0 } else { 0.0 }
+            }
+            PathLoss::LogDistance { tx_power, reference_loss, exponent, sensitivity } => {
+                let power = tx_power - reference_loss - 10.0 * exponent * distance.max(1.0).log10();
+                1.0 / (1.0 + (-(power - sensitivity) / 2.0).exp())
+            }
+        }
+    }
+
+    /// Return `true` if a transmission at the given distance can be sensed,
+    /// it is used for carrier sensing and interference.
+    pub fn is_sensed(&self, distance: f64) -> bool {
+        self.delivery_probability(distance) >= 0.5
+    }
+
+}
+
+/// Statistics of a wireless medium.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WirelessStats {
+    /// Number of transmissions.
+    pub transmitted: u64,
+    /// Number of data received.
+    pub delivered: u64,
+    /// Number of data lost because of the distance.
+    pub lost: u64,
+    /// Number of data lost at a receiver because of interference.
+    pub collisions: u64,
+    /// Number of data dropped after too many retries.
+    pub dropped: u64,
+}
+
+/// Queues of a wireless medium.
+pub(super) struct WirelessQueues<T> {
+    members: Vec<WirelessMember<T>>,
+    state: WirelessState,
+}
+
+struct WirelessMember<T> {
+    node: NodeHandle,
+    /// Data waiting to be transmitted on the medium.
+    tx: Queue<T>,
+    /// Data received from the medium.
+    rx: Queue<T>,
+}
+
+/// Untyped state of a wireless medium.
+pub(super) struct WirelessState {
+    model: PathLoss,
+    stations: Vec<Station>,
+    rng: XorShift,
+    stats: WirelessStats,
+}
+
+/// Medium access state of a member.
+struct Station {
+    node: NodeHandle,
+    position: [f64; 3],
+    /// Remaining backoff ticks, only decremented while the medium is idle.
+    backoff: Option<u64>,
+    /// Current contention window.
+    cw: u64,
+    retries: u32,
+    /// Members that already received the data being transmitted, they
+    /// are skipped when it is retried.
+    received: Vec<usize>,
+    /// True if a transmission was sensed on the last tick.
+    busy: bool,
+}
+
+impl WirelessState {
+
+    fn distance(&self, a: usize, b: usize) -> f64 {
+        let [ax, ay, az] = self.stations[a].position;
+        let [bx, by, bz] = self.stations[b].position;
+        ((ax - bx).powi(2) + (ay - by).powi(2) + (az - bz).powi(2)).sqrt()
+    }
+
+    fn is_sensed(&self, a: usize, b: usize) -> bool {
+        self.model.is_sensed(self.distance(a, b))
+    }
+
+}
+
+impl Network {
+
+    /// Create a wireless medium with the given path loss model, each member
+    /// node is linked through the given interface and placed at the given
+    /// position. The medium access uses CSMA/CA, data is retried with an
+    /// increasing contention window when a collision happened at one of the
+    /// receivers in range, or when one of them was transmitting at the same
+    /// time, and only these receivers get the retried data.
+    pub fn wireless<T: Clone + 'static>(&mut self,
+        model: PathLoss,
+        members: &[(NodeHandle, usize, [f64; 3])]
+    ) -> WirelessHandle {
+
+        let index = self.queues.len();
+
+        for (member, &(node, iface, _)) in members.iter().enumerate() {
+            let handle = RawLinkHandle::new_member::<T>(index, member);
+            if !self.nodes.get_mut(node.index).unwrap().link(iface, handle) {
+                panic!()
+            }
+        }
+
+        self.queues.push(Box::new(WirelessQueues::<T> {
+            members: members.iter().map(|&(node, _, _)| WirelessMember {
+                node,
+                tx: Vec::new(),
+                rx: Vec::new(),
+            }).collect(),
+            state: WirelessState {
+                model,
+                stations: members.iter().map(|&(node, _, position)| Station {
+                    node,
+                    position,
+                    backoff: None,
+                    cw: CW_MIN,
+                    retries: 0,
+                    received: Vec::new(),
+                    busy: false,
+                }).collect(),
+                rng: XorShift::new(index as u64),
+                stats: WirelessStats::default(),
+            },
+        }));
+
+        WirelessHandle { index }
+
+    }
+
+    /// Move a node on a wireless medium.
+    pub fn set_wireless_position(&mut self, wireless: WirelessHandle, node: NodeHandle, position: [f64; 3]) {
+        let state = self.wireless_state_mut(wireless);
+        for station in &mut state.stations {
+            if station.node == node {
+                station.position = position;
+            }
+        }
+    }
+
+    /// Change the path loss model of a wireless medium.
+    pub fn set_wireless_model(&mut self, wireless: WirelessHandle, model: PathLoss) {
+        self.wireless_state_mut(wireless).model = model;
+    }
+
+    /// Get the statistics of a wireless medium.
+    pub fn wireless_stats(&self, wireless: WirelessHandle) -> WirelessStats {
+        self.queues.get(wireless.index)
+            .and_then(|queues| queues.wireless_state())
+            .expect("invalid wireless medium")
+            .stats
+    }
+
+    fn wireless_state_mut(&mut self, wireless: WirelessHandle) -> &mut WirelessState {
+        self.queues.get_mut(wireless.index)
+            .and_then(|queues| queues.wireless_state_mut())
+            .expect("invalid wireless medium")
+    }
+
+}
+
+impl<T> WirelessQueues<T> {
+
+    /// Get the link queues of a member.
+    pub(super) fn member_mut(&mut self, member: usize) -> (&mut Queue<T>, &mut Queue<T>, NodeHandle) {
+        let member = &mut self.members[member];
+        (&mut member.tx, &mut member.rx, member.node)
+    }
+
+}
+
+impl<T: Clone + 'static> Medium for WirelessQueues<T> {
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn tick(&mut self, _time: u64) {
+
+        let state = &mut self.state;
+        let count = self.members.len();
+
+        // Select transmitters, stations with pending data wait for a random
+        // backoff that is only decremented while the medium is idle.
+        let mut transmitters = Vec::new();
+        for (index, member) in self.members.iter().enumerate() {
+            let station = &mut state.stations[index];
+            if member.tx.is_empty() {
+                station.backoff = None;
+                continue;
+            }
+            let backoff = match station.backoff {
+                Some(backoff) => backoff,
+                None => state.rng.next_u64() % station.cw,
+            };
+            if station.busy {
+                station.backoff = Some(backoff);
+            } else if backoff == 0 {
+                station.backoff = None;
+                transmitters.push(index);
+            } else {
+                station.backoff = Some(backoff - 1);
+            }
+        }
+
+        let mut failed = vec![false; count];
+
+        for receiver in 0..count {
+
+            // Half-duplex, a transmitter can't receive and the others will
+            // retry unless this receiver already got their data.
+            if transmitters.contains(&receiver) {
+                for &transmitter in &transmitters {
+                    if transmitter != receiver
+                        && state.is_sensed(transmitter, receiver)
+                        && !state.stations[transmitter].received.contains(&receiver) {
+                        failed[transmitter] = true;
+                    }
+                }
+                continue;
+            }
+
+            let audible: Vec<usize> = transmitters.iter()
+                .copied()
+                .filter(|&transmitter| state.is_sensed(transmitter, receiver))
+                .collect();
+
+            state.stations[receiver].busy = !audible.is_empty();
+
+            if audible.len() > 1 {
+                // Interference, nothing is received and the senders will
+                // retry unless this receiver already got their data.
+                state.stats.collisions += 1;
+                for transmitter in audible {
+                    if !state.stations[transmitter].received.contains(&receiver) {
+                        failed[transmitter] = true;
+                    }
+                }
+                continue;
+            }
+
+            // Only a transmission strong enough to be sensed can be received,
+            // and retried data is not received twice.
+            let Some(&transmitter) = audible.first() else { continue };
+            if state.stations[transmitter].received.contains(&receiver) {
+                continue;
+            }
+
+            let probability = state.model.delivery_probability(state.distance(transmitter, receiver));
+            if state.rng.next_f64() < probability {
+                let (src, data) = &self.members[transmitter].tx[0];
+                let data = (*src, data.clone());
+                self.members[receiver].rx.push(data);
+                state.stations[transmitter].received.push(receiver);
+                state.stats.delivered += 1;
+            } else {
+                state.stats.lost += 1;
+            }
+
+        }
+
+        for transmitter in transmitters {
+            state.stats.transmitted += 1;
+            let station = &mut state.stations[transmitter];
+            // Carrier sense of the transmitter is not possible while transmitting.
+            station.busy = false;
+            if failed[transmitter] && station.retries < MAX_RETRIES {
+                station.retries += 1;
+                station.cw = (station.cw * 2).min(CW_MAX);
+            } else {
+                if failed[transmitter] {
+                    state.stats.dropped += 1;
+                }
+                station.retries = 0;
+                station.cw = CW_MIN;
+                station.received.clear();
+                self.members[transmitter].tx.remove(0);
+            }
+        }
+
+    }
+
+    fn wireless_state(&self) -> Option<&WirelessState> {
+        Some(&self.state)
+    }
+
+    fn wireless_state_mut(&mut self) -> Option<&mut WirelessState> {
+        Some(&mut self.state)
+    }
+
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::net::{LinkHandle, Node, RawLinkHandle, Links};
+use crate::proto::{EthFrame, MacAddr};
+
+
+/// A wireless access point, it bridges the ethernet frames of a wireless
+/// medium, linked on interface 1, with a wired link, linked on interface 0,
+/// typically connected to an `EthSwitch` port.
+pub struct EthAccessPoint {
+    wired: Option<LinkHandle<EthFrame>>,
+    wireless: Option<LinkHandle<EthFrame>>,
+    /// Association of MAC addresses and the side they were seen on,
+    /// `true` for the wireless side.
+    mac_sides: HashMap<MacAddr, bool>,
+}
+
+impl EthAccessPoint {
+
+    /// Interface index of the wired side.
+    pub const WIRED_IFACE: usize = 0;
+    /// Interface index of the wireless side.
+    pub const WIRELESS_IFACE: usize = 1;
+
+    pub fn new() -> Self {
+        Self {
+            wired: None,
+            wireless: None,
+            mac_sides: HashMap::new(),
+        }
+    }
+
+}
+
+impl Default for EthAccessPoint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node for EthAccessPoint {
+
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
+        let Some(link) = link.cast::<EthFrame>() else { return false };
+        match iface {
+            Self::WIRED_IFACE => self.wired = Some(link),
+            Self::WIRELESS_IFACE => self.wireless = Some(link),
+            _ => return false,
+        }
+        true
+    }
+
+    fn tick(&mut self, links: &mut Links) {
+
+        let (Some(wired), Some(wireless)) = (&self.wired, &self.wireless) else { return };
+
+        let mut to_wired = Vec::new();
+        let mut to_wireless = Vec::new();
+
+        for (handle, from_wireless) in [(wired, false), (wireless, true)] {
+            let mut link = links.get(handle);
+            while let Some(frame) = link.recv() {
+                self.mac_sides.insert(frame.src, from_wireless);
+                // Frames are not bridged if the destination is known to be
+                // on the same side.
+                if self.mac_sides.get(&frame.dst) == Some(&from_wireless) && frame.dst.is_unicast() {
+                    continue;
+                }
+                if from_wireless {
+                    to_wired.push(frame);
+                } else {
+                    to_wireless.push(frame);
+                }
+            }
+        }
+
+        let mut link = links.get(wired);
+        for frame in to_wired {
+            link.send(frame);
+        }
+
+        let mut link = links.get(wireless);
+        for frame in to_wireless {
+            link.send(frame);
+        }
+
+    }
+
+}
@@ -0,0 +1,70 @@
+use crate::net::{Node, RawLinkHandle, Links, LinkHandle};
+
+
+/// A node that records all data received on its links, it never
+/// sends anything. It can be used on the monitor port of a switch
+/// to analyze the traffic from inside the simulation.
+pub struct CaptureNode<T> {
+    links: Vec<(usize, LinkHandle<T>)>,
+    captured: Vec<Captured<T>>,
+}
+
+/// A data recorded by a `CaptureNode`.
+#[derive(Debug, Clone)]
+pub struct Captured<T> {
+    /// Time of the simulated clock when the data was received.
+    pub time: u64,
+    /// The interface that received the data.
+    pub iface: usize,
+    pub data: Box<T>,
+}
+
+impl<T> CaptureNode<T> {
+
+    pub const fn new() -> Self {
+        Self {
+            links: Vec::new(),
+            captured: Vec::new(),
+        }
+    }
+
+    /// Get all data captured so far.
+    pub fn captured(&self) -> &[Captured<T>] {
+        &self.captured
+    }
+
+    /// Take all data captured so far.
+    pub fn take(&mut self) -> Vec<Captured<T>> {
+        std::mem::take(&mut self.captured)
+    }
+
+}
+
+impl<T> Default for CaptureNode<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static> Node for CaptureNode<T> {
+
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
+        if let Some(link) = link.cast::<T>() {
+            self.links.push((iface, link));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn tick(&mut self, links: &mut Links) {
+        let time = links.time();
+        for (iface, handle) in &self.links {
+            let mut link = links.get(handle);
+            while let Some(data) = link.recv() {
+                self.captured.push(Captured { time, iface: *iface, data });
+            }
+        }
+    }
+
+}
@@ -1,39 +1,452 @@
 use std::collections::HashMap;
 
 use crate::net::{LinkHandle, Node, RawLinkHandle, Links};
-use crate::proto::{EthFrame, MacAddr};
+use crate::proto::{EthFrame, EthPayload, MacAddr};
+
+use super::{StpBridge, StpPortState, LacpGroup, IgmpSnooping, lacp_frame};
+
+
+/// Default aging time of dynamic MAC table entries, in ticks.
+pub const DEFAULT_MAC_AGING_TIME: u64 = 300;
+
+/// The default VLAN, all ports are access ports of this VLAN
+/// unless configured otherwise.
+pub const DEFAULT_VLAN: u16 = 1;
+
+/// Port mode of unconfigured ports.
+const DEFAULT_PORT_MODE: VlanPortMode = VlanPortMode::Access(DEFAULT_VLAN);
 
 
 /// An ethernet switch node.
 pub struct EthSwitch {
     /// All registered links and their handles.
     link_handles: HashMap<usize, LinkHandle<EthFrame>>,
-    /// Association of MAC addresses and the port that sent 
-    /// the last frame with this source MAC addr.
-    mac_to_iface: HashMap<MacAddr, usize>,
-    /// Temporary vector of eth frames to broadcast and the 
-    /// interface that received them.
-    broadcast_queue: Vec<(Box<EthFrame>, usize)>,
-    /// Temporary vector of eth frames to send to a specific
-    /// interface.
-    unicast_queue: Vec<(Box<EthFrame>, usize)>,
+    /// VLAN configuration of ports, unconfigured ports are access
+    /// ports of the default VLAN.
+    port_modes: HashMap<usize, VlanPortMode>,
+    /// Association of VLAN and MAC addresses and the port that sent
+    /// the last frame with this source MAC addr in this VLAN.
+    mac_table: HashMap<(u16, MacAddr), MacEntry>,
+    /// Number of ticks after which a dynamic entry that has not
+    /// been refreshed is removed, `None` to never age entries.
+    mac_aging_time: Option<u64>,
+    /// Maximum number of dynamic entries in the MAC table.
+    mac_table_limit: Option<usize>,
+    /// What to do when the MAC table is full.
+    mac_table_overflow: MacTableOverflow,
+    /// Time of the last tick.
+    time: u64,
+    /// Spanning tree protocol state, if enabled.
+    stp: Option<StpBridge>,
+    /// Port mirroring sessions.
+    mirrors: Vec<PortMirror>,
+    /// Link aggregation groups, each group is seen as a single logical
+    /// port by the rest of the switch.
+    port_groups: Vec<LacpGroup>,
+    /// IGMP snooping state, if enabled.
+    igmp_snooping: Option<IgmpSnooping>,
+    /// Temporary vector of eth frames copied to monitor ports.
+    mirror_queue: Vec<(Box<EthFrame>, usize)>,
+    /// Temporary vector of untagged eth frames to broadcast, the
+    /// interface that received them and their VLAN.
+    broadcast_queue: Vec<(Box<EthFrame>, usize, u16)>,
+    /// Temporary vector of untagged eth frames to send to a specific
+    /// interface and their VLAN.
+    unicast_queue: Vec<(Box<EthFrame>, usize, u16)>,
+}
+
+/// VLAN configuration of a switch port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VlanPortMode {
+    /// The port belongs to a single VLAN, frames are untagged on
+    /// the link and tagged frames are dropped.
+    Access(u16),
+    /// The port carries tagged frames of many VLANs.
+    Trunk {
+        /// Allowed VLANs, `None` to allow all of them.
+        allowed: Option<Vec<u16>>,
+        /// The VLAN of untagged frames, if `None` untagged frames
+        /// are dropped.
+        native: Option<u16>,
+    },
+}
+
+impl VlanPortMode {
+
+    /// Return `true` if the port carries frames of the given VLAN.
+    pub fn is_member(&self, vlan_id: u16) -> bool {
+        match self {
+            VlanPortMode::Access(pvid) => *pvid == vlan_id,
+            VlanPortMode::Trunk { allowed, native } => {
+                *native == Some(vlan_id) || allowed.as_ref().is_none_or(|allowed| allowed.contains(&vlan_id))
+            }
+        }
+    }
+
+    /// Classify a received payload, returning its VLAN and untagged 
+    /// payload, or `None` if the frame must be dropped.
+    fn ingress(&self, payload: EthPayload) -> Option<(u16, EthPayload)> {
+        if !payload.is_valid() {
+            return None;
+        }
+        match (self, payload) {
+            (VlanPortMode::Access(_), EthPayload::Vlan { .. }) => None,
+            (&VlanPortMode::Access(pvid), payload) => Some((pvid, payload)),
+            (VlanPortMode::Trunk { .. }, EthPayload::Vlan { vlan_id, inner }) => {
+                self.is_member(vlan_id).then_some((vlan_id, *inner))
+            }
+            (&VlanPortMode::Trunk { native, .. }, payload) => native.map(|native| (native, payload)),
+        }
+    }
+
+    /// Prepare an untagged payload of the given VLAN to be sent on the
+    /// port, returning `None` if the port doesn't carry this VLAN.
+    fn egress(&self, vlan_id: u16, payload: EthPayload) -> Option<EthPayload> {
+        if !self.is_member(vlan_id) {
+            return None;
+        }
+        match self {
+            VlanPortMode::Trunk { native, .. } if *native != Some(vlan_id) => {
+                Some(payload.with_vlan(vlan_id))
+            }
+            _ => Some(payload),
+        }
+    }
+
+}
+
+/// Internal MAC table entry.
+struct MacEntry {
+    iface: usize,
+    /// Time of the last refresh.
+    time: u64,
+    is_static: bool,
+}
+
+/// Behavior of the switch when a new MAC address must be learned
+/// but the MAC table is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MacTableOverflow {
+    /// The new address is not learned, frames to it are flooded.
+    #[default]
+    Flood,
+    /// The oldest dynamic entry is removed to make space.
+    EvictOldest,
+}
+
+/// A port mirroring session, frames received and/or sent on source
+/// ports are copied, as they appear on the link, to the destination
+/// port. The destination port is dedicated to monitoring, it doesn't
+/// forward nor receive other traffic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortMirror {
+    /// Mirrored ports and the direction of mirrored traffic.
+    pub sources: Vec<(usize, MirrorDirection)>,
+    /// The monitor port.
+    pub destination: usize,
+}
+
+/// Direction of the traffic mirrored on a source port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorDirection {
+    /// Frames received on the port.
+    Rx,
+    /// Frames sent out of the port.
+    Tx,
+    /// Frames received and sent.
+    Both,
+}
+
+impl MirrorDirection {
+
+    fn matches(self, tx: bool) -> bool {
+        match self {
+            MirrorDirection::Rx => !tx,
+            MirrorDirection::Tx => tx,
+            MirrorDirection::Both => true,
+        }
+    }
+
+}
+
+/// Copy a frame to the monitor ports of all sessions that mirror the given
+/// port in the given direction.
+fn mirror_frame(mirrors: &[PortMirror], queue: &mut Vec<(Box<EthFrame>, usize)>, frame: &EthFrame, iface: usize, tx: bool) {
+    for mirror in mirrors {
+        let mirrored = mirror.sources.iter()
+            .any(|&(source, direction)| source == iface && direction.matches(tx));
+        if mirrored && mirror.destination != iface {
+            queue.push((Box::new(frame.clone()), mirror.destination));
+        }
+    }
+}
+
+/// A snapshot of a MAC table entry, returned by `EthSwitch::mac_entries`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacTableEntry {
+    /// The VLAN of the entry.
+    pub vlan_id: u16,
+    /// The learned MAC address.
+    pub mac: MacAddr,
+    /// The port the MAC address is associated to.
+    pub iface: usize,
+    /// Number of ticks since the entry was last refreshed.
+    pub age: u64,
+    /// True if the entry has been manually added.
+    pub is_static: bool,
 }
 
 impl EthSwitch {
+
     pub fn new() -> Self {
         Self {
             link_handles: HashMap::new(),
-            mac_to_iface: HashMap::new(),
+            port_modes: HashMap::new(),
+            mac_table: HashMap::new(),
+            mac_aging_time: Some(DEFAULT_MAC_AGING_TIME),
+            mac_table_limit: None,
+            mac_table_overflow: MacTableOverflow::Flood,
+            time: 0,
+            stp: None,
+            mirrors: Vec::new(),
+            port_groups: Vec::new(),
+            igmp_snooping: None,
+            mirror_queue: Vec::new(),
             broadcast_queue: Vec::new(),
             unicast_queue: Vec::new(),
         }
     }
+
+    /// Set the VLAN configuration of a port.
+    pub fn set_port_mode(&mut self, iface: usize, mode: VlanPortMode) {
+        self.port_modes.insert(iface, mode);
+        // Entries learned with the previous configuration are no longer valid.
+        self.mac_table.retain(|_, entry| entry.is_static || entry.iface != iface);
+    }
+
+    /// Get the VLAN configuration of a port.
+    pub fn port_mode(&self, iface: usize) -> &VlanPortMode {
+        self.port_modes.get(&iface).unwrap_or(&DEFAULT_PORT_MODE)
+    }
+
+    /// Enable the spanning tree protocol with the given bridge state, or
+    /// disable it with `None`. When enabled, all ports start blocking.
+    /// Port numbers above `MAX_PORT_NUMBER` can't be used with the
+    /// spanning tree protocol, such ports stay blocking and can no
+    /// longer be linked.
+    pub fn set_stp(&mut self, stp: Option<StpBridge>) {
+        self.stp = stp;
+        if let Some(stp) = &mut self.stp {
+            for &iface in self.link_handles.keys() {
+                let logical = self.port_groups.iter()
+                    .find(|group| group.is_member(iface))
+                    .map(LacpGroup::logical_port)
+                    .unwrap_or(iface);
+                if logical == iface {
+                    stp.add_port(iface);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn stp(&self) -> Option<&StpBridge> {
+        self.stp.as_ref()
+    }
+
+    #[inline]
+    pub fn stp_mut(&mut self) -> Option<&mut StpBridge> {
+        self.stp.as_mut()
+    }
+
+    /// Enable IGMP snooping with the given state, or disable it with `None`.
+    /// When disabled, multicast frames are flooded.
+    pub fn set_igmp_snooping(&mut self, snooping: Option<IgmpSnooping>) {
+        self.igmp_snooping = snooping;
+    }
+
+    #[inline]
+    pub fn igmp_snooping(&self) -> Option<&IgmpSnooping> {
+        self.igmp_snooping.as_ref()
+    }
+
+    #[inline]
+    pub fn igmp_snooping_mut(&mut self) -> Option<&mut IgmpSnooping> {
+        self.igmp_snooping.as_mut()
+    }
+
+    /// Add a port mirroring session.
+    pub fn add_mirror(&mut self, mirror: PortMirror) {
+        self.mirrors.push(mirror);
+    }
+
+    /// Remove all port mirroring sessions.
+    pub fn clear_mirrors(&mut self) {
+        self.mirrors.clear();
+    }
+
+    /// Get the port mirroring sessions.
+    pub fn mirrors(&self) -> &[PortMirror] {
+        &self.mirrors
+    }
+
+    /// Add a link aggregation group, its members are seen as a single port
+    /// by the rest of the switch: the logical port of the group. VLAN and
+    /// spanning tree configurations of the group are the ones of the 
+    /// logical port.
+    /// 
+    /// # Panics
+    /// 
+    /// If one of the member is already in a group.
+    pub fn add_port_group(&mut self, group: LacpGroup) {
+        for iface in group.members() {
+            assert!(self.port_group(iface).is_none(), "port already in a group");
+            if iface != group.logical_port() {
+                if let Some(stp) = &mut self.stp {
+                    stp.remove_port(iface);
+                }
+                self.port_modes.remove(&iface);
+            }
+        }
+        self.port_groups.push(group);
+    }
+
+    /// Get the link aggregation group of a member port.
+    pub fn port_group(&self, iface: usize) -> Option<&LacpGroup> {
+        self.port_groups.iter().find(|group| group.is_member(iface))
+    }
+
+    /// Get the link aggregation group of a member port.
+    pub fn port_group_mut(&mut self, iface: usize) -> Option<&mut LacpGroup> {
+        self.port_groups.iter_mut().find(|group| group.is_member(iface))
+    }
+
+    /// Get the logical port of a port, this is the port itself unless it is
+    /// a member of a link aggregation group.
+    fn logical_port(&self, iface: usize) -> usize {
+        self.port_group(iface).map(LacpGroup::logical_port).unwrap_or(iface)
+    }
+
+    /// Get the physical port to send a frame out of the given logical port.
+    fn egress_port(&self, iface: usize, frame: &EthFrame) -> Option<usize> {
+        match self.port_group(iface) {
+            Some(group) if group.logical_port() == iface => group.select(frame),
+            Some(_) => None,
+            None => Some(iface),
+        }
+    }
+
+    /// Return `true` if the port is the destination of a mirroring session.
+    fn is_monitor_port(&self, iface: usize) -> bool {
+        self.mirrors.iter().any(|mirror| mirror.destination == iface)
+    }
+
+    /// Get the spanning tree state of a port, always forwarding if the
+    /// protocol is disabled. Monitor ports are always blocking.
+    fn port_state(&self, iface: usize) -> StpPortState {
+        if self.is_monitor_port(iface) {
+            return StpPortState::Blocking;
+        }
+        match &self.stp {
+            Some(stp) => stp.port_state(iface),
+            None => StpPortState::Forwarding,
+        }
+    }
+
+    /// Set the number of ticks after which a dynamic entry that has
+    /// not been refreshed is removed, `None` to never age entries.
+    pub fn set_mac_aging_time(&mut self, aging_time: Option<u64>) {
+        self.mac_aging_time = aging_time;
+    }
+
+    /// Set the maximum number of dynamic entries in the MAC table and
+    /// the behavior when it's full, `None` for no limit.
+    pub fn set_mac_table_limit(&mut self, limit: Option<usize>, overflow: MacTableOverflow) {
+        self.mac_table_limit = limit;
+        self.mac_table_overflow = overflow;
+    }
+
+    /// Add a static entry to the MAC table of a VLAN, it never ages and
+    /// is not replaced by learning.
+    pub fn add_static_mac(&mut self, vlan_id: u16, mac: MacAddr, iface: usize) {
+        self.mac_table.insert((vlan_id, mac), MacEntry { iface, time: self.time, is_static: true });
+    }
+
+    /// Remove the MAC table entry for the given VLAN and address, static
+    /// or not. Returns `true` if an entry was present.
+    pub fn remove_mac(&mut self, vlan_id: u16, mac: MacAddr) -> bool {
+        self.mac_table.remove(&(vlan_id, mac)).is_some()
+    }
+
+    /// Remove all dynamic entries from the MAC table.
+    pub fn flush_macs(&mut self) {
+        self.mac_table.retain(|_, entry| entry.is_static);
+    }
+
+    /// Iterate over all entries of the MAC table, in no particular order.
+    pub fn mac_entries(&self) -> impl Iterator<Item = MacTableEntry> + '_ {
+        self.mac_table.iter().map(|(&(vlan_id, mac), entry)| MacTableEntry {
+            vlan_id,
+            mac,
+            iface: entry.iface,
+            age: self.time.saturating_sub(entry.time),
+            is_static: entry.is_static,
+        })
+    }
+
+    /// Internal function to learn the port of a source MAC address.
+    fn learn_mac(&mut self, vlan_id: u16, mac: MacAddr, iface: usize, time: u64) {
+
+        if let Some(entry) = self.mac_table.get_mut(&(vlan_id, mac)) {
+            if !entry.is_static {
+                entry.iface = iface;
+                entry.time = time;
+            }
+            return;
+        }
+
+        if let Some(limit) = self.mac_table_limit {
+            let count = self.mac_table.values().filter(|entry| !entry.is_static).count();
+            if count >= limit {
+                match self.mac_table_overflow {
+                    MacTableOverflow::Flood => return,
+                    MacTableOverflow::EvictOldest => {
+                        let oldest = self.mac_table.iter()
+                            .filter(|(_, entry)| !entry.is_static)
+                            .min_by_key(|(_, entry)| entry.time)
+                            .map(|(&key, _)| key);
+                        match oldest {
+                            Some(oldest) => { self.mac_table.remove(&oldest); }
+                            None => return,
+                        }
+                    }
+                }
+            }
+        }
+
+        self.mac_table.insert((vlan_id, mac), MacEntry { iface, time, is_static: false });
+
+    }
+
+}
+
+impl Default for EthSwitch {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Node for EthSwitch {
 
     fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
         if let Some(link) = link.cast::<EthFrame>() {
+            if self.logical_port(iface) == iface {
+                if let Some(stp) = &mut self.stp {
+                    if !stp.add_port(iface) {
+                        return false;
+                    }
+                }
+            }
             self.link_handles.insert(iface, link);
             true
         } else {
@@ -42,41 +455,187 @@ impl Node for EthSwitch {
     }
 
     fn tick(&mut self, links: &mut Links) {
-        
+
         self.broadcast_queue.clear();
         self.unicast_queue.clear();
 
-        for (iface, handle) in &self.link_handles {
+        let time = links.time();
+        self.time = time;
+
+        // Remove aged entries.
+        if let Some(aging_time) = self.mac_aging_time {
+            self.mac_table.retain(|_, entry| {
+                entry.is_static || time.saturating_sub(entry.time) < aging_time
+            });
+        }
+
+        for (&iface, handle) in &self.link_handles {
+
             let mut link = links.get(handle);
-            while let Some(frame) = link.recv() {
-                // Associate the source MAC addr to the port.
-                self.mac_to_iface.insert(frame.src, *iface);
-                if frame.dst.is_multicast() {
-                    self.broadcast_queue.push((frame, *iface));
-                } else {
-                    if let Some(dst_iface) = self.mac_to_iface.get(&frame.dst) {
-                        self.unicast_queue.push((frame, *dst_iface));
-                    } else {
-                        self.broadcast_queue.push((frame, *iface));
+
+            // Members of link aggregation groups only accept frames once
+            // active, and frames are then received from the logical port.
+            let group_index = self.port_groups.iter().position(|group| group.is_member(iface));
+            let (logical, active) = match group_index {
+                Some(index) => (self.port_groups[index].logical_port(), self.port_groups[index].is_active(iface)),
+                None => (iface, true),
+            };
+
+            let mode = self.port_modes.get(&logical).unwrap_or(&DEFAULT_PORT_MODE);
+            let state = self.stp.as_ref().map(|stp| stp.port_state(logical));
+            let is_monitor = self.mirrors.iter().any(|mirror| mirror.destination == iface);
+
+            while let Some(mut frame) = link.recv() {
+                if is_monitor {
+                    continue;
+                }
+                mirror_frame(&self.mirrors, &mut self.mirror_queue, &frame, iface, false);
+                if let Some(index) = group_index {
+                    if let EthPayload::Lacp(du) = &frame.payload {
+                        if frame.dst == MacAddr::SLOW_PROTOCOLS {
+                            self.port_groups[index].recv(iface, du, time);
+                            continue;
+                        }
                     }
+                    if !active {
+                        continue;
+                    }
+                }
+                if let Some(stp) = &mut self.stp {
+                    // BPDUs are consumed by the bridge, and other frames are
+                    // discarded if the port is not learning or forwarding.
+                    if let EthPayload::Bpdu(bpdu) = &frame.payload {
+                        if frame.dst == MacAddr::BRIDGE_GROUP {
+                            stp.recv_bpdu(logical, bpdu, time);
+                            continue;
+                        }
+                    }
+                    if matches!(state, Some(StpPortState::Blocking | StpPortState::Listening)) {
+                        continue;
+                    }
+                }
+                // Remove the VLAN tag, it is added back on egress if needed.
+                let payload = std::mem::replace(&mut frame.payload, EthPayload::Custom(Vec::new()));
+                if let Some((vlan_id, payload)) = mode.ingress(payload) {
+                    frame.payload = payload;
+                    self.broadcast_queue.push((frame, logical, vlan_id));
                 }
             }
+
         }
 
-        for (link_iface, handle) in &self.link_handles {
-            let mut link = links.get(handle);
-            for (frame, frame_iface) in &self.broadcast_queue {
+        for group in &mut self.port_groups {
+            let up: HashMap<usize, bool> = group.members()
+                .filter_map(|iface| self.link_handles.get(&iface).map(|handle| (iface, links.get(handle).is_up())))
+                .collect();
+            for (iface, du) in group.tick(time, |iface| up.get(&iface).copied().unwrap_or(false)) {
+                if let Some(handle) = self.link_handles.get(&iface) {
+                    links.get(handle).send(lacp_frame(group.system(), du));
+                }
+            }
+        }
+
+        if let Some(mut stp) = self.stp.take() {
+            let src = stp.bridge_id().mac;
+            for (iface, bpdu) in stp.tick(time) {
+                let frame = Box::new(EthFrame {
+                    src,
+                    dst: MacAddr::BRIDGE_GROUP,
+                    payload: EthPayload::Bpdu(Box::new(bpdu)),
+                });
+                if let Some(handle) = self.egress_port(iface, &frame).and_then(|iface| self.link_handles.get(&iface)) {
+                    links.get(handle).send(frame);
+                }
+            }
+            if stp.take_flush() {
+                self.flush_macs();
+            }
+            self.stp = Some(stp);
+        }
+
+        if self.igmp_snooping.is_some() {
+            // Memberships and routers learned on ports that are down or no
+            // longer forward frames are forgotten.
+            let mut ports_up = HashMap::new();
+            for (&iface, handle) in &self.link_handles {
+                *ports_up.entry(self.logical_port(iface)).or_insert(false) |= links.get(handle).is_up();
+            }
+            let stopped = ports_up.into_iter()
+                .filter(|&(iface, up)| !up || matches!(self.port_state(iface), StpPortState::Blocking | StpPortState::Listening))
+                .map(|(iface, _)| iface)
+                .collect::<Vec<_>>();
+            if let Some(snooping) = &mut self.igmp_snooping {
+                for iface in stopped {
+                    snooping.remove_port(iface);
+                }
+                snooping.tick(time);
+            }
+        }
+
+        // Frames are first all received, then dispatched, this allows
+        // the learning to be done while not borrowing the links.
+        let received = std::mem::take(&mut self.broadcast_queue);
+        for (frame, iface, vlan_id) in received {
+            // Associate the source MAC addr to the port.
+            self.learn_mac(vlan_id, frame.src, iface, time);
+            if self.port_state(iface) != StpPortState::Forwarding {
+                // Learning ports don't forward frames.
+                continue;
+            }
+            if frame.dst.is_multicast() {
+                let ports = self.igmp_snooping.as_mut()
+                    .and_then(|snooping| snooping.process(vlan_id, iface, &frame, time));
+                if let Some(ports) = ports {
+                    for port in ports {
+                        if port != iface {
+                            self.unicast_queue.push((frame.clone(), port, vlan_id));
+                        }
+                    }
+                } else {
+                    self.broadcast_queue.push((frame, iface, vlan_id));
+                }
+            } else if let Some(dst_entry) = self.mac_table.get(&(vlan_id, frame.dst)) {
+                // Never send back a frame to the port it came from.
+                if dst_entry.iface != iface {
+                    self.unicast_queue.push((frame, dst_entry.iface, vlan_id));
+                }
+            } else {
+                self.broadcast_queue.push((frame, iface, vlan_id));
+            }
+        }
+
+        // Broadcast frames are sent out of every logical port.
+        let ports: Vec<usize> = self.link_handles.keys()
+            .copied()
+            .filter(|&iface| self.logical_port(iface) == iface)
+            .collect();
+
+        for (frame, frame_iface, vlan_id) in std::mem::take(&mut self.broadcast_queue) {
+            for &iface in &ports {
                 // Don't send the broadcast frame to the sender iface.
-                if *link_iface != *frame_iface {
-                    link.send(frame.clone());
+                if iface != frame_iface {
+                    self.unicast_queue.push((frame.clone(), iface, vlan_id));
                 }
             }
         }
 
-        for (frame, iface) in self.unicast_queue.drain(..) {
+        for (mut frame, iface, vlan_id) in std::mem::take(&mut self.unicast_queue) {
+            if self.port_state(iface) != StpPortState::Forwarding {
+                continue;
+            }
+            let mode = self.port_modes.get(&iface).unwrap_or(&DEFAULT_PORT_MODE);
+            let Some(payload) = mode.egress(vlan_id, frame.payload) else { continue };
+            frame.payload = payload;
+            let Some(iface) = self.egress_port(iface, &frame) else { continue };
+            if let Some(handle) = self.link_handles.get(&iface) {
+                mirror_frame(&self.mirrors, &mut self.mirror_queue, &frame, iface, true);
+                links.get(handle).send(frame);
+            }
+        }
+
+        for (frame, iface) in self.mirror_queue.drain(..) {
             if let Some(handle) = self.link_handles.get(&iface) {
-                let mut link = links.get(handle);
-                link.send(frame);
+                links.get(handle).send(frame);
             }
         }
 
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use crate::net::{LinkHandle, Node, RawLinkHandle, Links};
+use crate::proto::EthFrame;
+
+
+/// An ethernet hub node, it repeats every received frame out of
+/// all of its other ports, without any learning.
+pub struct EthHub {
+    /// All registered links and their handles.
+    link_handles: HashMap<usize, LinkHandle<EthFrame>>,
+    /// Temporary vector of eth frames to repeat and the interface
+    /// that received them.
+    queue: Vec<(Box<EthFrame>, usize)>,
+}
+
+impl EthHub {
+    pub fn new() -> Self {
+        Self {
+            link_handles: HashMap::new(),
+            queue: Vec::new(),
+        }
+    }
+}
+
+impl Default for EthHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Node for EthHub {
+
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
+        if let Some(link) = link.cast::<EthFrame>() {
+            self.link_handles.insert(iface, link);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn tick(&mut self, links: &mut Links) {
+
+        self.queue.clear();
+
+        for (&iface, handle) in &self.link_handles {
+            let mut link = links.get(handle);
+            while let Some(frame) = link.recv() {
+                self.queue.push((frame, iface));
+            }
+        }
+
+        for (&link_iface, handle) in &self.link_handles {
+            let mut link = links.get(handle);
+            for (frame, frame_iface) in &self.queue {
+                if link_iface != *frame_iface {
+                    link.send(frame.clone());
+                }
+            }
+        }
+
+    }
+
+}
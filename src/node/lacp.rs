@@ -0,0 +1,239 @@
+//! Implementation of the link aggregation control protocol, shared
+//! by switches and servers to bundle many links in a logical port.
+
+use crate::proto::{EthFrame, EthPayload, MacAddr, LacpDu, LacpInfo};
+
+
+/// Number of ticks between two LACPDUs.
+const LACP_PERIOD: u64 = 1;
+/// Number of ticks without LACPDU after which the partner is forgotten.
+const LACP_TIMEOUT: u64 = 3 * LACP_PERIOD;
+
+
+/// Policy used to distribute frames over the active members of a group,
+/// all frames of a flow are sent on the same member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LacpHash {
+    /// Hash source and destination MAC addresses.
+    #[default]
+    Mac,
+    /// Hash source and destination IP addresses, falling back to MAC
+    /// addresses for non-IP frames.
+    Ip,
+}
+
+/// A group of ports aggregated with LACP. A member is active, and used
+/// to distribute frames, once both ends agreed to aggregate it. Members
+/// whose link is down or whose partner stopped sending LACPDUs are
+/// removed from the active set.
+pub struct LacpGroup {
+    /// Identifier of the local system.
+    system: MacAddr,
+    /// Aggregation key of the group.
+    key: u16,
+    hash: LacpHash,
+    members: Vec<LacpMember>,
+    /// Time of the last LACPDUs sent.
+    last_send: Option<u64>,
+}
+
+struct LacpMember {
+    iface: usize,
+    /// Port number advertised in LACPDUs, zero is reserved.
+    port: u16,
+    up: bool,
+    /// Information received from the partner and time of expiration.
+    partner: Option<(LacpInfo, u64)>,
+    /// True if the partner sees us in its last LACPDU.
+    partner_sees_us: bool,
+    /// True if the member is selected for aggregation.
+    selected: bool,
+}
+
+impl LacpGroup {
+
+    /// Create a new group with the given system identifier, key and
+    /// member interfaces. The first member is the logical port.
+    ///
+    /// Member interfaces must be below `u16::MAX`, they are advertised as
+    /// port numbers starting at 1.
+    pub fn new(system: MacAddr, key: u16, members: &[usize]) -> Self {
+        assert!(!members.is_empty(), "a group needs at least one member");
+        assert!(members.iter().all(|&iface| iface < u16::MAX as usize), "member interface too large");
+        Self {
+            system,
+            key,
+            hash: LacpHash::default(),
+            members: members.iter().map(|&iface| LacpMember {
+                iface,
+                port: iface as u16 + 1,
+                up: true,
+                partner: None,
+                partner_sees_us: false,
+                selected: false,
+            }).collect(),
+            last_send: None,
+        }
+    }
+
+    /// Set the policy to distribute frames over members.
+    pub fn set_hash(&mut self, hash: LacpHash) {
+        self.hash = hash;
+    }
+
+    /// Get the identifier of the local system.
+    #[inline]
+    pub fn system(&self) -> MacAddr {
+        self.system
+    }
+
+    /// Get the interface used to represent the whole group.
+    #[inline]
+    pub fn logical_port(&self) -> usize {
+        self.members[0].iface
+    }
+
+    /// Iterate over the member interfaces.
+    pub fn members(&self) -> impl Iterator<Item = usize> + '_ {
+        self.members.iter().map(|member| member.iface)
+    }
+
+    pub fn is_member(&self, iface: usize) -> bool {
+        self.members.iter().any(|member| member.iface == iface)
+    }
+
+    /// Return `true` if the member distributes and collects frames.
+    pub fn is_active(&self, iface: usize) -> bool {
+        self.members.iter().any(|member| member.iface == iface && member.is_active())
+    }
+
+    /// Get the active members.
+    pub fn active_members(&self) -> impl Iterator<Item = usize> + '_ {
+        self.members.iter()
+            .filter(|member| member.is_active())
+            .map(|member| member.iface)
+    }
+
+    /// Choose the active member to send the given frame on.
+    pub fn select(&self, frame: &EthFrame) -> Option<usize> {
+        let active: Vec<usize> = self.active_members().collect();
+        if active.is_empty() {
+            None
+        } else {
+            let hash = frame_hash(frame, self.hash);
+            Some(active[(hash % active.len() as u64) as usize])
+        }
+    }
+
+    fn actor_info(&self, member: &LacpMember) -> LacpInfo {
+        LacpInfo {
+            system: self.system,
+            key: self.key,
+            port: member.port,
+            sync: member.selected,
+        }
+    }
+
+    /// Process a LACPDU received on a member.
+    pub(crate) fn recv(&mut self, iface: usize, du: &LacpDu, time: u64) {
+        let (system, key) = (self.system, self.key);
+        if let Some(member) = self.members.iter_mut().find(|member| member.iface == iface) {
+            let port = member.port;
+            member.partner = Some((du.actor, time + LACP_TIMEOUT));
+            member.partner_sees_us = (du.partner.system, du.partner.key, du.partner.port) == (system, key, port);
+        }
+    }
+
+    /// Update the state of members and return LACPDUs to send.
+    pub(crate) fn tick(&mut self, time: u64, is_up: impl Fn(usize) -> bool) -> Vec<(usize, LacpDu)> {
+
+        for member in &mut self.members {
+            member.up = is_up(member.iface);
+            if !member.up || member.partner.is_some_and(|(_, expire)| expire <= time) {
+                member.partner = None;
+                member.partner_sees_us = false;
+            }
+        }
+
+        // The aggregator partner is the one of the first member that can
+        // be aggregated, members connected to another partner are not
+        // selected.
+        let aggregator = self.members.iter()
+            .filter(|member| member.partner_sees_us)
+            .find_map(|member| member.partner)
+            .map(|(info, _)| (info.system, info.key));
+
+        for member in &mut self.members {
+            member.selected = member.partner_sees_us &&
+                member.partner.map(|(info, _)| (info.system, info.key)) == aggregator;
+        }
+
+        let mut dus = Vec::new();
+        if self.last_send.is_some_and(|last| time < last + LACP_PERIOD) {
+            return dus;
+        }
+
+        self.last_send = Some(time);
+        for member in &self.members {
+            if member.up {
+                dus.push((member.iface, LacpDu {
+                    actor: self.actor_info(member),
+                    partner: member.partner.map(|(info, _)| info).unwrap_or(LacpInfo {
+                        system: MacAddr::ZERO,
+                        key: 0,
+                        port: 0,
+                        sync: false,
+                    }),
+                }));
+            }
+        }
+
+        dus
+
+    }
+
+}
+
+impl LacpMember {
+
+    fn is_active(&self) -> bool {
+        self.selected && self.partner.is_some_and(|(info, _)| info.sync)
+    }
+
+}
+
+/// Build the ethernet frame of a LACPDU.
+pub(crate) fn lacp_frame(src: MacAddr, du: LacpDu) -> Box<EthFrame> {
+    Box::new(EthFrame {
+        src,
+        dst: MacAddr::SLOW_PROTOCOLS,
+        payload: EthPayload::Lacp(Box::new(du)),
+    })
+}
+
+/// Compute the hash of a frame's flow.
+fn frame_hash(frame: &EthFrame, hash: LacpHash) -> u64 {
+
+    let mut payload = &frame.payload;
+    if let EthPayload::Vlan { inner, .. } = payload {
+        payload = inner;
+    }
+
+    let mut bytes = Vec::with_capacity(12);
+    match (hash, payload) {
+        (LacpHash::Ip, EthPayload::Ipv4(packet)) => {
+            bytes.extend_from_slice(&packet.src.octets());
+            bytes.extend_from_slice(&packet.dst.octets());
+        }
+        _ => {
+            bytes.extend_from_slice(&frame.src.0);
+            bytes.extend_from_slice(&frame.dst.0);
+        }
+    }
+
+    // FNV-1a
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+
+}
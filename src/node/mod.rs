@@ -1,11 +1,25 @@
 //! Module for complex nodes.
 
 mod noop;
+mod ap;
+mod capture;
 mod eth;
+mod hub;
+mod lacp;
 mod simple;
 mod server;
+mod snooping;
+mod spoof;
+mod stp;
 
 pub use noop::*;
+pub use ap::*;
+pub use capture::*;
 pub use eth::*;
+pub use hub::*;
+pub use lacp::*;
 pub use simple::*;
 pub use server::*;
+pub use snooping::*;
+pub use spoof::*;
+pub use stp::*;
@@ -14,6 +14,12 @@ impl<T> NoopNode<T> {
     }
 }
 
+impl<T> Default for NoopNode<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: 'static> Node for NoopNode<T> {
 
     fn link(&mut self, _iface: usize, link: RawLinkHandle) -> bool {
@@ -28,7 +34,7 @@ impl<T: 'static> Node for NoopNode<T> {
     fn tick(&mut self, links: &mut Links) {
         for handle in &self.links {
             let mut link = links.get(handle);
-            while let Some(_) = link.recv() { }
+            while link.recv().is_some() { }
         }
     }
 
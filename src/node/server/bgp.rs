@@ -0,0 +1,788 @@
+//! Implementation of a BGP-4 speaker, exchanging routes with configured
+//! peers over TCP sessions. Best routes are installed in the IPv4 routes
+//! of the node, their next hop is resolved through other routes.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::Ordering;
+use std::ops::RangeInclusive;
+
+use crate::proto::{
+    Ipv4Addr, Ipv4Packet, Ipv4Payload, IpPrefix, TcpSegment, TcpFlags,
+    BgpMessage, BgpPathAttrs, BgpOrigin, bgp_error, BGP_PORT, BGP_HOLD_TIME,
+};
+
+use super::tcp::{TcpConnection, TcpState};
+use super::{ServerNode, IpRouteLink, IpRouteSource};
+
+
+/// Local preference of routes that don't have one.
+pub const BGP_DEFAULT_LOCAL_PREF: u32 = 100;
+
+/// Time between two connection attempts to a peer, in ticks.
+const BGP_CONNECT_RETRY: u64 = 30;
+/// Hold time used until the open message of the peer is received.
+const BGP_OPEN_HOLD_TIME: u64 = 240;
+/// First local port of connections opened by the speaker.
+const BGP_EPHEMERAL_PORT: u16 = 49152;
+/// Maximum number of prefixes in an update message.
+const BGP_MAX_UPDATE_PREFIXES: usize = 200;
+
+
+/// Configuration of a peer.
+#[derive(Debug, Clone)]
+pub struct BgpPeerConf {
+    pub address: Ipv4Addr,
+    /// Autonomous system of the peer, the peer is internal if it is the
+    /// one of the speaker.
+    pub asn: u16,
+    pub hold_time: u16,
+    /// Set the next hop of routes sent to an internal peer to the address
+    /// of the speaker, instead of keeping the external next hop.
+    pub next_hop_self: bool,
+    /// Policy applied to routes received from the peer.
+    pub import: BgpPolicy,
+    /// Policy applied to routes sent to the peer.
+    pub export: BgpPolicy,
+}
+
+impl BgpPeerConf {
+
+    pub fn new(address: Ipv4Addr, asn: u16) -> Self {
+        Self {
+            address,
+            asn,
+            hold_time: BGP_HOLD_TIME,
+            next_hop_self: false,
+            import: BgpPolicy::default(),
+            export: BgpPolicy::default(),
+        }
+    }
+
+    #[inline]
+    pub fn with_hold_time(mut self, hold_time: u16) -> Self {
+        self.hold_time = hold_time;
+        self
+    }
+
+    #[inline]
+    pub fn with_next_hop_self(mut self) -> Self {
+        self.next_hop_self = true;
+        self
+    }
+
+    #[inline]
+    pub fn with_import(mut self, policy: BgpPolicy) -> Self {
+        self.import = policy;
+        self
+    }
+
+    #[inline]
+    pub fn with_export(mut self, policy: BgpPolicy) -> Self {
+        self.export = policy;
+        self
+    }
+
+}
+
+/// A list of rules filtering and modifying routes, the first rule matching
+/// the prefix of a route applies, or the default action if none matches.
+#[derive(Debug, Clone)]
+pub struct BgpPolicy {
+    rules: Vec<BgpPolicyRule>,
+    default: BgpPolicyAction,
+}
+
+/// Action of a policy rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BgpPolicyAction {
+    #[default]
+    Permit,
+    Deny,
+}
+
+/// A rule of a policy, matching prefixes contained in a prefix with a
+/// length in the given range, by default the exact prefix.
+#[derive(Debug, Clone)]
+pub struct BgpPolicyRule {
+    pub prefix: IpPrefix<Ipv4Addr>,
+    pub len: RangeInclusive<u8>,
+    pub action: BgpPolicyAction,
+    /// Set the local preference of permitted routes.
+    pub local_pref: Option<u32>,
+    /// Set the multi-exit discriminator of permitted routes.
+    pub med: Option<u32>,
+    /// Number of times the autonomous system of the speaker is prepended
+    /// to the path of permitted routes.
+    pub prepend: u8,
+}
+
+impl BgpPolicy {
+
+    /// Construct a policy without rule.
+    pub fn new(default: BgpPolicyAction) -> Self {
+        Self { rules: Vec::new(), default }
+    }
+
+    /// Add a rule at the end of the policy.
+    #[inline]
+    pub fn add_rule(&mut self, rule: BgpPolicyRule) {
+        self.rules.push(rule);
+    }
+
+    #[inline]
+    pub fn with_rule(mut self, rule: BgpPolicyRule) -> Self {
+        self.add_rule(rule);
+        self
+    }
+
+    #[inline]
+    pub fn rules(&self) -> &[BgpPolicyRule] {
+        &self.rules
+    }
+
+    /// Apply the policy to a route, return `false` if it is denied.
+    fn apply(&self, prefix: IpPrefix<Ipv4Addr>, attrs: &mut BgpPathAttrs, asn: u16) -> bool {
+        let Some(rule) = self.rules.iter().find(|rule| rule.matches(prefix)) else {
+            return self.default == BgpPolicyAction::Permit;
+        };
+        if rule.action == BgpPolicyAction::Deny {
+            return false;
+        }
+        if let Some(local_pref) = rule.local_pref {
+            attrs.local_pref = Some(local_pref);
+        }
+        if let Some(med) = rule.med {
+            attrs.med = Some(med);
+        }
+        attrs.as_path.splice(0..0, std::iter::repeat_n(asn, rule.prepend as usize));
+        true
+    }
+
+}
+
+impl Default for BgpPolicy {
+    fn default() -> Self {
+        Self::new(BgpPolicyAction::Permit)
+    }
+}
+
+impl BgpPolicyRule {
+
+    /// Construct a rule matching exactly the given prefix.
+    pub fn new(prefix: IpPrefix<Ipv4Addr>, action: BgpPolicyAction) -> Self {
+        Self {
+            prefix,
+            len: prefix.prefix_len()..=prefix.prefix_len(),
+            action,
+            local_pref: None,
+            med: None,
+            prepend: 0,
+        }
+    }
+
+    /// Match prefixes contained in the prefix of the rule with a length in
+    /// the given range.
+    #[inline]
+    pub fn with_len(mut self, len: RangeInclusive<u8>) -> Self {
+        self.len = len;
+        self
+    }
+
+    #[inline]
+    pub fn with_local_pref(mut self, local_pref: u32) -> Self {
+        self.local_pref = Some(local_pref);
+        self
+    }
+
+    #[inline]
+    pub fn with_med(mut self, med: u32) -> Self {
+        self.med = Some(med);
+        self
+    }
+
+    #[inline]
+    pub fn with_prepend(mut self, count: u8) -> Self {
+        self.prepend = count;
+        self
+    }
+
+    /// Return `true` if the rule matches the given prefix.
+    pub fn matches(&self, prefix: IpPrefix<Ipv4Addr>) -> bool {
+        prefix.prefix_len() >= self.prefix.prefix_len() &&
+        self.prefix.matches(prefix.ip()) &&
+        self.len.contains(&prefix.prefix_len())
+    }
+
+}
+
+/// State of the session with a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BgpPeerState {
+    /// Waiting before the next connection attempt.
+    Idle,
+    /// Connecting to the peer.
+    Connect,
+    /// Waiting for the peer to connect.
+    Active,
+    OpenSent,
+    OpenConfirm,
+    /// Routes are exchanged with the peer.
+    Established,
+}
+
+/// A best route selected by the speaker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BgpRoute {
+    pub prefix: IpPrefix<Ipv4Addr>,
+    pub attrs: BgpPathAttrs,
+    /// Address of the peer the route was learned from, `None` if it is
+    /// originated by the speaker.
+    pub peer: Option<Ipv4Addr>,
+}
+
+/// Internal state of a peer.
+#[derive(Debug)]
+struct BgpPeer {
+    conf: BgpPeerConf,
+    state: BgpPeerState,
+    conn: Option<TcpConnection>,
+    /// Received data not yet decoded as messages.
+    buf: Vec<u8>,
+    router_id: Ipv4Addr,
+    hold_time: u64,
+    hold_deadline: u64,
+    next_keepalive: u64,
+    next_connect: u64,
+    /// Routes received from the peer, before the import policy.
+    adj_rib_in: BTreeMap<IpPrefix<Ipv4Addr>, BgpPathAttrs>,
+    /// Routes sent to the peer.
+    adj_rib_out: BTreeMap<IpPrefix<Ipv4Addr>, BgpPathAttrs>,
+}
+
+impl BgpPeer {
+
+    fn send(&mut self, message: &BgpMessage) {
+        if let Some(conn) = &mut self.conn {
+            conn.write(&message.encode());
+        }
+    }
+
+    /// Close the session, sending a notification if the connection is
+    /// still established.
+    fn close(&mut self, notification: Option<(u8, u8)>, time: u64) {
+        if let Some((code, subcode)) = notification {
+            self.send(&BgpMessage::Notification { code, subcode });
+        }
+        if let Some(conn) = &mut self.conn {
+            conn.abort();
+        }
+        self.state = BgpPeerState::Idle;
+        self.next_connect = time + BGP_CONNECT_RETRY;
+        self.buf.clear();
+        self.adj_rib_in.clear();
+        self.adj_rib_out.clear();
+    }
+
+}
+
+/// A BGP speaker of an autonomous system.
+#[derive(Debug)]
+pub struct Bgp {
+    asn: u16,
+    router_id: Ipv4Addr,
+    peers: BTreeMap<Ipv4Addr, BgpPeer>,
+    /// Prefixes originated by the speaker.
+    networks: BTreeSet<IpPrefix<Ipv4Addr>>,
+    /// Best routes of each prefix.
+    loc_rib: BTreeMap<IpPrefix<Ipv4Addr>, BgpRoute>,
+    next_port: u16,
+}
+
+impl Bgp {
+
+    pub fn new(asn: u16, router_id: Ipv4Addr) -> Self {
+        Self {
+            asn,
+            router_id,
+            peers: BTreeMap::new(),
+            networks: BTreeSet::new(),
+            loc_rib: BTreeMap::new(),
+            next_port: BGP_EPHEMERAL_PORT,
+        }
+    }
+
+    #[inline]
+    pub fn asn(&self) -> u16 {
+        self.asn
+    }
+
+    #[inline]
+    pub fn router_id(&self) -> Ipv4Addr {
+        self.router_id
+    }
+
+    /// Add a peer, replacing any peer with the same address.
+    pub fn add_peer(&mut self, conf: BgpPeerConf) {
+        self.peers.insert(conf.address, BgpPeer {
+            conf,
+            state: BgpPeerState::Idle,
+            conn: None,
+            buf: Vec::new(),
+            router_id: Ipv4Addr::UNSPECIFIED,
+            hold_time: 0,
+            hold_deadline: 0,
+            next_keepalive: 0,
+            next_connect: 0,
+            adj_rib_in: BTreeMap::new(),
+            adj_rib_out: BTreeMap::new(),
+        });
+    }
+
+    /// Get the configuration of a peer.
+    pub fn peer_conf(&self, address: Ipv4Addr) -> Option<&BgpPeerConf> {
+        self.peers.get(&address).map(|peer| &peer.conf)
+    }
+
+    /// Get the configuration of a peer to change its policies, which are
+    /// applied again to all routes on the next tick.
+    pub fn peer_conf_mut(&mut self, address: Ipv4Addr) -> Option<&mut BgpPeerConf> {
+        self.peers.get_mut(&address).map(|peer| &mut peer.conf)
+    }
+
+    /// Get the session state with a peer.
+    pub fn peer_state(&self, address: Ipv4Addr) -> Option<BgpPeerState> {
+        self.peers.get(&address).map(|peer| peer.state)
+    }
+
+    /// Originate routes for the given prefix, a route for it doesn't need
+    /// to exist in the node.
+    #[inline]
+    pub fn add_network(&mut self, prefix: IpPrefix<Ipv4Addr>) {
+        self.networks.insert(prefix);
+    }
+
+    /// Stop originating routes for the given prefix.
+    #[inline]
+    pub fn remove_network(&mut self, prefix: IpPrefix<Ipv4Addr>) -> bool {
+        self.networks.remove(&prefix)
+    }
+
+    /// Iterate over the best routes.
+    pub fn routes(&self) -> impl Iterator<Item = &BgpRoute> + '_ {
+        self.loc_rib.values()
+    }
+
+    /// Get the best route of the given prefix.
+    pub fn route(&self, prefix: IpPrefix<Ipv4Addr>) -> Option<&BgpRoute> {
+        self.loc_rib.get(&prefix)
+    }
+
+    /// Iterate over the routes received from a peer, before its import
+    /// policy is applied.
+    pub fn received_routes(&self, address: Ipv4Addr) -> impl Iterator<Item = (IpPrefix<Ipv4Addr>, &BgpPathAttrs)> + '_ {
+        self.peers.get(&address).into_iter()
+            .flat_map(|peer| peer.adj_rib_in.iter().map(|(&prefix, attrs)| (prefix, attrs)))
+    }
+
+    /// Iterate over the routes sent to a peer.
+    pub fn advertised_routes(&self, address: Ipv4Addr) -> impl Iterator<Item = (IpPrefix<Ipv4Addr>, &BgpPathAttrs)> + '_ {
+        self.peers.get(&address).into_iter()
+            .flat_map(|peer| peer.adj_rib_out.iter().map(|(&prefix, attrs)| (prefix, attrs)))
+    }
+
+    /// Process the messages received from a peer.
+    fn recv_messages(&mut self, address: Ipv4Addr, time: u64) {
+
+        let asn = self.asn;
+        let Some(peer) = self.peers.get_mut(&address) else { return };
+        if matches!(peer.state, BgpPeerState::Idle | BgpPeerState::Connect | BgpPeerState::Active) {
+            return;
+        }
+
+        if let Some(conn) = &mut peer.conn {
+            let data = conn.read();
+            peer.buf.extend(data);
+        }
+
+        while let Some(len) = BgpMessage::frame_len(&peer.buf) {
+
+            if peer.buf.len() < len {
+                break;
+            }
+
+            let Some(message) = BgpMessage::decode(&peer.buf[..len]) else {
+                peer.close(Some((bgp_error::MESSAGE_HEADER, 0)), time);
+                return;
+            };
+            peer.buf.drain(..len);
+
+            match (peer.state, message) {
+                (BgpPeerState::OpenSent, BgpMessage::Open { asn: peer_asn, hold_time, router_id }) => {
+                    if peer_asn != peer.conf.asn {
+                        // Bad peer AS.
+                        peer.close(Some((bgp_error::OPEN_MESSAGE, 2)), time);
+                        return;
+                    }
+                    peer.router_id = router_id;
+                    peer.hold_time = hold_time.min(peer.conf.hold_time) as u64;
+                    peer.state = BgpPeerState::OpenConfirm;
+                    peer.send(&BgpMessage::Keepalive);
+                    peer.next_keepalive = time + peer.hold_time / 3;
+                }
+                (BgpPeerState::OpenConfirm, BgpMessage::Keepalive) => {
+                    peer.state = BgpPeerState::Established;
+                }
+                (BgpPeerState::Established, BgpMessage::Keepalive) => {}
+                (BgpPeerState::Established, BgpMessage::Update { withdrawn, attrs, nlri }) => {
+                    for prefix in withdrawn {
+                        peer.adj_rib_in.remove(&prefix);
+                    }
+                    if let Some(attrs) = attrs {
+                        for prefix in nlri {
+                            peer.adj_rib_in.insert(prefix, attrs.clone());
+                        }
+                    }
+                }
+                (_, BgpMessage::Notification { .. }) => {
+                    peer.close(None, time);
+                    return;
+                }
+                _ => {
+                    peer.close(Some((bgp_error::FSM, 0)), time);
+                    return;
+                }
+            }
+
+            if peer.hold_time != 0 {
+                peer.hold_deadline = time + peer.hold_time;
+            }
+
+        }
+
+        // Routes looping through our autonomous system are discarded.
+        peer.adj_rib_in.retain(|_, attrs| !attrs.as_path.contains(&asn));
+
+    }
+
+    /// Run the decision process, selecting the best route of each prefix
+    /// among originated routes and routes accepted by import policies.
+    /// Routes whose next hop can't be resolved are ignored.
+    fn decide(&mut self, resolve: impl Fn(Ipv4Addr) -> bool) {
+
+        let mut candidates = BTreeMap::<IpPrefix<Ipv4Addr>, Vec<(BgpPathAttrs, Option<&BgpPeer>)>>::new();
+
+        for &prefix in &self.networks {
+            candidates.entry(prefix).or_default().push((BgpPathAttrs {
+                origin: BgpOrigin::Igp,
+                as_path: Vec::new(),
+                next_hop: Ipv4Addr::UNSPECIFIED,
+                med: None,
+                local_pref: Some(BGP_DEFAULT_LOCAL_PREF),
+            }, None));
+        }
+
+        for peer in self.peers.values() {
+            if peer.state != BgpPeerState::Established {
+                continue;
+            }
+            for (&prefix, attrs) in &peer.adj_rib_in {
+                let mut attrs = attrs.clone();
+                if peer.conf.asn != self.asn {
+                    // The local preference is only meaningful inside the
+                    // autonomous system.
+                    attrs.local_pref = None;
+                }
+                attrs.local_pref.get_or_insert(BGP_DEFAULT_LOCAL_PREF);
+                if resolve(attrs.next_hop) && peer.conf.import.apply(prefix, &mut attrs, self.asn) {
+                    candidates.entry(prefix).or_default().push((attrs, Some(peer)));
+                }
+            }
+        }
+
+        let asn = self.asn;
+        self.loc_rib = candidates.into_iter()
+            .filter_map(|(prefix, candidates)| {
+                let (attrs, peer) = candidates.into_iter()
+                    .reduce(|best, candidate| {
+                        match compare_routes(asn, (&candidate.0, candidate.1), (&best.0, best.1)) {
+                            Ordering::Greater => candidate,
+                            _ => best,
+                        }
+                    })?;
+                Some((prefix, BgpRoute { prefix, attrs, peer: peer.map(|peer| peer.conf.address) }))
+            })
+            .collect();
+
+    }
+
+    /// Send updates to established peers for the difference between the
+    /// routes to send and the routes already sent.
+    fn export(&mut self) {
+
+        let internal_peers = self.peers.values()
+            .filter(|peer| peer.conf.asn == self.asn)
+            .map(|peer| peer.conf.address)
+            .collect::<BTreeSet<_>>();
+
+        for peer in self.peers.values_mut() {
+
+            if peer.state != BgpPeerState::Established {
+                continue;
+            }
+
+            let Some(local) = peer.conn.as_ref().map(|conn| conn.local.0) else { continue };
+            let external = peer.conf.asn != self.asn;
+            let mut adj_rib_out = BTreeMap::new();
+
+            for route in self.loc_rib.values() {
+
+                if route.peer == Some(peer.conf.address) {
+                    continue;
+                }
+
+                let mut attrs = route.attrs.clone();
+                if external {
+                    attrs.as_path.insert(0, self.asn);
+                    attrs.next_hop = local;
+                    attrs.local_pref = None;
+                    attrs.med = None;
+                } else {
+                    // Routes learned from internal peers are not sent to
+                    // other internal peers.
+                    if route.peer.is_some_and(|address| internal_peers.contains(&address)) {
+                        continue;
+                    }
+                    if route.peer.is_none() || peer.conf.next_hop_self {
+                        attrs.next_hop = local;
+                    }
+                }
+
+                if peer.conf.export.apply(route.prefix, &mut attrs, self.asn) {
+                    adj_rib_out.insert(route.prefix, attrs);
+                }
+
+            }
+
+            let withdrawn = peer.adj_rib_out.keys()
+                .filter(|prefix| !adj_rib_out.contains_key(prefix))
+                .copied()
+                .collect::<Vec<_>>();
+
+            // Announced prefixes are grouped by path attributes.
+            let mut announced = Vec::<(BgpPathAttrs, Vec<IpPrefix<Ipv4Addr>>)>::new();
+            for (&prefix, attrs) in &adj_rib_out {
+                if peer.adj_rib_out.get(&prefix) == Some(attrs) {
+                    continue;
+                }
+                match announced.iter_mut().find(|(group_attrs, _)| group_attrs == attrs) {
+                    Some((_, prefixes)) => prefixes.push(prefix),
+                    None => announced.push((attrs.clone(), vec![prefix])),
+                }
+            }
+
+            for withdrawn in withdrawn.chunks(BGP_MAX_UPDATE_PREFIXES) {
+                peer.send(&BgpMessage::Update { withdrawn: withdrawn.to_vec(), attrs: None, nlri: Vec::new() });
+            }
+
+            for (attrs, prefixes) in announced {
+                for nlri in prefixes.chunks(BGP_MAX_UPDATE_PREFIXES) {
+                    peer.send(&BgpMessage::Update { withdrawn: Vec::new(), attrs: Some(attrs.clone()), nlri: nlri.to_vec() });
+                }
+            }
+
+            peer.adj_rib_out = adj_rib_out;
+
+        }
+
+    }
+
+}
+
+/// Compare two routes of a prefix in the decision process, the preferred
+/// route is greater. Routes are compared by highest local preference,
+/// local origination, shortest AS path, lowest origin, lowest MED if
+/// received from the same autonomous system, external over internal peer,
+/// lowest peer router identifier and lowest peer address.
+fn compare_routes(asn: u16, a: (&BgpPathAttrs, Option<&BgpPeer>), b: (&BgpPathAttrs, Option<&BgpPeer>)) -> Ordering {
+
+    let (a, a_peer) = a;
+    let (b, b_peer) = b;
+
+    let same_neighbor_as = a.as_path.first() == b.as_path.first();
+    let external = |peer: Option<&BgpPeer>| peer.is_some_and(|peer| peer.conf.asn != asn);
+
+    a.local_pref.cmp(&b.local_pref)
+        .then_with(|| a_peer.is_none().cmp(&b_peer.is_none()))
+        .then_with(|| b.as_path.len().cmp(&a.as_path.len()))
+        .then_with(|| b.origin.cmp(&a.origin))
+        .then_with(|| match same_neighbor_as {
+            true => b.med.unwrap_or(0).cmp(&a.med.unwrap_or(0)),
+            false => Ordering::Equal,
+        })
+        .then_with(|| external(a_peer).cmp(&external(b_peer)))
+        .then_with(|| {
+            let key = |peer: Option<&BgpPeer>| peer.map(|peer| (peer.router_id, peer.conf.address));
+            key(b_peer).cmp(&key(a_peer))
+        })
+
+}
+
+impl ServerNode {
+
+    /// Enable a BGP speaker, or disable it with `None`, in which case its
+    /// routes are removed.
+    pub fn set_bgp(&mut self, bgp: Option<Bgp>) {
+        if bgp.is_none() {
+            self.ipv4_routes.remove_routes_from(IpRouteSource::Bgp);
+            self.ipv4_routes.remove_routes_from(IpRouteSource::Ibgp);
+        }
+        self.bgp = bgp;
+    }
+
+    #[inline]
+    pub fn bgp(&self) -> Option<&Bgp> {
+        self.bgp.as_ref()
+    }
+
+    #[inline]
+    pub fn bgp_mut(&mut self) -> Option<&mut Bgp> {
+        self.bgp.as_mut()
+    }
+
+    /// Resolve the interface and gateway to reach the given next hop of a
+    /// BGP route, without using BGP routes.
+    fn bgp_resolve(&self, next_hop: Ipv4Addr) -> Option<(usize, Ipv4Addr)> {
+        if self.is_local_ipv4(next_hop) {
+            return None;
+        }
+        self.ipv4_routes.fib()
+            .find(|&(prefix, _, _, source)| !matches!(source, IpRouteSource::Bgp | IpRouteSource::Ibgp) && prefix.matches(next_hop))
+            .map(|(_, iface, link, _)| (iface, link.ip_or_default(next_hop)))
+    }
+
+    /// Process a TCP segment addressed to the node from or to the BGP port,
+    /// return `false` if it doesn't belong to a configured peer.
+    pub(super) fn bgp_recv(&mut self, src: Ipv4Addr, dst: Ipv4Addr, segment: &TcpSegment) -> bool {
+
+        let mss = self.tcp_mss(src);
+        let time = self.time;
+        let Some(bgp) = &mut self.bgp else { return false };
+        let Some(peer) = bgp.peers.get_mut(&src) else { return false };
+
+        if let Some(conn) = peer.conn.as_mut().filter(|conn| conn.matches(src, segment)) {
+            conn.recv(segment, time);
+            return true;
+        }
+
+        if segment.dst_port != BGP_PORT || !segment.flags.contains(TcpFlags::SYN) || segment.flags.contains(TcpFlags::ACK) {
+            return false;
+        }
+
+        // Connections are opened by the peer with the lowest address, we
+        // only accept one while not yet connected.
+        let accept = match peer.state {
+            BgpPeerState::Idle | BgpPeerState::Active => true,
+            BgpPeerState::Connect => src < dst,
+            _ => false,
+        };
+
+        if let (true, Some(mss)) = (accept, mss) {
+            peer.conn = Some(TcpConnection::accept((dst, BGP_PORT), (src, segment.src_port), segment, mss, time));
+            peer.state = BgpPeerState::Active;
+            peer.hold_deadline = time + BGP_OPEN_HOLD_TIME;
+        }
+
+        true
+
+    }
+
+    /// Run the session of each peer, the decision process and send updates.
+    /// Best routes are installed in the node if they changed.
+    pub(super) fn bgp_tick(&mut self, time: u64) {
+
+        let Some(mut bgp) = self.bgp.take() else { return };
+
+        let addresses = bgp.peers.keys().copied().collect::<Vec<_>>();
+        for address in addresses {
+
+            let local = self.source_ipv4(address);
+            let mss = self.tcp_mss(address);
+            let peer = bgp.peers.get_mut(&address).unwrap();
+
+            let conn_state = peer.conn.as_ref().map(TcpConnection::state);
+            match peer.state {
+                BgpPeerState::Idle if time >= peer.next_connect => {
+                    if let (Some(local), Some(mss)) = (local, mss) {
+                        if local < address {
+                            let port = bgp.next_port;
+                            bgp.next_port = bgp.next_port.checked_add(1).unwrap_or(BGP_EPHEMERAL_PORT);
+                            peer.conn = Some(TcpConnection::connect((local, port), (address, BGP_PORT), mss, time));
+                            peer.state = BgpPeerState::Connect;
+                        } else {
+                            peer.state = BgpPeerState::Active;
+                        }
+                        peer.hold_deadline = time + BGP_OPEN_HOLD_TIME;
+                    }
+                }
+                BgpPeerState::Connect | BgpPeerState::Active if conn_state == Some(TcpState::Established) => {
+                    peer.state = BgpPeerState::OpenSent;
+                    peer.hold_deadline = time + BGP_OPEN_HOLD_TIME;
+                    peer.send(&BgpMessage::Open {
+                        asn: bgp.asn,
+                        hold_time: peer.conf.hold_time,
+                        router_id: bgp.router_id,
+                    });
+                }
+                BgpPeerState::Idle => {}
+                _ if conn_state == Some(TcpState::Closed) => {
+                    peer.close(None, time);
+                }
+                BgpPeerState::Connect | BgpPeerState::Active if time >= peer.hold_deadline => {
+                    peer.close(None, time);
+                }
+                BgpPeerState::OpenSent | BgpPeerState::OpenConfirm | BgpPeerState::Established => {
+                    if time >= peer.hold_deadline && (peer.state == BgpPeerState::OpenSent || peer.hold_time != 0) {
+                        peer.close(Some((bgp_error::HOLD_TIMER_EXPIRED, 0)), time);
+                    } else if peer.state != BgpPeerState::OpenSent && peer.hold_time != 0 && time >= peer.next_keepalive {
+                        peer.send(&BgpMessage::Keepalive);
+                        peer.next_keepalive = time + peer.hold_time / 3;
+                    }
+                }
+                _ => {}
+            }
+
+            bgp.recv_messages(address, time);
+
+        }
+
+        bgp.decide(|next_hop| next_hop.is_unspecified() || self.bgp_resolve(next_hop).is_some());
+        bgp.export();
+
+        for peer in bgp.peers.values_mut() {
+            let Some(conn) = &mut peer.conn else { continue };
+            for segment in conn.poll(time) {
+                self.send_ipv4(Box::new(Ipv4Packet::new(conn.local.0, conn.remote.0, Ipv4Payload::Tcp(segment))));
+            }
+            if conn.state() == TcpState::Closed {
+                peer.conn = None;
+            }
+        }
+
+        // Routes from internal peers have their own source, so that IGP
+        // routes are preferred over them.
+        let mut external = Vec::new();
+        let mut internal = Vec::new();
+        for route in bgp.loc_rib.values() {
+            let Some(peer) = route.peer.and_then(|address| bgp.peers.get(&address)) else { continue };
+            let Some((iface, gateway)) = self.bgp_resolve(route.attrs.next_hop) else { continue };
+            let routes = if peer.conf.asn == bgp.asn { &mut internal } else { &mut external };
+            routes.push((route.prefix, iface, IpRouteLink::Indirect(gateway)));
+        }
+        self.ipv4_routes.replace_routes_from(IpRouteSource::Bgp, external);
+        self.ipv4_routes.replace_routes_from(IpRouteSource::Ibgp, internal);
+
+        self.bgp = Some(bgp);
+
+    }
+
+}
@@ -0,0 +1,122 @@
+//! Implementation of an Ethernet interface bonding many links with LACP.
+
+use crate::net::Link;
+use crate::node::{LacpGroup, lacp_frame};
+use crate::proto::{MacAddr, EthFrame, EthPayload, Ipv4Packet, Ipv4Addr};
+
+use super::{ServerEthIface, ServerIface, ServerIfaceConf, ServerIfaceIpv4, ServerIfaceCtx, ServerIfaceLinks};
+
+
+/// Ethernet interface aggregating many member links with LACP, it is
+/// seen as a single interface by the node. Frames are received from
+/// and distributed over the active members of the group. The members
+/// are linked to the bond once it is added to the node.
+pub struct ServerBondIface {
+    eth: ServerEthIface,
+    group: LacpGroup,
+}
+
+impl ServerBondIface {
+
+    /// Create a new bond with the given MAC address, that is also used as
+    /// LACP system identifier, aggregation key and member interfaces.
+    pub fn new(mac_addr: MacAddr, key: u16, members: &[usize]) -> Self {
+        Self {
+            eth: ServerEthIface::new(mac_addr),
+            group: LacpGroup::new(mac_addr, key, members),
+        }
+    }
+
+    /// Get the underlying Ethernet interface, used for ARP.
+    #[inline]
+    pub fn eth(&self) -> &ServerEthIface {
+        &self.eth
+    }
+
+    #[inline]
+    pub fn eth_mut(&mut self) -> &mut ServerEthIface {
+        &mut self.eth
+    }
+
+    /// Get the aggregation group of member links.
+    #[inline]
+    pub fn group(&self) -> &LacpGroup {
+        &self.group
+    }
+
+    #[inline]
+    pub fn group_mut(&mut self) -> &mut LacpGroup {
+        &mut self.group
+    }
+
+    /// Distribute queued frames over active members, frames are dropped
+    /// if there is no active member.
+    fn flush(&mut self, links: &mut ServerIfaceLinks<EthFrame>) {
+        for frame in self.eth.take_tx_queue() {
+            let Some(member) = self.group.select(&frame) else { continue };
+            if let Some(mut link) = links.get(member) {
+                link.send(frame);
+            }
+        }
+    }
+
+}
+
+impl ServerIface<EthFrame> for ServerBondIface {
+
+    /// A bond without member is a plain Ethernet interface.
+    fn tick(&mut self, link: Link<EthFrame>, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+        self.eth.tick(link, conf, ctx);
+    }
+
+    fn send_ipv4(&mut self, link: Link<EthFrame>, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
+        self.eth.send_ipv4(link, conf, packet, link_addr);
+    }
+
+    fn members(&self) -> Vec<usize> {
+        self.group.members().collect()
+    }
+
+    fn tick_members(&mut self, mut links: ServerIfaceLinks<EthFrame>, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+
+        let time = links.time();
+        self.eth.set_time(time);
+
+        let mut up = Vec::new();
+        for iface in links.ifaces().collect::<Vec<_>>() {
+            let Some(mut link) = links.get(iface) else { continue };
+            up.push((iface, link.is_up()));
+            while let Some(frame) = link.recv() {
+                if let EthPayload::Lacp(du) = &frame.payload {
+                    if frame.dst == MacAddr::SLOW_PROTOCOLS {
+                        self.group.recv(iface, du, time);
+                        continue;
+                    }
+                }
+                if self.group.is_active(iface) {
+                    self.eth.recv_frame(*frame, conf, ctx);
+                }
+            }
+        }
+
+        self.eth.check_next_hops(conf, ctx);
+        self.eth.announce_virtual(ctx);
+
+        let system = self.group.system();
+        for (iface, du) in self.group.tick(time, |iface| up.contains(&(iface, true))) {
+            if let Some(mut link) = links.get(iface) {
+                link.send(lacp_frame(system, du));
+            }
+        }
+
+        self.flush(&mut links);
+
+    }
+
+    fn send_ipv4_members(&mut self, mut links: ServerIfaceLinks<EthFrame>, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
+        self.eth.set_time(links.time());
+        self.eth.queue_ipv4(conf, packet, link_addr);
+        self.flush(&mut links);
+    }
+
+}
@@ -1,8 +1,7 @@
 //! Implementation of the Ethernet data-link layer handler.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry;
-use std::time::{Instant, Duration};
 
 use crate::net::Link;
 use crate::proto::{
@@ -11,10 +10,12 @@ use crate::proto::{
     Ipv4Packet, Ipv4Addr,
 };
 
-use super::{ServerIface, ServerIfaceConf, ServerIfaceIpv4};
+use super::{ServerIface, ServerIfaceConf, ServerIfaceIpv4, ServerIfaceCtx, IpRouteLink};
 
 
-const ARP_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of ticks before an unanswered ARP request is sent again, the
+/// neighbor is then considered dead until it answers.
+const ARP_REQUEST_TIMEOUT: u64 = 10;
 
 
 /// Ethernet interface.
@@ -22,59 +23,364 @@ pub struct ServerEthIface {
     /// MAC address of the interface.
     mac_addr: MacAddr,
     arp_cache: HashMap<Ipv4Addr, ArpEntry>,
+    /// Frames waiting to be sent on the link, frames are queued while
+    /// processing and sent at the end of ticks, or at the next tick if 
+    /// they are released outside of a tick.
+    #[allow(clippy::vec_box)]
+    tx_queue: Vec<Box<EthFrame>>,
+    /// When enabled, ARP requests for addresses that the node routes
+    /// through another interface are answered with our MAC address.
+    proxy_arp: bool,
+    /// Time of the last tick.
+    time: u64,
+    /// Multicast MAC addresses accepted in addition to the ones of
+    /// the groups joined by the node.
+    multicast_macs: HashSet<MacAddr>,
+    /// When enabled, all frames are accepted, whatever their destination.
+    promiscuous: bool,
+    /// Virtual addresses already announced with gratuitous ARP.
+    announced_virtual: Vec<(Ipv4Addr, MacAddr)>,
 }
 
 enum ArpEntry {
-    Known(MacAddr),
+    Known {
+        mac: MacAddr,
+        time: u64,
+    },
+    Static {
+        mac: MacAddr,
+        time: u64,
+    },
     Pending {
-        time: Instant,
+        time: u64,
+        #[allow(clippy::vec_box)]
         packets: Vec<Box<Ipv4Packet>>,
     }
 }
 
+/// State of an ARP cache entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpState {
+    /// An ARP request has been sent and no reply has been received yet.
+    Incomplete,
+    /// The MAC address has been learned from ARP traffic.
+    Reachable(MacAddr),
+    /// The MAC address has been manually added and never changes.
+    Permanent(MacAddr),
+}
+
+impl ArpState {
+
+    /// Get the resolved MAC address, if any.
+    pub fn mac(self) -> Option<MacAddr> {
+        match self {
+            ArpState::Incomplete => None,
+            ArpState::Reachable(mac) |
+            ArpState::Permanent(mac) => Some(mac),
+        }
+    }
+
+}
+
+/// A snapshot of an ARP cache entry, returned by `ServerEthIface::arp_entries`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpCacheEntry {
+    /// The IPv4 address of the neighbor.
+    pub ip: Ipv4Addr,
+    /// The current state of the entry.
+    pub state: ArpState,
+    /// Number of ticks since the entry was last updated.
+    pub age: u64,
+}
+
+impl ArpEntry {
+
+    fn info(&self, ip: Ipv4Addr, now: u64) -> ArpCacheEntry {
+        let (state, time) = match *self {
+            ArpEntry::Known { mac, time } => (ArpState::Reachable(mac), time),
+            ArpEntry::Static { mac, time } => (ArpState::Permanent(mac), time),
+            ArpEntry::Pending { time, .. } => (ArpState::Incomplete, time),
+        };
+        ArpCacheEntry { ip, state, age: now.saturating_sub(time) }
+    }
+
+}
+
 impl ServerEthIface {
 
     pub fn new(mac_addr: MacAddr) -> Self {
         Self {
             mac_addr,
             arp_cache: HashMap::new(),
+            tx_queue: Vec::new(),
+            proxy_arp: false,
+            time: 0,
+            multicast_macs: HashSet::new(),
+            promiscuous: false,
+            announced_virtual: Vec::new(),
         }
     }
 
+    /// Enable or disable proxy ARP on this interface, disabled by default.
+    #[inline]
+    pub fn set_proxy_arp(&mut self, enabled: bool) {
+        self.proxy_arp = enabled;
+    }
+
+    #[inline]
+    pub fn is_proxy_arp(&self) -> bool {
+        self.proxy_arp
+    }
+
+    /// Accept frames sent to the given multicast MAC address, in addition
+    /// to the ones of the groups joined by the node.
+    pub fn add_multicast_mac(&mut self, mac: MacAddr) {
+        assert!(mac.is_multicast(), "not a multicast address");
+        self.multicast_macs.insert(mac);
+    }
+
+    /// Stop accepting frames sent to the given multicast MAC address.
+    pub fn remove_multicast_mac(&mut self, mac: MacAddr) {
+        self.multicast_macs.remove(&mac);
+    }
+
+    /// Iterate over the multicast MAC addresses manually added to the filter.
+    pub fn multicast_macs(&self) -> impl Iterator<Item = MacAddr> + '_ {
+        self.multicast_macs.iter().copied()
+    }
+
+    /// Enable or disable promiscuous mode, disabled by default. In this mode
+    /// all frames are accepted, IPv4 packets of frames sent to other hosts
+    /// are only given to the node if they are addressed to this interface,
+    /// and are never forwarded.
+    #[inline]
+    pub fn set_promiscuous(&mut self, enabled: bool) {
+        self.promiscuous = enabled;
+    }
+
+    #[inline]
+    pub fn is_promiscuous(&self) -> bool {
+        self.promiscuous
+    }
+
+    /// Return `true` if a frame sent to the given MAC address passes the
+    /// filter of this interface.
+    fn is_accepted(&self, dst: MacAddr, ctx: &ServerIfaceCtx) -> bool {
+        if self.promiscuous || dst == self.mac_addr || dst == MacAddr::BROADCAST || ctx.is_virtual_mac(dst) {
+            true
+        } else if dst.is_multicast() {
+            self.multicast_macs.contains(&dst) || ctx.is_multicast_accepted(dst)
+        } else {
+            false
+        }
+    }
+
+    /// Get the MAC address of this interface.
+    #[inline]
+    pub fn mac_addr(&self) -> MacAddr {
+        self.mac_addr
+    }
+
+    /// Add a permanent entry to the ARP cache, it will not be replaced
+    /// by ARP traffic. Packets waiting for this address to be resolved
+    /// are sent on the next tick.
+    pub fn add_static_arp(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        let entry = ArpEntry::Static { mac, time: self.time };
+        if let Some(ArpEntry::Pending { packets, .. }) = self.arp_cache.insert(ip, entry) {
+            for packet in packets {
+                self.tx_queue.push(Box::new(EthFrame {
+                    src: self.mac_addr,
+                    dst: mac,
+                    payload: EthPayload::Ipv4(packet),
+                }));
+            }
+        }
+    }
+
+    /// Queue a raw frame, sent on the next tick of the interface.
+    pub fn queue_frame(&mut self, frame: Box<EthFrame>) {
+        self.tx_queue.push(frame);
+    }
+
+    /// Remove the ARP cache entry for the given IP, static or not.
+    /// Packets waiting for resolution are discarded. Returns `true`
+    /// if an entry was present.
+    pub fn remove_arp(&mut self, ip: Ipv4Addr) -> bool {
+        self.arp_cache.remove(&ip).is_some()
+    }
+
+    /// Remove all non-permanent entries from the ARP cache.
+    pub fn flush_arp(&mut self) {
+        self.arp_cache.retain(|_, entry| matches!(entry, ArpEntry::Static { .. }));
+    }
+
+    /// Get the ARP cache entry for the given IP.
+    pub fn get_arp(&self, ip: Ipv4Addr) -> Option<ArpCacheEntry> {
+        self.arp_cache.get(&ip).map(|entry| entry.info(ip, self.time))
+    }
+
+    /// Iterate over all entries of the ARP cache, in no particular order.
+    pub fn arp_entries(&self) -> impl Iterator<Item = ArpCacheEntry> + '_ {
+        self.arp_cache.iter().map(|(&ip, entry)| entry.info(ip, self.time))
+    }
+
 }
 
 impl ServerIface<EthFrame> for ServerEthIface {
 
-    fn tick(&mut self, mut link: Link<EthFrame>, conf: &mut ServerIfaceConf) {
-
+    fn tick(&mut self, mut link: Link<EthFrame>, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+        self.time = link.time();
         while let Some(frame) = link.recv() {
+            self.recv_frame(*frame, conf, ctx);
+        }
+        self.check_next_hops(conf, ctx);
+        self.announce_virtual(ctx);
+        self.flush(&mut link);
+    }
 
-            if !frame.dst.is_multicast() && frame.dst != self.mac_addr {
-                // Filter incomming frames and ignore frames that don't 
-                // target this interface.
-                continue;
+    fn send_ipv4(&mut self, mut link: Link<EthFrame>, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
+        self.time = link.time();
+        self.queue_ipv4(conf, packet, link_addr);
+        self.flush(&mut link);
+    }
+
+}
+
+impl ServerEthIface {
+
+    /// Send all queued frames on the link.
+    fn flush(&mut self, link: &mut Link<EthFrame>) {
+        for frame in self.tx_queue.drain(..) {
+            link.send(frame);
+        }
+    }
+
+    /// Set the time of the current tick, for interfaces driven by another
+    /// handler.
+    #[inline]
+    pub(super) fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+
+    /// Take all queued frames.
+    #[allow(clippy::vec_box)]
+    pub(super) fn take_tx_queue(&mut self) -> Vec<Box<EthFrame>> {
+        std::mem::take(&mut self.tx_queue)
+    }
+
+    /// Process a frame received on the link, frames to send in response 
+    /// are queued.
+    pub(super) fn recv_frame(&mut self, frame: EthFrame, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+
+        if !self.is_accepted(frame.dst, ctx) {
+            // Filter incomming frames and ignore frames that don't 
+            // target this interface.
+            return;
+        }
+
+        // Frames for other hosts are only accepted in promiscuous mode.
+        let other_host = frame.dst.is_unicast() && frame.dst != self.mac_addr && !ctx.is_virtual_mac(frame.dst);
+
+        match frame.payload {
+            EthPayload::Ipv4(packet) if other_host && conf.has_ipv4(packet.dst) => {
+                ctx.recv_ipv4(packet);
+            }
+            _ if other_host => {}
+            EthPayload::Arp(arp) if !conf.ipv4.is_empty() => {
+                self.recv_arp(&arp, conf, ctx);
+            }
+            EthPayload::Ipv4(packet) if !conf.ipv4.is_empty() => {
+                ctx.recv_ipv4(packet);
             }
+            _ => {}
+        }
+
+    }
 
-            match frame.payload {
-                EthPayload::Arp(arp) => {
-                    if let Some(ipv4) = &conf.ipv4 {
-                        self.recv_arp(&mut link, &*arp, ipv4.ip);
+    /// Report the liveness of next hops to the node. A next hop is dead
+    /// when its ARP request timed out, and it is probed again until it
+    /// answers.
+    pub(super) fn check_next_hops(&mut self, conf: &ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+
+        let iface = ctx.iface();
+        let is_next_hop = |ip| ctx.ipv4_routes().routes()
+            .any(|(_, route_iface, link, _)| route_iface == iface && link == IpRouteLink::Indirect(ip));
+
+        let mut probes = Vec::new();
+        let mut changes = Vec::new();
+
+        for (&ip, entry) in &mut self.arp_cache {
+            match entry {
+                ArpEntry::Known { .. } | ArpEntry::Static { .. } => {
+                    if !ctx.ipv4_routes().is_next_hop_alive(iface, ip) {
+                        changes.push((ip, true));
                     }
                 }
-                EthPayload::Ipv4(_ip) => {
-                    if let Some(_ipv4) = &conf.ipv4 {
-                        
+                ArpEntry::Pending { time, .. } if self.time >= *time + ARP_REQUEST_TIMEOUT && is_next_hop(ip) => {
+                    if ctx.ipv4_routes().is_next_hop_alive(iface, ip) {
+                        changes.push((ip, false));
                     }
+                    *time = self.time;
+                    probes.push(ip);
                 }
-                _ => {}
+                ArpEntry::Pending { .. } => {}
             }
+        }
+
+        for (ip, alive) in changes {
+            ctx.set_next_hop_alive(ip, alive);
+        }
 
+        for ip in probes {
+            if let Some(ipv4) = conf.ipv4_for(ip) {
+                self.queue_arp_request(ipv4.ip, ip);
+            }
         }
 
     }
 
-    fn send_ipv4(&mut self, mut link: Link<EthFrame>, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
-        
+    /// Queue an ARP request for the given address.
+    fn queue_arp_request(&mut self, sender_ip: Ipv4Addr, target_ip: Ipv4Addr) {
+        self.tx_queue.push(Box::new(EthFrame {
+            src: self.mac_addr,
+            dst: MacAddr::BROADCAST,
+            payload: EthPayload::Arp(Box::new(ArpIpv4Packet {
+                op: ArpOp::Request,
+                sender_mac: self.mac_addr,
+                target_mac: MacAddr::ZERO, // Zero because it's a request.
+                sender_ip,
+                target_ip,
+            }))
+        }));
+    }
+
+    /// Queue gratuitous ARP for the virtual addresses that the node started
+    /// to answer for, so that neighbors and switches learn where their
+    /// virtual MAC address now is.
+    pub(super) fn announce_virtual(&mut self, ctx: &ServerIfaceCtx) {
+        for &(ip, mac) in ctx.ipv4_virtual() {
+            if !self.announced_virtual.contains(&(ip, mac)) {
+                self.tx_queue.push(Box::new(EthFrame {
+                    src: mac,
+                    dst: MacAddr::BROADCAST,
+                    payload: EthPayload::Arp(Box::new(ArpIpv4Packet {
+                        op: ArpOp::Request,
+                        sender_mac: mac,
+                        target_mac: MacAddr::ZERO,
+                        sender_ip: ip,
+                        target_ip: ip,
+                    })),
+                }));
+            }
+        }
+        self.announced_virtual.clear();
+        self.announced_virtual.extend_from_slice(ctx.ipv4_virtual());
+    }
+
+    /// Queue an IPv4 packet to be sent to the link address, resolving its
+    /// MAC address if needed.
+    pub(super) fn queue_ipv4(&mut self, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
+
         // Here we need to find the correct MAC address for the IP destination.
         let link_mac;
 
@@ -93,13 +399,13 @@ impl ServerIface<EthFrame> for ServerEthIface {
             let send_arp;
 
             match self.arp_cache.get_mut(&link_addr) {
-                Some(ArpEntry::Known(mac)) => {
+                Some(ArpEntry::Known { mac, .. } | ArpEntry::Static { mac, .. }) => {
                     // We know the mac address from ARP cache.
                     link_mac = *mac;
                     send_arp = false;
                 }
                 Some(ArpEntry::Pending { time, packets }) => {
-                    if time.elapsed() < ARP_REQUEST_TIMEOUT {
+                    if self.time < *time + ARP_REQUEST_TIMEOUT {
                         // A request is already in-progress, enqueue the current packet.
                         packets.push(packet);
                         return;
@@ -116,21 +422,11 @@ impl ServerIface<EthFrame> for ServerEthIface {
             }
 
             if send_arp {
-                
-                link.send(Box::new(EthFrame { 
-                    src: self.mac_addr, 
-                    dst: MacAddr::BROADCAST, 
-                    payload: EthPayload::Arp(Box::new(ArpIpv4Packet {
-                        op: ArpOp::Request,
-                        sender_mac: self.mac_addr,
-                        target_mac: MacAddr::ZERO, // Zero because it's a request.
-                        sender_ip: conf.ip, 
-                        target_ip: link_addr
-                    }))
-                }));
+
+                self.queue_arp_request(conf.ip, link_addr);
 
                 self.arp_cache.insert(link_addr, ArpEntry::Pending { 
-                    time: Instant::now(), 
+                    time: self.time,
                     packets: vec![packet],
                 });
 
@@ -141,7 +437,7 @@ impl ServerIface<EthFrame> for ServerEthIface {
         }
 
         // Actually send the packet to the right MAC address.
-        link.send(Box::new(EthFrame { 
+        self.tx_queue.push(Box::new(EthFrame { 
             src: self.mac_addr, 
             dst: link_mac, 
             payload: EthPayload::Ipv4(packet),
@@ -149,60 +445,89 @@ impl ServerIface<EthFrame> for ServerEthIface {
 
     }
 
-}
-
-impl ServerEthIface {
-
-    /// Manually associate an IPv4 to a MAC in the ARP cache.
-    fn set_arp(&mut self, link: &mut Link<EthFrame>, ip: Ipv4Addr, mac: MacAddr) {
+    /// Associate an IPv4 to a MAC in the ARP cache, learned from ARP traffic.
+    /// Static entries are left untouched.
+    fn set_arp(&mut self, ip: Ipv4Addr, mac: MacAddr) {
         match self.arp_cache.entry(ip) {
             Entry::Occupied(mut o) => {
-                if let ArpEntry::Pending { packets, .. } = o.get_mut() {
-                    for packet in packets.drain(..) {
-                        link.send(Box::new(EthFrame { 
-                            src: self.mac_addr, 
-                            dst: mac, 
-                            payload: EthPayload::Ipv4(packet)
-                        }));
+                match o.get_mut() {
+                    ArpEntry::Static { .. } => return,
+                    ArpEntry::Pending { packets, .. } => {
+                        for packet in packets.drain(..) {
+                            self.tx_queue.push(Box::new(EthFrame { 
+                                src: self.mac_addr, 
+                                dst: mac, 
+                                payload: EthPayload::Ipv4(packet)
+                            }));
+                        }
                     }
+                    ArpEntry::Known { .. } => {}
                 }
-                o.insert(ArpEntry::Known(mac));
+                o.insert(ArpEntry::Known { mac, time: self.time });
             }
             Entry::Vacant(v) => {
-                v.insert(ArpEntry::Known(mac));
+                v.insert(ArpEntry::Known { mac, time: self.time });
             }
         }
     }
 
+    /// Return true if we should answer an ARP request for the given 
+    /// target IP on behalf of another host, because it is routed through
+    /// another interface. The default route is not considered, the node
+    /// would otherwise claim every address.
+    fn is_proxied(&self, target_ip: Ipv4Addr, ctx: &ServerIfaceCtx) -> bool {
+        if !self.proxy_arp {
+            return false;
+        }
+        match ctx.ipv4_routes().fetch_specific(target_ip) {
+            Some((iface, _)) => iface != ctx.iface(),
+            None => false,
+        }
+    }
+
     /// Internal function to handle ARP IPv4.
-    fn recv_arp(&mut self, link: &mut Link<EthFrame>, arp: &ArpIpv4Packet, local_ipv4: Ipv4Addr) {
+    fn recv_arp(&mut self, arp: &ArpIpv4Packet, conf: &ServerIfaceConf, ctx: &ServerIfaceCtx) {
 
         match arp.op {
             ArpOp::Request => {
 
-                // Arp requests are only processed if we have a local
-                // IPv4 set for the interface.
-                if arp.target_ip == local_ipv4 {
-                    // If the local IP is the requested one, send reply.
-                    link.send(Box::new(EthFrame { 
+                // Arp requests are only processed if we have local
+                // IPv4 addresses set for the interface.
+                if let Some(virtual_mac) = ctx.ipv4_virtual_mac(arp.target_ip) {
+                    // Virtual addresses are answered with their virtual MAC.
+                    self.tx_queue.push(Box::new(EthFrame {
+                        src: virtual_mac,
+                        dst: arp.sender_mac,
+                        payload: EthPayload::Arp(Box::new(ArpIpv4Packet {
+                            op: ArpOp::Reply,
+                            sender_mac: virtual_mac,
+                            target_mac: arp.sender_mac,
+                            sender_ip: arp.target_ip,
+                            target_ip: arp.sender_ip
+                        }))
+                    }));
+                } else if conf.has_ipv4(arp.target_ip) || self.is_proxied(arp.target_ip, ctx) {
+                    // If a local IP is the requested one, or if we proxy it,
+                    // send reply.
+                    self.tx_queue.push(Box::new(EthFrame { 
                         src: self.mac_addr, 
                         dst: arp.sender_mac, 
                         payload: EthPayload::Arp(Box::new(ArpIpv4Packet { 
                             op: ArpOp::Reply, 
                             sender_mac: self.mac_addr, 
                             target_mac: arp.sender_mac, 
-                            sender_ip: local_ipv4, 
+                            sender_ip: arp.target_ip, 
                             target_ip: arp.sender_ip 
                         }))
                     }));
                 }
 
                 // We also take the sender IP/MAC and save it.
-                self.set_arp(link, arp.sender_ip, arp.sender_mac);
+                self.set_arp(arp.sender_ip, arp.sender_mac);
 
             }
             ArpOp::Reply => {
-                self.set_arp(link, arp.sender_ip, arp.sender_mac);
+                self.set_arp(arp.sender_ip, arp.sender_mac);
             }
         }
 
@@ -0,0 +1,489 @@
+//! Implementation of a packet filtering firewall with connection
+//! tracking, filtering packets received, forwarded and sent by a node.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use crate::proto::{
+    Ipv4Addr, Ipv4Packet, Ipv4Payload, IpPrefix, Icmpv4Message, Icmpv4Unreachable,
+    TcpSegment, TcpFlags,
+};
+
+use super::ServerNode;
+
+
+/// Number of ticks a TCP connection is tracked after a FIN or RST.
+const CONNTRACK_TCP_CLOSING_TIMEOUT: u64 = 10;
+
+
+/// Point where packets are filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FirewallChain {
+    /// Packets addressed to the node.
+    Input,
+    /// Packets forwarded by the node.
+    Forward,
+    /// Packets sent by the node.
+    Output,
+}
+
+/// Action taken on packets matching a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirewallAction {
+    #[default]
+    Accept,
+    /// Discard the packet silently.
+    Drop,
+    /// Discard the packet and answer with a TCP reset for TCP segments,
+    /// or an ICMP port unreachable error otherwise.
+    Reject,
+}
+
+/// Protocol of a packet, matched by rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FirewallProtocol {
+    Udp,
+    Tcp,
+    Icmp,
+    Igmp,
+    Ospf,
+    Vrrp,
+    Gre,
+    Ipip,
+}
+
+/// State of the connection of a packet, matched by rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnState {
+    /// The packet starts a new connection, or no reply has been seen yet.
+    New,
+    /// A reply has been seen for the connection of the packet, ICMP errors
+    /// about packets of a connection are also in this state.
+    Established,
+}
+
+/// A firewall rule, fields that are `None` match any packet. Ports only
+/// match UDP and TCP packets, and non-first fragments have no protocol.
+#[derive(Debug, Clone, Default)]
+pub struct FirewallRule {
+    pub in_iface: Option<usize>,
+    pub out_iface: Option<usize>,
+    pub src: Option<IpPrefix<Ipv4Addr>>,
+    pub dst: Option<IpPrefix<Ipv4Addr>>,
+    pub protocol: Option<FirewallProtocol>,
+    pub src_ports: Option<RangeInclusive<u16>>,
+    pub dst_ports: Option<RangeInclusive<u16>>,
+    pub state: Option<ConnState>,
+    pub action: FirewallAction,
+}
+
+impl FirewallRule {
+
+    /// Construct a rule matching all packets.
+    pub fn new(action: FirewallAction) -> Self {
+        Self { action, ..Self::default() }
+    }
+
+    #[inline]
+    pub fn with_in_iface(mut self, iface: usize) -> Self {
+        self.in_iface = Some(iface);
+        self
+    }
+
+    #[inline]
+    pub fn with_out_iface(mut self, iface: usize) -> Self {
+        self.out_iface = Some(iface);
+        self
+    }
+
+    #[inline]
+    pub fn with_src(mut self, prefix: IpPrefix<Ipv4Addr>) -> Self {
+        self.src = Some(prefix);
+        self
+    }
+
+    #[inline]
+    pub fn with_dst(mut self, prefix: IpPrefix<Ipv4Addr>) -> Self {
+        self.dst = Some(prefix);
+        self
+    }
+
+    #[inline]
+    pub fn with_protocol(mut self, protocol: FirewallProtocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    #[inline]
+    pub fn with_src_ports(mut self, ports: RangeInclusive<u16>) -> Self {
+        self.src_ports = Some(ports);
+        self
+    }
+
+    #[inline]
+    pub fn with_dst_ports(mut self, ports: RangeInclusive<u16>) -> Self {
+        self.dst_ports = Some(ports);
+        self
+    }
+
+    #[inline]
+    pub fn with_state(mut self, state: ConnState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    fn matches(&self, packet: &PacketInfo, in_iface: Option<usize>, out_iface: Option<usize>) -> bool {
+        self.in_iface.is_none_or(|iface| in_iface == Some(iface)) &&
+        self.out_iface.is_none_or(|iface| out_iface == Some(iface)) &&
+        self.src.as_ref().is_none_or(|prefix| prefix.matches(packet.src)) &&
+        self.dst.as_ref().is_none_or(|prefix| prefix.matches(packet.dst)) &&
+        self.protocol.is_none_or(|protocol| packet.protocol == Some(protocol)) &&
+        self.src_ports.as_ref().is_none_or(|ports| packet.ports.is_some_and(|(port, _)| ports.contains(&port))) &&
+        self.dst_ports.as_ref().is_none_or(|ports| packet.ports.is_some_and(|(_, port)| ports.contains(&port))) &&
+        self.state.is_none_or(|state| packet.state == state)
+    }
+
+}
+
+/// Number of packets and bytes that matched a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FirewallHits {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl FirewallHits {
+
+    fn add(&mut self, size: usize) {
+        self.packets += 1;
+        self.bytes += size as u64;
+    }
+
+}
+
+/// Number of ticks after which an unused connection is forgotten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConntrackTimeouts {
+    pub udp: u64,
+    pub tcp: u64,
+    pub icmp: u64,
+    pub other: u64,
+}
+
+impl Default for ConntrackTimeouts {
+    fn default() -> Self {
+        Self {
+            udp: 30,
+            tcp: 300,
+            icmp: 10,
+            other: 60,
+        }
+    }
+}
+
+/// Rules of a chain, the policy is applied to packets matching no rule.
+#[derive(Debug, Clone, Default)]
+struct FirewallRules {
+    rules: Vec<(FirewallRule, FirewallHits)>,
+    policy: FirewallAction,
+    policy_hits: FirewallHits,
+}
+
+/// A firewall with an ordered list of rules for each chain, the first
+/// matching rule decides of the action. Accepted packets are tracked
+/// as connections, so that replies can be matched as established.
+#[derive(Debug, Clone, Default)]
+pub struct Firewall {
+    input: FirewallRules,
+    forward: FirewallRules,
+    output: FirewallRules,
+    timeouts: ConntrackTimeouts,
+    /// Tracked connections, indexed by the flow of their first packet,
+    /// with their expiration and `true` if a reply has been seen.
+    conntrack: HashMap<Flow, (u64, bool)>,
+}
+
+/// A flow identified by the protocol, the source and destination
+/// addresses and ports.
+type Flow = (Option<FirewallProtocol>, Ipv4Addr, u16, Ipv4Addr, u16);
+
+impl Firewall {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn chain(&self, chain: FirewallChain) -> &FirewallRules {
+        match chain {
+            FirewallChain::Input => &self.input,
+            FirewallChain::Forward => &self.forward,
+            FirewallChain::Output => &self.output,
+        }
+    }
+
+    fn chain_mut(&mut self, chain: FirewallChain) -> &mut FirewallRules {
+        match chain {
+            FirewallChain::Input => &mut self.input,
+            FirewallChain::Forward => &mut self.forward,
+            FirewallChain::Output => &mut self.output,
+        }
+    }
+
+    /// Append a rule to a chain, returning its index in the chain.
+    pub fn add_rule(&mut self, chain: FirewallChain, rule: FirewallRule) -> usize {
+        let rules = &mut self.chain_mut(chain).rules;
+        rules.push((rule, FirewallHits::default()));
+        rules.len() - 1
+    }
+
+    /// Insert a rule in a chain at the given index.
+    pub fn insert_rule(&mut self, chain: FirewallChain, index: usize, rule: FirewallRule) {
+        self.chain_mut(chain).rules.insert(index, (rule, FirewallHits::default()));
+    }
+
+    /// Remove the rule at the given index of a chain.
+    pub fn remove_rule(&mut self, chain: FirewallChain, index: usize) -> FirewallRule {
+        self.chain_mut(chain).rules.remove(index).0
+    }
+
+    /// Iterate over the rules of a chain, with their hit counters.
+    pub fn rules(&self, chain: FirewallChain) -> impl Iterator<Item = (&FirewallRule, FirewallHits)> + '_ {
+        self.chain(chain).rules.iter().map(|(rule, hits)| (rule, *hits))
+    }
+
+    /// Set the action for packets matching no rule of a chain, packets are
+    /// accepted by default.
+    #[inline]
+    pub fn set_policy(&mut self, chain: FirewallChain, action: FirewallAction) {
+        self.chain_mut(chain).policy = action;
+    }
+
+    #[inline]
+    pub fn policy(&self, chain: FirewallChain) -> FirewallAction {
+        self.chain(chain).policy
+    }
+
+    /// Get the counters of packets matching no rule of a chain.
+    #[inline]
+    pub fn policy_hits(&self, chain: FirewallChain) -> FirewallHits {
+        self.chain(chain).policy_hits
+    }
+
+    /// Reset hit counters of all rules.
+    pub fn reset_hits(&mut self) {
+        for rules in [&mut self.input, &mut self.forward, &mut self.output] {
+            rules.policy_hits = FirewallHits::default();
+            for (_, hits) in &mut rules.rules {
+                *hits = FirewallHits::default();
+            }
+        }
+    }
+
+    #[inline]
+    pub fn set_conntrack_timeouts(&mut self, timeouts: ConntrackTimeouts) {
+        self.timeouts = timeouts;
+    }
+
+    #[inline]
+    pub fn conntrack_timeouts(&self) -> ConntrackTimeouts {
+        self.timeouts
+    }
+
+    /// Get the number of tracked connections.
+    #[inline]
+    pub fn connection_count(&self) -> usize {
+        self.conntrack.len()
+    }
+
+    /// Forget all tracked connections.
+    pub fn flush_conntrack(&mut self) {
+        self.conntrack.clear();
+    }
+
+    /// Get the state of the connection of a packet.
+    fn state(&self, flow: Flow, payload: &Ipv4Payload) -> ConnState {
+        let (protocol, src, src_port, dst, dst_port) = flow;
+        if matches!(self.conntrack.get(&flow), Some(&(_, true))) ||
+            self.conntrack.contains_key(&(protocol, dst, dst_port, src, src_port)) {
+            return ConnState::Established;
+        }
+        if let Ipv4Payload::Icmp(Icmpv4Message::DestinationUnreachable { original, .. }) = payload {
+            if self.conntrack.contains_key(&flow_of(original)) {
+                return ConnState::Established;
+            }
+        }
+        ConnState::New
+    }
+
+    /// Track an accepted packet.
+    fn track(&mut self, flow: Flow, payload: &Ipv4Payload, time: u64) {
+
+        let mut timeout = match flow.0 {
+            Some(FirewallProtocol::Udp) => self.timeouts.udp,
+            Some(FirewallProtocol::Tcp) => self.timeouts.tcp,
+            Some(FirewallProtocol::Icmp) => self.timeouts.icmp,
+            _ => self.timeouts.other,
+        };
+
+        match payload {
+            Ipv4Payload::Tcp(segment) if segment.flags.contains(TcpFlags::FIN) || segment.flags.contains(TcpFlags::RST) => {
+                timeout = timeout.min(CONNTRACK_TCP_CLOSING_TIMEOUT);
+            }
+            // Errors are related to an existing connection.
+            Ipv4Payload::Icmp(message) if message.is_error() => return,
+            _ => {}
+        }
+
+        let (protocol, src, src_port, dst, dst_port) = flow;
+        if let Some((expire, replied)) = self.conntrack.get_mut(&(protocol, dst, dst_port, src, src_port)) {
+            *expire = time + timeout;
+            *replied = true;
+        } else {
+            self.conntrack.entry(flow).or_insert((0, false)).0 = time + timeout;
+        }
+
+    }
+
+    /// Forget expired connections.
+    pub(super) fn tick(&mut self, time: u64) {
+        self.conntrack.retain(|_, &mut (expire, _)| expire > time);
+    }
+
+    /// Get the action for a packet, tracking it if accepted.
+    fn filter(&mut self, chain: FirewallChain, in_iface: Option<usize>, out_iface: Option<usize>, packet: &Ipv4Packet, time: u64) -> FirewallAction {
+
+        let flow = flow_of(packet);
+        let (protocol, src, src_port, dst, dst_port) = flow;
+        let info = PacketInfo {
+            src,
+            dst,
+            protocol,
+            ports: matches!(protocol, Some(FirewallProtocol::Udp | FirewallProtocol::Tcp)).then_some((src_port, dst_port)),
+            state: self.state(flow, &packet.payload),
+        };
+
+        let rules = self.chain_mut(chain);
+        let action = match rules.rules.iter_mut().find(|(rule, _)| rule.matches(&info, in_iface, out_iface)) {
+            Some((rule, hits)) => {
+                hits.add(packet.size());
+                rule.action
+            }
+            None => {
+                rules.policy_hits.add(packet.size());
+                rules.policy
+            }
+        };
+
+        if action == FirewallAction::Accept {
+            self.track(flow, &packet.payload, time);
+        }
+
+        action
+
+    }
+
+}
+
+/// Properties of a packet matched by rules.
+struct PacketInfo {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    protocol: Option<FirewallProtocol>,
+    ports: Option<(u16, u16)>,
+    state: ConnState,
+}
+
+/// Get the flow of a packet, the identifier of ICMP echo is used as
+/// ports, and the original payload of first fragments is used.
+fn flow_of(packet: &Ipv4Packet) -> Flow {
+    let mut payload = &packet.payload;
+    if let Ipv4Payload::Fragment(fragment) = payload {
+        if let Some(original) = &fragment.original {
+            payload = original;
+        }
+    }
+    let (protocol, src_port, dst_port) = match payload {
+        Ipv4Payload::Udp(datagram) => (Some(FirewallProtocol::Udp), datagram.src_port, datagram.dst_port),
+        Ipv4Payload::Tcp(segment) => (Some(FirewallProtocol::Tcp), segment.src_port, segment.dst_port),
+        Ipv4Payload::Icmp(Icmpv4Message::EchoRequest { id, .. } | Icmpv4Message::EchoReply { id, .. }) => {
+            (Some(FirewallProtocol::Icmp), *id, *id)
+        }
+        Ipv4Payload::Icmp(_) => (Some(FirewallProtocol::Icmp), 0, 0),
+        Ipv4Payload::Igmp(_) => (Some(FirewallProtocol::Igmp), 0, 0),
+        Ipv4Payload::Ospf(_) => (Some(FirewallProtocol::Ospf), 0, 0),
+        Ipv4Payload::Vrrp(_) => (Some(FirewallProtocol::Vrrp), 0, 0),
+        Ipv4Payload::Gre(_) => (Some(FirewallProtocol::Gre), 0, 0),
+        Ipv4Payload::Ipip(_) => (Some(FirewallProtocol::Ipip), 0, 0),
+        Ipv4Payload::Custom(_) | Ipv4Payload::Fragment(_) => (None, 0, 0),
+    };
+    (protocol, packet.src, src_port, packet.dst, dst_port)
+}
+
+impl ServerNode {
+
+    /// Enable the firewall with the given rules, or disable it with `None`.
+    pub fn set_firewall(&mut self, firewall: Option<Firewall>) {
+        self.firewall = firewall;
+    }
+
+    #[inline]
+    pub fn firewall(&self) -> Option<&Firewall> {
+        self.firewall.as_ref()
+    }
+
+    #[inline]
+    pub fn firewall_mut(&mut self) -> Option<&mut Firewall> {
+        self.firewall.as_mut()
+    }
+
+    /// Filter a packet in the given chain, returning it if accepted.
+    pub(super) fn firewall_filter(&mut self, chain: FirewallChain, in_iface: Option<usize>, out_iface: Option<usize>, packet: Box<Ipv4Packet>) -> Option<Box<Ipv4Packet>> {
+
+        let time = self.time;
+        let Some(firewall) = &mut self.firewall else { return Some(packet) };
+
+        match firewall.filter(chain, in_iface, out_iface, &packet, time) {
+            FirewallAction::Accept => Some(packet),
+            FirewallAction::Drop => None,
+            FirewallAction::Reject => {
+                match &packet.payload {
+                    Ipv4Payload::Tcp(segment) => self.send_tcp_reset(&packet, segment),
+                    _ => self.send_icmpv4_error(packet, Icmpv4Unreachable::Port),
+                }
+                None
+            }
+        }
+
+    }
+
+    /// Answer a TCP segment with a reset, sent on behalf of its destination.
+    fn send_tcp_reset(&mut self, packet: &Ipv4Packet, segment: &TcpSegment) {
+
+        if segment.flags.contains(TcpFlags::RST) {
+            return;
+        }
+
+        let mut ack = segment.seq.wrapping_add(segment.data.len() as u32);
+        if segment.flags.contains(TcpFlags::SYN) || segment.flags.contains(TcpFlags::FIN) {
+            ack = ack.wrapping_add(1);
+        }
+
+        let reset = Box::new(Ipv4Packet::new(packet.dst, packet.src, Ipv4Payload::Tcp(TcpSegment {
+            src_port: segment.dst_port,
+            dst_port: segment.src_port,
+            seq: if segment.flags.contains(TcpFlags::ACK) { segment.ack } else { 0 },
+            ack,
+            flags: TcpFlags::RST | TcpFlags::ACK,
+            window: 0,
+            data: Vec::new(),
+        })));
+
+        if self.is_local_ipv4(reset.dst) {
+            self.ipv4_inbox.push(reset);
+        } else {
+            self.ipv4_queue.push((None, reset));
+        }
+
+    }
+
+}
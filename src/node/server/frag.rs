@@ -0,0 +1,258 @@
+//! Implementation of IPv4 fragmentation on output, reassembly of
+//! fragments addressed to the node, path MTU discovery and ICMP
+//! error messages.
+//!
+//! The path MTU cache is only updated by ICMPv4 fragmentation-needed
+//! errors, ICMPv6 packet-too-big messages will update it once the node
+//! can send and receive IPv6 packets.
+
+use std::collections::HashMap;
+
+use crate::net::Links;
+use crate::proto::{Ipv4Addr, Ipv4Packet, Ipv4Payload, Icmpv4Message, Icmpv4Unreachable, IPV4_MIN_MTU};
+
+use super::{ServerNode, FirewallChain};
+
+
+/// Number of ticks after which an incomplete packet is discarded.
+pub const IPV4_REASSEMBLY_TIMEOUT: u64 = 30;
+/// Number of ticks after which a path MTU learned from an ICMP error
+/// is forgotten, so that increases of the path MTU are discovered.
+pub const PMTU_CACHE_TIMEOUT: u64 = 600;
+/// Length of IPv4 and TCP headers, removed from the MTU to get the MSS.
+const TCP_IPV4_HEADERS_LEN: u16 = 40;
+
+
+/// Fragments of packets being reassembled, identified by their source,
+/// destination and identifier.
+#[derive(Default)]
+pub(super) struct Ipv4Reassembly {
+    buffers: HashMap<(Ipv4Addr, Ipv4Addr, u16), ReassemblyBuffer>,
+}
+
+struct ReassemblyBuffer {
+    /// Time when the buffer is discarded if still incomplete.
+    expire: u64,
+    #[allow(clippy::vec_box)]
+    fragments: Vec<Box<Ipv4Packet>>,
+}
+
+impl ReassemblyBuffer {
+
+    /// Return the packet if all fragments have been received.
+    fn try_complete(&mut self) -> Option<Box<Ipv4Packet>> {
+
+        self.fragments.sort_by_key(|fragment| fragment.fragment_offset);
+
+        // Check that fragments cover the whole packet without holes, up to
+        // the last fragment.
+        let mut end = 0;
+        for fragment in &self.fragments {
+            let offset = fragment.fragment_offset as usize * 8;
+            if offset > end {
+                return None;
+            }
+            end = end.max(offset + fragment.payload.size());
+            if !fragment.is_fragment {
+                let mut first = self.fragments.remove(0);
+                let Ipv4Payload::Fragment(data) = &mut first.payload else { return None };
+                let original = data.original.take()?;
+                first.payload = *original;
+                first.is_fragment = false;
+                return Some(first);
+            }
+        }
+
+        None
+
+    }
+
+}
+
+impl ServerNode {
+
+    /// Get the path MTU to the given destination, if it has been learned
+    /// from an ICMP fragmentation-needed error.
+    pub fn ipv4_pmtu(&self, dst: Ipv4Addr) -> Option<u16> {
+        self.pmtu_cache.get(&dst).map(|&(mtu, _)| mtu)
+    }
+
+    /// Forget all learned path MTUs.
+    pub fn flush_pmtu_cache(&mut self) {
+        self.pmtu_cache.clear();
+    }
+
+    /// Get the TCP maximum segment size to use for the given destination,
+    /// it respects the MTU of the output interface and the path MTU. 
+    /// The output interface is found like for packets sent with `send_ipv4`.
+    /// Return `None` if there is no route to the destination.
+    #[inline]
+    pub fn tcp_mss(&self, dst: Ipv4Addr) -> Option<u16> {
+        self.tcp_mss_for(None, dst)
+    }
+
+    /// Same as `tcp_mss` for segments sent in the given VRF, like with
+    /// `send_ipv4_in`.
+    #[inline]
+    pub fn tcp_mss_in(&self, vrf: &str, dst: Ipv4Addr) -> Option<u16> {
+        self.tcp_mss_for(Some(vrf), dst)
+    }
+
+    fn tcp_mss_for(&self, vrf: Option<&str>, dst: Ipv4Addr) -> Option<u16> {
+        let src = self.source_ipv4_in(vrf, dst).unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let probe = Ipv4Packet::new(src, dst, Ipv4Payload::Custom(Vec::new()));
+        let (iface, _) = self.ipv4_table_for(None, vrf, &probe).fetch(dst)?;
+        let mut mtu = self.ifaces.get(&iface)?.conf.mtu;
+        if let Some(pmtu) = self.ipv4_pmtu(dst) {
+            mtu = mtu.min(pmtu);
+        }
+        Some(mtu.saturating_sub(TCP_IPV4_HEADERS_LEN))
+    }
+
+    /// Process an ICMP message addressed to the node, echo requests are
+    /// answered and fragmentation-needed errors update the path MTU of
+    /// the original destination. Replies are routed in the VRF of the input
+    /// interface, if any.
+    pub(super) fn recv_icmpv4(&mut self, in_iface: Option<usize>, src: Ipv4Addr, dst: Ipv4Addr, message: &Icmpv4Message) {
+        match message {
+            Icmpv4Message::EchoRequest { id, seq, data } if !dst.is_multicast() && !dst.is_broadcast() => {
+                let vrf = in_iface
+                    .and_then(|iface| self.get_iface_conf(iface))
+                    .and_then(|conf| conf.vrf.clone());
+                self.ipv4_queue.push((vrf, Box::new(Ipv4Packet::new(dst, src, Ipv4Payload::Icmp(Icmpv4Message::EchoReply {
+                    id: *id,
+                    seq: *seq,
+                    data: data.clone(),
+                })))));
+            }
+            // Invalid MTUs are ignored, the packet was smaller than the MTU.
+            &Icmpv4Message::DestinationUnreachable { code: Icmpv4Unreachable::FragmentationNeeded { mtu }, ref original }
+            if mtu >= IPV4_MIN_MTU && (mtu as usize) < original.size() => {
+                let mtu = self.ipv4_pmtu(original.dst).map_or(mtu, |pmtu| pmtu.min(mtu));
+                self.pmtu_cache.insert(original.dst, (mtu, self.time + PMTU_CACHE_TIMEOUT));
+            }
+            _ => {}
+        }
+    }
+
+    /// Add a received fragment addressed to the node, returning the whole
+    /// packet once all its fragments have been received.
+    pub(super) fn reassemble_ipv4(&mut self, packet: Box<Ipv4Packet>, time: u64) -> Option<Box<Ipv4Packet>> {
+        let key = (packet.src, packet.dst, packet.fragment_identifier);
+        let buffer = self.ipv4_reassembly.buffers.entry(key).or_insert_with(|| ReassemblyBuffer {
+            expire: time + IPV4_REASSEMBLY_TIMEOUT,
+            fragments: Vec::new(),
+        });
+        buffer.fragments.push(packet);
+        let packet = buffer.try_complete()?;
+        self.ipv4_reassembly.buffers.remove(&key);
+        Some(packet)
+    }
+
+    /// Discard incomplete packets and path MTUs that timed out.
+    pub(super) fn frag_tick(&mut self, time: u64) {
+        self.ipv4_reassembly.buffers.retain(|_, buffer| buffer.expire > time);
+        self.pmtu_cache.retain(|_, &mut (_, expire)| expire > time);
+    }
+
+    /// Send a packet on an interface, fragmenting it if it is bigger than
+    /// the MTU of the interface, the path MTU for packets sent by this
+    /// node, or the path MTU to the remote endpoint of tunnels. Packets
+    /// that can't be fragmented are discarded and an ICMP error is
+    /// returned to their source.
+    pub(super) fn output_ipv4(&mut self, links: &mut Links, iface_index: usize, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
+
+        let Some(packet) = self.nat_output(iface_index, packet) else { return };
+
+        let pmtu = self.is_local_ipv4(packet.src)
+            .then(|| self.ipv4_pmtu(packet.dst))
+            .flatten();
+
+        // Inner packets of tunnels must also fit in the path to the
+        // remote endpoint once encapsulated.
+        let pmtu = pmtu.into_iter().chain(self.tunnel_mtu(iface_index)).min();
+
+        let Some(iface) = self.ifaces.get_mut(&iface_index) else { return };
+        let mtu = pmtu.map_or(iface.conf.mtu, |pmtu| pmtu.min(iface.conf.mtu));
+        let Some(ipv4_conf) = iface.conf.ipv4_for_mut(link_addr) else {
+            // Packets that are sent to interfaces without IPv4 configuration are
+            // currently discarded silently.
+            return;
+        };
+
+        if packet.size() <= mtu as usize {
+            iface.inner.send_ipv4(links, ipv4_conf, packet, link_addr);
+        } else if !packet.allow_fragmentation {
+            self.send_icmpv4_error(packet, Icmpv4Unreachable::FragmentationNeeded { mtu });
+        } else if let Some(fragments) = packet.fragment(mtu) {
+            for fragment in fragments {
+                iface.inner.send_ipv4(&mut *links, ipv4_conf, Box::new(fragment), link_addr);
+            }
+        }
+
+    }
+
+    /// Send a packet generated by the node on the given interface without
+    /// routing it, like protocol messages to neighbors or link-local
+    /// groups. It goes through the output chain of the firewall.
+    pub(super) fn output_ipv4_local(&mut self, links: &mut Links, iface_index: usize, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
+        if let Some(packet) = self.firewall_filter(FirewallChain::Output, None, Some(iface_index), packet) {
+            self.output_ipv4(links, iface_index, packet, link_addr);
+        }
+    }
+
+    /// Get the primary IPv4 address of an interface, used as source of
+    /// the packets generated by the node on this interface.
+    pub(super) fn iface_primary_ipv4(&self, iface_index: usize) -> Option<Ipv4Addr> {
+        self.ifaces.get(&iface_index)?.conf.primary_ipv4().map(|ipv4| ipv4.ip)
+    }
+
+    /// Send an ICMP destination unreachable message to the source of the
+    /// given packet. No error is sent for ICMP errors, non-first fragments,
+    /// and packets to multicast or broadcast addresses.
+    pub(super) fn send_icmpv4_error(&mut self, original: Box<Ipv4Packet>, code: Icmpv4Unreachable) {
+
+        if original.fragment_offset != 0 || original.dst.is_multicast() || original.dst.is_broadcast() {
+            return;
+        }
+
+        if let Ipv4Payload::Icmp(message) = &original.payload {
+            if message.is_error() {
+                return;
+            }
+        }
+
+        let dst = original.src;
+        let message = Icmpv4Message::DestinationUnreachable { code, original };
+
+        if self.is_local_ipv4(dst) {
+            // The error is for a packet sent by this node.
+            self.recv_icmpv4(None, dst, dst, &message);
+            self.ipv4_inbox.push(Box::new(Ipv4Packet::new(dst, dst, Ipv4Payload::Icmp(message))));
+        } else if let Some(src) = self.source_ipv4(dst) {
+            self.ipv4_queue.push((None, Box::new(Ipv4Packet::new(src, dst, Ipv4Payload::Icmp(message)))));
+        }
+
+    }
+
+    /// Select the source address of packets sent to the given destination,
+    /// this is the destination itself if it is an address of the node,
+    /// or the address of the output interface on the network of the next
+    /// hop, or its primary address.
+    #[inline]
+    pub(super) fn source_ipv4(&self, dst: Ipv4Addr) -> Option<Ipv4Addr> {
+        self.source_ipv4_in(None, dst)
+    }
+
+    /// Same as `source_ipv4` for packets routed in the given VRF, or the
+    /// main table with `None`.
+    pub(super) fn source_ipv4_in(&self, vrf: Option<&str>, dst: Ipv4Addr) -> Option<Ipv4Addr> {
+        if self.is_own_ipv4_in(vrf, dst) {
+            return Some(dst);
+        }
+        let routes = vrf.and_then(|vrf| self.get_ipv4_table(vrf)).unwrap_or(&self.ipv4_routes);
+        let (iface, link_addr) = routes.fetch(dst)?;
+        self.ifaces.get(&iface)?.conf.ipv4_for(link_addr).map(|ipv4| ipv4.ip)
+    }
+
+}
@@ -0,0 +1,314 @@
+//! Implementation of IGMP, for hosts joining IPv4 multicast groups
+//! and for routers querying the groups that have members.
+
+use std::collections::{HashMap, BTreeSet};
+
+use crate::net::{Links, XorShift};
+use crate::proto::{
+    Ipv4Addr, Ipv4Packet, Ipv4Payload,
+    IgmpMessage, IgmpGroupRecord, IgmpRecordKind, IgmpVersion,
+    IGMP_ALL_SYSTEMS, IGMP_ALL_ROUTERS, IGMPV3_ROUTERS,
+    IGMP_QUERY_INTERVAL, IGMP_QUERY_RESPONSE_TIME, IGMP_LAST_MEMBER_QUERY_TIME,
+    IGMP_GROUP_MEMBERSHIP_INTERVAL, is_local_multicast_group,
+};
+
+use super::ServerNode;
+
+
+/// Time after which a querier that heard a query from another querier
+/// with a lower address starts querying again, in ticks.
+const OTHER_QUERIER_PRESENT_INTERVAL: u64 = 2 * IGMP_QUERY_INTERVAL + IGMP_QUERY_RESPONSE_TIME as u64 / 2;
+
+
+/// IGMP state of a server node.
+#[derive(Default)]
+pub(super) struct IgmpState {
+    version: IgmpVersion,
+    /// Groups joined on each interface.
+    groups: HashMap<usize, BTreeSet<Ipv4Addr>>,
+    /// Reports to send in response to queries and their deadline.
+    pending_reports: HashMap<(usize, Ipv4Addr), u64>,
+    /// Interfaces on which this node is a querier, and the state of
+    /// the querier.
+    queriers: HashMap<usize, Querier>,
+    /// Memberships learned by queriers and their expiration time.
+    memberships: HashMap<(usize, Ipv4Addr), u64>,
+    /// Messages to send on an interface and their destination.
+    queue: Vec<(usize, Ipv4Addr, IgmpMessage)>,
+}
+
+impl IgmpState {
+
+    /// Get the groups joined on an interface.
+    pub(super) fn groups(&self, iface: usize) -> Option<&BTreeSet<Ipv4Addr>> {
+        self.groups.get(&iface)
+    }
+
+    /// Return `true` if the node is a querier on an interface.
+    pub(super) fn is_querier(&self, iface: usize) -> bool {
+        self.queriers.contains_key(&iface)
+    }
+
+}
+
+#[derive(Default)]
+struct Querier {
+    /// Time of the last general query sent.
+    last_query: Option<u64>,
+    /// Time until another querier with a lower address is present.
+    other_querier: Option<u64>,
+}
+
+impl ServerNode {
+
+    /// Set the IGMP version used to report memberships, IGMPv3 by default.
+    #[inline]
+    pub fn set_igmp_version(&mut self, version: IgmpVersion) {
+        self.igmp.version = version;
+    }
+
+    #[inline]
+    pub fn igmp_version(&self) -> IgmpVersion {
+        self.igmp.version
+    }
+
+    /// Join an IPv4 multicast group on the given interface, an unsolicited
+    /// report is sent on the next tick. Return `false` if the address is not
+    /// multicast or the group is already joined.
+    pub fn join_ipv4_group(&mut self, iface: usize, group: Ipv4Addr) -> bool {
+        if !group.is_multicast() || !self.igmp.groups.entry(iface).or_default().insert(group) {
+            return false;
+        }
+        if !is_local_multicast_group(group) {
+            let (dst, message) = match self.igmp.version {
+                IgmpVersion::V2 => (group, IgmpMessage::V2Report { group }),
+                IgmpVersion::V3 => (IGMPV3_ROUTERS, v3_report(IgmpRecordKind::ChangeToExclude, [group])),
+            };
+            self.igmp.queue.push((iface, dst, message));
+        }
+        true
+    }
+
+    /// Leave an IPv4 multicast group on the given interface. Return `false`
+    /// if the group was not joined.
+    pub fn leave_ipv4_group(&mut self, iface: usize, group: Ipv4Addr) -> bool {
+        if !self.igmp.groups.get_mut(&iface).is_some_and(|groups| groups.remove(&group)) {
+            return false;
+        }
+        self.igmp.pending_reports.remove(&(iface, group));
+        if !is_local_multicast_group(group) {
+            let (dst, message) = match self.igmp.version {
+                IgmpVersion::V2 => (IGMP_ALL_ROUTERS, IgmpMessage::Leave { group }),
+                IgmpVersion::V3 => (IGMPV3_ROUTERS, v3_report(IgmpRecordKind::ChangeToInclude, [group])),
+            };
+            self.igmp.queue.push((iface, dst, message));
+        }
+        true
+    }
+
+    /// Iterate over the IPv4 multicast groups joined on the given interface.
+    pub fn ipv4_groups(&self, iface: usize) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.igmp.groups.get(&iface).into_iter().flatten().copied()
+    }
+
+    /// Return `true` if the given IPv4 multicast group is joined on the
+    /// given interface.
+    pub fn is_ipv4_group_joined(&self, iface: usize, group: Ipv4Addr) -> bool {
+        self.igmp.groups.get(&iface).is_some_and(|groups| groups.contains(&group))
+    }
+
+    /// Enable or disable the IGMP querier on the given interface, the
+    /// querier periodically sends general queries and tracks the groups
+    /// that have members on the link. If many queriers are on the same
+    /// link, only the one with the lowest address sends queries.
+    pub fn set_igmp_querier(&mut self, iface: usize, enabled: bool) {
+        if enabled {
+            self.igmp.queriers.entry(iface).or_default();
+        } else {
+            self.igmp.queriers.remove(&iface);
+            self.igmp.memberships.retain(|&(member_iface, _), _| member_iface != iface);
+        }
+    }
+
+    #[inline]
+    pub fn is_igmp_querier(&self, iface: usize) -> bool {
+        self.igmp.queriers.contains_key(&iface)
+    }
+
+    /// Iterate over the IPv4 multicast groups that have members on the
+    /// link of the given interface, as learned by its querier.
+    pub fn ipv4_group_members(&self, iface: usize) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.igmp.memberships.keys()
+            .filter(move |&&(member_iface, _)| member_iface == iface)
+            .map(|&(_, group)| group)
+    }
+
+    /// Return `true` if the querier of the given interface knows members
+    /// of the given group.
+    pub fn has_ipv4_group_members(&self, iface: usize, group: Ipv4Addr) -> bool {
+        self.igmp.memberships.contains_key(&(iface, group))
+    }
+
+    /// Process an IGMP message received on an interface.
+    pub(super) fn igmp_recv(&mut self, iface: usize, src: Ipv4Addr, message: &IgmpMessage, time: u64) {
+
+        let local_ip = self.get_iface_conf(iface)
+            .and_then(|conf| conf.primary_ipv4())
+            .map(|ipv4| ipv4.ip);
+
+        match message {
+            IgmpMessage::Query { group, max_resp_time, .. } => {
+
+                if let Some(querier) = self.igmp.queriers.get_mut(&iface) {
+                    if local_ip.is_some_and(|local_ip| src < local_ip) {
+                        querier.other_querier = Some(time + OTHER_QUERIER_PRESENT_INTERVAL);
+                    }
+                }
+
+                let Some(groups) = self.igmp.groups.get(&iface) else { return };
+                let seed = local_ip.map(u32::from).unwrap_or(0) as u64 ^ time;
+                let mut rng = XorShift::new(seed);
+                let max_resp_time = (*max_resp_time).max(1) as u64;
+
+                for &joined in groups {
+                    if is_local_multicast_group(joined) || (!group.is_unspecified() && *group != joined) {
+                        continue;
+                    }
+                    let deadline = time + rng.next_u64() % max_resp_time;
+                    let pending = self.igmp.pending_reports.entry((iface, joined)).or_insert(deadline);
+                    *pending = (*pending).min(deadline);
+                }
+
+            }
+            IgmpMessage::V2Report { group } => {
+                // Another member reported the group, our report is suppressed.
+                if self.igmp.version == IgmpVersion::V2 {
+                    self.igmp.pending_reports.remove(&(iface, *group));
+                }
+                self.igmp_report(iface, *group, true, time);
+            }
+            IgmpMessage::Leave { group } => {
+                self.igmp_report(iface, *group, false, time);
+            }
+            IgmpMessage::V3Report { records } => {
+                for record in records {
+                    self.igmp_report(iface, record.group, record.is_join(), time);
+                }
+            }
+        }
+
+    }
+
+    /// Get the interfaces, other than the given one, with members of the
+    /// given group, where multicast packets are forwarded.
+    pub(super) fn igmp_forward_ifaces(&self, iface: usize, group: Ipv4Addr) -> Vec<usize> {
+        let mut ifaces: Vec<usize> = self.igmp.memberships.keys()
+            .filter(|&&(member_iface, member_group)| member_iface != iface && member_group == group)
+            .map(|&(member_iface, _)| member_iface)
+            .collect();
+        ifaces.sort();
+        ifaces
+    }
+
+    /// Update memberships known by the querier of an interface.
+    fn igmp_report(&mut self, iface: usize, group: Ipv4Addr, join: bool, time: u64) {
+
+        if !self.igmp.queriers.contains_key(&iface) {
+            return;
+        }
+
+        if join {
+            self.igmp.memberships.insert((iface, group), time + IGMP_GROUP_MEMBERSHIP_INTERVAL);
+        } else if let Some(expire) = self.igmp.memberships.get_mut(&(iface, group)) {
+            // Check that no other member remains with a group-specific query.
+            *expire = (*expire).min(time + 2 * IGMP_LAST_MEMBER_QUERY_TIME as u64);
+            self.igmp.queue.push((iface, group, IgmpMessage::Query {
+                group,
+                max_resp_time: IGMP_LAST_MEMBER_QUERY_TIME,
+                sources: Vec::new(),
+            }));
+        }
+
+    }
+
+    /// Update timers, queue queries and reports.
+    pub(super) fn igmp_tick(&mut self, time: u64) {
+
+        let igmp = &mut self.igmp;
+
+        igmp.memberships.retain(|_, expire| *expire > time);
+
+        for (&iface, querier) in &mut igmp.queriers {
+            if querier.other_querier.is_some_and(|other| other > time) {
+                continue;
+            }
+            querier.other_querier = None;
+            if querier.last_query.is_none_or(|last| time >= last + IGMP_QUERY_INTERVAL) {
+                querier.last_query = Some(time);
+                igmp.queue.push((iface, IGMP_ALL_SYSTEMS, IgmpMessage::Query {
+                    group: Ipv4Addr::UNSPECIFIED,
+                    max_resp_time: IGMP_QUERY_RESPONSE_TIME,
+                    sources: Vec::new(),
+                }));
+            }
+        }
+
+        let mut due: Vec<(usize, Ipv4Addr)> = igmp.pending_reports.iter()
+            .filter(|&(_, &deadline)| deadline <= time)
+            .map(|(&key, _)| key)
+            .collect();
+
+        if due.is_empty() {
+            return;
+        }
+
+        due.sort();
+        for key in &due {
+            igmp.pending_reports.remove(key);
+        }
+
+        match igmp.version {
+            IgmpVersion::V2 => {
+                for (iface, group) in due {
+                    igmp.queue.push((iface, group, IgmpMessage::V2Report { group }));
+                }
+            }
+            IgmpVersion::V3 => {
+                // All groups of an interface are reported in a single message.
+                let mut index = 0;
+                while index < due.len() {
+                    let iface = due[index].0;
+                    let count = due[index..].iter().take_while(|(other, _)| *other == iface).count();
+                    let groups = due[index..index + count].iter().map(|&(_, group)| group);
+                    igmp.queue.push((iface, IGMPV3_ROUTERS, v3_report(IgmpRecordKind::ModeIsExclude, groups)));
+                    index += count;
+                }
+            }
+        }
+
+    }
+
+    /// Send queued IGMP messages, messages queued for interfaces without
+    /// IPv4 configuration are discarded.
+    pub(super) fn igmp_flush(&mut self, links: &mut Links) {
+        for (iface, dst, message) in std::mem::take(&mut self.igmp.queue) {
+            let Some(src) = self.iface_primary_ipv4(iface) else { continue };
+            let mut packet = Ipv4Packet::new(src, dst, Ipv4Payload::Igmp(message));
+            packet.ttl = 1;
+            self.output_ipv4_local(&mut *links, iface, Box::new(packet), dst);
+        }
+    }
+
+}
+
+/// Build an IGMPv3 report with a record of the given kind, without
+/// sources, for each group.
+fn v3_report(kind: IgmpRecordKind, groups: impl IntoIterator<Item = Ipv4Addr>) -> IgmpMessage {
+    IgmpMessage::V3Report {
+        records: groups.into_iter().map(|group| IgmpGroupRecord {
+            kind,
+            group,
+            sources: Vec::new(),
+        }).collect(),
+    }
+}
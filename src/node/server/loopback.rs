@@ -0,0 +1,54 @@
+//! Implementation of the loopback interface, that every server node has
+//! and that delivers packets to the node itself without any link.
+
+use std::any::Any;
+
+use crate::net::{Links, RawLinkHandle};
+use crate::proto::{Ipv4Addr, Ipv4Packet};
+
+use super::{ServerIfaceConf, ServerIfaceIpv4, ServerIfaceCtx, IfaceInnerUntyped};
+
+
+/// Index of the loopback interface of server nodes.
+pub const LOOPBACK_IFACE: usize = usize::MAX;
+/// Prefix length of the loopback address.
+pub(super) const LOOPBACK_PREFIX_LEN: u8 = 8;
+
+
+/// Loopback interface, packets sent on it are received by the node on the
+/// next tick.
+#[derive(Debug, Default)]
+pub struct ServerLoopbackIface {
+    #[allow(clippy::vec_box)]
+    queue: Vec<Box<Ipv4Packet>>,
+}
+
+impl IfaceInnerUntyped for ServerLoopbackIface {
+
+    fn handler(&self) -> &dyn Any {
+        self
+    }
+
+    fn handler_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn link(&mut self, _iface: usize, _link: RawLinkHandle) -> bool {
+        false
+    }
+
+    fn tick(&mut self, _links: &mut Links, _conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+        for packet in self.queue.drain(..) {
+            ctx.recv_ipv4(packet);
+        }
+    }
+
+    fn send_ipv4(&mut self, _links: &mut Links, _conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, _link_addr: Ipv4Addr) {
+        self.queue.push(packet);
+    }
+
+    fn is_up(&self, _links: &mut Links) -> bool {
+        true
+    }
+
+}
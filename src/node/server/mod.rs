@@ -1,32 +1,139 @@
 //! Implementation of a complex server supporting an 
 //! IPv4 and IPv6 stack with ARP and NDP support.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::cell::Cell;
+use std::any::Any;
 
 use crate::net::{LinkHandle, Node, RawLinkHandle, Links, Link};
-use crate::proto::{Ipv4Addr, IpAddrExt, IpPrefix, Ipv4Packet};
+use crate::proto::{
+    MacAddr, Ipv4Addr, Ipv6Addr, IpAddrExt, IpPrefix, Ipv4Packet, Ipv4Payload,
+    is_local_multicast_group, IGMP_ALL_SYSTEMS, RIP_PORT, BGP_PORT, VRRP_MULTICAST, GRE_PROTOCOL, IPIP_PROTOCOL, IPV6_ALL_NODES, IPV4_MIN_MTU,
+};
 
 mod eth;
+mod ppp;
+mod bond;
+mod igmp;
+mod frag;
+mod nat;
+mod firewall;
+mod rip;
+mod ospf;
+mod tcp;
+mod bgp;
+mod vrrp;
+mod table;
+mod loopback;
+mod tunnel;
 pub use eth::*;
+pub use ppp::*;
+pub use bond::*;
+pub use frag::*;
+pub use nat::*;
+pub use firewall::*;
+pub use rip::*;
+pub use ospf::*;
+pub use bgp::*;
+pub use vrrp::*;
+pub use table::*;
+pub use loopback::*;
+pub use tunnel::*;
+
+use igmp::IgmpState;
+
+
+/// Default MTU of interfaces, in bytes.
+pub const DEFAULT_MTU: u16 = 1500;
 
 
 /// A complex node that supports whole IP stack.
 /// With this type of node you need to manually register interfaces.
 pub struct ServerNode {
     ifaces: HashMap<usize, Iface>,
-    ipv4_queue: Vec<Box<Ipv4Packet>>,
+    /// Association of member interfaces, such as bond members, to the
+    /// interface they are linked to.
+    iface_members: HashMap<usize, usize>,
+    /// IPv4 packets sent by the node, with the VRF they are routed in.
+    ipv4_queue: Vec<(Option<String>, Box<Ipv4Packet>)>,
     ipv4_routes: IpRoutes<Ipv4Addr>,
+    /// Named IPv4 routing tables, in addition to the main one.
+    ipv4_tables: BTreeMap<String, IpRoutes<Ipv4Addr>>,
+    /// Policy routing rules selecting the table of packets.
+    ipv4_rules: Vec<RoutingRule>,
+    /// IPv4 packets received by interfaces and the interface 
+    /// that received them, to be processed by the node.
+    ipv4_received: Vec<(usize, Box<Ipv4Packet>)>,
+    /// IPv4 packets received and addressed to this node.
+    #[allow(clippy::vec_box)]
+    ipv4_inbox: Vec<Box<Ipv4Packet>>,
+    /// True when this node forwards IPv4 packets that are not 
+    /// addressed to it, acting as a router.
+    ipv4_forwarding: bool,
+    /// Number of received IPv4 packets that were not addressed to this
+    /// node, whether they were forwarded or not.
+    ipv4_transit: u64,
+    /// IGMP state for multicast groups.
+    igmp: IgmpState,
+    /// IPv6 multicast groups joined on each interface.
+    ipv6_groups: HashMap<usize, BTreeSet<Ipv6Addr>>,
+    /// Identifier given to the next IPv4 packet sent.
+    ipv4_identifier: u16,
+    /// Fragments of IPv4 packets being reassembled.
+    ipv4_reassembly: Ipv4Reassembly,
+    /// Path MTUs learned for destinations and their expiration time.
+    pmtu_cache: HashMap<Ipv4Addr, (u16, u64)>,
+    /// Network address translation, if enabled.
+    nat: Option<Nat>,
+    /// Packet filtering firewall, if enabled.
+    firewall: Option<Firewall>,
+    /// RIP routing, if enabled.
+    rip: Option<Rip>,
+    /// OSPF routing, if enabled.
+    ospf: Option<Ospf>,
+    /// BGP speaker, if enabled.
+    bgp: Option<Bgp>,
+    /// VRRP virtual routers, if enabled.
+    vrrp: Option<Vrrp>,
+    /// Time of the last tick.
+    time: u64,
 }
 
 impl ServerNode {
 
-    /// Construct a new server node.
+    /// Construct a new server node, with only its loopback interface.
     pub fn new() -> Self {
-        Self {
-            ifaces: HashMap::new(),
+        let loopback = Iface {
+            inner: Box::new(ServerLoopbackIface::default()),
+            conf: ServerIfaceConf::with_ipv4(Ipv4Addr::LOCALHOST, LOOPBACK_PREFIX_LEN).with_mtu(u16::MAX),
+        };
+        let mut node = Self {
+            ifaces: HashMap::from([(LOOPBACK_IFACE, loopback)]),
+            iface_members: HashMap::new(),
             ipv4_queue: Vec::new(),
             ipv4_routes: IpRoutes::new(),
-        }
+            ipv4_tables: BTreeMap::new(),
+            ipv4_rules: Vec::new(),
+            ipv4_received: Vec::new(),
+            ipv4_inbox: Vec::new(),
+            ipv4_forwarding: false,
+            ipv4_transit: 0,
+            igmp: IgmpState::default(),
+            ipv6_groups: HashMap::new(),
+            ipv4_identifier: 0,
+            ipv4_reassembly: Ipv4Reassembly::default(),
+            pmtu_cache: HashMap::new(),
+            nat: None,
+            firewall: None,
+            rip: None,
+            ospf: None,
+            bgp: None,
+            vrrp: None,
+            time: 0,
+        };
+        node.update_connected_routes();
+        node
     }
 
     #[inline]
@@ -49,25 +156,37 @@ impl ServerNode {
         Self::with_iface_conf(iface, handler, ServerIfaceConf::default())
     }
 
-    /// Define a new interface with the given common configuration.
+    /// Define a new interface with the given common configuration, the
+    /// members of the interface, if any, are linked to it.
     pub fn add_iface_conf<T, H>(&mut self, iface: usize, handler: H, conf: ServerIfaceConf)
     where
         T: 'static,
         H: ServerIface<T> + 'static,
     {
 
-        if self.ifaces.contains_key(&iface) {
+        if self.ifaces.contains_key(&iface) || self.iface_members.contains_key(&iface) {
             panic!("this interface is already defined");
         }
 
+        let members = handler.members();
+        for &member in &members {
+            if self.iface_members.contains_key(&member) || (member != iface && self.ifaces.contains_key(&member)) {
+                panic!("this interface is already defined");
+            }
+            self.iface_members.insert(member, iface);
+        }
+
         self.ifaces.insert(iface, Iface { 
             inner: Box::new(IfaceInner {
-                link: None,
+                links: Vec::new(),
+                has_members: !members.is_empty(),
                 handler
             }), 
             conf
         });
 
+        self.update_connected_routes();
+
     }
 
     /// Define a new interface.
@@ -89,6 +208,18 @@ impl ServerNode {
         &mut self.ipv4_routes
     }
 
+    /// Get a reference to the given interface's handler, if it exists
+    /// and is of the given type.
+    pub fn get_iface<H: 'static>(&self, iface: usize) -> Option<&H> {
+        self.ifaces.get(&iface).and_then(|iface| iface.inner.handler().downcast_ref())
+    }
+
+    /// Get a mutable reference to the given interface's handler, if it 
+    /// exists and is of the given type.
+    pub fn get_iface_mut<H: 'static>(&mut self, iface: usize) -> Option<&mut H> {
+        self.ifaces.get_mut(&iface).and_then(|iface| iface.inner.handler_mut().downcast_mut())
+    }
+
     /// Get a refernce to the given interface's configuration.
     pub fn get_iface_conf(&self, iface: usize) -> Option<&ServerIfaceConf> {
         self.ifaces.get(&iface).map(|iface| &iface.conf)
@@ -99,20 +230,190 @@ impl ServerNode {
         self.ifaces.get_mut(&iface).map(|iface| &mut iface.conf)
     }
 
+    /// Enable or disable forwarding of IPv4 packets that are not 
+    /// addressed to this node, disabled by default.
+    #[inline]
+    pub fn set_ipv4_forwarding(&mut self, enabled: bool) {
+        self.ipv4_forwarding = enabled;
+    }
+
+    #[inline]
+    pub fn is_ipv4_forwarding(&self) -> bool {
+        self.ipv4_forwarding
+    }
+
+    /// Number of received IPv4 packets that were not addressed to this
+    /// node, forwarded or not.
+    #[inline]
+    pub fn ipv4_transit_count(&self) -> u64 {
+        self.ipv4_transit
+    }
+
     /// Schedule a packet to be forwarded and sent through an interface.
-    /// This function doesn't touch the source address.
+    /// An unspecified source address is replaced by the address selected
+    /// for the destination, and the packet is given a new identifier used
+    /// for fragmentation. The packet is routed in the VRF of the interface
+    /// with its source address, see `send_ipv4_in` to give it explicitly.
     #[inline]
     pub fn send_ipv4(&mut self, packet: Box<Ipv4Packet>) {
-        self.ipv4_queue.push(packet);
+        self.queue_ipv4(None, packet);
+    }
+
+    /// Schedule a packet to be sent like `send_ipv4`, routed in the given
+    /// VRF, the main one being `MAIN_ROUTING_TABLE`. Packets are routed in
+    /// the main table if the VRF doesn't exist.
+    #[inline]
+    pub fn send_ipv4_in(&mut self, vrf: &str, packet: Box<Ipv4Packet>) {
+        self.queue_ipv4(Some(vrf.to_string()), packet);
+    }
+
+    fn queue_ipv4(&mut self, vrf: Option<String>, mut packet: Box<Ipv4Packet>) {
+        if packet.src.is_unspecified() {
+            if let Some(src) = self.source_ipv4_in(vrf.as_deref(), packet.dst) {
+                packet.src = src;
+            }
+        }
+        packet.fragment_identifier = self.ipv4_identifier;
+        self.ipv4_identifier = self.ipv4_identifier.wrapping_add(1);
+        self.ipv4_queue.push((vrf, packet));
+    }
+
+    /// Join an IPv6 multicast group on the given interface, frames of this
+    /// group are then accepted by the interface. The all-nodes group is
+    /// always joined. Return `false` if the address is not multicast or
+    /// the group is already joined.
+    pub fn join_ipv6_group(&mut self, iface: usize, group: Ipv6Addr) -> bool {
+        group.is_multicast() && self.ipv6_groups.entry(iface).or_default().insert(group)
+    }
+
+    /// Leave an IPv6 multicast group on the given interface. Return `false`
+    /// if the group was not joined.
+    pub fn leave_ipv6_group(&mut self, iface: usize, group: Ipv6Addr) -> bool {
+        self.ipv6_groups.get_mut(&iface).is_some_and(|groups| groups.remove(&group))
+    }
+
+    /// Iterate over the IPv6 multicast groups joined on the given interface.
+    pub fn ipv6_groups(&self, iface: usize) -> impl Iterator<Item = Ipv6Addr> + '_ {
+        self.ipv6_groups.get(&iface).into_iter().flatten().copied()
+    }
+
+    /// Take the oldest received IPv4 packet addressed to this node.
+    pub fn recv_ipv4(&mut self) -> Option<Box<Ipv4Packet>> {
+        if self.ipv4_inbox.is_empty() {
+            None
+        } else {
+            Some(self.ipv4_inbox.remove(0))
+        }
+    }
+
+    /// Derive connected routes from the IPv4 configuration of interfaces,
+    /// it is called on each tick to follow configuration changes. Routes
+    /// of interfaces in a VRF go to its table, which is created if needed.
+    /// The peer of point-to-point interfaces gets a host route.
+    fn update_connected_routes(&mut self) {
+        let mut routes = self.ifaces.iter()
+            .flat_map(|(&index, iface)| {
+                let vrf = iface.conf.vrf.as_deref();
+                iface.conf.ipv4.iter().map(ServerIfaceIpv4::prefix)
+                    .chain(iface.conf.ipv4_peer.map(|peer| peer.take_prefix(32)))
+                    .map(move |prefix| (vrf, prefix, index))
+            })
+            .collect::<Vec<_>>();
+        routes.sort_by_key(|&(_, prefix, index)| (index, prefix));
+        routes.dedup();
+        for &(vrf, _, _) in &routes {
+            if let Some(vrf) = vrf.filter(|&vrf| vrf != MAIN_ROUTING_TABLE) {
+                if !self.ipv4_tables.contains_key(vrf) {
+                    self.ipv4_tables.insert(vrf.to_string(), IpRoutes::new());
+                }
+            }
+        }
+        let table_routes = |table: &str| routes.iter()
+            .filter(|&&(vrf, _, _)| vrf.unwrap_or(MAIN_ROUTING_TABLE) == table)
+            .map(|&(_, prefix, index)| (prefix, index, IpRouteLink::Direct))
+            .collect::<Vec<_>>();
+        self.ipv4_routes.replace_routes_from(IpRouteSource::Connected, table_routes(MAIN_ROUTING_TABLE));
+        for (name, table) in &mut self.ipv4_tables {
+            table.replace_routes_from(IpRouteSource::Connected, table_routes(name));
+        }
+    }
+
+    /// Return `true` if the given IPv4 address is one that this node 
+    /// accepts packets for: an address of the node, a broadcast address
+    /// or a multicast group joined on one of its interfaces.
+    fn is_local_ipv4(&self, ip: Ipv4Addr) -> bool {
+        self.is_local_ipv4_in(None, ip)
+    }
+
+    /// Return `true` if a packet received on the given interface with the
+    /// given destination is addressed to this node, only addresses of the
+    /// interfaces in the same VRF are considered. Packets from the loopback
+    /// interface were sent by the node and can be for any of its addresses.
+    fn is_local_ipv4_on(&self, in_iface: usize, ip: Ipv4Addr) -> bool {
+        if in_iface == LOOPBACK_IFACE {
+            return self.is_local_ipv4(ip);
+        }
+        if ip.is_multicast() {
+            return self.is_ipv4_group_local(in_iface, ip);
+        }
+        let vrf = self.ifaces.get(&in_iface)
+            .and_then(|iface| iface.conf.vrf.as_deref())
+            .unwrap_or(MAIN_ROUTING_TABLE);
+        self.is_local_ipv4_in(Some(vrf), ip)
+    }
+
+    /// Same as `is_local_ipv4`, only considering the interfaces of the
+    /// given VRF, or all interfaces with `None`.
+    fn is_local_ipv4_in(&self, vrf: Option<&str>, ip: Ipv4Addr) -> bool {
+        if ip.is_multicast() {
+            return self.ifaces.iter().any(|(&index, iface)| iface.is_in_vrf(vrf) && self.is_ipv4_group_local(index, ip));
+        }
+        if ip.is_broadcast() || self.is_own_ipv4_in(vrf, ip) {
+            return true;
+        }
+        self.ifaces.values()
+            .filter(|iface| iface.is_in_vrf(vrf))
+            .flat_map(|iface| &iface.conf.ipv4)
+            .any(|ipv4| ipv4.broadcast() == ip)
+    }
+
+    /// Return `true` if packets to the given multicast group are received
+    /// by the node on the interface, the all systems group is implicitly
+    /// joined on all interfaces.
+    fn is_ipv4_group_local(&self, iface: usize, group: Ipv4Addr) -> bool {
+        group == IGMP_ALL_SYSTEMS || self.is_ipv4_group_joined(iface, group)
+    }
+
+    /// Return `true` if the given IPv4 address is an address of this node:
+    /// an address of its interfaces in the given VRF, or all interfaces with
+    /// `None`, a loopback address or a virtual address of which it is the
+    /// master.
+    fn is_own_ipv4_in(&self, vrf: Option<&str>, ip: Ipv4Addr) -> bool {
+        if ip.is_loopback() {
+            return true;
+        }
+        let in_vrf = |index| self.ifaces.get(&index).is_some_and(|iface| iface.is_in_vrf(vrf));
+        if self.vrrp.as_ref().is_some_and(|vrrp| vrrp.is_master_address(ip, in_vrf)) {
+            return true;
+        }
+        self.ifaces.values().any(|iface| iface.is_in_vrf(vrf) && iface.conf.has_ipv4(ip))
     }
 
 }
 
+impl Default for ServerNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Node for ServerNode {
 
     fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
-        if let Some(iface) = self.ifaces.get_mut(&iface) {
-            iface.inner.link(link)
+        // Member interfaces are linked to the interface they belong to.
+        let index = self.iface_members.get(&iface).copied().unwrap_or(iface);
+        if let Some(inner_iface) = self.ifaces.get_mut(&index) {
+            inner_iface.inner.link(iface, link)
         } else {
             false
         }
@@ -120,22 +421,171 @@ impl Node for ServerNode {
 
     fn tick(&mut self, links: &mut Links) {
 
-        for iface in self.ifaces.values_mut() {
-            iface.inner.tick(&mut *links, &mut iface.conf);
+        let time = links.time();
+        self.time = time;
+        self.update_connected_routes();
+        for (&index, iface) in &self.ifaces {
+            let up = iface.inner.is_up(&mut *links);
+            self.ipv4_routes.set_iface_up(index, up);
+            for table in self.ipv4_tables.values_mut() {
+                table.set_iface_up(index, up);
+            }
         }
 
-        for packet in self.ipv4_queue.drain(..) {
-            if let Some((iface_index, link_addr)) = self.ipv4_routes.fetch(packet.dst) {
-                if let Some(iface) = self.ifaces.get_mut(&iface_index) {
-                    if let Some(ipv4_conf) = &mut iface.conf.ipv4 {
-                        iface.inner.send_ipv4(&mut *links, ipv4_conf, packet, link_addr);
-                    } else {
-                        // Packets that are sent to interfaces without IPv4 configuration are
-                        // currently discarded silently.
+        let ipv4_virtual = self.vrrp.as_ref().map(Vrrp::virtual_addrs).unwrap_or_default();
+        let mut ipv4_next_hops = Vec::new();
+        let mut ipv4_sent = Vec::new();
+        for (&index, iface) in &mut self.ifaces {
+            let mut ctx = ServerIfaceCtx {
+                iface: index,
+                ipv4_routes: iface.conf.vrf.as_deref()
+                    .and_then(|vrf| self.ipv4_tables.get(vrf))
+                    .unwrap_or(&self.ipv4_routes),
+                ipv4_received: &mut self.ipv4_received,
+                ipv4_next_hops: &mut ipv4_next_hops,
+                ipv4_sent: &mut ipv4_sent,
+                ipv4_groups: self.igmp.groups(index),
+                ipv6_groups: self.ipv6_groups.get(&index),
+                ipv4_multicast_router: self.ipv4_forwarding && self.igmp.is_querier(index),
+                ipv4_virtual: ipv4_virtual.get(&index).map(Vec::as_slice).unwrap_or_default(),
+            };
+            iface.inner.tick(&mut *links, &mut iface.conf, &mut ctx);
+        }
+        for (iface, ip, alive) in ipv4_next_hops {
+            self.ipv4_routes.set_next_hop_alive(iface, ip, alive);
+            for routes in self.ipv4_tables.values_mut() {
+                routes.set_next_hop_alive(iface, ip, alive);
+            }
+        }
+        for packet in ipv4_sent {
+            self.send_ipv4(packet);
+        }
+
+        // Forwarded packets with their input and output interfaces.
+        let mut forward = Vec::new();
+
+        let mut received = std::mem::take(&mut self.ipv4_received);
+        for (iface, mut packet) in received.drain(..) {
+            // Loopback addresses never come from a link.
+            if packet.dst.is_loopback() && iface != LOOPBACK_IFACE {
+                continue;
+            }
+            if packet.is_fragmented() && self.is_local_ipv4_on(iface, packet.dst) {
+                match self.reassemble_ipv4(packet, time) {
+                    Some(whole_packet) => packet = whole_packet,
+                    None => continue,
+                }
+            }
+            packet = self.nat_input(iface, packet);
+            if packet.dst.is_multicast() && self.ipv4_forwarding && !is_local_multicast_group(packet.dst) && packet.ttl > 1 &&
+                !matches!(packet.payload, Ipv4Payload::Igmp(_)) {
+                // Multicast packets are forwarded to interfaces with members,
+                // through the forward chain, whether they are also delivered
+                // to the node or not.
+                for out_iface in self.igmp_forward_ifaces(iface, packet.dst) {
+                    let mut packet = packet.clone();
+                    packet.ttl -= 1;
+                    let dst = packet.dst;
+                    forward.push((iface, out_iface, packet, dst));
+                }
+            }
+            let local = self.is_local_ipv4_on(iface, packet.dst);
+            if local {
+                match self.firewall_filter(FirewallChain::Input, Some(iface), None, packet) {
+                    Some(accepted) => packet = accepted,
+                    None => continue,
+                }
+            }
+            if let Ipv4Payload::Gre(_) | Ipv4Payload::Ipip(_) = &packet.payload {
+                if local && self.tunnel_recv(&packet) {
+                    continue;
+                }
+            }
+            if let Ipv4Payload::Igmp(message) = &packet.payload {
+                self.igmp_recv(iface, packet.src, message, time);
+                continue;
+            }
+            if let Ipv4Payload::Ospf(ospf_packet) = &packet.payload {
+                if self.ospf.is_some() && local {
+                    self.ospf_recv(iface, packet.src, ospf_packet);
+                    continue;
+                }
+            }
+            if let Ipv4Payload::Vrrp(vrrp_packet) = &packet.payload {
+                if self.vrrp.is_some() && packet.dst == VRRP_MULTICAST {
+                    self.vrrp_recv(iface, packet.src, packet.ttl, vrrp_packet);
+                    continue;
+                }
+            }
+            if let Ipv4Payload::Udp(datagram) = &packet.payload {
+                if datagram.dst_port == RIP_PORT && self.rip.is_some() && local {
+                    self.rip_recv(iface, &packet);
+                    continue;
+                }
+            }
+            if let Ipv4Payload::Tcp(segment) = &packet.payload {
+                if (segment.dst_port == BGP_PORT || segment.src_port == BGP_PORT) && local &&
+                    self.bgp_recv(packet.src, packet.dst, segment) {
+                    continue;
+                }
+            }
+            if let Ipv4Payload::Icmp(message) = &packet.payload {
+                if local {
+                    self.recv_icmpv4(Some(iface), packet.src, packet.dst, message);
+                }
+            }
+            if local {
+                self.ipv4_inbox.push(packet);
+            } else if !packet.dst.is_multicast() {
+                self.ipv4_transit += 1;
+                // Packets with expired TTL are discarded.
+                if self.ipv4_forwarding && packet.ttl > 1 {
+                    if let Some((out_iface, link_addr)) = self.ipv4_table_for(Some(iface), None, &packet).fetch_flow(&IpFlow::from_ipv4(&packet)) {
+                        packet.ttl -= 1;
+                        forward.push((iface, out_iface, packet, link_addr));
                     }
                 }
             }
         }
+        // Give back the allocation.
+        self.ipv4_received = received;
+
+        for (iface, out_iface, packet, link_addr) in forward {
+            if let Some(packet) = self.firewall_filter(FirewallChain::Forward, Some(iface), Some(out_iface), packet) {
+                self.output_ipv4(&mut *links, out_iface, packet, link_addr);
+            }
+        }
+
+        self.frag_tick(time);
+        self.igmp_tick(time);
+        self.rip_tick(time);
+        self.ospf_tick(time);
+        self.bgp_tick(time);
+        self.vrrp_tick(time);
+        if let Some(nat) = &mut self.nat {
+            nat.tick(time);
+        }
+        if let Some(firewall) = &mut self.firewall {
+            firewall.tick(time);
+        }
+        self.igmp_flush(links);
+        self.rip_flush(links);
+        self.ospf_flush(links);
+        self.vrrp_flush(links);
+
+        // ICMP errors raised while sending are queued for the next tick.
+        // Packets to addresses of the node are sent on the loopback.
+        for (vrf, packet) in std::mem::take(&mut self.ipv4_queue) {
+            let route = match self.is_own_ipv4_in(vrf.as_deref(), packet.dst) {
+                true => Some((LOOPBACK_IFACE, packet.dst)),
+                false => self.ipv4_table_for(None, vrf.as_deref(), &packet).fetch_flow(&IpFlow::from_ipv4(&packet)),
+            };
+            if let Some((iface_index, link_addr)) = route {
+                if let Some(packet) = self.firewall_filter(FirewallChain::Output, None, Some(iface_index), packet) {
+                    self.output_ipv4(&mut *links, iface_index, packet, link_addr);
+                }
+            }
+        }
 
     }
 
@@ -147,7 +597,7 @@ pub trait ServerIface<T> {
 
     /// Called each tick when this interface is linked. This is commonly
     /// used for polling incomming data-link frames.
-    fn tick(&mut self, link: Link<T>, conf: &mut ServerIfaceConf);
+    fn tick(&mut self, link: Link<T>, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx);
 
     /// Send an IPv4 packet to the link address.
     /// 
@@ -156,13 +606,166 @@ pub trait ServerIface<T> {
     /// same as the packet's destination.
     fn send_ipv4(&mut self, link: Link<T>, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr);
 
+    /// Node interfaces whose links are given to this interface, like the
+    /// members of a bond. Interfaces with members are called through
+    /// `tick_members` and `send_ipv4_members` instead of `tick` and
+    /// `send_ipv4`. This is called once when the interface is added to
+    /// the node, and by default there is no member.
+    fn members(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// Called each tick, with the links of the members, for interfaces
+    /// with members.
+    fn tick_members(&mut self, links: ServerIfaceLinks<T>, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+        let _ = (links, conf, ctx);
+    }
+
+    /// Send an IPv4 packet to the link address on one of the links of the
+    /// members, for interfaces with members.
+    fn send_ipv4_members(&mut self, links: ServerIfaceLinks<T>, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
+        let _ = (links, conf, packet, link_addr);
+    }
+
+}
+
+/// The links of the member interfaces of a server interface.
+pub struct ServerIfaceLinks<'a, 'b, T> {
+    links: &'a mut Links<'b>,
+    handles: &'a [(usize, LinkHandle<T>)],
+}
+
+impl<T: 'static> ServerIfaceLinks<'_, '_, T> {
+
+    /// Get the current time of the simulated clock, in ticks.
+    #[inline]
+    pub fn time(&self) -> u64 {
+        self.links.time()
+    }
+
+    /// Iterate over the member interfaces that are linked.
+    pub fn ifaces(&self) -> impl Iterator<Item = usize> + '_ {
+        self.handles.iter().map(|&(iface, _)| iface)
+    }
+
+    /// Get the link of a member interface, if linked.
+    pub fn get(&mut self, iface: usize) -> Option<Link<'_, T>> {
+        let (_, handle) = self.handles.iter().find(|&&(member, _)| member == iface)?;
+        Some(self.links.get(handle))
+    }
+
+}
+
+/// Node-level context given to interfaces when ticked.
+pub struct ServerIfaceCtx<'a> {
+    /// Index of the ticked interface.
+    iface: usize,
+    /// IPv4 routes of the node.
+    ipv4_routes: &'a IpRoutes<Ipv4Addr>,
+    /// Queue of IPv4 packets received by all interfaces.
+    ipv4_received: &'a mut Vec<(usize, Box<Ipv4Packet>)>,
+    /// Changes of the liveness of IPv4 next hops detected by interfaces.
+    ipv4_next_hops: &'a mut Vec<(usize, Ipv4Addr, bool)>,
+    /// Queue of IPv4 packets given by interfaces to be sent by the node.
+    #[allow(clippy::vec_box)]
+    ipv4_sent: &'a mut Vec<Box<Ipv4Packet>>,
+    /// IPv4 multicast groups joined on the interface.
+    ipv4_groups: Option<&'a BTreeSet<Ipv4Addr>>,
+    /// IPv6 multicast groups joined on the interface.
+    ipv6_groups: Option<&'a BTreeSet<Ipv6Addr>>,
+    /// True if the interface receives all IPv4 multicast to forward it.
+    ipv4_multicast_router: bool,
+    /// Virtual IPv4 addresses and their MAC address that the node answers
+    /// for on the interface, as master of virtual routers.
+    ipv4_virtual: &'a [(Ipv4Addr, MacAddr)],
+}
+
+impl<'a> ServerIfaceCtx<'a> {
+
+    /// Index of the ticked interface in the node.
+    #[inline]
+    pub fn iface(&self) -> usize {
+        self.iface
+    }
+
+    /// IPv4 routes of the node.
+    #[inline]
+    pub fn ipv4_routes(&self) -> &IpRoutes<Ipv4Addr> {
+        self.ipv4_routes
+    }
+
+    /// Return `true` if frames sent to the given multicast MAC address are
+    /// expected by the node on the interface, because it joined a group
+    /// mapped to this address or it routes IPv4 multicast.
+    pub fn is_multicast_accepted(&self, mac: MacAddr) -> bool {
+        if mac == MacAddr::from_multicast_ipv4(IGMP_ALL_SYSTEMS) || mac == MacAddr::from_multicast_ipv6(IPV6_ALL_NODES) {
+            return true;
+        }
+        if self.ipv4_multicast_router && mac.0[..3] == [0x01, 0x00, 0x5E] {
+            return true;
+        }
+        self.ipv4_groups.into_iter().flatten().any(|&group| MacAddr::from_multicast_ipv4(group) == mac) ||
+        self.ipv6_groups.into_iter().flatten().any(|&group| MacAddr::from_multicast_ipv6(group) == mac)
+    }
+
+    /// Virtual IPv4 addresses and their MAC address that the node answers
+    /// for on the interface.
+    #[inline]
+    pub fn ipv4_virtual(&self) -> &[(Ipv4Addr, MacAddr)] {
+        self.ipv4_virtual
+    }
+
+    /// Get the virtual MAC address answering for the given IPv4 address on
+    /// the interface, if any.
+    pub fn ipv4_virtual_mac(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.ipv4_virtual.iter().find(|&&(virtual_ip, _)| virtual_ip == ip).map(|&(_, mac)| mac)
+    }
+
+    /// Return `true` if the given MAC address is a virtual MAC address
+    /// answered by the node on the interface.
+    pub fn is_virtual_mac(&self, mac: MacAddr) -> bool {
+        self.ipv4_virtual.iter().any(|&(_, virtual_mac)| virtual_mac == mac)
+    }
+
+    /// Give a received IPv4 packet to the node, it will be delivered
+    /// locally or forwarded.
+    #[inline]
+    pub fn recv_ipv4(&mut self, mut packet: Box<Ipv4Packet>) {
+        packet.mark = 0;
+        self.ipv4_received.push((self.iface, packet));
+    }
+
+    /// Report that a neighbor on the interface answers or stopped answering
+    /// to address resolution, routes through it are selected accordingly.
+    #[inline]
+    pub fn set_next_hop_alive(&mut self, ip: Ipv4Addr, alive: bool) {
+        self.ipv4_next_hops.push((self.iface, ip, alive));
+    }
+
+    /// Give an IPv4 packet to the node, it will be routed and sent as if
+    /// it was sent by the node, this is used by virtual interfaces.
+    #[inline]
+    pub fn send_ipv4(&mut self, packet: Box<Ipv4Packet>) {
+        self.ipv4_sent.push(packet);
+    }
+
 }
 
 /// Generic protocols config for an interface. It contains configurations
 /// for protocols such as IPv4 and IPv6.
-#[derive(Default)]
 pub struct ServerIfaceConf {
-    pub ipv4: Option<ServerIfaceIpv4>,
+    /// IPv4 addresses of the interface, the first one is the primary
+    /// address and the others are secondary addresses.
+    pub ipv4: Vec<ServerIfaceIpv4>,
+    /// Maximum size of IPv4 packets sent on the interface, in bytes,
+    /// bigger packets are fragmented.
+    pub mtu: u16,
+    /// Name of the VRF of the interface, packets it receives are routed
+    /// with the table of the same name instead of the main table.
+    pub vrf: Option<String>,
+    /// Address of the other end of a point-to-point link, a connected
+    /// route to it is added whatever the addresses of the interface.
+    pub ipv4_peer: Option<Ipv4Addr>,
 }
 
 impl ServerIfaceConf {
@@ -175,10 +778,96 @@ impl ServerIfaceConf {
     #[inline]
     pub fn with_ipv4(ip: Ipv4Addr, prefix_len: u8) -> Self {
         Self {
-            ipv4: Some(ServerIfaceIpv4 { ip, prefix_len }),
+            ipv4: vec![ServerIfaceIpv4 { ip, prefix_len }],
+            ..Self::default()
         }
     }
 
+    /// Add a secondary IPv4 address, or the primary one if the interface
+    /// has no address yet.
+    #[inline]
+    pub fn with_secondary_ipv4(mut self, ip: Ipv4Addr, prefix_len: u8) -> Self {
+        self.add_ipv4(ip, prefix_len);
+        self
+    }
+
+    /// Set the MTU of the interface.
+    #[inline]
+    pub fn with_mtu(mut self, mtu: u16) -> Self {
+        assert!(mtu >= IPV4_MIN_MTU, "mtu is too small");
+        self.mtu = mtu;
+        self
+    }
+
+    /// Put the interface in a VRF.
+    #[inline]
+    pub fn with_vrf(mut self, vrf: impl Into<String>) -> Self {
+        self.vrf = Some(vrf.into());
+        self
+    }
+
+    /// Set the address of the other end of a point-to-point link.
+    #[inline]
+    pub fn with_ipv4_peer(mut self, peer: Ipv4Addr) -> Self {
+        self.ipv4_peer = Some(peer);
+        self
+    }
+
+    /// Add an IPv4 address to the interface, after the existing ones.
+    pub fn add_ipv4(&mut self, ip: Ipv4Addr, prefix_len: u8) {
+        assert!(prefix_len <= 32, "invalid prefix length");
+        self.ipv4.retain(|ipv4| ipv4.ip != ip);
+        self.ipv4.push(ServerIfaceIpv4 { ip, prefix_len });
+    }
+
+    /// Remove an IPv4 address from the interface, the first remaining
+    /// address becomes the primary one. Return `true` if it was present.
+    pub fn remove_ipv4(&mut self, ip: Ipv4Addr) -> bool {
+        let len = self.ipv4.len();
+        self.ipv4.retain(|ipv4| ipv4.ip != ip);
+        self.ipv4.len() != len
+    }
+
+    /// Get the primary IPv4 address of the interface.
+    #[inline]
+    pub fn primary_ipv4(&self) -> Option<&ServerIfaceIpv4> {
+        self.ipv4.first()
+    }
+
+    /// Return `true` if the given address is one of the interface.
+    pub fn has_ipv4(&self, ip: Ipv4Addr) -> bool {
+        self.ipv4.iter().any(|ipv4| ipv4.ip == ip)
+    }
+
+    /// Get the address of the interface to use toward the given neighbor,
+    /// the first one on its network or the primary address.
+    pub fn ipv4_for(&self, neighbor: Ipv4Addr) -> Option<&ServerIfaceIpv4> {
+        self.ipv4.get(self.ipv4_index_for(neighbor))
+    }
+
+    /// Get the address of the interface to use toward the given neighbor.
+    pub fn ipv4_for_mut(&mut self, neighbor: Ipv4Addr) -> Option<&mut ServerIfaceIpv4> {
+        let index = self.ipv4_index_for(neighbor);
+        self.ipv4.get_mut(index)
+    }
+
+    fn ipv4_index_for(&self, neighbor: Ipv4Addr) -> usize {
+        self.ipv4.iter()
+            .position(|ipv4| ipv4.prefix().matches(neighbor))
+            .unwrap_or(0)
+    }
+
+}
+
+impl Default for ServerIfaceConf {
+    fn default() -> Self {
+        Self {
+            ipv4: Vec::new(),
+            mtu: DEFAULT_MTU,
+            vrf: None,
+            ipv4_peer: None,
+        }
+    }
 }
 
 /// IPv4 configuration for an interface.
@@ -189,6 +878,22 @@ pub struct ServerIfaceIpv4 {
     pub prefix_len: u8,
 }
 
+impl ServerIfaceIpv4 {
+
+    /// Get the network prefix of this configuration.
+    #[inline]
+    pub fn prefix(&self) -> IpPrefix<Ipv4Addr> {
+        self.ip.take_prefix(self.prefix_len)
+    }
+
+    /// Get the directed broadcast address of the subnet.
+    pub fn broadcast(&self) -> Ipv4Addr {
+        let host_mask = u32::MAX.checked_shr(self.prefix_len as u32).unwrap_or(0);
+        (u32::from(self.ip) | host_mask).into()
+    }
+
+}
+
 // INTERNALS //
 
 /// Internal structure to store an interface's state.
@@ -199,56 +904,107 @@ struct Iface {
     conf: ServerIfaceConf,
 }
 
+impl Iface {
+
+    /// Return `true` if the interface is in the given VRF, interfaces in no
+    /// VRF are in the main one. Any VRF matches with `None`.
+    fn is_in_vrf(&self, vrf: Option<&str>) -> bool {
+        vrf.is_none_or(|vrf| self.conf.vrf.as_deref().unwrap_or(MAIN_ROUTING_TABLE) == vrf)
+    }
+
+}
+
 /// Internal structure for storage interface link.
 struct IfaceInner<T, H: ServerIface<T>> {
-    /// Link handle if linked, None if not yet linked.
-    link: Option<LinkHandle<T>>,
+    /// Link handles of the interface, or of its members if it has any.
+    links: Vec<(usize, LinkHandle<T>)>,
+    /// True if the interface is called with the links of its members.
+    has_members: bool,
     /// Inner implementation.
     handler: H,
 }
 
 /// Internal type to allow dynamic dispatching of calls to 
-/// `IfaceLink`. It is only implemented for `IfaceLink`.
+/// `IfaceLink`. It is implemented for `IfaceLink` and virtual interfaces.
 trait IfaceInnerUntyped {
-    fn link(&mut self, link: RawLinkHandle) -> bool;
-    fn tick(&mut self, links: &mut Links, conf: &mut ServerIfaceConf);
+    fn handler(&self) -> &dyn Any;
+    fn handler_mut(&mut self) -> &mut dyn Any;
+    /// Link the given node interface, that is the interface itself or
+    /// one of its members.
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool;
+    fn tick(&mut self, links: &mut Links, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx);
     fn send_ipv4(&mut self, links: &mut Links, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr);
+    /// Return `true` if the interface is linked and its link is up, or
+    /// the link of any of its members.
+    fn is_up(&self, links: &mut Links) -> bool;
 }
 
 impl<T, H> IfaceInnerUntyped for IfaceInner<T, H>
 where
     T: 'static,
-    H: ServerIface<T>,
+    H: ServerIface<T> + 'static,
 {
 
-    fn link(&mut self, link: RawLinkHandle) -> bool {
+    fn handler(&self) -> &dyn Any {
+        &self.handler
+    }
+
+    fn handler_mut(&mut self) -> &mut dyn Any {
+        &mut self.handler
+    }
+
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
         if let Some(link) = link.cast::<T>() {
-            self.link = Some(link);
+            self.links.retain(|&(member, _)| member != iface);
+            self.links.push((iface, link));
             true
         } else {
             false
         }
     }
 
-    fn tick(&mut self, links: &mut Links, conf: &mut ServerIfaceConf) {
-        if let Some(link) = &self.link {
-            self.handler.tick(links.get(link), conf);
+    fn tick(&mut self, links: &mut Links, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+        if self.has_members {
+            self.handler.tick_members(ServerIfaceLinks { links, handles: &self.links }, conf, ctx);
+        } else if let Some((_, link)) = self.links.first() {
+            self.handler.tick(links.get(link), conf, ctx);
         }
     }
 
     fn send_ipv4(&mut self, links: &mut Links, conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, link_addr: Ipv4Addr) {
-        if let Some(link) = &self.link {
+        if self.has_members {
+            self.handler.send_ipv4_members(ServerIfaceLinks { links, handles: &self.links }, conf, packet, link_addr);
+        } else if let Some((_, link)) = self.links.first() {
             self.handler.send_ipv4(links.get(link), conf, packet, link_addr);
         }
     }
 
+    fn is_up(&self, links: &mut Links) -> bool {
+        self.links.iter().any(|(_, link)| links.get(link).is_up())
+    }
+
 }
 
 
 
+/// Routing information base, holding candidate routes from all sources.
+/// The route with the lowest administrative distance is selected for each
+/// prefix, selected routes make the forwarding table where the longest
+/// matching prefix is used. Several routes of the selected source for a
+/// prefix are equal-cost paths, packets are spread over them according to
+/// their weight. Routes through dead next hops or interfaces whose link
+/// is down are not selected.
 pub struct IpRoutes<T: IpAddrExt> {
     routes: Vec<IpRoute<T>>,
-    default: Option<IpRoute<T>>,
+    /// Administrative distances that differ from the default of their source.
+    distances: HashMap<IpRouteSource, u8>,
+    /// Interfaces whose link is down.
+    down_ifaces: HashSet<usize>,
+    /// Next hops known to be dead, with their interface.
+    dead_next_hops: HashSet<(usize, T)>,
+    multipath_mode: IpMultipathMode,
+    /// Selected routes, most specific first.
+    fib: Vec<IpFibEntry<T>>,
 }
 
 impl<T: IpAddrExt> IpRoutes<T> {
@@ -256,43 +1012,228 @@ impl<T: IpAddrExt> IpRoutes<T> {
     pub fn new() -> Self {
         Self {
             routes: Vec::new(),
-            default: None,
+            distances: HashMap::new(),
+            down_ifaces: HashSet::new(),
+            dead_next_hops: HashSet::new(),
+            multipath_mode: IpMultipathMode::default(),
+            fib: Vec::new(),
         }
     }
  
     /// Add a new route for the given address prefix.
     pub fn add_route(&mut self, prefix: IpPrefix<T>, iface: usize, link: IpRouteLink<T>) {
-        self.routes.push(IpRoute { prefix, iface, link });
+        self.add_route_from(prefix, iface, link, IpRouteSource::Static);
+    }
+
+    /// Add a new route for the given address prefix, with a weight among
+    /// the equal-cost paths of the prefix.
+    pub fn add_route_weighted(&mut self, prefix: IpPrefix<T>, iface: usize, link: IpRouteLink<T>, weight: u32) {
+        assert!(weight != 0, "weight must not be zero");
+        self.routes.push(IpRoute { prefix, iface, link, source: IpRouteSource::Static, weight });
+        self.update_fib_prefix(prefix);
+    }
+
+    /// Add a new route for the given address prefix, learned from the 
+    /// given source.
+    pub fn add_route_from(&mut self, prefix: IpPrefix<T>, iface: usize, link: IpRouteLink<T>, source: IpRouteSource) {
+        self.routes.push(IpRoute { prefix, iface, link, source, weight: 1 });
+        self.update_fib_prefix(prefix);
+    }
+
+    /// Remove all routes learned from the given source.
+    pub fn remove_routes_from(&mut self, source: IpRouteSource) {
+        self.routes.retain(|route| route.source != source);
+        self.update_fib();
     }
 
-    /// Set the default route.
+    /// Replace all routes learned from the given source, the forwarding
+    /// table is only updated if the routes changed.
+    pub fn replace_routes_from<I>(&mut self, source: IpRouteSource, routes: I)
+    where
+        I: IntoIterator<Item = (IpPrefix<T>, usize, IpRouteLink<T>)>,
+    {
+        let routes = routes.into_iter()
+            .map(|(prefix, iface, link)| IpRoute { prefix, iface, link, source, weight: 1 })
+            .collect::<Vec<_>>();
+        if self.routes.iter().filter(|route| route.source == source).eq(routes.iter()) {
+            return;
+        }
+        self.routes.retain(|route| route.source != source);
+        self.routes.extend(routes);
+        self.update_fib();
+    }
+
+    /// Set the default route, a static route for the zero-length prefix.
     pub fn set_default_route(&mut self, iface: usize, link: IpRouteLink<T>) {
-        self.default = Some(IpRoute { prefix: IpPrefix::ZERO, iface, link });
+        self.routes.retain(|route| route.source != IpRouteSource::Static || route.prefix != IpPrefix::ZERO);
+        self.add_route(IpPrefix::ZERO, iface, link);
+    }
+
+    /// Set the administrative distance of a source, routes from the source
+    /// with the lowest distance are preferred.
+    pub fn set_admin_distance(&mut self, source: IpRouteSource, distance: u8) {
+        self.distances.insert(source, distance);
+        self.update_fib();
+    }
+
+    /// Get the administrative distance of a source.
+    pub fn admin_distance(&self, source: IpRouteSource) -> u8 {
+        self.distances.get(&source).copied().unwrap_or(source.default_admin_distance())
+    }
+
+    /// Set how packets are spread over equal-cost paths.
+    #[inline]
+    pub fn set_multipath_mode(&mut self, mode: IpMultipathMode) {
+        self.multipath_mode = mode;
+    }
+
+    #[inline]
+    pub fn multipath_mode(&self) -> IpMultipathMode {
+        self.multipath_mode
+    }
+
+    /// Mark a next hop on an interface as alive or dead, routes through
+    /// dead next hops are removed from the forwarding table.
+    pub fn set_next_hop_alive(&mut self, iface: usize, next_hop: T, alive: bool) {
+        let changed = match alive {
+            true => self.dead_next_hops.remove(&(iface, next_hop)),
+            false => self.dead_next_hops.insert((iface, next_hop)),
+        };
+        if changed {
+            self.update_fib();
+        }
+    }
+
+    /// Return `true` if the next hop on the interface is not known to be
+    /// dead.
+    pub fn is_next_hop_alive(&self, iface: usize, next_hop: T) -> bool {
+        !self.dead_next_hops.contains(&(iface, next_hop))
+    }
+
+    /// Return `true` unless the link of the interface is known to be down.
+    pub fn is_iface_up(&self, iface: usize) -> bool {
+        !self.down_ifaces.contains(&iface)
+    }
+
+    /// Set the link state of an interface, updated by the node on each tick.
+    fn set_iface_up(&mut self, iface: usize, up: bool) {
+        let changed = match up {
+            true => self.down_ifaces.remove(&iface),
+            false => self.down_ifaces.insert(iface),
+        };
+        if changed {
+            self.update_fib();
+        }
+    }
+
+    /// Iterate over the candidate routes of all sources, with their prefix,
+    /// interface, link and source.
+    pub fn routes(&self) -> impl Iterator<Item = (IpPrefix<T>, usize, IpRouteLink<T>, IpRouteSource)> + '_ {
+        self.routes.iter().map(IpRoute::tuple)
+    }
+
+    /// Iterate over the paths of selected routes of the forwarding table,
+    /// in the order they are looked up.
+    pub fn fib(&self) -> impl Iterator<Item = (IpPrefix<T>, usize, IpRouteLink<T>, IpRouteSource)> + '_ {
+        self.fib.iter().flat_map(|entry| entry.paths.iter().map(IpRoute::tuple))
     }
 
     /// Try to find a route for the given address regarding this routes table.
     /// If found, the interface index and the next hop IP is returned.
+    /// Equal-cost paths are selected by a hash of the address.
     #[inline]
     pub fn fetch(&self, ip: T) -> Option<(usize, T)> {
+        let entry = self.fib.iter().find(|entry| entry.prefix.matches(ip))?;
+        let route = entry.select(|| hash(&ip));
+        Some((route.iface, route.link.ip_or_default(ip)))
+    }
 
-        let route = self.routes.iter()
-            .find(|route| route.prefix.matches(ip));
-        
-        // Take default route into account.
-        let route = match route {
-            Some(route) => Some(route),
-            None => self.default.as_ref(),
-        };
+    /// Like `fetch`, but routes for the zero-length prefix are ignored.
+    pub fn fetch_specific(&self, ip: T) -> Option<(usize, T)> {
+        let entry = self.fib.iter().find(|entry| entry.prefix.prefix_len() != 0 && entry.prefix.matches(ip))?;
+        let route = entry.select(|| hash(&ip));
+        Some((route.iface, route.link.ip_or_default(ip)))
+    }
 
-        route.map(|route| {
-            (route.iface, route.link.ip_or_default(ip))
-        })
+    /// Find a route for a packet of the given flow, equal-cost paths are
+    /// selected according to the multipath mode.
+    pub fn fetch_flow(&self, flow: &IpFlow<T>) -> Option<(usize, T)> {
+        let entry = self.fib.iter().find(|entry| entry.prefix.matches(flow.dst))?;
+        let route = entry.select(|| match self.multipath_mode {
+            IpMultipathMode::FlowHash => hash(flow),
+            IpMultipathMode::RoundRobin => {
+                let count = entry.packets.get();
+                entry.packets.set(count.wrapping_add(1));
+                count
+            }
+        });
+        Some((route.iface, route.link.ip_or_default(flow.dst)))
+    }
 
+    /// Rebuild the forwarding table from all routes.
+    fn update_fib(&mut self) {
+        let mut fib = self.select_routes(self.routes.iter());
+        fib.sort_by_key(|entry| std::cmp::Reverse(entry.prefix.prefix_len()));
+        self.fib = fib;
+    }
+
+    /// Update the forwarding table entry of a single prefix, after routes
+    /// of this prefix were added.
+    fn update_fib_prefix(&mut self, prefix: IpPrefix<T>) {
+        let entry = self.select_routes(self.routes.iter().filter(|route| route.prefix == prefix)).pop();
+        match (self.fib.iter().position(|entry| entry.prefix == prefix), entry) {
+            (Some(index), Some(entry)) => self.fib[index] = entry,
+            (Some(index), None) => { self.fib.remove(index); }
+            (None, Some(entry)) => {
+                let index = self.fib.partition_point(|other| other.prefix.prefix_len() >= prefix.prefix_len());
+                self.fib.insert(index, entry);
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Select the alive routes of each prefix with the lowest distance, the
+    /// routes of the first added source win between sources of equal
+    /// distance.
+    fn select_routes<'a>(&self, routes: impl Iterator<Item = &'a IpRoute<T>>) -> Vec<IpFibEntry<T>>
+    where
+        T: 'a,
+    {
+        let mut fib = Vec::<IpFibEntry<T>>::new();
+        let mut indices = HashMap::<IpPrefix<T>, usize>::new();
+        for route in routes.filter(|route| self.is_alive(route)) {
+            let Some(&index) = indices.get(&route.prefix) else {
+                indices.insert(route.prefix, fib.len());
+                fib.push(IpFibEntry::new(route));
+                continue;
+            };
+            let entry = &mut fib[index];
+            if self.admin_distance(route.source) < self.admin_distance(entry.source) {
+                *entry = IpFibEntry::new(route);
+            } else if route.source == entry.source && !entry.paths.iter().any(|path| path.iface == route.iface && path.link == route.link) {
+                entry.paths.push(route.clone());
+            }
+        }
+        fib
+    }
+
+    fn is_alive(&self, route: &IpRoute<T>) -> bool {
+        !self.down_ifaces.contains(&route.iface) && match route.link {
+            IpRouteLink::Indirect(ip) => self.is_next_hop_alive(route.iface, ip),
+            IpRouteLink::Direct => true,
+        }
     }
 
 }
 
+impl<T: IpAddrExt> Default for IpRoutes<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Different kinds of IP routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IpRouteLink<T: IpAddrExt> {
     /// The packet needs to pass trough the given router.
     Indirect(T),
@@ -314,11 +1255,150 @@ impl<T: IpAddrExt> IpRouteLink<T> {
 
 }
 
+#[derive(Clone, PartialEq, Eq)]
 struct IpRoute<T: IpAddrExt> {
     /// Prefix IP.
     prefix: IpPrefix<T>,
     /// The interface to find the 
     iface: usize,
     /// The kind of route to take.
-    link: IpRouteLink<T>
+    link: IpRouteLink<T>,
+    /// Where the route comes from.
+    source: IpRouteSource,
+    /// Share of packets among equal-cost paths.
+    weight: u32,
+}
+
+impl<T: IpAddrExt> IpRoute<T> {
+
+    #[inline]
+    fn tuple(&self) -> (IpPrefix<T>, usize, IpRouteLink<T>, IpRouteSource) {
+        (self.prefix, self.iface, self.link, self.source)
+    }
+
+}
+
+/// A selected prefix of the forwarding table and its paths.
+struct IpFibEntry<T: IpAddrExt> {
+    prefix: IpPrefix<T>,
+    source: IpRouteSource,
+    paths: Vec<IpRoute<T>>,
+    /// Packets sent through the entry, for round-robin.
+    packets: Cell<u64>,
+}
+
+impl<T: IpAddrExt> IpFibEntry<T> {
+
+    fn new(route: &IpRoute<T>) -> Self {
+        Self {
+            prefix: route.prefix,
+            source: route.source,
+            paths: vec![route.clone()],
+            packets: Cell::new(0),
+        }
+    }
+
+    /// Select a path from a number, each path gets a share of the numbers
+    /// matching its weight.
+    fn select(&self, number: impl FnOnce() -> u64) -> &IpRoute<T> {
+        if let [path] = &self.paths[..] {
+            return path;
+        }
+        let total = self.paths.iter().map(|path| path.weight as u64).sum::<u64>();
+        let mut point = number() % total;
+        for path in &self.paths {
+            match point.checked_sub(path.weight as u64) {
+                Some(rest) => point = rest,
+                None => return path,
+            }
+        }
+        unreachable!()
+    }
+
+}
+
+/// How packets are spread over equal-cost paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpMultipathMode {
+    /// Packets of a flow always take the same path, selected by a hash of
+    /// their addresses, protocol and ports.
+    #[default]
+    FlowHash,
+    /// Each packet takes the next path.
+    RoundRobin,
+}
+
+/// Fields identifying a flow of packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpFlow<T> {
+    pub src: T,
+    pub dst: T,
+    pub protocol: u8,
+    pub src_port: u16,
+    pub dst_port: u16,
+}
+
+impl IpFlow<Ipv4Addr> {
+
+    /// Get the flow of an IPv4 packet, fragments are only identified by
+    /// their addresses.
+    pub fn from_ipv4(packet: &Ipv4Packet) -> Self {
+        let (protocol, src_port, dst_port) = match &packet.payload {
+            Ipv4Payload::Udp(datagram) => (17, datagram.src_port, datagram.dst_port),
+            Ipv4Payload::Tcp(segment) => (6, segment.src_port, segment.dst_port),
+            Ipv4Payload::Icmp(_) => (1, 0, 0),
+            Ipv4Payload::Igmp(_) => (2, 0, 0),
+            Ipv4Payload::Ospf(_) => (89, 0, 0),
+            Ipv4Payload::Vrrp(_) => (112, 0, 0),
+            // The key of GRE packets spreads tunnels sharing endpoints.
+            Ipv4Payload::Gre(packet) => {
+                let key = packet.key.unwrap_or(0);
+                (GRE_PROTOCOL, (key >> 16) as u16, key as u16)
+            }
+            Ipv4Payload::Ipip(_) => (IPIP_PROTOCOL, 0, 0),
+            Ipv4Payload::Custom(_) | Ipv4Payload::Fragment(_) => (0, 0, 0),
+        };
+        Self { src: packet.src, dst: packet.dst, protocol, src_port, dst_port }
+    }
+
+}
+
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Origin of a route, routing protocols only replace their own routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpRouteSource {
+    /// Route to the network of an interface address, derived from the
+    /// interface configuration.
+    Connected,
+    /// Route added manually.
+    Static,
+    /// Route learned from RIP.
+    Rip,
+    /// Route computed by OSPF.
+    Ospf,
+    /// Route selected by BGP, learned from an external peer.
+    Bgp,
+    /// Route selected by BGP, learned from an internal peer.
+    Ibgp,
+}
+
+impl IpRouteSource {
+
+    /// Default administrative distance of routes from this source.
+    pub fn default_admin_distance(self) -> u8 {
+        match self {
+            IpRouteSource::Connected => 0,
+            IpRouteSource::Static => 1,
+            IpRouteSource::Bgp => 20,
+            IpRouteSource::Ospf => 110,
+            IpRouteSource::Rip => 120,
+            IpRouteSource::Ibgp => 200,
+        }
+    }
+
 }
@@ -0,0 +1,404 @@
+//! Implementation of network address and port translation, used by
+//! routers to connect inside networks to outside ones.
+
+use std::collections::HashMap;
+
+use crate::proto::{Ipv4Addr, Ipv4Packet, Ipv4Payload, Ipv4Fragment, Icmpv4Message, TcpFlags};
+
+use super::{ServerNode, IPV4_REASSEMBLY_TIMEOUT};
+
+
+/// First port allocated for translations.
+const NAT_PORT_MIN: u16 = 1024;
+/// Number of ticks a TCP translation is kept after a FIN or RST.
+const NAT_TCP_CLOSING_TIMEOUT: u64 = 10;
+
+
+/// Protocol of a translated flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NatProtocol {
+    Udp,
+    Tcp,
+    /// ICMP echo, the identifier is translated like a port.
+    Icmp,
+}
+
+/// Number of ticks after which an unused translation is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatTimeouts {
+    pub udp: u64,
+    pub tcp: u64,
+    pub icmp: u64,
+}
+
+impl Default for NatTimeouts {
+    fn default() -> Self {
+        Self {
+            udp: 30,
+            tcp: 300,
+            icmp: 10,
+        }
+    }
+}
+
+/// A port forwarding rule, flows to the given port of the address of an
+/// outside interface are translated to the given inside address and port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatPortForward {
+    pub protocol: NatProtocol,
+    pub outside_port: u16,
+    pub inside: Ipv4Addr,
+    pub inside_port: u16,
+}
+
+/// A snapshot of a translation, returned by `Nat::translations`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatTranslation {
+    pub protocol: NatProtocol,
+    /// Inside address and port.
+    pub inside: (Ipv4Addr, u16),
+    /// Outside address and port.
+    pub outside: (Ipv4Addr, u16),
+    /// Time of the simulated clock when the translation expires.
+    pub expire: u64,
+}
+
+/// Network address translation configuration and state of a router.
+/// Flows from inside networks going out of an outside interface get
+/// the address of this interface, and a port allocated for the flow.
+/// Packets of other protocols only get the address translated. Packets
+/// received on outside interfaces are translated back if they match a
+/// translation or a port forwarding rule.
+///
+/// Static translations map a whole outside address to an inside address,
+/// the outside address must be routed to the router.
+#[derive(Debug, Clone, Default)]
+pub struct Nat {
+    inside: Vec<usize>,
+    outside: Vec<usize>,
+    timeouts: NatTimeouts,
+    port_forwards: Vec<NatPortForward>,
+    /// Static translations of outside addresses to inside addresses.
+    static_nat: Vec<(Ipv4Addr, Ipv4Addr)>,
+    /// Translations indexed by their outside address.
+    by_outside: HashMap<(NatProtocol, Ipv4Addr, u16), NatEntry>,
+    /// Outside address of translations indexed by their inside address.
+    by_inside: HashMap<(NatProtocol, Ipv4Addr, u16), (Ipv4Addr, u16)>,
+    /// Inside address of fragmented packets received on outside interfaces,
+    /// indexed by their source, outside destination and identifier, and the
+    /// time when it expires. The ports are only in the first fragment, other
+    /// fragments are translated with the address of the first one.
+    fragments: HashMap<(Ipv4Addr, Ipv4Addr, u16), (Ipv4Addr, u64)>,
+    /// Port where the search of a free port starts, `NAT_PORT_MIN` if below.
+    next_port: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NatEntry {
+    inside: (Ipv4Addr, u16),
+    expire: u64,
+}
+
+impl Nat {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an inside interface, flows from its networks are translated.
+    pub fn add_inside(&mut self, iface: usize) {
+        self.inside.push(iface);
+    }
+
+    /// Add an outside interface, flows going out of it are translated.
+    pub fn add_outside(&mut self, iface: usize) {
+        self.outside.push(iface);
+    }
+
+    #[inline]
+    pub fn set_timeouts(&mut self, timeouts: NatTimeouts) {
+        self.timeouts = timeouts;
+    }
+
+    #[inline]
+    pub fn timeouts(&self) -> NatTimeouts {
+        self.timeouts
+    }
+
+    /// Add a port forwarding rule.
+    pub fn add_port_forward(&mut self, port_forward: NatPortForward) {
+        self.port_forwards.push(port_forward);
+    }
+
+    /// Add a static translation of an outside address to an inside one.
+    pub fn add_static(&mut self, outside: Ipv4Addr, inside: Ipv4Addr) {
+        self.static_nat.push((outside, inside));
+    }
+
+    /// Iterate over active translations.
+    pub fn translations(&self) -> impl Iterator<Item = NatTranslation> + '_ {
+        self.by_outside.iter().map(|(&(protocol, ip, port), entry)| NatTranslation {
+            protocol,
+            inside: entry.inside,
+            outside: (ip, port),
+            expire: entry.expire,
+        })
+    }
+
+    /// Remove all dynamic translations.
+    pub fn flush(&mut self) {
+        self.by_outside.clear();
+        self.by_inside.clear();
+    }
+
+    fn timeout(&self, protocol: NatProtocol) -> u64 {
+        match protocol {
+            NatProtocol::Udp => self.timeouts.udp,
+            NatProtocol::Tcp => self.timeouts.tcp,
+            NatProtocol::Icmp => self.timeouts.icmp,
+        }
+    }
+
+    /// Refresh a translation when a packet of the flow is translated.
+    fn refresh(&mut self, protocol: NatProtocol, outside: (Ipv4Addr, u16), payload: &Ipv4Payload, time: u64) {
+        let mut expire = time + self.timeout(protocol);
+        if let Ipv4Payload::Tcp(segment) = payload {
+            if segment.flags.contains(TcpFlags::FIN) || segment.flags.contains(TcpFlags::RST) {
+                expire = time + NAT_TCP_CLOSING_TIMEOUT;
+            }
+        }
+        if let Some(entry) = self.by_outside.get_mut(&(protocol, outside.0, outside.1)) {
+            entry.expire = expire;
+        }
+    }
+
+    /// Add a translation.
+    fn insert(&mut self, protocol: NatProtocol, inside: (Ipv4Addr, u16), outside: (Ipv4Addr, u16)) {
+        self.by_outside.insert((protocol, outside.0, outside.1), NatEntry { inside, expire: 0 });
+        self.by_inside.insert((protocol, inside.0, inside.1), outside);
+    }
+
+    /// Get the translation of an inside address, allocating one if needed.
+    /// The inside port is kept if it is not used by another translation.
+    fn translate_inside(&mut self, protocol: NatProtocol, inside: (Ipv4Addr, u16), outside_ip: Ipv4Addr) -> Option<(Ipv4Addr, u16)> {
+
+        if let Some(&outside) = self.by_inside.get(&(protocol, inside.0, inside.1)) {
+            return Some(outside);
+        }
+
+        let is_free = |nat: &Self, port: u16| {
+            !nat.by_outside.contains_key(&(protocol, outside_ip, port)) &&
+            !nat.port_forwards.iter().any(|fwd| fwd.protocol == protocol && fwd.outside_port == port)
+        };
+
+        let mut port = inside.1;
+        if port < NAT_PORT_MIN || !is_free(self, port) {
+            let start = self.next_port.max(NAT_PORT_MIN);
+            port = (start..=u16::MAX).chain(NAT_PORT_MIN..start)
+                .find(|&port| is_free(self, port))?;
+            self.next_port = port.checked_add(1).unwrap_or(NAT_PORT_MIN);
+        }
+
+        self.insert(protocol, inside, (outside_ip, port));
+        Some((outside_ip, port))
+
+    }
+
+    /// Get the inside address of an outside address, using translations
+    /// and port forwarding rules. Port forwarding rules only apply if
+    /// `forward` is true, when the outside address is the one of an
+    /// outside interface.
+    fn translate_outside(&mut self, protocol: NatProtocol, outside: (Ipv4Addr, u16), forward: bool) -> Option<(Ipv4Addr, u16)> {
+        if let Some(entry) = self.by_outside.get(&(protocol, outside.0, outside.1)) {
+            return Some(entry.inside);
+        }
+        if !forward {
+            return None;
+        }
+        let port_forward = self.port_forwards.iter()
+            .find(|fwd| fwd.protocol == protocol && fwd.outside_port == outside.1)?;
+        let inside = (port_forward.inside, port_forward.inside_port);
+        self.insert(protocol, inside, outside);
+        Some(inside)
+    }
+
+    /// Remove expired translations.
+    pub(super) fn tick(&mut self, time: u64) {
+        self.by_outside.retain(|_, entry| entry.expire > time);
+        self.fragments.retain(|_, &mut (_, expire)| expire > time);
+        let by_outside = &self.by_outside;
+        self.by_inside.retain(|&(protocol, _, _), &mut (ip, port)| by_outside.contains_key(&(protocol, ip, port)));
+    }
+
+}
+
+impl ServerNode {
+
+    /// Enable network address translation with the given configuration,
+    /// or disable it with `None`.
+    pub fn set_nat(&mut self, nat: Option<Nat>) {
+        self.nat = nat;
+    }
+
+    #[inline]
+    pub fn nat(&self) -> Option<&Nat> {
+        self.nat.as_ref()
+    }
+
+    #[inline]
+    pub fn nat_mut(&mut self) -> Option<&mut Nat> {
+        self.nat.as_mut()
+    }
+
+    /// Translate a packet received on an interface, before routing.
+    pub(super) fn nat_input(&mut self, iface: usize, mut packet: Box<Ipv4Packet>) -> Box<Ipv4Packet> {
+
+        let time = self.time;
+        let Some(nat) = &mut self.nat else { return packet };
+        if !nat.outside.contains(&iface) {
+            return packet;
+        }
+
+        if let Some(&(_, inside)) = nat.static_nat.iter().find(|&&(outside, _)| outside == packet.dst) {
+            packet.dst = inside;
+            if let Some(original) = icmp_error_original(&mut packet.payload) {
+                original.src = inside;
+            }
+            return packet;
+        }
+
+        let fragment_key = (packet.src, packet.dst, packet.fragment_identifier);
+        let to_outside_iface = nat.outside.iter()
+            .filter_map(|outside| self.ifaces.get(outside))
+            .any(|iface| iface.conf.has_ipv4(packet.dst));
+
+        if let Some((protocol, _, dst_port)) = flow(&packet.payload) {
+            if let Some((ip, port)) = nat.translate_outside(protocol, (packet.dst, dst_port), to_outside_iface) {
+                nat.refresh(protocol, (packet.dst, dst_port), &packet.payload, time);
+                if packet.is_fragmented() {
+                    nat.fragments.insert(fragment_key, (ip, time + IPV4_REASSEMBLY_TIMEOUT));
+                }
+                packet.dst = ip;
+                set_ports(&mut packet.payload, None, Some(port));
+            }
+        } else if packet.fragment_offset != 0 {
+            // Fragments received before the first one are not translated.
+            if let Some(&(ip, _)) = nat.fragments.get(&fragment_key) {
+                packet.dst = ip;
+            }
+        } else if let Some(original) = icmp_error_original(&mut packet.payload) {
+            // The error is about a translated packet sent by an inside host.
+            if let Some((protocol, src_port, _)) = flow(&original.payload) {
+                if let Some(entry) = nat.by_outside.get(&(protocol, original.src, src_port)) {
+                    let (ip, port) = entry.inside;
+                    original.src = ip;
+                    set_ports(&mut original.payload, Some(port), None);
+                    packet.dst = ip;
+                }
+            }
+        }
+
+        packet
+
+    }
+
+    /// Translate a packet sent out of an interface, after routing. Return
+    /// `None` if the packet is dropped because no port is available.
+    pub(super) fn nat_output(&mut self, iface: usize, mut packet: Box<Ipv4Packet>) -> Option<Box<Ipv4Packet>> {
+
+        let time = self.time;
+        let Some(nat) = &mut self.nat else { return Some(packet) };
+        if !nat.outside.contains(&iface) {
+            return Some(packet);
+        }
+
+        let Some(outside_ip) = self.ifaces.get(&iface)
+            .and_then(|iface| iface.conf.primary_ipv4())
+            .map(|ipv4| ipv4.ip) else { return Some(packet) };
+
+        if let Some(&(outside, _)) = nat.static_nat.iter().find(|&&(_, inside)| inside == packet.src) {
+            packet.src = outside;
+            if let Some(original) = icmp_error_original(&mut packet.payload) {
+                original.dst = outside;
+            }
+            return Some(packet);
+        }
+
+        let from_inside = nat.inside.iter()
+            .filter_map(|inside| self.ifaces.get(inside))
+            .flat_map(|iface| &iface.conf.ipv4)
+            .any(|ipv4| ipv4.prefix().matches(packet.src));
+
+        if !from_inside {
+            return Some(packet);
+        }
+
+        if let Some((protocol, src_port, _)) = flow(&packet.payload) {
+            // Without a free port, the packet could not be answered.
+            let (ip, port) = nat.translate_inside(protocol, (packet.src, src_port), outside_ip)?;
+            nat.refresh(protocol, (ip, port), &packet.payload, time);
+            packet.src = ip;
+            set_ports(&mut packet.payload, Some(port), None);
+        } else {
+            let src = packet.src;
+            if let Some(original) = icmp_error_original(&mut packet.payload) {
+                // The error is about a translated packet sent to an inside host.
+                if let Some((protocol, _, dst_port)) = flow(&original.payload) {
+                    if let Some(&(ip, port)) = nat.by_inside.get(&(protocol, original.dst, dst_port)) {
+                        original.dst = ip;
+                        set_ports(&mut original.payload, None, Some(port));
+                    }
+                }
+            }
+            if src == packet.src {
+                packet.src = outside_ip;
+            }
+        }
+
+        Some(packet)
+
+    }
+
+}
+
+/// Get the protocol, source and destination ports of a flow, the ports
+/// of fragmented packets are in their first fragment.
+fn flow(payload: &Ipv4Payload) -> Option<(NatProtocol, u16, u16)> {
+    match payload {
+        Ipv4Payload::Fragment(Ipv4Fragment { original: Some(original), .. }) => flow(original),
+        Ipv4Payload::Udp(datagram) => Some((NatProtocol::Udp, datagram.src_port, datagram.dst_port)),
+        Ipv4Payload::Tcp(segment) => Some((NatProtocol::Tcp, segment.src_port, segment.dst_port)),
+        Ipv4Payload::Icmp(Icmpv4Message::EchoRequest { id, .. } | Icmpv4Message::EchoReply { id, .. }) => {
+            Some((NatProtocol::Icmp, *id, *id))
+        }
+        _ => None,
+    }
+}
+
+/// Change the ports of a flow, the identifier of ICMP echo is changed
+/// with any port.
+fn set_ports(payload: &mut Ipv4Payload, src: Option<u16>, dst: Option<u16>) {
+    match payload {
+        Ipv4Payload::Fragment(Ipv4Fragment { original: Some(original), .. }) => set_ports(original, src, dst),
+        Ipv4Payload::Udp(datagram) => {
+            datagram.src_port = src.unwrap_or(datagram.src_port);
+            datagram.dst_port = dst.unwrap_or(datagram.dst_port);
+        }
+        Ipv4Payload::Tcp(segment) => {
+            segment.src_port = src.unwrap_or(segment.src_port);
+            segment.dst_port = dst.unwrap_or(segment.dst_port);
+        }
+        Ipv4Payload::Icmp(Icmpv4Message::EchoRequest { id, .. } | Icmpv4Message::EchoReply { id, .. }) => {
+            *id = src.or(dst).unwrap_or(*id);
+        }
+        _ => {}
+    }
+}
+
+/// Get the original packet of an ICMP error.
+fn icmp_error_original(payload: &mut Ipv4Payload) -> Option<&mut Ipv4Packet> {
+    match payload {
+        Ipv4Payload::Icmp(Icmpv4Message::DestinationUnreachable { original, .. }) => Some(original),
+        _ => None,
+    }
+}
@@ -0,0 +1,976 @@
+//! Implementation of the OSPFv2 link-state routing protocol, for a single
+//! area made of broadcast networks. Routes computed by SPF are installed
+//! in the IPv4 routes of the node.
+
+use std::collections::{HashMap, BTreeMap, BTreeSet, BinaryHeap};
+use std::cmp::{Ordering, Reverse};
+
+use crate::net::Links;
+use crate::proto::{
+    Ipv4Addr, Ipv4Packet, Ipv4Payload, IpAddrExt, IpPrefix,
+    OspfPacket, OspfMessage, OspfHello, OspfLsa, OspfLsaHeader, OspfLsaKey, OspfLsaKind, OspfLsaBody,
+    OspfRouterLink, OspfLinkKind,
+    OSPF_ALL_ROUTERS, OSPF_HELLO_INTERVAL, OSPF_DEAD_INTERVAL, OSPF_RXMT_INTERVAL,
+    OSPF_LS_REFRESH_TIME, OSPF_MAX_AGE,
+};
+
+use super::{ServerNode, IpRouteLink, IpRouteSource};
+
+
+/// Identifier of the backbone area, the only area supported.
+const OSPF_BACKBONE: Ipv4Addr = Ipv4Addr::UNSPECIFIED;
+/// Sequence number of the first instance of an LSA.
+const OSPF_INITIAL_SEQ: i32 = i32::MIN + 1;
+
+
+/// Configuration of OSPF on an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OspfIfaceConf {
+    /// Cost of sending packets on the interface.
+    pub cost: u16,
+    /// Priority in the designated router election, routers with a priority
+    /// of 0 are never elected.
+    pub priority: u8,
+    pub hello_interval: u16,
+    pub dead_interval: u16,
+}
+
+impl Default for OspfIfaceConf {
+    fn default() -> Self {
+        Self {
+            cost: 10,
+            priority: 1,
+            hello_interval: OSPF_HELLO_INTERVAL,
+            dead_interval: OSPF_DEAD_INTERVAL,
+        }
+    }
+}
+
+/// State of an OSPF interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OspfIfaceState {
+    /// Waiting for hellos of other routers before electing the designated
+    /// router, for a dead interval.
+    Waiting,
+    DrOther,
+    Backup,
+    Dr,
+}
+
+/// State of a neighbor, neighbors are removed when they go down. The
+/// exchange of databases is simplified to one database description sent
+/// by each neighbor, so there is no exchange state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OspfNeighborState {
+    /// A hello has been received from the neighbor.
+    Init,
+    /// The neighbor has seen hellos of this router.
+    TwoWay,
+    /// Databases descriptions are being exchanged.
+    ExStart,
+    /// LSAs missing from the database are requested.
+    Loading,
+    /// Databases are synchronized.
+    Full,
+}
+
+/// A neighbor of an OSPF router, returned by `Ospf::neighbors`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OspfNeighborInfo {
+    pub iface: usize,
+    pub router_id: Ipv4Addr,
+    pub ip: Ipv4Addr,
+    pub priority: u8,
+    pub state: OspfNeighborState,
+}
+
+/// A route computed by SPF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OspfRoute {
+    pub prefix: IpPrefix<Ipv4Addr>,
+    pub iface: usize,
+    pub next_hop: Ipv4Addr,
+    pub cost: u32,
+}
+
+#[derive(Debug, Clone)]
+struct OspfIface {
+    conf: OspfIfaceConf,
+    state: OspfIfaceState,
+    /// End of the waiting state, set on the first tick.
+    wait_until: Option<u64>,
+    next_hello: u64,
+    /// Interface addresses of the designated and backup routers.
+    dr: Ipv4Addr,
+    bdr: Ipv4Addr,
+    neighbors: BTreeMap<Ipv4Addr, OspfNeighbor>,
+}
+
+#[derive(Debug, Clone)]
+struct OspfNeighbor {
+    ip: Ipv4Addr,
+    priority: u8,
+    state: OspfNeighborState,
+    /// Designated and backup routers declared by the neighbor.
+    dr: Ipv4Addr,
+    bdr: Ipv4Addr,
+    /// Time when the neighbor is down without hello.
+    dead: u64,
+    /// LSAs to request from the neighbor.
+    requests: BTreeSet<OspfLsaKey>,
+    /// LSAs flooded to the neighbor and not acknowledged.
+    retransmit: BTreeMap<OspfLsaKey, OspfLsa>,
+    /// Time of the next retransmission of the database description or
+    /// the requests.
+    next_rxmt: u64,
+    /// Time of the next retransmission of unacknowledged LSAs.
+    next_lsu_rxmt: u64,
+}
+
+impl OspfNeighbor {
+
+    /// Stop exchanging databases with the neighbor.
+    fn reset_adjacency(&mut self) {
+        self.requests.clear();
+        self.retransmit.clear();
+    }
+
+}
+
+#[derive(Debug, Clone)]
+struct LsdbEntry {
+    lsa: OspfLsa,
+    /// Time when the LSA has been installed, with the age of its header.
+    installed: u64,
+}
+
+impl LsdbEntry {
+
+    fn age(&self, time: u64) -> u16 {
+        (self.lsa.header.age as u64 + time - self.installed).min(OSPF_MAX_AGE as u64) as u16
+    }
+
+    /// Get the LSA with its current age.
+    fn current(&self, time: u64) -> OspfLsa {
+        let mut lsa = self.lsa.clone();
+        lsa.header.age = self.age(time);
+        lsa
+    }
+
+}
+
+/// Addresses and prefix lengths of interfaces.
+type IfaceAddrs = HashMap<usize, (Ipv4Addr, u8)>;
+
+/// OSPFv2 configuration and state of a router, in the backbone area.
+/// All interfaces are broadcast networks, with a designated router
+/// elected on each network.
+#[derive(Debug, Clone)]
+pub struct Ospf {
+    router_id: Ipv4Addr,
+    ifaces: BTreeMap<usize, OspfIface>,
+    lsdb: BTreeMap<OspfLsaKey, LsdbEntry>,
+    routes: Vec<OspfRoute>,
+    spf_pending: bool,
+    spf_runs: u64,
+    last_route_change: Option<u64>,
+    /// Messages to send on an interface to a destination.
+    queue: Vec<(usize, Ipv4Addr, OspfMessage)>,
+}
+
+impl Ospf {
+
+    pub fn new(router_id: Ipv4Addr) -> Self {
+        Self {
+            router_id,
+            ifaces: BTreeMap::new(),
+            lsdb: BTreeMap::new(),
+            routes: Vec::new(),
+            spf_pending: false,
+            spf_runs: 0,
+            last_route_change: None,
+            queue: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn router_id(&self) -> Ipv4Addr {
+        self.router_id
+    }
+
+    /// Enable OSPF on an interface.
+    pub fn add_iface(&mut self, iface: usize, conf: OspfIfaceConf) {
+        self.ifaces.insert(iface, OspfIface {
+            conf,
+            state: OspfIfaceState::Waiting,
+            wait_until: None,
+            next_hello: 0,
+            dr: Ipv4Addr::UNSPECIFIED,
+            bdr: Ipv4Addr::UNSPECIFIED,
+            neighbors: BTreeMap::new(),
+        });
+    }
+
+    /// Get the state of an interface.
+    pub fn iface_state(&self, iface: usize) -> Option<OspfIfaceState> {
+        self.ifaces.get(&iface).map(|ospf_iface| ospf_iface.state)
+    }
+
+    /// Get the interface address of the designated router of the network
+    /// of an interface.
+    pub fn dr(&self, iface: usize) -> Option<Ipv4Addr> {
+        self.ifaces.get(&iface).map(|ospf_iface| ospf_iface.dr).filter(|dr| !dr.is_unspecified())
+    }
+
+    /// Get the interface address of the backup designated router of the
+    /// network of an interface.
+    pub fn bdr(&self, iface: usize) -> Option<Ipv4Addr> {
+        self.ifaces.get(&iface).map(|ospf_iface| ospf_iface.bdr).filter(|bdr| !bdr.is_unspecified())
+    }
+
+    /// Iterate over neighbors of all interfaces.
+    pub fn neighbors(&self) -> impl Iterator<Item = OspfNeighborInfo> + '_ {
+        self.ifaces.iter().flat_map(|(&iface, ospf_iface)| {
+            ospf_iface.neighbors.iter().map(move |(&router_id, neighbor)| OspfNeighborInfo {
+                iface,
+                router_id,
+                ip: neighbor.ip,
+                priority: neighbor.priority,
+                state: neighbor.state,
+            })
+        })
+    }
+
+    /// Iterate over the LSAs of the database, with their age when they
+    /// were installed.
+    pub fn lsdb(&self) -> impl Iterator<Item = &OspfLsa> + '_ {
+        self.lsdb.values().map(|entry| &entry.lsa)
+    }
+
+    /// Get the routes computed by the last SPF run.
+    #[inline]
+    pub fn routes(&self) -> &[OspfRoute] {
+        &self.routes
+    }
+
+    /// Get the number of SPF runs.
+    #[inline]
+    pub fn spf_runs(&self) -> u64 {
+        self.spf_runs
+    }
+
+    /// Get the time of the simulated clock when routes last changed, this
+    /// is used to measure convergence.
+    #[inline]
+    pub fn last_route_change(&self) -> Option<u64> {
+        self.last_route_change
+    }
+
+    /// Process a hello received from a neighbor.
+    fn recv_hello(&mut self, iface: usize, addr: (Ipv4Addr, u8), src: Ipv4Addr, router_id: Ipv4Addr, hello: &OspfHello, time: u64) {
+
+        let my_router_id = self.router_id;
+        let Some(ospf_iface) = self.ifaces.get_mut(&iface) else { return };
+        if hello.prefix_len != addr.1 ||
+            hello.hello_interval != ospf_iface.conf.hello_interval ||
+            hello.dead_interval != ospf_iface.conf.dead_interval {
+            return;
+        }
+
+        let neighbor = ospf_iface.neighbors.entry(router_id).or_insert_with(|| OspfNeighbor {
+            ip: src,
+            priority: hello.priority,
+            state: OspfNeighborState::Init,
+            dr: hello.dr,
+            bdr: hello.bdr,
+            dead: 0,
+            requests: BTreeSet::new(),
+            retransmit: BTreeMap::new(),
+            next_rxmt: 0,
+            next_lsu_rxmt: 0,
+        });
+
+        neighbor.dead = time + hello.dead_interval as u64;
+        let mut neighbor_change = (neighbor.ip, neighbor.priority, neighbor.dr, neighbor.bdr) != (src, hello.priority, hello.dr, hello.bdr);
+        neighbor.ip = src;
+        neighbor.priority = hello.priority;
+        neighbor.dr = hello.dr;
+        neighbor.bdr = hello.bdr;
+
+        if hello.neighbors.contains(&my_router_id) {
+            if neighbor.state == OspfNeighborState::Init {
+                neighbor.state = OspfNeighborState::TwoWay;
+                neighbor_change = true;
+            }
+        } else if neighbor.state >= OspfNeighborState::TwoWay {
+            neighbor.state = OspfNeighborState::Init;
+            neighbor.reset_adjacency();
+            neighbor_change = true;
+        }
+
+        // A backup designated router, or a designated router without
+        // backup, means that the election already happened.
+        let backup_seen = hello.bdr == src || (hello.dr == src && hello.bdr.is_unspecified());
+        if ospf_iface.state == OspfIfaceState::Waiting {
+            if backup_seen {
+                self.elect(iface, addr.0, time);
+            }
+        } else if neighbor_change {
+            self.elect(iface, addr.0, time);
+        }
+
+        self.update_adjacencies(iface, addr.0, time);
+
+    }
+
+    /// Elect the designated and backup routers of the network of an
+    /// interface.
+    fn elect(&mut self, iface: usize, ip: Ipv4Addr, time: u64) {
+
+        let router_id = self.router_id;
+        let Some(ospf_iface) = self.ifaces.get_mut(&iface) else { return };
+        ospf_iface.wait_until = Some(time);
+
+        // Address, router identifier, priority, declared designated and
+        // backup routers of routers of the network.
+        let mut candidates = ospf_iface.neighbors.iter()
+            .filter(|(_, neighbor)| neighbor.state >= OspfNeighborState::TwoWay)
+            .map(|(&neighbor_id, neighbor)| (neighbor.ip, neighbor_id, neighbor.priority, neighbor.dr, neighbor.bdr))
+            .collect::<Vec<_>>();
+        candidates.push((ip, router_id, ospf_iface.conf.priority, ospf_iface.dr, ospf_iface.bdr));
+
+        // The election is run again if this router's role changed.
+        for _ in 0..2 {
+            let (dr, bdr) = elect(&candidates);
+            let me = candidates.last_mut().unwrap();
+            let changed = (me.3 == ip) != (dr == ip) || (me.4 == ip) != (bdr == ip);
+            (me.3, me.4) = (dr, bdr);
+            if !changed {
+                break;
+            }
+        }
+
+        let &(_, _, _, dr, bdr) = candidates.last().unwrap();
+        ospf_iface.dr = dr;
+        ospf_iface.bdr = bdr;
+        ospf_iface.state = if dr == ip {
+            OspfIfaceState::Dr
+        } else if bdr == ip {
+            OspfIfaceState::Backup
+        } else {
+            OspfIfaceState::DrOther
+        };
+
+    }
+
+    /// Start or stop database exchanges with neighbors of an interface,
+    /// adjacencies are only formed with the designated and backup routers.
+    fn update_adjacencies(&mut self, iface: usize, ip: Ipv4Addr, time: u64) {
+        let Some(ospf_iface) = self.ifaces.get_mut(&iface) else { return };
+        if ospf_iface.state == OspfIfaceState::Waiting {
+            return;
+        }
+        let (dr, bdr) = (ospf_iface.dr, ospf_iface.bdr);
+        for neighbor in ospf_iface.neighbors.values_mut() {
+            let adjacent = dr == ip || bdr == ip || dr == neighbor.ip || bdr == neighbor.ip;
+            if neighbor.state == OspfNeighborState::TwoWay && adjacent {
+                neighbor.state = OspfNeighborState::ExStart;
+                neighbor.next_rxmt = time;
+            } else if neighbor.state > OspfNeighborState::TwoWay && !adjacent {
+                neighbor.state = OspfNeighborState::TwoWay;
+                neighbor.reset_adjacency();
+            }
+        }
+    }
+
+    /// Get the headers of all LSAs of the database.
+    fn headers(&self, time: u64) -> Vec<OspfLsaHeader> {
+        self.lsdb.values().map(|entry| entry.current(time).header).collect()
+    }
+
+    /// Process a database description received from an adjacent neighbor.
+    fn recv_dd(&mut self, iface: usize, router_id: Ipv4Addr, received: bool, headers: &[OspfLsaHeader], time: u64) {
+
+        let my_headers = self.headers(time);
+        let Some(ospf_iface) = self.ifaces.get_mut(&iface) else { return };
+        let Some(neighbor) = ospf_iface.neighbors.get_mut(&router_id) else { return };
+        if neighbor.state < OspfNeighborState::ExStart {
+            return;
+        }
+
+        if neighbor.state == OspfNeighborState::ExStart {
+            for header in headers {
+                let newer = self.lsdb.get(&header.key)
+                    .is_none_or(|entry| header.cmp_instance(&entry.current(time).header) == Ordering::Greater);
+                if newer {
+                    neighbor.requests.insert(header.key);
+                }
+            }
+            if neighbor.requests.is_empty() {
+                neighbor.state = OspfNeighborState::Full;
+            } else {
+                neighbor.state = OspfNeighborState::Loading;
+                neighbor.next_rxmt = time;
+            }
+        }
+
+        if !received {
+            let ip = neighbor.ip;
+            self.queue.push((iface, ip, OspfMessage::DatabaseDescription { received: true, headers: my_headers }));
+        }
+
+    }
+
+    /// Process a link state request received from an adjacent neighbor.
+    fn recv_lsr(&mut self, iface: usize, router_id: Ipv4Addr, keys: &[OspfLsaKey], time: u64) {
+        let Some(neighbor) = self.ifaces.get(&iface).and_then(|ospf_iface| ospf_iface.neighbors.get(&router_id)) else { return };
+        if neighbor.state < OspfNeighborState::ExStart {
+            return;
+        }
+        let lsas = keys.iter()
+            .filter_map(|key| self.lsdb.get(key))
+            .map(|entry| entry.current(time))
+            .collect::<Vec<_>>();
+        if !lsas.is_empty() {
+            self.queue.push((iface, neighbor.ip, OspfMessage::LinkStateUpdate(lsas)));
+        }
+    }
+
+    /// Process a link state update received from an adjacent neighbor.
+    fn recv_lsu(&mut self, iface: usize, router_id: Ipv4Addr, lsas: &[OspfLsa], time: u64) {
+
+        let Some(neighbor) = self.ifaces.get(&iface).and_then(|ospf_iface| ospf_iface.neighbors.get(&router_id)) else { return };
+        if neighbor.state < OspfNeighborState::ExStart {
+            return;
+        }
+        let ip = neighbor.ip;
+
+        let mut acks = Vec::new();
+        let mut newer_copies = Vec::new();
+
+        for lsa in lsas {
+
+            let key = lsa.header.key;
+            let current = self.lsdb.get(&key).map(|entry| entry.current(time));
+            let neighbor = self.ifaces.get_mut(&iface).unwrap().neighbors.get_mut(&router_id).unwrap();
+
+            match current.as_ref().map(|current| lsa.header.cmp_instance(&current.header)) {
+                None if lsa.header.age >= OSPF_MAX_AGE => {
+                    acks.push(lsa.header);
+                }
+                None | Some(Ordering::Greater) => {
+                    neighbor.requests.remove(&key);
+                    neighbor.retransmit.remove(&key);
+                    acks.push(lsa.header);
+                    self.install(lsa.clone(), time);
+                    self.flood(lsa, Some((iface, router_id)), time);
+                }
+                Some(Ordering::Equal) => {
+                    neighbor.requests.remove(&key);
+                    // A copy from a neighbor waiting for it is an implied
+                    // acknowledgment.
+                    if neighbor.retransmit.remove(&key).is_none() {
+                        acks.push(lsa.header);
+                    }
+                }
+                Some(Ordering::Less) => {
+                    newer_copies.extend(current);
+                }
+            }
+
+        }
+
+        if !acks.is_empty() {
+            self.queue.push((iface, ip, OspfMessage::LinkStateAck(acks)));
+        }
+        if !newer_copies.is_empty() {
+            self.queue.push((iface, ip, OspfMessage::LinkStateUpdate(newer_copies)));
+        }
+
+        let neighbor = self.ifaces.get_mut(&iface).unwrap().neighbors.get_mut(&router_id).unwrap();
+        if neighbor.state == OspfNeighborState::Loading && neighbor.requests.is_empty() {
+            neighbor.state = OspfNeighborState::Full;
+        }
+
+    }
+
+    /// Process a link state acknowledgment received from a neighbor.
+    fn recv_ack(&mut self, iface: usize, router_id: Ipv4Addr, headers: &[OspfLsaHeader]) {
+        let Some(neighbor) = self.ifaces.get_mut(&iface).and_then(|ospf_iface| ospf_iface.neighbors.get_mut(&router_id)) else { return };
+        for header in headers {
+            if neighbor.retransmit.get(&header.key).is_some_and(|lsa| lsa.header.cmp_instance(header) == Ordering::Equal) {
+                neighbor.retransmit.remove(&header.key);
+            }
+        }
+    }
+
+    /// Install an LSA in the database, SPF is run at the end of the tick.
+    fn install(&mut self, lsa: OspfLsa, time: u64) {
+        self.lsdb.insert(lsa.header.key, LsdbEntry { lsa, installed: time });
+        self.spf_pending = true;
+    }
+
+    /// Flood an LSA to adjacent neighbors, except the one it has been
+    /// received from.
+    fn flood(&mut self, lsa: &OspfLsa, from: Option<(usize, Ipv4Addr)>, time: u64) {
+
+        for (&iface, ospf_iface) in &mut self.ifaces {
+
+            if let Some((from_iface, from_router_id)) = from {
+                if from_iface == iface {
+                    // The designated router floods on the network, and the
+                    // backup router waits for it.
+                    let from_ip = ospf_iface.neighbors.get(&from_router_id).map(|neighbor| neighbor.ip);
+                    if from_ip == Some(ospf_iface.dr) || from_ip == Some(ospf_iface.bdr) || ospf_iface.state == OspfIfaceState::Backup {
+                        continue;
+                    }
+                }
+            }
+
+            let mut flooded = false;
+            for (&router_id, neighbor) in &mut ospf_iface.neighbors {
+                if neighbor.state < OspfNeighborState::ExStart || from == Some((iface, router_id)) {
+                    continue;
+                }
+                if neighbor.retransmit.is_empty() {
+                    neighbor.next_lsu_rxmt = time + OSPF_RXMT_INTERVAL;
+                }
+                neighbor.retransmit.insert(lsa.header.key, lsa.clone());
+                flooded = true;
+            }
+
+            if flooded {
+                self.queue.push((iface, OSPF_ALL_ROUTERS, OspfMessage::LinkStateUpdate(vec![lsa.clone()])));
+            }
+
+        }
+
+    }
+
+    /// Originate a new instance of an LSA of this router.
+    fn originate(&mut self, key: OspfLsaKey, body: OspfLsaBody, time: u64) {
+        let seq = self.lsdb.get(&key).map_or(OSPF_INITIAL_SEQ, |entry| entry.lsa.header.seq + 1);
+        let lsa = OspfLsa { header: OspfLsaHeader { key, age: 0, seq }, body };
+        self.install(lsa.clone(), time);
+        self.flood(&lsa, None, time);
+    }
+
+    /// Originate LSAs of this router whose content changed or that must
+    /// be refreshed, and flush network LSAs no longer originated.
+    fn originate_all(&mut self, addrs: &IfaceAddrs, time: u64) {
+
+        let router_id = self.router_id;
+        let mut links = Vec::new();
+        let mut networks = Vec::new();
+
+        for (&iface, ospf_iface) in &self.ifaces {
+            let Some(&(ip, prefix_len)) = addrs.get(&iface) else { continue };
+            let full = |neighbor: &OspfNeighbor| neighbor.state == OspfNeighborState::Full;
+            let transit = match ospf_iface.state {
+                OspfIfaceState::Waiting => false,
+                OspfIfaceState::Dr => ospf_iface.neighbors.values().any(full),
+                _ => ospf_iface.neighbors.values().any(|neighbor| neighbor.ip == ospf_iface.dr && full(neighbor)),
+            };
+            if transit {
+                links.push(OspfRouterLink { kind: OspfLinkKind::Transit, id: ospf_iface.dr, data: ip, metric: ospf_iface.conf.cost });
+            } else {
+                links.push(OspfRouterLink {
+                    kind: OspfLinkKind::Stub,
+                    id: ip.take_prefix(prefix_len).ip(),
+                    data: prefix_mask(prefix_len),
+                    metric: ospf_iface.conf.cost,
+                });
+            }
+            if transit && ospf_iface.state == OspfIfaceState::Dr {
+                let mut routers = vec![router_id];
+                routers.extend(ospf_iface.neighbors.iter().filter(|(_, neighbor)| full(neighbor)).map(|(&id, _)| id));
+                networks.push((OspfLsaKey { kind: OspfLsaKind::Network, id: ip, advertising_router: router_id }, OspfLsaBody::Network { prefix_len, routers }));
+            }
+        }
+
+        let mut lsas = networks;
+        lsas.push((OspfLsaKey { kind: OspfLsaKind::Router, id: router_id, advertising_router: router_id }, OspfLsaBody::Router(links)));
+
+        for (key, body) in lsas {
+            let current = self.lsdb.get(&key);
+            if current.is_none_or(|entry| entry.lsa.body != body || entry.age(time) >= OSPF_LS_REFRESH_TIME) {
+                self.originate(key, body, time);
+            }
+        }
+
+        // Network LSAs of networks where this router is no longer the
+        // designated router are flushed by aging them prematurely.
+        let flushed = self.lsdb.iter()
+            .filter(|(key, entry)| key.kind == OspfLsaKind::Network && key.advertising_router == router_id && entry.age(time) < OSPF_MAX_AGE)
+            .filter(|(key, _)| !self.ifaces.iter().any(|(iface, ospf_iface)| {
+                ospf_iface.state == OspfIfaceState::Dr && addrs.get(iface).is_some_and(|&(ip, _)| ip == key.id)
+                    && ospf_iface.neighbors.values().any(|neighbor| neighbor.state == OspfNeighborState::Full)
+            }))
+            .map(|(_, entry)| entry.lsa.clone())
+            .collect::<Vec<_>>();
+
+        for mut lsa in flushed {
+            lsa.header.age = OSPF_MAX_AGE;
+            self.install(lsa.clone(), time);
+            self.flood(&lsa, None, time);
+        }
+
+    }
+
+    /// Flood LSAs reaching the maximum age, and remove them once they are
+    /// acknowledged by all neighbors.
+    fn age_lsdb(&mut self, time: u64) {
+
+        let aged = self.lsdb.values()
+            .filter(|entry| entry.lsa.header.age < OSPF_MAX_AGE && entry.age(time) >= OSPF_MAX_AGE)
+            .map(|entry| entry.current(time))
+            .collect::<Vec<_>>();
+
+        for lsa in aged {
+            self.install(lsa.clone(), time);
+            self.flood(&lsa, None, time);
+        }
+
+        let ifaces = &self.ifaces;
+        self.lsdb.retain(|key, entry| {
+            entry.lsa.header.age < OSPF_MAX_AGE || ifaces.values()
+                .flat_map(|ospf_iface| ospf_iface.neighbors.values())
+                .any(|neighbor| neighbor.retransmit.contains_key(key))
+        });
+
+    }
+
+    /// Compute shortest paths from this router with the Dijkstra algorithm,
+    /// return `true` if routes changed.
+    fn spf(&mut self, addrs: &IfaceAddrs) -> bool {
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        enum Vertex {
+            Router(Ipv4Addr),
+            Network(Ipv4Addr),
+        }
+
+        let lsdb = &self.lsdb;
+        let router_links = |id: Ipv4Addr| {
+            lsdb.get(&OspfLsaKey { kind: OspfLsaKind::Router, id, advertising_router: id })
+                .filter(|entry| entry.lsa.header.age < OSPF_MAX_AGE)
+                .and_then(|entry| match &entry.lsa.body {
+                    OspfLsaBody::Router(links) => Some(links),
+                    _ => None,
+                })
+        };
+        let network = |id: Ipv4Addr| {
+            lsdb.iter()
+                .filter(|(key, entry)| key.kind == OspfLsaKind::Network && key.id == id && entry.lsa.header.age < OSPF_MAX_AGE)
+                .find_map(|(_, entry)| match &entry.lsa.body {
+                    OspfLsaBody::Network { prefix_len, routers } => Some((*prefix_len, routers)),
+                    _ => None,
+                })
+        };
+
+        let root = Vertex::Router(self.router_id);
+        // Distance and next hop of vertices, the next hop is unspecified for
+        // networks attached to this router.
+        let mut distances: HashMap<Vertex, (u32, Option<(usize, Ipv4Addr)>)> = HashMap::new();
+        let mut done = BTreeSet::new();
+        let mut heap = BinaryHeap::new();
+        let mut stubs = Vec::new();
+
+        distances.insert(root, (0, None));
+        heap.push(Reverse((0, root)));
+
+        while let Some(Reverse((distance, vertex))) = heap.pop() {
+
+            if !done.insert(vertex) {
+                continue;
+            }
+
+            let next_hop = distances[&vertex].1;
+            let mut edges = Vec::new();
+
+            match vertex {
+                Vertex::Router(router_id) => {
+                    for link in router_links(router_id).into_iter().flatten() {
+                        match link.kind {
+                            OspfLinkKind::Transit => {
+                                // The network must list the router.
+                                if !network(link.id).is_some_and(|(_, routers)| routers.contains(&router_id)) {
+                                    continue;
+                                }
+                                let next_hop = if vertex == root {
+                                    addrs.iter()
+                                        .find(|&(_, &(ip, _))| ip == link.data)
+                                        .map(|(&iface, _)| (iface, Ipv4Addr::UNSPECIFIED))
+                                } else {
+                                    next_hop
+                                };
+                                edges.push((Vertex::Network(link.id), distance + link.metric as u32, next_hop));
+                            }
+                            OspfLinkKind::Stub => {
+                                let prefix = link.id.take_prefix(u32::from(link.data).leading_ones() as u8);
+                                stubs.push((prefix, distance + link.metric as u32, next_hop, vertex == root));
+                            }
+                        }
+                    }
+                }
+                Vertex::Network(network_id) => {
+                    let Some((_, routers)) = network(network_id) else { continue };
+                    for &router_id in routers {
+                        // The router must have a link to the network.
+                        let Some(link) = router_links(router_id).and_then(|links| {
+                            links.iter().find(|link| link.kind == OspfLinkKind::Transit && link.id == network_id)
+                        }) else { continue };
+                        let next_hop = match next_hop {
+                            Some((iface, ip)) if ip.is_unspecified() => Some((iface, link.data)),
+                            next_hop => next_hop,
+                        };
+                        edges.push((Vertex::Router(router_id), distance, next_hop));
+                    }
+                }
+            }
+
+            for (vertex, distance, next_hop) in edges {
+                if !done.contains(&vertex) && distances.get(&vertex).is_none_or(|&(current, _)| distance < current) {
+                    distances.insert(vertex, (distance, next_hop));
+                    heap.push(Reverse((distance, vertex)));
+                }
+            }
+
+        }
+
+        let mut routes: HashMap<IpPrefix<Ipv4Addr>, OspfRoute> = HashMap::new();
+        let mut add_route = |prefix: IpPrefix<Ipv4Addr>, cost: u32, next_hop: Option<(usize, Ipv4Addr)>| {
+            // Networks of interfaces are already reachable.
+            if addrs.values().any(|&(ip, prefix_len)| ip.take_prefix(prefix_len) == prefix) {
+                return;
+            }
+            let Some((iface, next_hop)) = next_hop.filter(|(_, ip)| !ip.is_unspecified()) else { return };
+            if routes.get(&prefix).is_none_or(|route| cost < route.cost) {
+                routes.insert(prefix, OspfRoute { prefix, iface, next_hop, cost });
+            }
+        };
+
+        for (vertex, &(distance, next_hop)) in &distances {
+            if let &Vertex::Network(network_id) = vertex {
+                if let Some((prefix_len, _)) = network(network_id) {
+                    add_route(network_id.take_prefix(prefix_len), distance, next_hop);
+                }
+            }
+        }
+
+        for (prefix, cost, next_hop, local) in stubs {
+            if !local {
+                add_route(prefix, cost, next_hop);
+            }
+        }
+
+        let mut routes = routes.into_values().collect::<Vec<_>>();
+        routes.sort_by_key(|route| (Reverse(route.prefix.prefix_len()), route.prefix.ip()));
+
+        self.spf_runs += 1;
+        let changed = routes != self.routes;
+        self.routes = routes;
+        changed
+
+    }
+
+}
+
+/// Elect the designated and backup routers among candidates, given
+/// with their address, router identifier, priority, and declared
+/// designated and backup routers.
+fn elect(candidates: &[(Ipv4Addr, Ipv4Addr, u8, Ipv4Addr, Ipv4Addr)]) -> (Ipv4Addr, Ipv4Addr) {
+
+    let eligible = || candidates.iter().filter(|candidate| candidate.2 > 0);
+    let best = |iter: &mut dyn Iterator<Item = &(Ipv4Addr, Ipv4Addr, u8, Ipv4Addr, Ipv4Addr)>| {
+        iter.max_by_key(|candidate| (candidate.2, candidate.1)).map(|candidate| candidate.0)
+    };
+
+    // Routers declaring themselves backup are preferred for backup, and
+    // routers declaring themselves designated can't be backup.
+    let elect_bdr = |excluded: Ipv4Addr| {
+        best(&mut eligible().filter(|c| c.3 != c.0 && c.0 != excluded && c.4 == c.0))
+            .or_else(|| best(&mut eligible().filter(|c| c.3 != c.0 && c.0 != excluded)))
+    };
+
+    let mut bdr = elect_bdr(Ipv4Addr::UNSPECIFIED);
+    let dr = best(&mut eligible().filter(|c| c.3 == c.0)).or(bdr);
+    if let Some(dr) = dr.filter(|&dr| bdr == Some(dr)) {
+        bdr = elect_bdr(dr);
+    }
+
+    (dr.unwrap_or(Ipv4Addr::UNSPECIFIED), bdr.unwrap_or(Ipv4Addr::UNSPECIFIED))
+
+}
+
+/// Get the mask of a prefix length, as an address.
+fn prefix_mask(prefix_len: u8) -> Ipv4Addr {
+    Ipv4Addr::from(u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0))
+}
+
+impl ServerNode {
+
+    /// Enable OSPF with the given configuration, or disable it with `None`,
+    /// in which case computed routes are removed.
+    pub fn set_ospf(&mut self, ospf: Option<Ospf>) {
+        if ospf.is_none() {
+            self.ipv4_routes.remove_routes_from(IpRouteSource::Ospf);
+        }
+        self.ospf = ospf;
+    }
+
+    #[inline]
+    pub fn ospf(&self) -> Option<&Ospf> {
+        self.ospf.as_ref()
+    }
+
+    #[inline]
+    pub fn ospf_mut(&mut self) -> Option<&mut Ospf> {
+        self.ospf.as_mut()
+    }
+
+    /// Get addresses of interfaces where OSPF is enabled.
+    fn ospf_addrs(&self, ospf: &Ospf) -> IfaceAddrs {
+        ospf.ifaces.keys()
+            .filter_map(|&iface| {
+                let ipv4 = self.ifaces.get(&iface)?.conf.primary_ipv4()?;
+                Some((iface, (ipv4.ip, ipv4.prefix_len)))
+            })
+            .collect()
+    }
+
+    /// Process an OSPF packet received on an interface.
+    pub(super) fn ospf_recv(&mut self, iface: usize, src: Ipv4Addr, packet: &OspfPacket) {
+
+        let Some(mut ospf) = self.ospf.take() else { return };
+        let addrs = self.ospf_addrs(&ospf);
+        let time = self.time;
+
+        // Packets are only accepted from neighbors on the network of the
+        // interface.
+        let valid = addrs.get(&iface).copied()
+            .filter(|&(ip, prefix_len)| ip != src && ip.take_prefix(prefix_len).matches(src))
+            .filter(|_| packet.area_id == OSPF_BACKBONE && packet.router_id != ospf.router_id);
+
+        if let Some(addr) = valid {
+            match &packet.message {
+                OspfMessage::Hello(hello) => ospf.recv_hello(iface, addr, src, packet.router_id, hello, time),
+                OspfMessage::DatabaseDescription { received, headers } => ospf.recv_dd(iface, packet.router_id, *received, headers, time),
+                OspfMessage::LinkStateRequest(keys) => ospf.recv_lsr(iface, packet.router_id, keys, time),
+                OspfMessage::LinkStateUpdate(lsas) => ospf.recv_lsu(iface, packet.router_id, lsas, time),
+                OspfMessage::LinkStateAck(headers) => ospf.recv_ack(iface, packet.router_id, headers),
+            }
+        }
+
+        self.ospf = Some(ospf);
+
+    }
+
+    /// Send hellos, expire neighbors, retransmit unacknowledged packets,
+    /// originate LSAs and run SPF if the database changed.
+    pub(super) fn ospf_tick(&mut self, time: u64) {
+
+        let Some(mut ospf) = self.ospf.take() else { return };
+        let addrs = self.ospf_addrs(&ospf);
+
+        for &iface in ospf.ifaces.keys() {
+            self.join_ipv4_group(iface, OSPF_ALL_ROUTERS);
+        }
+
+        let ifaces = ospf.ifaces.keys().copied().collect::<Vec<_>>();
+
+        for iface in ifaces {
+
+            let Some(&(ip, prefix_len)) = addrs.get(&iface) else { continue };
+            let ospf_iface = ospf.ifaces.get_mut(&iface).unwrap();
+
+            let wait_until = *ospf_iface.wait_until.get_or_insert(time + ospf_iface.conf.dead_interval as u64);
+
+            let dead = ospf_iface.neighbors.len();
+            ospf_iface.neighbors.retain(|_, neighbor| neighbor.dead > time);
+            let neighbor_change = ospf_iface.neighbors.len() != dead;
+
+            if (ospf_iface.state == OspfIfaceState::Waiting && time >= wait_until) ||
+                (ospf_iface.state != OspfIfaceState::Waiting && neighbor_change) {
+                ospf.elect(iface, ip, time);
+                ospf.update_adjacencies(iface, ip, time);
+            }
+
+            let headers = ospf.headers(time);
+            let ospf_iface = ospf.ifaces.get_mut(&iface).unwrap();
+
+            if time >= ospf_iface.next_hello {
+                ospf_iface.next_hello = time + ospf_iface.conf.hello_interval as u64;
+                ospf.queue.push((iface, OSPF_ALL_ROUTERS, OspfMessage::Hello(OspfHello {
+                    prefix_len,
+                    hello_interval: ospf_iface.conf.hello_interval,
+                    dead_interval: ospf_iface.conf.dead_interval,
+                    priority: ospf_iface.conf.priority,
+                    dr: ospf_iface.dr,
+                    bdr: ospf_iface.bdr,
+                    neighbors: ospf_iface.neighbors.keys().copied().collect(),
+                })));
+            }
+
+            for neighbor in ospf_iface.neighbors.values_mut() {
+                if time >= neighbor.next_rxmt {
+                    match neighbor.state {
+                        OspfNeighborState::ExStart => {
+                            ospf.queue.push((iface, neighbor.ip, OspfMessage::DatabaseDescription {
+                                received: false,
+                                headers: headers.clone(),
+                            }));
+                        }
+                        OspfNeighborState::Loading => {
+                            ospf.queue.push((iface, neighbor.ip, OspfMessage::LinkStateRequest(
+                                neighbor.requests.iter().copied().collect()
+                            )));
+                        }
+                        _ => {}
+                    }
+                    neighbor.next_rxmt = time + OSPF_RXMT_INTERVAL;
+                }
+                if !neighbor.retransmit.is_empty() && time >= neighbor.next_lsu_rxmt {
+                    ospf.queue.push((iface, neighbor.ip, OspfMessage::LinkStateUpdate(
+                        neighbor.retransmit.values().cloned().collect()
+                    )));
+                    neighbor.next_lsu_rxmt = time + OSPF_RXMT_INTERVAL;
+                }
+            }
+
+        }
+
+        ospf.originate_all(&addrs, time);
+        ospf.age_lsdb(time);
+
+        if ospf.spf_pending {
+            ospf.spf_pending = false;
+            if ospf.spf(&addrs) {
+                ospf.last_route_change = Some(time);
+                let routes = ospf.routes.iter()
+                    .map(|route| (route.prefix, route.iface, IpRouteLink::Indirect(route.next_hop)));
+                self.ipv4_routes.replace_routes_from(IpRouteSource::Ospf, routes);
+            }
+        }
+
+        self.ospf = Some(ospf);
+
+    }
+
+    /// Send queued OSPF packets.
+    pub(super) fn ospf_flush(&mut self, links: &mut Links) {
+        let Some(ospf) = &mut self.ospf else { return };
+        let router_id = ospf.router_id;
+        for (iface, dst, message) in std::mem::take(&mut ospf.queue) {
+            let Some(src) = self.iface_primary_ipv4(iface) else { continue };
+            let mut packet = Ipv4Packet::new(src, dst, Ipv4Payload::Ospf(OspfPacket {
+                router_id,
+                area_id: OSPF_BACKBONE,
+                message,
+            }));
+            packet.ttl = 1;
+            self.output_ipv4_local(&mut *links, iface, Box::new(packet), dst);
+        }
+    }
+
+}
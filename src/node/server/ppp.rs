@@ -0,0 +1,391 @@
+//! Implementation of the PPP data-link layer handler, for point-to-point
+//! links where the peer is the only neighbor and no address resolution is
+//! needed. LCP opens the link, then IPCP negotiates the addresses of both
+//! ends before IPv4 packets are exchanged.
+
+use crate::net::Link;
+use crate::proto::{
+    PppFrame, PppControlPacket, PppCode, LcpOption, IpcpOption,
+    Ipv4Packet, Ipv4Addr, IPV4_MIN_MTU,
+};
+
+use super::{ServerIface, ServerIfaceConf, ServerIfaceIpv4, ServerIfaceCtx};
+
+
+/// Time before a configure request without answer is sent again, in ticks.
+const PPP_RESTART_TIME: u64 = 3;
+/// Interval between two LCP echo requests on an opened link, in ticks.
+const PPP_ECHO_INTERVAL: u64 = 10;
+/// Number of unanswered echo requests after which the link is restarted.
+const PPP_ECHO_FAILURES: u32 = 3;
+
+
+/// State of the negotiation of a PPP control protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PppState {
+    /// The negotiation has not started, or the link is down.
+    Closed,
+    /// Our configure request has been sent.
+    RequestSent,
+    /// Our configure request has been acknowledged.
+    AckReceived,
+    /// The configure request of the peer has been acknowledged.
+    AckSent,
+    /// Both ends acknowledged the configuration of the other.
+    Opened,
+}
+
+/// Negotiation of a control protocol, this is the option negotiation
+/// automaton of RFC 1661 restarting by itself.
+#[derive(Debug)]
+struct PppNegotiation {
+    state: PppState,
+    /// Our option has been rejected by the peer, requests are then sent
+    /// without it.
+    rejected: bool,
+    /// Identifier of our last configure request.
+    identifier: u8,
+    /// Time to send our configure request again.
+    restart_at: u64,
+}
+
+impl PppNegotiation {
+
+    fn new() -> Self {
+        Self { state: PppState::Closed, rejected: false, identifier: 0, restart_at: 0 }
+    }
+
+    /// Close the negotiation, it starts again from scratch.
+    fn close(&mut self) {
+        self.state = PppState::Closed;
+        self.rejected = false;
+    }
+
+    /// Return the identifier of a configure request to send now, if any.
+    fn poll(&mut self, time: u64) -> Option<u8> {
+        match self.state {
+            PppState::Opened => return None,
+            PppState::Closed => self.state = PppState::RequestSent,
+            _ if time < self.restart_at => return None,
+            // The peer didn't send its request in time, ours is sent again.
+            PppState::AckReceived => self.state = PppState::RequestSent,
+            PppState::RequestSent | PppState::AckSent => {}
+        }
+        self.identifier = self.identifier.wrapping_add(1);
+        self.restart_at = time + PPP_RESTART_TIME;
+        Some(self.identifier)
+    }
+
+    /// Our configure request has been acknowledged.
+    fn recv_ack(&mut self, identifier: u8) {
+        if identifier == self.identifier {
+            self.state = match self.state {
+                PppState::RequestSent => PppState::AckReceived,
+                PppState::AckSent => PppState::Opened,
+                state => state,
+            };
+        }
+    }
+
+    /// Our configure request has been refused with values to use, it is
+    /// sent again immediately.
+    fn recv_nak(&mut self, identifier: u8) {
+        if identifier == self.identifier {
+            self.restart_at = 0;
+        }
+    }
+
+    /// Our option is not accepted by the peer, the request is sent again
+    /// immediately without it.
+    fn recv_reject(&mut self, identifier: u8) {
+        if identifier == self.identifier {
+            self.rejected = true;
+            self.restart_at = 0;
+        }
+    }
+
+    /// The configure request of the peer has been acknowledged.
+    fn sent_ack(&mut self) {
+        self.state = match self.state {
+            PppState::AckReceived => PppState::Opened,
+            // The peer renegotiates, our request must be sent again.
+            PppState::Opened | PppState::Closed => {
+                self.restart_at = 0;
+                PppState::AckSent
+            }
+            _ => PppState::AckSent,
+        };
+    }
+
+}
+
+/// PPP interface.
+pub struct ServerPppIface {
+    lcp: PppNegotiation,
+    ipcp: PppNegotiation,
+    /// Maximum receive unit of the peer.
+    peer_mru: Option<u16>,
+    /// Address of the peer, acknowledged by IPCP.
+    peer_ipv4: Option<Ipv4Addr>,
+    /// Address given to the peer if it asks for one.
+    offered_ipv4: Option<Ipv4Addr>,
+    /// Address given by the peer when the interface has none.
+    assigned_ipv4: Option<Ipv4Addr>,
+    /// MTU of the interface before it was lowered to the maximum receive
+    /// unit of the peer.
+    configured_mtu: Option<u16>,
+    /// Time to send the next echo request.
+    echo_at: u64,
+    /// Echo requests sent since the last reply.
+    echo_failures: u32,
+}
+
+impl ServerPppIface {
+
+    pub fn new() -> Self {
+        Self {
+            lcp: PppNegotiation::new(),
+            ipcp: PppNegotiation::new(),
+            peer_mru: None,
+            peer_ipv4: None,
+            offered_ipv4: None,
+            assigned_ipv4: None,
+            configured_mtu: None,
+            echo_at: 0,
+            echo_failures: 0,
+        }
+    }
+
+    /// Set the address given to the peer if it asks for one during IPCP
+    /// negotiation, none by default.
+    #[inline]
+    pub fn set_offered_ipv4(&mut self, ip: Option<Ipv4Addr>) {
+        self.offered_ipv4 = ip;
+    }
+
+    #[inline]
+    pub fn offered_ipv4(&self) -> Option<Ipv4Addr> {
+        self.offered_ipv4
+    }
+
+    /// State of the link control protocol.
+    #[inline]
+    pub fn lcp_state(&self) -> PppState {
+        self.lcp.state
+    }
+
+    /// State of the IP control protocol.
+    #[inline]
+    pub fn ipcp_state(&self) -> PppState {
+        self.ipcp.state
+    }
+
+    /// Return `true` if IPv4 packets can be exchanged on the link.
+    #[inline]
+    pub fn is_opened(&self) -> bool {
+        self.lcp.state == PppState::Opened && self.ipcp.state == PppState::Opened
+    }
+
+    /// Address of the peer, once negotiated.
+    #[inline]
+    pub fn peer_ipv4(&self) -> Option<Ipv4Addr> {
+        self.peer_ipv4
+    }
+
+    /// Maximum receive unit of the peer, once negotiated.
+    #[inline]
+    pub fn peer_mru(&self) -> Option<u16> {
+        self.peer_mru
+    }
+
+}
+
+impl Default for ServerPppIface {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerIface<PppFrame> for ServerPppIface {
+
+    fn tick(&mut self, mut link: Link<PppFrame>, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+
+        let time = link.time();
+
+        if !link.is_up() {
+            while link.recv().is_some() {}
+            self.down(conf);
+            return;
+        }
+
+        while let Some(frame) = link.recv() {
+            self.recv_frame(&mut link, *frame, conf, ctx);
+        }
+
+        if let Some(identifier) = self.lcp.poll(time) {
+            let options = match self.lcp.rejected {
+                true => Vec::new(),
+                false => vec![LcpOption::Mru(conf.mtu)],
+            };
+            link.send(Box::new(PppFrame::Lcp(PppControlPacket::new(PppCode::ConfigureRequest, identifier, options))));
+        }
+
+        if self.lcp.state != PppState::Opened {
+            return;
+        }
+
+        // Packets are fragmented by the node to fit in the peer's MRU.
+        if let Some(mru) = self.peer_mru.filter(|&mru| mru < conf.mtu) {
+            self.configured_mtu.get_or_insert(conf.mtu);
+            conf.mtu = mru;
+        }
+
+        if time >= self.echo_at {
+            if self.echo_failures >= PPP_ECHO_FAILURES {
+                // The peer is dead, the link is negotiated again.
+                self.down(conf);
+                return;
+            }
+            self.echo_failures += 1;
+            self.echo_at = time + PPP_ECHO_INTERVAL;
+            link.send(Box::new(PppFrame::Lcp(PppControlPacket::new(PppCode::EchoRequest, self.echo_failures as u8, Vec::new()))));
+        }
+
+        if let Some(identifier) = self.ipcp.poll(time) {
+            let ip = conf.primary_ipv4().map(|ipv4| ipv4.ip)
+                .or(self.assigned_ipv4)
+                .unwrap_or(Ipv4Addr::UNSPECIFIED);
+            let options = match self.ipcp.rejected {
+                true => Vec::new(),
+                false => vec![IpcpOption::Address(ip)],
+            };
+            link.send(Box::new(PppFrame::Ipcp(PppControlPacket::new(PppCode::ConfigureRequest, identifier, options))));
+        }
+
+        // The address given by the peer is used once acknowledged, and
+        // the peer is reachable through the link.
+        if self.ipcp.state == PppState::Opened {
+            if let Some(ip) = self.assigned_ipv4 {
+                if !conf.has_ipv4(ip) {
+                    conf.add_ipv4(ip, 32);
+                }
+            }
+            conf.ipv4_peer = self.peer_ipv4.filter(|ip| !ip.is_unspecified());
+        }
+
+    }
+
+    fn send_ipv4(&mut self, mut link: Link<PppFrame>, _conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, _link_addr: Ipv4Addr) {
+        // The peer is the only destination, packets are discarded until
+        // the link is opened.
+        if self.is_opened() {
+            link.send(Box::new(PppFrame::Ipv4(packet)));
+        }
+    }
+
+}
+
+impl ServerPppIface {
+
+    /// Close both protocols, the address given by the peer, the route to
+    /// the peer and the MTU limit are removed.
+    fn down(&mut self, conf: &mut ServerIfaceConf) {
+        self.lcp.close();
+        self.ipcp.close();
+        conf.ipv4_peer = None;
+        if let Some(mtu) = self.configured_mtu.take() {
+            conf.mtu = mtu;
+        }
+        self.peer_mru = None;
+        self.peer_ipv4 = None;
+        self.echo_at = 0;
+        self.echo_failures = 0;
+        if let Some(ip) = self.assigned_ipv4.take() {
+            conf.remove_ipv4(ip);
+        }
+    }
+
+    fn recv_frame(&mut self, link: &mut Link<PppFrame>, frame: PppFrame, conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+        match frame {
+            PppFrame::Lcp(packet) => self.recv_lcp(link, packet, conf),
+            PppFrame::Ipcp(packet) if self.lcp.state == PppState::Opened => self.recv_ipcp(link, packet, conf),
+            PppFrame::Ipv4(packet) if self.is_opened() => ctx.recv_ipv4(packet),
+            _ => {}
+        }
+    }
+
+    fn recv_lcp(&mut self, link: &mut Link<PppFrame>, packet: PppControlPacket<LcpOption>, conf: &mut ServerIfaceConf) {
+
+        let reply = |code, options| Box::new(PppFrame::Lcp(PppControlPacket::new(code, packet.identifier, options)));
+
+        match packet.code {
+            PppCode::ConfigureRequest => {
+                let mru = packet.options.iter().map(|&LcpOption::Mru(mru)| mru).next_back();
+                if mru.is_some_and(|mru| mru < IPV4_MIN_MTU) {
+                    link.send(reply(PppCode::ConfigureNak, vec![LcpOption::Mru(IPV4_MIN_MTU)]));
+                } else {
+                    link.send(reply(PppCode::ConfigureAck, packet.options.clone()));
+                    if self.lcp.state == PppState::Opened {
+                        // Upper layers are negotiated again with the link.
+                        self.down(conf);
+                    }
+                    self.peer_mru = mru;
+                    self.lcp.sent_ack();
+                }
+            }
+            PppCode::ConfigureAck => self.lcp.recv_ack(packet.identifier),
+            PppCode::ConfigureNak => self.lcp.recv_nak(packet.identifier),
+            PppCode::ConfigureReject => self.lcp.recv_reject(packet.identifier),
+            PppCode::TerminateRequest => {
+                link.send(reply(PppCode::TerminateAck, Vec::new()));
+                self.down(conf);
+            }
+            PppCode::EchoRequest if self.lcp.state == PppState::Opened => {
+                link.send(reply(PppCode::EchoReply, Vec::new()));
+            }
+            PppCode::EchoReply => self.echo_failures = 0,
+            _ => {}
+        }
+
+    }
+
+    fn recv_ipcp(&mut self, link: &mut Link<PppFrame>, packet: PppControlPacket<IpcpOption>, conf: &mut ServerIfaceConf) {
+
+        let reply = |code, options| Box::new(PppFrame::Ipcp(PppControlPacket::new(code, packet.identifier, options)));
+        let address = packet.options.iter().map(|&IpcpOption::Address(ip)| ip).next_back();
+
+        match packet.code {
+            PppCode::ConfigureRequest => match address {
+                Some(ip) if ip.is_unspecified() => match self.offered_ipv4 {
+                    // The peer asks for an address.
+                    Some(offered) => link.send(reply(PppCode::ConfigureNak, vec![IpcpOption::Address(offered)])),
+                    None => link.send(reply(PppCode::ConfigureReject, packet.options.clone())),
+                },
+                _ => {
+                    link.send(reply(PppCode::ConfigureAck, packet.options.clone()));
+                    self.peer_ipv4 = address;
+                    self.ipcp.sent_ack();
+                }
+            },
+            PppCode::ConfigureAck => self.ipcp.recv_ack(packet.identifier),
+            PppCode::ConfigureNak => {
+                // The address suggested is used if the interface has none.
+                if let Some(ip) = address.filter(|_| conf.primary_ipv4().is_none()) {
+                    self.assigned_ipv4 = Some(ip);
+                }
+                self.ipcp.recv_nak(packet.identifier);
+            }
+            // Without address from the peer, the link is opened without one.
+            PppCode::ConfigureReject => self.ipcp.recv_reject(packet.identifier),
+            PppCode::TerminateRequest => {
+                link.send(reply(PppCode::TerminateAck, Vec::new()));
+                self.ipcp.close();
+                self.peer_ipv4 = None;
+                conf.ipv4_peer = None;
+            }
+            _ => {}
+        }
+
+    }
+
+}
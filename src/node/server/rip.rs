@@ -0,0 +1,392 @@
+//! Implementation of the RIPv2 distance-vector routing protocol, learned
+//! routes are installed in the IPv4 routes of the node.
+
+use std::collections::HashMap;
+
+use crate::net::Links;
+use crate::proto::{
+    Ipv4Addr, Ipv4Packet, Ipv4Payload, IpPrefix, UdpDatagram,
+    RipMessage, RipCommand, RipEntry, RIP_PORT, RIP_MULTICAST, RIP_INFINITY, RIP_MAX_ENTRIES,
+};
+
+use super::{ServerNode, IpRouteLink, IpRouteSource};
+
+
+/// How routes are advertised on the interface they were learned from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RipSplitHorizon {
+    /// Routes are advertised on all interfaces.
+    Disabled,
+    /// Routes are not advertised on the interface they were learned from.
+    Simple,
+    /// Routes are advertised as unreachable on the interface they were
+    /// learned from.
+    #[default]
+    PoisonedReverse,
+}
+
+/// Timers of RIP, in ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RipTimers {
+    /// Interval between two updates of the whole table.
+    pub update: u64,
+    /// Time after which a route without update becomes unreachable.
+    pub timeout: u64,
+    /// Time after which an unreachable route is removed, it is advertised
+    /// as unreachable meanwhile.
+    pub garbage: u64,
+}
+
+impl Default for RipTimers {
+    fn default() -> Self {
+        Self {
+            update: 30,
+            timeout: 180,
+            garbage: 120,
+        }
+    }
+}
+
+/// A route of the RIP table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RipRoute {
+    pub prefix: IpPrefix<Ipv4Addr>,
+    pub iface: usize,
+    /// Next hop, unspecified for networks of the interfaces.
+    pub next_hop: Ipv4Addr,
+    pub metric: u8,
+}
+
+#[derive(Debug, Clone)]
+struct RipRouteState {
+    route: RipRoute,
+    /// Time when the route times out, `None` for networks of interfaces.
+    expire: Option<u64>,
+    /// Time when the unreachable route is removed.
+    garbage: Option<u64>,
+    /// True if the route changed since the last update sent.
+    changed: bool,
+}
+
+/// RIPv2 configuration and state of a router. Networks of the interfaces
+/// where RIP is enabled are advertised on these interfaces, with updates
+/// sent periodically and when routes change.
+#[derive(Debug, Clone, Default)]
+pub struct Rip {
+    ifaces: Vec<usize>,
+    split_horizon: RipSplitHorizon,
+    timers: RipTimers,
+    routes: HashMap<IpPrefix<Ipv4Addr>, RipRouteState>,
+    /// Time of the next update of the whole table.
+    next_update: u64,
+    /// True once the table has been requested to neighbors.
+    started: bool,
+    /// Messages to send on an interface, to a destination and port.
+    queue: Vec<(usize, Ipv4Addr, u16, RipMessage)>,
+}
+
+impl Rip {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable RIP on an interface.
+    pub fn add_iface(&mut self, iface: usize) {
+        self.ifaces.push(iface);
+    }
+
+    #[inline]
+    pub fn set_split_horizon(&mut self, split_horizon: RipSplitHorizon) {
+        self.split_horizon = split_horizon;
+    }
+
+    #[inline]
+    pub fn split_horizon(&self) -> RipSplitHorizon {
+        self.split_horizon
+    }
+
+    #[inline]
+    pub fn set_timers(&mut self, timers: RipTimers) {
+        self.timers = timers;
+    }
+
+    #[inline]
+    pub fn timers(&self) -> RipTimers {
+        self.timers
+    }
+
+    /// Iterate over the routes of the table, including unreachable ones
+    /// waiting to be removed.
+    pub fn routes(&self) -> impl Iterator<Item = RipRoute> + '_ {
+        self.routes.values().map(|state| state.route)
+    }
+
+    /// Get the route to the given prefix.
+    pub fn route(&self, prefix: IpPrefix<Ipv4Addr>) -> Option<RipRoute> {
+        self.routes.get(&prefix).map(|state| state.route)
+    }
+
+    /// Make a route unreachable, it is removed after the garbage timer.
+    fn poison(&mut self, prefix: IpPrefix<Ipv4Addr>, time: u64) {
+        let garbage = time + self.timers.garbage;
+        if let Some(state) = self.routes.get_mut(&prefix) {
+            if state.route.metric < RIP_INFINITY {
+                state.route.metric = RIP_INFINITY;
+                state.garbage = Some(garbage);
+                state.changed = true;
+            }
+        }
+    }
+
+    /// Process a route received from a neighbor, return `true` if the
+    /// table changed.
+    fn update(&mut self, route: RipRoute, time: u64) -> bool {
+
+        let expire = Some(time + self.timers.timeout);
+        let Some(state) = self.routes.get_mut(&route.prefix) else {
+            if route.metric >= RIP_INFINITY {
+                return false;
+            }
+            self.routes.insert(route.prefix, RipRouteState { route, expire, garbage: None, changed: true });
+            return true;
+        };
+
+        if state.expire.is_none() {
+            // Networks of interfaces are never replaced.
+            false
+        } else if state.route.iface == route.iface && state.route.next_hop == route.next_hop {
+            // The current next hop always updates the route.
+            if route.metric < RIP_INFINITY {
+                state.expire = expire;
+                state.garbage = None;
+            }
+            if state.route.metric == route.metric {
+                return false;
+            }
+            if route.metric >= RIP_INFINITY {
+                self.poison(route.prefix, time);
+            } else {
+                state.route.metric = route.metric;
+                state.changed = true;
+            }
+            true
+        } else if route.metric < state.route.metric {
+            *state = RipRouteState { route, expire, garbage: None, changed: true };
+            true
+        } else {
+            false
+        }
+
+    }
+
+    /// Queue responses with the given routes on an interface, applying
+    /// split horizon.
+    fn queue_response(&mut self, iface: usize, dst: Ipv4Addr, dst_port: u16, changed_only: bool) {
+
+        let entries = self.routes.values()
+            .filter(|state| !changed_only || state.changed)
+            .filter_map(|state| {
+                let mut metric = state.route.metric;
+                if state.route.iface == iface && state.expire.is_some() {
+                    match self.split_horizon {
+                        RipSplitHorizon::Disabled => {}
+                        RipSplitHorizon::Simple => return None,
+                        RipSplitHorizon::PoisonedReverse => metric = RIP_INFINITY,
+                    }
+                }
+                Some(RipEntry { prefix: state.route.prefix, next_hop: Ipv4Addr::UNSPECIFIED, metric })
+            })
+            .collect::<Vec<_>>();
+
+        for chunk in entries.chunks(RIP_MAX_ENTRIES) {
+            self.queue.push((iface, dst, dst_port, RipMessage {
+                command: RipCommand::Response,
+                entries: chunk.to_vec(),
+            }));
+        }
+
+    }
+
+}
+
+impl ServerNode {
+
+    /// Enable RIP with the given configuration, or disable it with `None`,
+    /// in which case learned routes are removed.
+    pub fn set_rip(&mut self, rip: Option<Rip>) {
+        if rip.is_none() {
+            self.ipv4_routes.remove_routes_from(IpRouteSource::Rip);
+        }
+        self.rip = rip;
+    }
+
+    #[inline]
+    pub fn rip(&self) -> Option<&Rip> {
+        self.rip.as_ref()
+    }
+
+    #[inline]
+    pub fn rip_mut(&mut self) -> Option<&mut Rip> {
+        self.rip.as_mut()
+    }
+
+    /// Process a RIP message received on an interface.
+    pub(super) fn rip_recv(&mut self, iface: usize, packet: &Ipv4Packet) {
+
+        let Ipv4Payload::Udp(datagram) = &packet.payload else { return };
+        let Some(mut rip) = self.rip.take() else { return };
+
+        // Messages are only accepted from neighbors on the network of the
+        // interface.
+        let on_link = |ip: Ipv4Addr| self.ifaces.get(&iface)
+            .is_some_and(|iface| iface.conf.ipv4.iter().any(|ipv4| ipv4.prefix().matches(ip)));
+        let neighbor = on_link(packet.src) && !self.is_local_ipv4(packet.src);
+
+        if let (true, true, Some(message)) = (rip.ifaces.contains(&iface), neighbor, RipMessage::decode(&datagram.data)) {
+            match message.command {
+                RipCommand::Request if message.entries.is_empty() => {
+                    rip.queue_response(iface, packet.src, datagram.src_port, false);
+                }
+                RipCommand::Request => {
+                    let entries = message.entries.iter().map(|entry| RipEntry {
+                        metric: rip.route(entry.prefix).map_or(RIP_INFINITY, |route| route.metric),
+                        ..*entry
+                    }).collect();
+                    rip.queue.push((iface, packet.src, datagram.src_port, RipMessage {
+                        command: RipCommand::Response,
+                        entries,
+                    }));
+                }
+                RipCommand::Response if datagram.src_port == RIP_PORT => {
+                    let mut changed = false;
+                    for entry in message.entries {
+                        // Next hops must be on the network of the interface.
+                        let next_hop = match entry.next_hop {
+                            next_hop if !next_hop.is_unspecified() && on_link(next_hop) => next_hop,
+                            _ => packet.src,
+                        };
+                        changed |= rip.update(RipRoute {
+                            prefix: entry.prefix,
+                            iface,
+                            next_hop,
+                            metric: entry.metric.saturating_add(1).min(RIP_INFINITY),
+                        }, self.time);
+                    }
+                    if changed {
+                        self.rip_install(&rip);
+                    }
+                }
+                RipCommand::Response => {}
+            }
+        }
+
+        self.rip = Some(rip);
+
+    }
+
+    /// Update the table with networks of interfaces, expire routes and
+    /// queue periodic and triggered updates.
+    pub(super) fn rip_tick(&mut self, time: u64) {
+
+        let Some(mut rip) = self.rip.take() else { return };
+
+        for &iface in &rip.ifaces {
+            self.join_ipv4_group(iface, RIP_MULTICAST);
+        }
+
+        if !rip.started {
+            rip.started = true;
+            for &iface in &rip.ifaces {
+                rip.queue.push((iface, RIP_MULTICAST, RIP_PORT, RipMessage {
+                    command: RipCommand::Request,
+                    entries: Vec::new(),
+                }));
+            }
+        }
+
+        let mut changed = false;
+
+        // Networks of interfaces, with a metric of 1.
+        let connected = rip.ifaces.iter()
+            .filter_map(|&iface| Some((iface, self.ifaces.get(&iface)?)))
+            .flat_map(|(index, iface)| iface.conf.ipv4.iter().map(move |ipv4| (index, ipv4.prefix())))
+            .collect::<Vec<_>>();
+
+        for &(iface, prefix) in &connected {
+            let route = RipRoute { prefix, iface, next_hop: Ipv4Addr::UNSPECIFIED, metric: 1 };
+            if rip.routes.get(&prefix).is_none_or(|state| state.route != route) {
+                rip.routes.insert(prefix, RipRouteState { route, expire: None, garbage: None, changed: true });
+                changed = true;
+            }
+        }
+
+        let removed = rip.routes.iter()
+            .filter(|(prefix, state)| {
+                match state.expire {
+                    None => !connected.iter().any(|(_, connected_prefix)| connected_prefix == *prefix),
+                    Some(expire) => expire <= time && state.garbage.is_none(),
+                }
+            })
+            .map(|(&prefix, _)| prefix)
+            .collect::<Vec<_>>();
+
+        for prefix in removed {
+            rip.poison(prefix, time);
+            if let Some(state) = rip.routes.get_mut(&prefix) {
+                state.expire = Some(time);
+            }
+            changed = true;
+        }
+
+        rip.routes.retain(|_, state| state.garbage.is_none_or(|garbage| garbage > time));
+
+        let full = time >= rip.next_update;
+        if full {
+            rip.next_update = time + rip.timers.update;
+        }
+
+        if full || rip.routes.values().any(|state| state.changed) {
+            for iface in rip.ifaces.clone() {
+                rip.queue_response(iface, RIP_MULTICAST, RIP_PORT, !full);
+            }
+            for state in rip.routes.values_mut() {
+                state.changed = false;
+            }
+        }
+
+        if changed {
+            self.rip_install(&rip);
+        }
+
+        self.rip = Some(rip);
+
+    }
+
+    /// Send queued RIP messages.
+    pub(super) fn rip_flush(&mut self, links: &mut Links) {
+        let Some(rip) = &mut self.rip else { return };
+        for (iface, dst, dst_port, message) in std::mem::take(&mut rip.queue) {
+            let Some(src) = self.iface_primary_ipv4(iface) else { continue };
+            let mut packet = Ipv4Packet::new(src, dst, Ipv4Payload::Udp(UdpDatagram {
+                src_port: RIP_PORT,
+                dst_port,
+                data: message.encode(),
+            }));
+            if dst.is_multicast() {
+                packet.ttl = 1;
+            }
+            self.output_ipv4_local(&mut *links, iface, Box::new(packet), dst);
+        }
+    }
+
+    /// Replace routes learned from RIP with the reachable routes of the
+    /// table.
+    fn rip_install(&mut self, rip: &Rip) {
+        let routes = rip.routes.values()
+            .filter(|state| state.expire.is_some() && state.route.metric < RIP_INFINITY)
+            .map(|state| (state.route.prefix, state.route.iface, IpRouteLink::Indirect(state.route.next_hop)));
+        self.ipv4_routes.replace_routes_from(IpRouteSource::Rip, routes);
+    }
+
+}
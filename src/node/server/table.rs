@@ -0,0 +1,163 @@
+//! Implementation of multiple IPv4 routing tables, selected for each packet
+//! by policy rules or by the VRF of the interface that received it. VRFs
+//! isolate interfaces with their own table, so that overlapping address
+//! spaces can be routed by the same node.
+
+use crate::proto::{Ipv4Addr, IpPrefix, Ipv4Packet};
+
+use super::{ServerNode, IpRoutes};
+
+
+/// Name of the main routing table, used when no rule or VRF selects
+/// another table.
+pub const MAIN_ROUTING_TABLE: &str = "main";
+
+
+/// A policy routing rule, selecting the routing table of the packets
+/// matching all of its criteria.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingRule {
+    pub src: Option<IpPrefix<Ipv4Addr>>,
+    /// Interface that received the packet, rules with an input interface
+    /// never match packets sent by the node.
+    pub in_iface: Option<usize>,
+    pub mark: Option<u32>,
+    /// Name of the table to use.
+    pub table: String,
+}
+
+impl RoutingRule {
+
+    /// Construct a rule selecting the given table for all packets.
+    pub fn new(table: impl Into<String>) -> Self {
+        Self {
+            src: None,
+            in_iface: None,
+            mark: None,
+            table: table.into(),
+        }
+    }
+
+    #[inline]
+    pub fn with_src(mut self, prefix: IpPrefix<Ipv4Addr>) -> Self {
+        self.src = Some(prefix);
+        self
+    }
+
+    #[inline]
+    pub fn with_in_iface(mut self, iface: usize) -> Self {
+        self.in_iface = Some(iface);
+        self
+    }
+
+    #[inline]
+    pub fn with_mark(mut self, mark: u32) -> Self {
+        self.mark = Some(mark);
+        self
+    }
+
+    /// Return `true` if the rule matches a packet received on the given
+    /// interface, or sent by the node.
+    pub fn matches(&self, in_iface: Option<usize>, packet: &Ipv4Packet) -> bool {
+        self.src.is_none_or(|prefix| prefix.matches(packet.src)) &&
+        self.in_iface.is_none_or(|iface| in_iface == Some(iface)) &&
+        self.mark.is_none_or(|mark| packet.mark == mark)
+    }
+
+}
+
+impl ServerNode {
+
+    /// Add a named routing table if it doesn't exist, and return it.
+    pub fn add_ipv4_table(&mut self, name: &str) -> &mut IpRoutes<Ipv4Addr> {
+        assert!(name != MAIN_ROUTING_TABLE, "the main table always exists");
+        self.ipv4_tables.entry(name.to_string()).or_default()
+    }
+
+    /// Remove a named routing table, unless it is the VRF of an interface,
+    /// rules using it are then skipped. Return `true` if the table was
+    /// removed.
+    pub fn remove_ipv4_table(&mut self, name: &str) -> bool {
+        if self.ifaces.values().any(|iface| iface.conf.vrf.as_deref() == Some(name)) {
+            return false;
+        }
+        self.ipv4_tables.remove(name).is_some()
+    }
+
+    /// Get a routing table by name, the main table included.
+    pub fn get_ipv4_table(&self, name: &str) -> Option<&IpRoutes<Ipv4Addr>> {
+        match name {
+            MAIN_ROUTING_TABLE => Some(&self.ipv4_routes),
+            _ => self.ipv4_tables.get(name),
+        }
+    }
+
+    /// Get a mutable routing table by name, the main table included.
+    pub fn get_ipv4_table_mut(&mut self, name: &str) -> Option<&mut IpRoutes<Ipv4Addr>> {
+        match name {
+            MAIN_ROUTING_TABLE => Some(&mut self.ipv4_routes),
+            _ => self.ipv4_tables.get_mut(name),
+        }
+    }
+
+    /// Iterate over the names of the routing tables, the main table first.
+    pub fn ipv4_tables(&self) -> impl Iterator<Item = &str> + '_ {
+        std::iter::once(MAIN_ROUTING_TABLE).chain(self.ipv4_tables.keys().map(String::as_str))
+    }
+
+    /// Add a policy routing rule, evaluated after the rules already added.
+    #[inline]
+    pub fn add_ipv4_rule(&mut self, rule: RoutingRule) {
+        self.ipv4_rules.push(rule);
+    }
+
+    /// Remove all rules selecting the given table.
+    pub fn remove_ipv4_rules(&mut self, table: &str) {
+        self.ipv4_rules.retain(|rule| rule.table != table);
+    }
+
+    /// Policy routing rules, in evaluation order.
+    #[inline]
+    pub fn ipv4_rules(&self) -> &[RoutingRule] {
+        &self.ipv4_rules
+    }
+
+    /// Get the routing table of the VRF of an interface, the main table if
+    /// it is in no VRF or its table doesn't exist.
+    pub(super) fn iface_ipv4_table(&self, iface: usize) -> &IpRoutes<Ipv4Addr> {
+        self.ifaces.get(&iface)
+            .and_then(|iface| iface.conf.vrf.as_deref())
+            .and_then(|vrf| self.get_ipv4_table(vrf))
+            .unwrap_or(&self.ipv4_routes)
+    }
+
+    /// Get the routing table for a packet received on the given interface,
+    /// or sent by the node in the given VRF. The first matching rule with an
+    /// existing table gives it, otherwise this is the table of the VRF of the
+    /// input interface, or the given VRF. Packets sent without VRF use the
+    /// one of the interface with the source address, the lowest index if
+    /// several interfaces have it.
+    pub(super) fn ipv4_table_for(&self, in_iface: Option<usize>, vrf: Option<&str>, packet: &Ipv4Packet) -> &IpRoutes<Ipv4Addr> {
+        for rule in &self.ipv4_rules {
+            if rule.matches(in_iface, packet) {
+                if let Some(table) = self.get_ipv4_table(&rule.table) {
+                    return table;
+                }
+            }
+        }
+        if let Some(vrf) = vrf.filter(|_| in_iface.is_none()) {
+            return self.get_ipv4_table(vrf).unwrap_or(&self.ipv4_routes);
+        }
+        let iface = in_iface.or_else(|| {
+            self.ifaces.iter()
+                .filter(|(_, iface)| iface.conf.has_ipv4(packet.src))
+                .map(|(&index, _)| index)
+                .min()
+        });
+        match iface {
+            Some(iface) => self.iface_ipv4_table(iface),
+            None => &self.ipv4_routes,
+        }
+    }
+
+}
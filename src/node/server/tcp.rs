@@ -0,0 +1,240 @@
+//! A minimal TCP connection used by protocols of the node that need a
+//! reliable stream, such as BGP. It implements the three-way handshake,
+//! cumulative acknowledgments and go-back-N retransmissions, but has no
+//! flow or congestion control and connections are aborted with a reset
+//! instead of being closed.
+
+use crate::proto::{Ipv4Addr, TcpSegment, TcpFlags};
+
+
+/// Initial retransmission timeout, in ticks.
+const TCP_INITIAL_RTO: u64 = 3;
+/// Maximum retransmission timeout, in ticks.
+const TCP_MAX_RTO: u64 = 30;
+/// Window advertised by connections, data is always accepted in order.
+const TCP_WINDOW: u16 = u16::MAX;
+
+
+/// State of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    Closed,
+}
+
+/// A TCP connection between a local and a remote endpoint.
+#[derive(Debug)]
+pub(super) struct TcpConnection {
+    state: TcpState,
+    pub local: (Ipv4Addr, u16),
+    pub remote: (Ipv4Addr, u16),
+    mss: usize,
+    /// Sequence number of the first unacknowledged byte.
+    snd_una: u32,
+    /// Sequence number of the next byte to send.
+    snd_nxt: u32,
+    /// Sequence number of the next byte expected.
+    rcv_nxt: u32,
+    /// Data not yet acknowledged, starting at `snd_una`.
+    send_buf: Vec<u8>,
+    /// Data received in order and not yet read.
+    recv_buf: Vec<u8>,
+    rto: u64,
+    retransmit_at: Option<u64>,
+    /// Segments to send.
+    out: Vec<TcpSegment>,
+}
+
+impl TcpConnection {
+
+    fn new(state: TcpState, local: (Ipv4Addr, u16), remote: (Ipv4Addr, u16), mss: u16, time: u64) -> Self {
+        let iss = (time as u32).wrapping_mul(2654435761) ^ u32::from(local.0);
+        Self {
+            state,
+            local,
+            remote,
+            mss: mss as usize,
+            snd_una: iss,
+            snd_nxt: iss.wrapping_add(1),
+            rcv_nxt: 0,
+            send_buf: Vec::new(),
+            recv_buf: Vec::new(),
+            rto: TCP_INITIAL_RTO,
+            retransmit_at: Some(time + TCP_INITIAL_RTO),
+            out: Vec::new(),
+        }
+    }
+
+    /// Open a connection to the remote endpoint.
+    pub fn connect(local: (Ipv4Addr, u16), remote: (Ipv4Addr, u16), mss: u16, time: u64) -> Self {
+        let mut conn = Self::new(TcpState::SynSent, local, remote, mss, time);
+        conn.push(conn.snd_una, TcpFlags::SYN, Vec::new());
+        conn
+    }
+
+    /// Accept a connection from the given SYN segment.
+    pub fn accept(local: (Ipv4Addr, u16), remote: (Ipv4Addr, u16), syn: &TcpSegment, mss: u16, time: u64) -> Self {
+        let mut conn = Self::new(TcpState::SynReceived, local, remote, mss, time);
+        conn.rcv_nxt = syn.seq.wrapping_add(1);
+        conn.push(conn.snd_una, TcpFlags::SYN | TcpFlags::ACK, Vec::new());
+        conn
+    }
+
+    #[inline]
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    /// Return `true` if the segment belongs to this connection.
+    pub fn matches(&self, src: Ipv4Addr, segment: &TcpSegment) -> bool {
+        self.remote == (src, segment.src_port) && self.local.1 == segment.dst_port
+    }
+
+    /// Queue data to send once the connection is established.
+    pub fn write(&mut self, data: &[u8]) {
+        self.send_buf.extend_from_slice(data);
+    }
+
+    /// Take the data received so far.
+    pub fn read(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.recv_buf)
+    }
+
+    /// Abort the connection, queued data is sent once and followed by a
+    /// reset to the remote endpoint.
+    pub fn abort(&mut self) {
+        if self.state != TcpState::Closed {
+            self.send_data();
+            self.push(self.snd_nxt, TcpFlags::RST | TcpFlags::ACK, Vec::new());
+            self.state = TcpState::Closed;
+        }
+    }
+
+    /// Process a segment received for this connection.
+    pub fn recv(&mut self, segment: &TcpSegment, time: u64) {
+
+        if segment.flags.contains(TcpFlags::RST) {
+            self.state = TcpState::Closed;
+            return;
+        }
+
+        let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+
+        match self.state {
+            TcpState::SynSent => {
+                if segment.flags.contains(syn_ack) && segment.ack == self.snd_nxt {
+                    self.rcv_nxt = segment.seq.wrapping_add(1);
+                    self.establish(segment.ack);
+                    self.push(self.snd_nxt, TcpFlags::ACK, Vec::new());
+                }
+                return;
+            }
+            TcpState::SynReceived => {
+                if segment.flags.contains(TcpFlags::SYN) {
+                    // Our SYN-ACK has been lost.
+                    self.push(self.snd_una, syn_ack, Vec::new());
+                    return;
+                } else if segment.flags.contains(TcpFlags::ACK) && segment.ack == self.snd_nxt {
+                    self.establish(segment.ack);
+                } else {
+                    return;
+                }
+            }
+            TcpState::Established => {
+                if segment.flags.contains(TcpFlags::SYN) {
+                    // Our last ACK of the handshake has been lost.
+                    self.push(self.snd_nxt, TcpFlags::ACK, Vec::new());
+                    return;
+                }
+            }
+            TcpState::Closed => return,
+        }
+
+        if segment.flags.contains(TcpFlags::ACK) {
+            let acked = segment.ack.wrapping_sub(self.snd_una);
+            if acked > 0 && acked <= self.snd_nxt.wrapping_sub(self.snd_una) {
+                self.send_buf.drain(..acked as usize);
+                self.snd_una = segment.ack;
+                self.rto = TCP_INITIAL_RTO;
+                self.retransmit_at = (self.snd_una != self.snd_nxt).then_some(time + self.rto);
+            }
+        }
+
+        if !segment.data.is_empty() {
+            // Out of order data is discarded, the remote endpoint will
+            // retransmit it after our duplicate acknowledgment.
+            if segment.seq == self.rcv_nxt {
+                self.recv_buf.extend_from_slice(&segment.data);
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(segment.data.len() as u32);
+            }
+            self.push(self.snd_nxt, TcpFlags::ACK, Vec::new());
+        }
+
+    }
+
+    /// Retransmit what timed out and send queued data, returning the
+    /// segments to send.
+    pub fn poll(&mut self, time: u64) -> Vec<TcpSegment> {
+
+        if self.retransmit_at.is_some_and(|at| time >= at) {
+            match self.state {
+                TcpState::SynSent => self.push(self.snd_una, TcpFlags::SYN, Vec::new()),
+                TcpState::SynReceived => self.push(self.snd_una, TcpFlags::SYN | TcpFlags::ACK, Vec::new()),
+                TcpState::Established => self.snd_nxt = self.snd_una,
+                TcpState::Closed => {}
+            }
+            self.rto = (self.rto * 2).min(TCP_MAX_RTO);
+            self.retransmit_at = Some(time + self.rto);
+        }
+
+        if self.state == TcpState::Established && self.send_data() {
+            self.retransmit_at.get_or_insert(time + self.rto);
+        }
+
+        std::mem::take(&mut self.out)
+
+    }
+
+    /// Send data that has not been sent yet, return `true` if some data
+    /// was sent.
+    fn send_data(&mut self) -> bool {
+        let mut sent = false;
+        if self.state == TcpState::Established {
+            loop {
+                let offset = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+                if offset >= self.send_buf.len() {
+                    break;
+                }
+                let end = (offset + self.mss).min(self.send_buf.len());
+                let data = self.send_buf[offset..end].to_vec();
+                self.push(self.snd_nxt, TcpFlags::ACK | TcpFlags::PSH, data);
+                self.snd_nxt = self.snd_nxt.wrapping_add((end - offset) as u32);
+                sent = true;
+            }
+        }
+        sent
+    }
+
+    fn establish(&mut self, ack: u32) {
+        self.state = TcpState::Established;
+        self.snd_una = ack;
+        self.rto = TCP_INITIAL_RTO;
+        self.retransmit_at = None;
+    }
+
+    fn push(&mut self, seq: u32, flags: TcpFlags, data: Vec<u8>) {
+        let ack = if flags.contains(TcpFlags::ACK) { self.rcv_nxt } else { 0 };
+        self.out.push(TcpSegment {
+            src_port: self.local.1,
+            dst_port: self.remote.1,
+            seq,
+            ack,
+            flags,
+            window: TCP_WINDOW,
+            data,
+        });
+    }
+
+}
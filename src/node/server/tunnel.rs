@@ -0,0 +1,290 @@
+//! Implementation of tunnel interfaces, virtual interfaces that encapsulate
+//! IPv4 packets in GRE or IP-in-IP toward a remote endpoint. Encapsulated
+//! packets are routed by the node through its other interfaces, and
+//! packets received from the remote endpoint are unwrapped and received
+//! on the tunnel interface.
+
+use std::any::Any;
+
+use crate::net::{Links, RawLinkHandle};
+use crate::proto::{IPV4_HEADER_LEN, Ipv4Addr, Ipv4Packet, Ipv4Payload, GrePacket, GrePayload};
+
+use super::{ServerNode, Iface, ServerIfaceConf, ServerIfaceIpv4, ServerIfaceCtx, IfaceInnerUntyped, IpFlow};
+
+
+/// Maximum number of encapsulations of a packet, packets routed back in
+/// their own tunnel are discarded when reaching it.
+const TUNNEL_MAX_NESTING: usize = 4;
+
+
+/// Encapsulation used by a tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunnelMode {
+    /// GRE, with an optional key distinguishing tunnels between the same
+    /// endpoints.
+    Gre { key: Option<u32> },
+    Ipip,
+}
+
+/// Tunnel interface, packets sent on it are encapsulated and sent by the
+/// node to the remote endpoint on the next tick.
+#[derive(Debug)]
+pub struct ServerTunnelIface {
+    mode: TunnelMode,
+    /// Local endpoint, the source address is selected by the node if
+    /// unspecified.
+    local: Ipv4Addr,
+    remote: Ipv4Addr,
+    /// Encapsulated packets to send.
+    #[allow(clippy::vec_box)]
+    outbox: Vec<Box<Ipv4Packet>>,
+    /// Unwrapped packets to receive.
+    #[allow(clippy::vec_box)]
+    inbox: Vec<Box<Ipv4Packet>>,
+    /// Packets discarded because they were encapsulated too many times.
+    nesting_drops: u64,
+}
+
+impl ServerTunnelIface {
+
+    pub fn new(mode: TunnelMode, remote: Ipv4Addr) -> Self {
+        Self {
+            mode,
+            local: Ipv4Addr::UNSPECIFIED,
+            remote,
+            outbox: Vec::new(),
+            inbox: Vec::new(),
+            nesting_drops: 0,
+        }
+    }
+
+    /// Construct a GRE tunnel without key.
+    #[inline]
+    pub fn gre(remote: Ipv4Addr) -> Self {
+        Self::new(TunnelMode::Gre { key: None }, remote)
+    }
+
+    /// Construct an IP-in-IP tunnel.
+    #[inline]
+    pub fn ipip(remote: Ipv4Addr) -> Self {
+        Self::new(TunnelMode::Ipip, remote)
+    }
+
+    /// Set the local endpoint of the tunnel, packets from the remote
+    /// endpoint are then only accepted on this address.
+    #[inline]
+    pub fn with_local(mut self, local: Ipv4Addr) -> Self {
+        self.local = local;
+        self
+    }
+
+    #[inline]
+    pub fn mode(&self) -> TunnelMode {
+        self.mode
+    }
+
+    #[inline]
+    pub fn local(&self) -> Ipv4Addr {
+        self.local
+    }
+
+    #[inline]
+    pub fn remote(&self) -> Ipv4Addr {
+        self.remote
+    }
+
+    /// Get the number of packets discarded because they were routed back
+    /// in tunnels too many times.
+    #[inline]
+    pub fn nesting_drops(&self) -> u64 {
+        self.nesting_drops
+    }
+
+    /// Size of the encapsulation added to inner packets, in bytes.
+    pub fn overhead(&self) -> u16 {
+        IPV4_HEADER_LEN as u16 + match self.mode {
+            TunnelMode::Gre { key: None } => 4,
+            TunnelMode::Gre { key: Some(_) } => 8,
+            TunnelMode::Ipip => 0,
+        }
+    }
+
+    /// Wrap an inner packet in the encapsulation of the tunnel.
+    fn encapsulate(&self, packet: Box<Ipv4Packet>) -> Ipv4Payload {
+        match self.mode {
+            TunnelMode::Gre { key } => Ipv4Payload::Gre(GrePacket { key, payload: GrePayload::Ipv4(packet) }),
+            TunnelMode::Ipip => Ipv4Payload::Ipip(packet),
+        }
+    }
+
+    /// Return `true` if this tunnel and the given one could both accept
+    /// the same packets.
+    fn overlaps(&self, other: &ServerTunnelIface) -> bool {
+        self.mode == other.mode
+            && self.remote == other.remote
+            && (self.local == other.local || self.local.is_unspecified() || other.local.is_unspecified())
+    }
+
+    /// Return `true` if the given packet comes from the remote endpoint
+    /// of this tunnel with its encapsulation.
+    fn accepts(&self, packet: &Ipv4Packet) -> bool {
+        if packet.src != self.remote || (!self.local.is_unspecified() && packet.dst != self.local) {
+            return false;
+        }
+        match (&packet.payload, self.mode) {
+            (Ipv4Payload::Gre(gre), TunnelMode::Gre { key }) => gre.key == key,
+            (Ipv4Payload::Ipip(_), TunnelMode::Ipip) => true,
+            _ => false,
+        }
+    }
+
+}
+
+impl IfaceInnerUntyped for ServerTunnelIface {
+
+    fn handler(&self) -> &dyn Any {
+        self
+    }
+
+    fn handler_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn link(&mut self, _iface: usize, _link: RawLinkHandle) -> bool {
+        false
+    }
+
+    fn tick(&mut self, _links: &mut Links, _conf: &mut ServerIfaceConf, ctx: &mut ServerIfaceCtx) {
+        for packet in self.inbox.drain(..) {
+            ctx.recv_ipv4(packet);
+        }
+        for packet in self.outbox.drain(..) {
+            ctx.send_ipv4(packet);
+        }
+    }
+
+    fn send_ipv4(&mut self, _links: &mut Links, _conf: &mut ServerIfaceIpv4, packet: Box<Ipv4Packet>, _link_addr: Ipv4Addr) {
+
+        if nesting(&packet) >= TUNNEL_MAX_NESTING {
+            self.nesting_drops += 1;
+            return;
+        }
+
+        let allow_fragmentation = packet.allow_fragmentation;
+        let payload = self.encapsulate(packet);
+
+        // The outer packet can't be fragmented if the inner one can't.
+        let mut outer = Ipv4Packet::new(self.local, self.remote, payload);
+        outer.allow_fragmentation = allow_fragmentation;
+        self.outbox.push(Box::new(outer));
+
+    }
+
+    fn is_up(&self, _links: &mut Links) -> bool {
+        true
+    }
+
+}
+
+/// Get the number of encapsulations of a packet.
+fn nesting(mut packet: &Ipv4Packet) -> usize {
+    let mut count = 0;
+    loop {
+        packet = match &packet.payload {
+            Ipv4Payload::Gre(GrePacket { payload: GrePayload::Ipv4(inner), .. }) |
+            Ipv4Payload::Ipip(inner) => inner,
+            _ => return count,
+        };
+        count += 1;
+    }
+}
+
+impl ServerNode {
+
+    /// Define a new tunnel interface with the given common configuration.
+    /// Packets bigger than the path MTU to the remote endpoint, minus the
+    /// encapsulation, are fragmented before being encapsulated.
+    /// 
+    /// # Panics
+    /// 
+    /// If the interface is already defined, or if another tunnel with the
+    /// same mode and remote endpoint could accept the same packets, their
+    /// local endpoints must then be specified and different.
+    pub fn add_tunnel(&mut self, iface: usize, tunnel: ServerTunnelIface, conf: ServerIfaceConf) {
+
+        if self.ifaces.contains_key(&iface) || self.iface_members.contains_key(&iface) {
+            panic!("this interface is already defined");
+        }
+
+        let ambiguous = self.ifaces.values()
+            .filter_map(|iface| iface.inner.handler().downcast_ref::<ServerTunnelIface>())
+            .any(|other| other.overlaps(&tunnel));
+
+        if ambiguous {
+            panic!("another tunnel accepts the same packets");
+        }
+
+        self.ifaces.insert(iface, Iface {
+            inner: Box::new(tunnel),
+            conf,
+        });
+
+        self.update_connected_routes();
+
+    }
+
+    /// Get the MTU of inner packets sent on the given tunnel interface,
+    /// this is the MTU to the remote endpoint minus the encapsulation.
+    /// Return `None` if the interface is not a tunnel.
+    pub(super) fn tunnel_mtu(&self, iface: usize) -> Option<u16> {
+
+        let tunnel = self.ifaces.get(&iface)?.inner.handler().downcast_ref::<ServerTunnelIface>()?;
+
+        // Encapsulated packets are routed like other packets of the node,
+        // in the table selected for their source address, and among
+        // equal-cost paths by their flow, which includes the GRE key.
+        let src = match tunnel.local.is_unspecified() {
+            true => self.source_ipv4(tunnel.remote).unwrap_or(Ipv4Addr::UNSPECIFIED),
+            false => tunnel.local,
+        };
+        let inner = Ipv4Packet::new(Ipv4Addr::UNSPECIFIED, Ipv4Addr::UNSPECIFIED, Ipv4Payload::Custom(Vec::new()));
+        let probe = Ipv4Packet::new(src, tunnel.remote, tunnel.encapsulate(Box::new(inner)));
+
+        let mut mtu = self.ipv4_table_for(None, None, &probe).fetch_flow(&IpFlow::from_ipv4(&probe))
+            .filter(|&(out_iface, _)| out_iface != iface)
+            .and_then(|(out_iface, _)| self.ifaces.get(&out_iface))
+            .map_or(u16::MAX, |out_iface| out_iface.conf.mtu);
+
+        if let Some(pmtu) = self.ipv4_pmtu(tunnel.remote) {
+            mtu = mtu.min(pmtu);
+        }
+
+        Some(mtu.saturating_sub(tunnel.overhead()))
+
+    }
+
+    /// Unwrap a packet received from the remote endpoint of a tunnel, the
+    /// inner packet is received on the tunnel interface on the next tick.
+    /// Return `true` if a tunnel accepted the packet.
+    pub(super) fn tunnel_recv(&mut self, packet: &Ipv4Packet) -> bool {
+
+        let inner = match &packet.payload {
+            Ipv4Payload::Gre(GrePacket { payload: GrePayload::Ipv4(inner), .. }) |
+            Ipv4Payload::Ipip(inner) => inner,
+            _ => return false,
+        };
+
+        for iface in self.ifaces.values_mut() {
+            if let Some(tunnel) = iface.inner.handler_mut().downcast_mut::<ServerTunnelIface>() {
+                if tunnel.accepts(packet) {
+                    tunnel.inbox.push(inner.clone());
+                    return true;
+                }
+            }
+        }
+
+        false
+
+    }
+
+}
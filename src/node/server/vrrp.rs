@@ -0,0 +1,305 @@
+//! Implementation of VRRP for IPv4, a group of routers of a network share
+//! a virtual address and MAC address, the master of the group answers for
+//! them and announces them with gratuitous ARP when it takes over.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::net::Links;
+use crate::proto::{
+    Ipv4Addr, Ipv4Packet, Ipv4Payload, MacAddr, VrrpPacket,
+    VRRP_MULTICAST, VRRP_ADVERT_INTERVAL, VRRP_DEFAULT_PRIORITY, VRRP_OWNER_PRIORITY,
+};
+
+use super::ServerNode;
+
+
+/// Configuration of a virtual router on an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VrrpGroupConf {
+    pub vrid: u8,
+    /// Virtual address, the router owning it on its interface always has
+    /// the highest priority.
+    pub address: Ipv4Addr,
+    pub priority: u8,
+    pub advert_interval: u16,
+    /// Take over from a master with a lower priority.
+    pub preempt: bool,
+}
+
+impl VrrpGroupConf {
+
+    pub fn new(vrid: u8, address: Ipv4Addr) -> Self {
+        Self {
+            vrid,
+            address,
+            priority: VRRP_DEFAULT_PRIORITY,
+            advert_interval: VRRP_ADVERT_INTERVAL,
+            preempt: true,
+        }
+    }
+
+    #[inline]
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        assert!(priority != 0 && priority != VRRP_OWNER_PRIORITY, "reserved priority");
+        self.priority = priority;
+        self
+    }
+
+    #[inline]
+    pub fn with_advert_interval(mut self, interval: u16) -> Self {
+        assert!(interval != 0, "interval must not be zero");
+        self.advert_interval = interval;
+        self
+    }
+
+    #[inline]
+    pub fn with_preempt(mut self, preempt: bool) -> Self {
+        self.preempt = preempt;
+        self
+    }
+
+}
+
+/// State of a router in a virtual router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VrrpState {
+    /// Waiting for the interface to be up with an address.
+    Initialize,
+    Backup,
+    /// The router answers for the virtual address.
+    Master,
+}
+
+#[derive(Debug)]
+struct VrrpGroup {
+    conf: VrrpGroupConf,
+    state: VrrpState,
+    /// Address of the current master.
+    master: Option<Ipv4Addr>,
+    /// Advertisement interval of the master.
+    master_advert_interval: u16,
+    master_down_at: u64,
+    next_advert: u64,
+    /// Times the router became master.
+    master_transitions: u64,
+}
+
+impl VrrpGroup {
+
+    /// Time after which a backup takes over without advertisement from the
+    /// master, routers with a higher priority take over first.
+    fn master_down_interval(&self, priority: u8) -> u64 {
+        let interval = self.master_advert_interval as u64;
+        let skew = ((256 - priority as u64) * interval).div_ceil(256);
+        3 * interval + skew
+    }
+
+    fn become_master(&mut self, ip: Ipv4Addr, time: u64) {
+        self.state = VrrpState::Master;
+        self.master = Some(ip);
+        self.next_advert = time;
+        self.master_transitions += 1;
+    }
+
+    fn become_backup(&mut self, master: Ipv4Addr, advert_interval: u16, priority: u8, time: u64) {
+        self.state = VrrpState::Backup;
+        self.master = Some(master);
+        self.master_advert_interval = advert_interval;
+        self.master_down_at = time + self.master_down_interval(priority);
+    }
+
+}
+
+/// VRRP state of a node, with its virtual routers on each interface.
+#[derive(Debug, Default)]
+pub struct Vrrp {
+    groups: BTreeMap<(usize, u8), VrrpGroup>,
+    /// Advertisements to send on an interface.
+    queue: Vec<(usize, VrrpPacket)>,
+}
+
+impl Vrrp {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a virtual router on an interface, replacing any virtual router
+    /// with the same identifier.
+    pub fn add_group(&mut self, iface: usize, conf: VrrpGroupConf) {
+        self.groups.insert((iface, conf.vrid), VrrpGroup {
+            conf,
+            state: VrrpState::Initialize,
+            master: None,
+            master_advert_interval: conf.advert_interval,
+            master_down_at: 0,
+            next_advert: 0,
+            master_transitions: 0,
+        });
+    }
+
+    /// Remove a virtual router, if the router was the master an
+    /// advertisement with priority 0 lets a backup take over quickly.
+    pub fn remove_group(&mut self, iface: usize, vrid: u8) -> bool {
+        let Some(group) = self.groups.remove(&(iface, vrid)) else { return false };
+        if group.state == VrrpState::Master {
+            self.queue.push((iface, VrrpPacket {
+                vrid,
+                priority: 0,
+                advert_interval: group.conf.advert_interval,
+                addresses: vec![group.conf.address],
+            }));
+        }
+        true
+    }
+
+    /// Get the state of the router in a virtual router.
+    pub fn state(&self, iface: usize, vrid: u8) -> Option<VrrpState> {
+        self.groups.get(&(iface, vrid)).map(|group| group.state)
+    }
+
+    /// Get the interface address of the master of a virtual router, as
+    /// last known by the router.
+    pub fn master(&self, iface: usize, vrid: u8) -> Option<Ipv4Addr> {
+        self.groups.get(&(iface, vrid)).and_then(|group| group.master)
+    }
+
+    /// Get the number of times the router became master of a virtual
+    /// router.
+    pub fn master_transitions(&self, iface: usize, vrid: u8) -> Option<u64> {
+        self.groups.get(&(iface, vrid)).map(|group| group.master_transitions)
+    }
+
+    /// Get the virtual addresses and MAC addresses answered on each
+    /// interface, where the router is master.
+    pub(super) fn virtual_addrs(&self) -> HashMap<usize, Vec<(Ipv4Addr, MacAddr)>> {
+        let mut addrs = HashMap::<usize, Vec<_>>::new();
+        for (&(iface, vrid), group) in &self.groups {
+            if group.state == VrrpState::Master {
+                addrs.entry(iface).or_default().push((group.conf.address, MacAddr::from_vrrp(vrid)));
+            }
+        }
+        addrs
+    }
+
+    /// Return `true` if the router is master of a virtual router with the
+    /// given address, on one of the interfaces accepted by the filter.
+    pub(super) fn is_master_address(&self, ip: Ipv4Addr, mut filter: impl FnMut(usize) -> bool) -> bool {
+        self.groups.iter().any(|(&(iface, _), group)| group.state == VrrpState::Master && group.conf.address == ip && filter(iface))
+    }
+
+}
+
+impl ServerNode {
+
+    /// Enable VRRP with the given virtual routers, or disable it with `None`.
+    #[inline]
+    pub fn set_vrrp(&mut self, vrrp: Option<Vrrp>) {
+        self.vrrp = vrrp;
+    }
+
+    #[inline]
+    pub fn vrrp(&self) -> Option<&Vrrp> {
+        self.vrrp.as_ref()
+    }
+
+    #[inline]
+    pub fn vrrp_mut(&mut self) -> Option<&mut Vrrp> {
+        self.vrrp.as_mut()
+    }
+
+    /// Get the priority of the router in a virtual router on an interface,
+    /// and the interface address, if the interface is up with an address.
+    fn vrrp_priority(&self, iface: usize, conf: &VrrpGroupConf) -> Option<(u8, Ipv4Addr)> {
+        if !self.ipv4_routes.is_iface_up(iface) {
+            return None;
+        }
+        let iface_conf = &self.ifaces.get(&iface)?.conf;
+        let ip = iface_conf.primary_ipv4()?.ip;
+        Some((if iface_conf.has_ipv4(conf.address) { VRRP_OWNER_PRIORITY } else { conf.priority }, ip))
+    }
+
+    /// Process an advertisement received on an interface.
+    pub(super) fn vrrp_recv(&mut self, iface: usize, src: Ipv4Addr, ttl: u8, packet: &VrrpPacket) {
+
+        let Some(mut vrrp) = self.vrrp.take() else { return };
+        let time = self.time;
+
+        // Advertisements that may come from another network are discarded.
+        if let Some(group) = vrrp.groups.get_mut(&(iface, packet.vrid)).filter(|_| ttl == 255) {
+            if let Some((priority, ip)) = self.vrrp_priority(iface, &group.conf) {
+                match group.state {
+                    VrrpState::Backup if packet.priority == 0 => {
+                        group.master_down_at = time + group.master_down_interval(priority) - 3 * group.master_advert_interval as u64;
+                    }
+                    VrrpState::Backup if !group.conf.preempt || packet.priority >= priority => {
+                        group.become_backup(src, packet.advert_interval, priority, time);
+                    }
+                    VrrpState::Master if packet.priority == 0 => {
+                        group.next_advert = time;
+                    }
+                    VrrpState::Master if packet.priority > priority || (packet.priority == priority && src > ip) => {
+                        group.become_backup(src, packet.advert_interval, priority, time);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.vrrp = Some(vrrp);
+
+    }
+
+    /// Run the timers of virtual routers and queue advertisements.
+    pub(super) fn vrrp_tick(&mut self, time: u64) {
+
+        let Some(mut vrrp) = self.vrrp.take() else { return };
+
+        for (&(iface, vrid), group) in &mut vrrp.groups {
+
+            let Some((priority, ip)) = self.vrrp_priority(iface, &group.conf) else {
+                group.state = VrrpState::Initialize;
+                continue;
+            };
+
+            self.join_ipv4_group(iface, VRRP_MULTICAST);
+
+            match group.state {
+                VrrpState::Initialize if priority == VRRP_OWNER_PRIORITY => group.become_master(ip, time),
+                VrrpState::Initialize => {
+                    group.master_advert_interval = group.conf.advert_interval;
+                    group.state = VrrpState::Backup;
+                    group.master_down_at = time + group.master_down_interval(priority);
+                }
+                VrrpState::Backup if time >= group.master_down_at => group.become_master(ip, time),
+                _ => {}
+            }
+
+            if group.state == VrrpState::Master && time >= group.next_advert {
+                group.next_advert = time + group.conf.advert_interval as u64;
+                vrrp.queue.push((iface, VrrpPacket {
+                    vrid,
+                    priority,
+                    advert_interval: group.conf.advert_interval,
+                    addresses: vec![group.conf.address],
+                }));
+            }
+
+        }
+
+        self.vrrp = Some(vrrp);
+
+    }
+
+    /// Send queued advertisements.
+    pub(super) fn vrrp_flush(&mut self, links: &mut Links) {
+        let Some(vrrp) = &mut self.vrrp else { return };
+        for (iface, packet) in std::mem::take(&mut vrrp.queue) {
+            let Some(src) = self.iface_primary_ipv4(iface) else { continue };
+            let mut packet = Ipv4Packet::new(src, VRRP_MULTICAST, Ipv4Payload::Vrrp(packet));
+            packet.ttl = 255;
+            self.output_ipv4_local(&mut *links, iface, Box::new(packet), VRRP_MULTICAST);
+        }
+    }
+
+}
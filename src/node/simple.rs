@@ -49,7 +49,7 @@ where
         for handle in &self.links {
             let mut link = links.get(handle);
             link.send(frame.clone());
-            while let Some(_) = link.recv() {}
+            while link.recv().is_some() {}
         }
 
     }
@@ -0,0 +1,127 @@
+//! Implementation of IGMP snooping, used by switches to forward IPv4
+//! multicast only to ports with interested receivers.
+
+use std::collections::{HashMap, BTreeSet};
+
+use crate::proto::{
+    EthFrame, EthPayload, Ipv4Addr, Ipv4Payload, IgmpMessage,
+    is_local_multicast_group, IGMP_LAST_MEMBER_QUERY_TIME, IGMP_GROUP_MEMBERSHIP_INTERVAL,
+};
+
+
+/// IGMP snooping state of a switch. The switch listens to IGMP messages
+/// to learn ports with members of groups, and ports where multicast
+/// routers are, from the queries they send. Multicast IPv4 packets are
+/// then only sent to router ports and to ports with members of the
+/// group. Groups of the local network control block (224.0.0.0/24) and
+/// non-IPv4 multicast are still flooded.
+#[derive(Debug, Clone, Default)]
+pub struct IgmpSnooping {
+    /// Ports with members of a group in a VLAN, and their expiration.
+    groups: HashMap<(u16, Ipv4Addr), HashMap<usize, u64>>,
+    /// Ports where a querier has been seen in a VLAN, and their expiration,
+    /// `None` for static router ports.
+    router_ports: HashMap<(u16, usize), Option<u64>>,
+}
+
+impl IgmpSnooping {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a static router port in a VLAN, all multicast traffic and
+    /// reports are sent to this port.
+    pub fn add_router_port(&mut self, vlan_id: u16, iface: usize) {
+        self.router_ports.insert((vlan_id, iface), None);
+    }
+
+    /// Get the ports with members of the given group in a VLAN.
+    pub fn group_ports(&self, vlan_id: u16, group: Ipv4Addr) -> BTreeSet<usize> {
+        self.groups.get(&(vlan_id, group))
+            .map(|ports| ports.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the router ports of a VLAN.
+    pub fn router_ports(&self, vlan_id: u16) -> BTreeSet<usize> {
+        self.router_ports.keys()
+            .filter(|&&(router_vlan_id, _)| router_vlan_id == vlan_id)
+            .map(|&(_, iface)| iface)
+            .collect()
+    }
+
+    /// Remove a port from all groups and dynamic router ports, this is used
+    /// when the port is down or no longer forwards frames.
+    pub(crate) fn remove_port(&mut self, iface: usize) {
+        for ports in self.groups.values_mut() {
+            ports.remove(&iface);
+        }
+        self.groups.retain(|_, ports| !ports.is_empty());
+        self.router_ports.retain(|&(_, router_iface), expire| router_iface != iface || expire.is_none());
+    }
+
+    /// Remove expired memberships and router ports.
+    pub(crate) fn tick(&mut self, time: u64) {
+        for ports in self.groups.values_mut() {
+            ports.retain(|_, expire| *expire > time);
+        }
+        self.groups.retain(|_, ports| !ports.is_empty());
+        self.router_ports.retain(|_, expire| expire.is_none_or(|expire| expire > time));
+    }
+
+    /// Snoop an untagged frame received on a port of a VLAN. Return the
+    /// ports where the frame must be sent, or `None` if it must be flooded.
+    pub(crate) fn process(&mut self, vlan_id: u16, iface: usize, frame: &EthFrame, time: u64) -> Option<BTreeSet<usize>> {
+
+        let EthPayload::Ipv4(packet) = &frame.payload else { return None };
+        if !packet.dst.is_multicast() {
+            return None;
+        }
+
+        match &packet.payload {
+            Ipv4Payload::Igmp(IgmpMessage::Query { .. }) => {
+                if !matches!(self.router_ports.get(&(vlan_id, iface)), Some(None)) {
+                    self.router_ports.insert((vlan_id, iface), Some(time + IGMP_GROUP_MEMBERSHIP_INTERVAL));
+                }
+                // Group-specific queries must reach all members.
+                return None;
+            }
+            Ipv4Payload::Igmp(IgmpMessage::V2Report { group }) => {
+                self.update(vlan_id, iface, *group, true, time);
+            }
+            Ipv4Payload::Igmp(IgmpMessage::Leave { group }) => {
+                self.update(vlan_id, iface, *group, false, time);
+            }
+            Ipv4Payload::Igmp(IgmpMessage::V3Report { records }) => {
+                for record in records {
+                    self.update(vlan_id, iface, record.group, record.is_join(), time);
+                }
+            }
+            _ if is_local_multicast_group(packet.dst) => return None,
+            _ => {
+                // Multicast data.
+                let mut ports = self.group_ports(vlan_id, packet.dst);
+                ports.extend(self.router_ports(vlan_id));
+                return Some(ports);
+            }
+        }
+
+        // Reports are only sent to routers, this avoids suppression of
+        // reports of other members, so all member ports are learned.
+        Some(self.router_ports(vlan_id))
+
+    }
+
+    fn update(&mut self, vlan_id: u16, iface: usize, group: Ipv4Addr, join: bool, time: u64) {
+        if join {
+            self.groups.entry((vlan_id, group)).or_default()
+                .insert(iface, time + IGMP_GROUP_MEMBERSHIP_INTERVAL);
+        } else if let Some(expire) = self.groups.get_mut(&(vlan_id, group)).and_then(|ports| ports.get_mut(&iface)) {
+            // Other members on the port have a chance to answer the query
+            // sent by the router after the leave.
+            *expire = (*expire).min(time + 2 * IGMP_LAST_MEMBER_QUERY_TIME as u64);
+        }
+    }
+
+}
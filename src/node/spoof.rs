@@ -0,0 +1,149 @@
+//! Implementation of an ARP spoofing node, used to simulate
+//! man-in-the-middle attacks on a LAN.
+
+use crate::net::{Node, RawLinkHandle, Links};
+use crate::proto::{EthFrame, EthPayload, MacAddr, Ipv4Addr, Ipv4Packet, Ipv4Payload, Icmpv4Message, ArpIpv4Packet, ArpOp};
+
+use super::{ServerNode, ServerEthIface, ServerIfaceConf, IpRouteLink, IpRouteSource, ArpState};
+
+
+/// Default number of ticks between two poisoning rounds.
+const DEFAULT_POISON_INTERVAL: u64 = 5;
+
+
+/// An attacker node with a single ethernet interface 0. It periodically
+/// sends forged ARP replies to each of its targets, claiming that the
+/// IP of every other target is at the attacker's MAC address. The real
+/// MAC addresses of the targets are resolved with ARP, by pinging them
+/// until they are known. IPv4 traffic received because of the poisoning
+/// is then forwarded to the real destination, unless relaying is
+/// disabled. Packets to other networks are forwarded to the gateway if
+/// one is set, or dropped otherwise.
+pub struct ArpSpoofNode {
+    /// Inner node resolving and forwarding on behalf of the attacker.
+    node: ServerNode,
+    /// MAC address of the attacker.
+    mac_addr: MacAddr,
+    /// Hosts to poison.
+    targets: Vec<Ipv4Addr>,
+    /// Number of ticks between two poisoning rounds.
+    poison_interval: u64,
+    /// Time of the next poisoning round.
+    next_poison: u64,
+}
+
+impl ArpSpoofNode {
+
+    /// Create an attacker with the given address on the network.
+    pub fn new(mac_addr: MacAddr, ip: Ipv4Addr, prefix_len: u8) -> Self {
+        let mut node = ServerNode::with_iface_conf(0, ServerEthIface::new(mac_addr), ServerIfaceConf::with_ipv4(ip, prefix_len));
+        node.set_ipv4_forwarding(true);
+        Self {
+            node,
+            mac_addr,
+            targets: Vec::new(),
+            poison_interval: DEFAULT_POISON_INTERVAL,
+            next_poison: 0,
+        }
+    }
+
+    /// Add a host to poison.
+    pub fn add_target(&mut self, ip: Ipv4Addr) {
+        self.targets.push(ip);
+    }
+
+    /// Set the host that relayed packets to other networks are sent to,
+    /// it should also be a target to intercept the traffic it sends.
+    pub fn set_gateway(&mut self, gateway: Option<Ipv4Addr>) {
+        let routes = self.node.get_ipv4_routes_mut();
+        match gateway {
+            Some(gateway) => routes.set_default_route(0, IpRouteLink::Indirect(gateway)),
+            None => routes.remove_routes_from(IpRouteSource::Static),
+        }
+    }
+
+    /// Set the number of ticks between two poisoning rounds.
+    pub fn set_poison_interval(&mut self, interval: u64) {
+        self.poison_interval = interval.max(1);
+    }
+
+    /// Enable or disable relaying of intercepted traffic, when disabled
+    /// the intercepted traffic is dropped. Enabled by default.
+    pub fn set_relay(&mut self, enabled: bool) {
+        self.node.set_ipv4_forwarding(enabled);
+    }
+
+    /// Number of IPv4 packets intercepted so far.
+    pub fn intercepted(&self) -> u64 {
+        self.node.ipv4_transit_count()
+    }
+
+    /// Get the real MAC address of a target, as resolved by ARP.
+    fn target_mac(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        let iface = self.node.get_iface::<ServerEthIface>(0)?;
+        match iface.get_arp(ip)?.state {
+            ArpState::Reachable(mac) | ArpState::Permanent(mac) => Some(mac),
+            ArpState::Incomplete => None,
+        }
+    }
+
+    /// Queue forged ARP replies to every resolved target, and ping the
+    /// targets that are not resolved yet.
+    fn poison(&mut self) {
+
+        let mut forged = Vec::new();
+        for &victim_ip in &self.targets {
+            let Some(victim_mac) = self.target_mac(victim_ip) else {
+                let request = Icmpv4Message::EchoRequest { id: 0, seq: 0, data: vec![] };
+                self.node.send_ipv4(Box::new(Ipv4Packet::new(Ipv4Addr::UNSPECIFIED, victim_ip, Ipv4Payload::Icmp(request))));
+                continue;
+            };
+            for &spoofed_ip in &self.targets {
+                if spoofed_ip != victim_ip {
+                    forged.push(Box::new(EthFrame {
+                        src: self.mac_addr,
+                        dst: victim_mac,
+                        payload: EthPayload::Arp(Box::new(ArpIpv4Packet {
+                            op: ArpOp::Reply,
+                            sender_mac: self.mac_addr,
+                            target_mac: victim_mac,
+                            sender_ip: spoofed_ip,
+                            target_ip: victim_ip,
+                        }))
+                    }));
+                }
+            }
+        }
+
+        if let Some(iface) = self.node.get_iface_mut::<ServerEthIface>(0) {
+            for frame in forged {
+                iface.queue_frame(frame);
+            }
+        }
+
+    }
+
+}
+
+impl Node for ArpSpoofNode {
+
+    fn link(&mut self, iface: usize, link: RawLinkHandle) -> bool {
+        self.node.link(iface, link)
+    }
+
+    fn tick(&mut self, links: &mut Links) {
+
+        let time = links.time();
+        if time >= self.next_poison {
+            self.poison();
+            self.next_poison = time + self.poison_interval;
+        }
+
+        self.node.tick(links);
+
+        // Echo replies and other packets for the attacker are discarded.
+        while self.node.recv_ipv4().is_some() {}
+
+    }
+
+}
@@ -0,0 +1,435 @@
+//! Implementation of the 802.1D spanning tree protocol, used by
+//! ethernet switches to block redundant links.
+
+use std::collections::HashMap;
+
+use crate::proto::{BridgeId, StpBpdu, StpConfigBpdu};
+
+
+/// Default bridge priority.
+pub const DEFAULT_BRIDGE_PRIORITY: u16 = 32768;
+/// Default port priority.
+pub const DEFAULT_PORT_PRIORITY: u8 = 128;
+/// Default port path cost, for a 100 Mb/s link.
+pub const DEFAULT_PORT_PATH_COST: u32 = 19;
+/// Maximum port number, the port identifier is made of a 4 bits
+/// priority and a 12 bits port number.
+pub const MAX_PORT_NUMBER: usize = 0xFFF;
+
+
+/// Role of a port in the spanning tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StpPortRole {
+    /// The port that leads to the root bridge.
+    Root,
+    /// The port that forwards toward its segment.
+    Designated,
+    /// A redundant port that is kept blocked.
+    Alternate,
+}
+
+/// Forwarding state of a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StpPortState {
+    /// Only BPDUs are processed.
+    Blocking,
+    /// Preparing to forward, only BPDUs are processed.
+    Listening,
+    /// Source MAC addresses are learned, but frames are not forwarded.
+    Learning,
+    /// Frames are forwarded.
+    Forwarding,
+}
+
+/// A snapshot of a port's spanning tree information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StpPortInfo {
+    pub role: StpPortRole,
+    pub state: StpPortState,
+    pub priority: u8,
+    pub path_cost: u32,
+}
+
+/// Spanning tree state of a bridge, given to `EthSwitch::set_stp`.
+/// All durations are given in ticks of the simulated clock.
+pub struct StpBridge {
+    /// Identifier of this bridge.
+    bridge_id: BridgeId,
+    hello_time: u16,
+    max_age: u16,
+    forward_delay: u16,
+    /// Per-port state.
+    ports: HashMap<usize, StpPort>,
+    /// Best priority vector to the root, the root port is `None` if
+    /// this bridge is the root.
+    root: (PriorityVector, Option<usize>),
+    /// Time of the last hello.
+    last_hello: Option<u64>,
+    /// While this bridge is the root, time when the topology change ends.
+    topology_change_until: u64,
+    /// True when a topology change notification must be sent to the root.
+    tcn_pending: bool,
+    /// True if the last BPDU received on the root port had a topology change.
+    topology_change_seen: bool,
+    /// True when the MAC table needs to be flushed.
+    flush_pending: bool,
+}
+
+/// Internal state of a port.
+struct StpPort {
+    priority: u8,
+    path_cost: u32,
+    role: StpPortRole,
+    state: StpPortState,
+    /// Time of the last state change.
+    state_time: u64,
+    /// The best information received on this port, if not expired.
+    info: Option<PortInfo>,
+    /// True when a topology change notification must be acknowledged.
+    tc_ack: bool,
+}
+
+/// Information received from the designated bridge of a port's segment.
+struct PortInfo {
+    vector: PriorityVector,
+    message_age: u16,
+    topology_change: bool,
+    /// Time when this information expires.
+    expire_time: u64,
+}
+
+/// Spanning tree priority vector, lower is better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct PriorityVector {
+    root_id: BridgeId,
+    root_path_cost: u32,
+    bridge_id: BridgeId,
+    port_id: u16,
+}
+
+impl StpBridge {
+
+    pub fn new(bridge_id: BridgeId) -> Self {
+        Self {
+            bridge_id,
+            hello_time: 2,
+            max_age: 20,
+            forward_delay: 15,
+            ports: HashMap::new(),
+            root: (PriorityVector {
+                root_id: bridge_id,
+                root_path_cost: 0,
+                bridge_id,
+                port_id: 0,
+            }, None),
+            last_hello: None,
+            topology_change_until: 0,
+            tcn_pending: false,
+            topology_change_seen: false,
+            flush_pending: false,
+        }
+    }
+
+    /// Set the protocol timers, in ticks.
+    pub fn set_timers(&mut self, hello_time: u16, max_age: u16, forward_delay: u16) {
+        self.hello_time = hello_time.max(1);
+        self.max_age = max_age;
+        self.forward_delay = forward_delay;
+    }
+
+    #[inline]
+    pub fn bridge_id(&self) -> BridgeId {
+        self.bridge_id
+    }
+
+    /// Get the identifier of the current root bridge.
+    #[inline]
+    pub fn root_id(&self) -> BridgeId {
+        self.root.0.root_id
+    }
+
+    /// Get the cost of the path to the root bridge.
+    #[inline]
+    pub fn root_path_cost(&self) -> u32 {
+        self.root.0.root_path_cost
+    }
+
+    /// Get the root port, `None` if this bridge is the root.
+    #[inline]
+    pub fn root_port(&self) -> Option<usize> {
+        self.root.1
+    }
+
+    #[inline]
+    pub fn is_root(&self) -> bool {
+        self.root.1.is_none()
+    }
+
+    /// Set the priority of a port, only the 4 most significant bits are
+    /// used so it should be a multiple of 16. Panics if the port number
+    /// is above `MAX_PORT_NUMBER`.
+    pub fn set_port_priority(&mut self, iface: usize, priority: u8) {
+        self.port_mut(iface).priority = priority;
+    }
+
+    /// Set the path cost of a port. Panics if the port number is above
+    /// `MAX_PORT_NUMBER`.
+    pub fn set_port_path_cost(&mut self, iface: usize, path_cost: u32) {
+        self.port_mut(iface).path_cost = path_cost;
+    }
+
+    /// Get the spanning tree information of a port.
+    pub fn port_info(&self, iface: usize) -> Option<StpPortInfo> {
+        self.ports.get(&iface).map(|port| StpPortInfo {
+            role: port.role,
+            state: port.state,
+            priority: port.priority,
+            path_cost: port.path_cost,
+        })
+    }
+
+    /// Get the state of a port, ports unknown to the protocol are blocking.
+    pub fn port_state(&self, iface: usize) -> StpPortState {
+        self.ports.get(&iface).map(|port| port.state).unwrap_or(StpPortState::Blocking)
+    }
+
+    /// Register a port, ports start in blocking state. Returns `false`
+    /// if the port number is above `MAX_PORT_NUMBER`.
+    pub(crate) fn add_port(&mut self, iface: usize) -> bool {
+        if iface > MAX_PORT_NUMBER {
+            return false;
+        }
+        self.port_mut(iface);
+        true
+    }
+
+    /// Unregister a port.
+    pub(crate) fn remove_port(&mut self, iface: usize) {
+        self.ports.remove(&iface);
+    }
+
+    fn port_mut(&mut self, iface: usize) -> &mut StpPort {
+        assert!(iface <= MAX_PORT_NUMBER, "interface {iface} can't be a spanning tree port");
+        self.ports.entry(iface).or_insert_with(|| StpPort {
+            priority: DEFAULT_PORT_PRIORITY,
+            path_cost: DEFAULT_PORT_PATH_COST,
+            role: StpPortRole::Designated,
+            state: StpPortState::Blocking,
+            state_time: 0,
+            info: None,
+            tc_ack: false,
+        })
+    }
+
+    fn port_id(iface: usize, port: &StpPort) -> u16 {
+        ((port.priority as u16 & 0xF0) << 8) | iface as u16
+    }
+
+    /// Return `true` once if the MAC table needs to be flushed.
+    pub(crate) fn take_flush(&mut self) -> bool {
+        std::mem::take(&mut self.flush_pending)
+    }
+
+    /// Process a BPDU received on a port.
+    pub(crate) fn recv_bpdu(&mut self, iface: usize, bpdu: &StpBpdu, time: u64) {
+
+        let is_root_port = self.root.1 == Some(iface);
+        let is_root = self.is_root();
+        let Some(port) = self.ports.get_mut(&iface) else { return };
+
+        match bpdu {
+            StpBpdu::Config(config) => {
+
+                if config.message_age >= config.max_age {
+                    return;
+                }
+
+                let vector = PriorityVector {
+                    root_id: config.root_id,
+                    root_path_cost: config.root_path_cost,
+                    bridge_id: config.bridge_id,
+                    port_id: config.port_id,
+                };
+
+                // The information is kept if better than the current one, or
+                // if it comes from the same designated port.
+                let accept = match &port.info {
+                    None => true,
+                    Some(info) => {
+                        vector <= info.vector ||
+                        (vector.bridge_id, vector.port_id) == (info.vector.bridge_id, info.vector.port_id)
+                    }
+                };
+
+                if accept {
+                    port.info = Some(PortInfo {
+                        vector,
+                        message_age: config.message_age,
+                        topology_change: config.topology_change,
+                        expire_time: time + (config.max_age - config.message_age) as u64,
+                    });
+                }
+
+                if is_root_port {
+                    if config.topology_change_ack {
+                        self.tcn_pending = false;
+                    }
+                    if config.topology_change && !self.topology_change_seen {
+                        self.flush_pending = true;
+                    }
+                    self.topology_change_seen = config.topology_change;
+                }
+
+            }
+            StpBpdu::Tcn => {
+                if port.role == StpPortRole::Designated {
+                    port.tc_ack = true;
+                    self.topology_change(time, is_root);
+                }
+            }
+        }
+
+    }
+
+    /// Handle a topology change detected or notified to this bridge.
+    fn topology_change(&mut self, time: u64, is_root: bool) {
+        self.flush_pending = true;
+        if is_root {
+            self.topology_change_until = time + (self.max_age + self.forward_delay) as u64;
+        } else {
+            self.tcn_pending = true;
+        }
+    }
+
+    /// Run the protocol, returning BPDUs to send on ports.
+    pub(crate) fn tick(&mut self, time: u64) -> Vec<(usize, StpBpdu)> {
+
+        // Discard expired information.
+        for port in self.ports.values_mut() {
+            if port.info.as_ref().is_some_and(|info| info.expire_time <= time) {
+                port.info = None;
+            }
+        }
+
+        // Root election, this bridge is the root unless a port received
+        // a better vector.
+        let own = PriorityVector {
+            root_id: self.bridge_id,
+            root_path_cost: 0,
+            bridge_id: self.bridge_id,
+            port_id: 0,
+        };
+
+        let mut root = (own, None);
+        let mut root_key = (own, 0);
+        for (&iface, port) in &self.ports {
+            if let Some(info) = &port.info {
+                let vector = PriorityVector {
+                    root_path_cost: info.vector.root_path_cost + port.path_cost,
+                    ..info.vector
+                };
+                // Ties are broken with the receiving port identifier.
+                let key = (vector, Self::port_id(iface, port));
+                if vector.root_id < self.bridge_id && (root.1.is_none() || key < root_key) {
+                    root = (vector, Some(iface));
+                    root_key = key;
+                }
+            }
+        }
+
+        if root.1.is_none() && self.root.1.is_some() {
+            // We become the root, forget about the previous root's topology change.
+            self.topology_change_seen = false;
+            self.tcn_pending = false;
+        }
+        self.root = root;
+
+        let is_root = self.is_root();
+        let mut changed = false;
+
+        for (&iface, port) in &mut self.ports {
+
+            let designated = PriorityVector {
+                root_id: root.0.root_id,
+                root_path_cost: root.0.root_path_cost,
+                bridge_id: self.bridge_id,
+                port_id: Self::port_id(iface, port),
+            };
+
+            port.role = if root.1 == Some(iface) {
+                StpPortRole::Root
+            } else if port.info.as_ref().is_none_or(|info| designated < info.vector) {
+                StpPortRole::Designated
+            } else {
+                StpPortRole::Alternate
+            };
+
+            let elapsed = time.saturating_sub(port.state_time);
+            let next_state = match (port.role, port.state) {
+                (StpPortRole::Alternate, _) => StpPortState::Blocking,
+                (_, StpPortState::Blocking) => StpPortState::Listening,
+                (_, StpPortState::Listening) if elapsed >= self.forward_delay as u64 => StpPortState::Learning,
+                (_, StpPortState::Learning) if elapsed >= self.forward_delay as u64 => StpPortState::Forwarding,
+                (_, state) => state,
+            };
+
+            if next_state != port.state {
+                if next_state == StpPortState::Forwarding || port.state == StpPortState::Forwarding {
+                    changed = true;
+                }
+                port.state = next_state;
+                port.state_time = time;
+            }
+
+        }
+
+        if changed {
+            self.topology_change(time, is_root);
+        }
+
+        let mut bpdus = Vec::new();
+
+        if self.last_hello.is_some_and(|last| time < last + self.hello_time as u64) {
+            return bpdus;
+        }
+
+        self.last_hello = Some(time);
+
+        let (message_age, topology_change) = match root.1 {
+            None => (0, time < self.topology_change_until),
+            Some(root_port) => {
+                let info = self.ports[&root_port].info.as_ref().unwrap();
+                (info.message_age + 1, info.topology_change)
+            }
+        };
+
+        if message_age < self.max_age {
+            for (&iface, port) in &mut self.ports {
+                if port.role == StpPortRole::Designated {
+                    bpdus.push((iface, StpBpdu::Config(StpConfigBpdu {
+                        topology_change,
+                        topology_change_ack: std::mem::take(&mut port.tc_ack),
+                        root_id: root.0.root_id,
+                        root_path_cost: root.0.root_path_cost,
+                        bridge_id: self.bridge_id,
+                        port_id: Self::port_id(iface, port),
+                        message_age,
+                        max_age: self.max_age,
+                        hello_time: self.hello_time,
+                        forward_delay: self.forward_delay,
+                    })));
+                }
+            }
+        }
+
+        if self.tcn_pending {
+            if let Some(root_port) = root.1 {
+                bpdus.push((root_port, StpBpdu::Tcn));
+            }
+        }
+
+        bpdus
+
+    }
+
+}
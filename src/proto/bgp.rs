@@ -0,0 +1,304 @@
+use super::{Ipv4Addr, IpAddrExt, IpPrefix};
+
+
+/// TCP port where BGP speakers listen.
+pub const BGP_PORT: u16 = 179;
+/// Default hold time of sessions, in ticks.
+pub const BGP_HOLD_TIME: u16 = 90;
+
+/// Length of the header of BGP messages, in bytes.
+const BGP_HEADER_LEN: usize = 19;
+
+
+/// A BGP-4 message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BgpMessage {
+    Open {
+        asn: u16,
+        hold_time: u16,
+        router_id: Ipv4Addr,
+    },
+    /// Routes withdrawn by the sender, and routes announced with the given
+    /// path attributes, which are required if some routes are announced.
+    Update {
+        withdrawn: Vec<IpPrefix<Ipv4Addr>>,
+        attrs: Option<BgpPathAttrs>,
+        nlri: Vec<IpPrefix<Ipv4Addr>>,
+    },
+    /// An error, the session is closed after this message.
+    Notification {
+        code: u8,
+        subcode: u8,
+    },
+    Keepalive,
+}
+
+/// Path attributes of routes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BgpPathAttrs {
+    pub origin: BgpOrigin,
+    /// Sequence of autonomous systems traversed by the announcement, the
+    /// last one originated the route.
+    pub as_path: Vec<u16>,
+    pub next_hop: Ipv4Addr,
+    /// Multi-exit discriminator, a lower value is preferred by the
+    /// neighbor autonomous system.
+    pub med: Option<u32>,
+    /// Preference of the route in the autonomous system, only sent to
+    /// internal peers.
+    pub local_pref: Option<u32>,
+}
+
+/// Origin of a route, a lower origin is preferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BgpOrigin {
+    Igp,
+    Egp,
+    Incomplete,
+}
+
+/// Codes of notification messages.
+pub mod bgp_error {
+    pub const MESSAGE_HEADER: u8 = 1;
+    pub const OPEN_MESSAGE: u8 = 2;
+    pub const UPDATE_MESSAGE: u8 = 3;
+    pub const HOLD_TIMER_EXPIRED: u8 = 4;
+    pub const FSM: u8 = 5;
+    pub const CEASE: u8 = 6;
+}
+
+impl BgpMessage {
+
+    /// Get the length of the message at the start of the given data, if
+    /// its header has been received.
+    pub fn frame_len(data: &[u8]) -> Option<usize> {
+        (data.len() >= BGP_HEADER_LEN).then(|| u16::from_be_bytes([data[16], data[17]]) as usize)
+    }
+
+    /// Encode the message.
+    pub fn encode(&self) -> Vec<u8> {
+
+        let mut data = vec![0xFF; 16];
+        data.extend([0, 0]);
+
+        match self {
+            BgpMessage::Open { asn, hold_time, router_id } => {
+                data.push(1);
+                data.push(4);
+                data.extend(asn.to_be_bytes());
+                data.extend(hold_time.to_be_bytes());
+                data.extend(router_id.octets());
+                data.push(0);
+            }
+            BgpMessage::Update { withdrawn, attrs, nlri } => {
+                data.push(2);
+                let mut withdrawn_data = Vec::new();
+                for prefix in withdrawn {
+                    encode_prefix(&mut withdrawn_data, *prefix);
+                }
+                data.extend((withdrawn_data.len() as u16).to_be_bytes());
+                data.extend(withdrawn_data);
+                let mut attrs_data = Vec::new();
+                if let Some(attrs) = attrs {
+                    attrs.encode(&mut attrs_data);
+                }
+                data.extend((attrs_data.len() as u16).to_be_bytes());
+                data.extend(attrs_data);
+                for prefix in nlri {
+                    encode_prefix(&mut data, *prefix);
+                }
+            }
+            BgpMessage::Notification { code, subcode } => {
+                data.push(3);
+                data.extend([*code, *subcode]);
+            }
+            BgpMessage::Keepalive => {
+                data.push(4);
+            }
+        }
+
+        let len = (data.len() as u16).to_be_bytes();
+        data[16..18].copy_from_slice(&len);
+        data
+
+    }
+
+    /// Decode a whole message, return `None` if it is invalid.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+
+        if data.len() < BGP_HEADER_LEN || data[..16] != [0xFF; 16] || Self::frame_len(data)? != data.len() {
+            return None;
+        }
+
+        let body = &data[BGP_HEADER_LEN..];
+        match data[18] {
+            1 => {
+                let &[4, asn0, asn1, hold0, hold1, id0, id1, id2, id3, _] = body else { return None };
+                Some(BgpMessage::Open {
+                    asn: u16::from_be_bytes([asn0, asn1]),
+                    hold_time: u16::from_be_bytes([hold0, hold1]),
+                    router_id: Ipv4Addr::new(id0, id1, id2, id3),
+                })
+            }
+            2 => {
+                let mut reader = Reader(body);
+                let withdrawn_len = reader.u16()? as usize;
+                let mut withdrawn_reader = Reader(reader.take(withdrawn_len)?);
+                let mut withdrawn = Vec::new();
+                while !withdrawn_reader.0.is_empty() {
+                    withdrawn.push(withdrawn_reader.prefix()?);
+                }
+                let attrs_len = reader.u16()? as usize;
+                let attrs = match attrs_len {
+                    0 => None,
+                    _ => Some(BgpPathAttrs::decode(reader.take(attrs_len)?)?),
+                };
+                let mut nlri = Vec::new();
+                while !reader.0.is_empty() {
+                    nlri.push(reader.prefix()?);
+                }
+                if !nlri.is_empty() && attrs.is_none() {
+                    return None;
+                }
+                Some(BgpMessage::Update { withdrawn, attrs, nlri })
+            }
+            3 => {
+                let &[code, subcode, ..] = body else { return None };
+                Some(BgpMessage::Notification { code, subcode })
+            }
+            4 if body.is_empty() => Some(BgpMessage::Keepalive),
+            _ => None,
+        }
+
+    }
+
+}
+
+impl BgpPathAttrs {
+
+    fn encode(&self, data: &mut Vec<u8>) {
+
+        // Well-known transitive attributes.
+        data.extend([0x40, 1, 1, self.origin as u8]);
+        data.extend([0x40, 2, 2 + 2 * self.as_path.len() as u8]);
+        if !self.as_path.is_empty() {
+            // A single AS_SEQUENCE segment.
+            data.extend([2, self.as_path.len() as u8]);
+            for asn in &self.as_path {
+                data.extend(asn.to_be_bytes());
+            }
+        } else {
+            data.truncate(data.len() - 1);
+            data.push(0);
+        }
+        data.extend([0x40, 3, 4]);
+        data.extend(self.next_hop.octets());
+
+        // Optional non-transitive attribute.
+        if let Some(med) = self.med {
+            data.extend([0x80, 4, 4]);
+            data.extend(med.to_be_bytes());
+        }
+
+        if let Some(local_pref) = self.local_pref {
+            data.extend([0x40, 5, 4]);
+            data.extend(local_pref.to_be_bytes());
+        }
+
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+
+        let mut reader = Reader(data);
+        let mut origin = None;
+        let mut as_path = None;
+        let mut next_hop = None;
+        let mut med = None;
+        let mut local_pref = None;
+
+        while !reader.0.is_empty() {
+            let flags = reader.u8()?;
+            let kind = reader.u8()?;
+            let len = if flags & 0x10 != 0 { reader.u16()? as usize } else { reader.u8()? as usize };
+            let mut value = Reader(reader.take(len)?);
+            match kind {
+                1 => {
+                    origin = Some(match value.u8()? {
+                        0 => BgpOrigin::Igp,
+                        1 => BgpOrigin::Egp,
+                        2 => BgpOrigin::Incomplete,
+                        _ => return None,
+                    });
+                }
+                2 => {
+                    let mut path = Vec::new();
+                    while !value.0.is_empty() {
+                        let _segment_kind = value.u8()?;
+                        let count = value.u8()?;
+                        for _ in 0..count {
+                            path.push(value.u16()?);
+                        }
+                    }
+                    as_path = Some(path);
+                }
+                3 => next_hop = Some(Ipv4Addr::from(value.u32()?)),
+                4 => med = Some(value.u32()?),
+                5 => local_pref = Some(value.u32()?),
+                // Unknown attributes are ignored.
+                _ => {}
+            }
+        }
+
+        Some(BgpPathAttrs {
+            origin: origin?,
+            as_path: as_path?,
+            next_hop: next_hop?,
+            med,
+            local_pref,
+        })
+
+    }
+
+}
+
+fn encode_prefix(data: &mut Vec<u8>, prefix: IpPrefix<Ipv4Addr>) {
+    let len = prefix.prefix_len();
+    data.push(len);
+    data.extend(&prefix.ip().octets()[..len.div_ceil(8) as usize]);
+}
+
+/// A cursor over big-endian data.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (value, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(value)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn prefix(&mut self) -> Option<IpPrefix<Ipv4Addr>> {
+        let len = self.u8()?;
+        if len > 32 {
+            return None;
+        }
+        let mut octets = [0; 4];
+        let bytes = self.take(len.div_ceil(8) as usize)?;
+        octets[..bytes.len()].copy_from_slice(bytes);
+        Some(Ipv4Addr::from(octets).take_prefix(len))
+    }
+
+}
@@ -2,10 +2,14 @@ use std::fmt;
 
 use super::{
     Ipv4Packet, ArpIpv4Packet, Ipv4Addr,
-    Ipv6Addr,
+    Ipv6Addr, StpBpdu, LacpDu,
 };
 
 
+/// Length of the ethernet header, without VLAN tag, in bytes.
+pub const ETH_HEADER_LEN: usize = 14;
+
+
 #[derive(Clone)]
 pub struct EthFrame {
     pub src: MacAddr,
@@ -24,10 +28,58 @@ pub enum EthPayload {
     },
     Arp(Box<ArpIpv4Packet>),
     Ipv4(Box<Ipv4Packet>),
+    Bpdu(Box<StpBpdu>),
+    Lacp(Box<LacpDu>),
+}
+
+impl EthPayload {
+
+    /// Wrap this payload in a VLAN tag. 
+    /// 
+    /// # Panics
+    /// 
+    /// If this payload is already tagged, QinQ is not supported.
+    pub fn with_vlan(self, vlan_id: u16) -> Self {
+        assert!(!matches!(self, EthPayload::Vlan { .. }), "payload is already tagged");
+        EthPayload::Vlan { vlan_id, inner: Box::new(self) }
+    }
+
+    /// Get the VLAN identifier if this payload is tagged.
+    pub fn vlan_id(&self) -> Option<u16> {
+        match *self {
+            EthPayload::Vlan { vlan_id, .. } => Some(vlan_id),
+            _ => None,
+        }
+    }
+
+    /// Size of the payload in bytes, including VLAN tags.
+    pub fn size(&self) -> usize {
+        match self {
+            EthPayload::Custom(data) => data.len(),
+            EthPayload::Vlan { inner, .. } => 4 + inner.size(),
+            EthPayload::Arp(_) => 28,
+            EthPayload::Ipv4(packet) => packet.size(),
+            EthPayload::Bpdu(bpdu) => match **bpdu {
+                StpBpdu::Config(_) => 35,
+                StpBpdu::Tcn => 4,
+            },
+            EthPayload::Lacp(_) => 110,
+        }
+    }
+
+    /// Return `true` if this payload is valid, it is invalid if a
+    /// VLAN tag is nested in another one.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            EthPayload::Vlan { inner, .. } => !matches!(**inner, EthPayload::Vlan { .. }),
+            _ => true,
+        }
+    }
+
 }
 
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MacAddr(pub [u8; 6]);
 
 impl MacAddr {
@@ -36,6 +88,12 @@ impl MacAddr {
     
     pub const ZERO: Self = Self([0, 0, 0, 0, 0, 0]);
 
+    /// Multicast address used by bridges to send spanning tree BPDUs.
+    pub const BRIDGE_GROUP: Self = Self([0x01, 0x80, 0xC2, 0, 0, 0]);
+
+    /// Multicast address used by slow protocols, such as LACP.
+    pub const SLOW_PROTOCOLS: Self = Self([0x01, 0x80, 0xC2, 0, 0, 0x02]);
+
     /// Create a new MAC address based on a multicast IPv4 address.
     pub const fn from_multicast_ipv4(ip: Ipv4Addr) -> Self {
         let o = ip.octets();
@@ -66,6 +124,15 @@ impl MacAddr {
 
 }
 
+impl EthFrame {
+
+    /// Size of the frame in bytes, header included.
+    pub fn size(&self) -> usize {
+        ETH_HEADER_LEN + self.payload.size()
+    }
+
+}
+
 impl fmt::Debug for EthFrame {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("EthFrame")
@@ -0,0 +1,35 @@
+use super::Ipv4Packet;
+
+
+/// IP protocol number of GRE.
+pub const GRE_PROTOCOL: u8 = 47;
+/// IP protocol number of IP-in-IP encapsulation.
+pub const IPIP_PROTOCOL: u8 = 4;
+
+
+/// A GRE packet, encapsulating a packet of another protocol.
+#[derive(Debug, Clone)]
+pub struct GrePacket {
+    /// Key identifying a flow of packets, for example a tunnel when several
+    /// tunnels share the same endpoints.
+    pub key: Option<u32>,
+    pub payload: GrePayload,
+}
+
+/// Packet encapsulated by GRE.
+#[derive(Debug, Clone)]
+pub enum GrePayload {
+    Ipv4(Box<Ipv4Packet>),
+}
+
+impl GrePacket {
+
+    /// Size of the packet in bytes, header included.
+    pub fn size(&self) -> usize {
+        let header = if self.key.is_some() { 8 } else { 4 };
+        header + match &self.payload {
+            GrePayload::Ipv4(packet) => packet.size(),
+        }
+    }
+
+}
@@ -0,0 +1,63 @@
+use super::Ipv4Packet;
+
+
+/// Length of the header of ICMP messages, in bytes.
+pub const ICMP_HEADER_LEN: usize = 8;
+
+/// An ICMP message for IPv4.
+#[derive(Debug, Clone)]
+pub enum Icmpv4Message {
+    EchoRequest {
+        id: u16,
+        seq: u16,
+        data: Vec<u8>,
+    },
+    EchoReply {
+        id: u16,
+        seq: u16,
+        data: Vec<u8>,
+    },
+    /// The packet couldn't be delivered to its destination, the original
+    /// packet is included for the sender to identify it.
+    DestinationUnreachable {
+        code: Icmpv4Unreachable,
+        original: Box<Ipv4Packet>,
+    },
+}
+
+/// Reason of an ICMP destination unreachable message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv4Unreachable {
+    Net,
+    Host,
+    Protocol,
+    Port,
+    /// The packet is bigger than the MTU of the next link and can't be
+    /// fragmented, the MTU is given for path MTU discovery.
+    FragmentationNeeded {
+        mtu: u16,
+    },
+}
+
+impl Icmpv4Message {
+
+    /// Size of the message in bytes. Like on real networks, error messages
+    /// only account for the header and the first 8 bytes of the payload of
+    /// the original packet.
+    pub fn size(&self) -> usize {
+        match self {
+            Icmpv4Message::EchoRequest { data, .. } |
+            Icmpv4Message::EchoReply { data, .. } => ICMP_HEADER_LEN + data.len(),
+            Icmpv4Message::DestinationUnreachable { original, .. } => {
+                ICMP_HEADER_LEN + original.size().min(super::IPV4_HEADER_LEN + 8)
+            }
+        }
+    }
+
+    /// Return `true` if this message is an error message, no error message
+    /// is sent in response to an error message.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Icmpv4Message::DestinationUnreachable { .. })
+    }
+
+}
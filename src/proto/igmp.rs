@@ -0,0 +1,150 @@
+use super::Ipv4Addr;
+use std::fmt;
+
+
+/// The all-systems group, general queries are sent to it.
+pub const IGMP_ALL_SYSTEMS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 1);
+/// The all-routers group, IGMPv2 leave messages are sent to it.
+pub const IGMP_ALL_ROUTERS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 2);
+/// The group of IGMPv3 capable routers, IGMPv3 reports are sent to it.
+pub const IGMPV3_ROUTERS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 22);
+
+/// Default interval between two general queries, in ticks.
+pub const IGMP_QUERY_INTERVAL: u64 = 60;
+/// Default maximum response time of general queries, in ticks.
+pub const IGMP_QUERY_RESPONSE_TIME: u8 = 10;
+/// Default maximum response time of group-specific queries, in ticks.
+pub const IGMP_LAST_MEMBER_QUERY_TIME: u8 = 2;
+/// Time after which a group without report is considered to have
+/// no member, in ticks.
+pub const IGMP_GROUP_MEMBERSHIP_INTERVAL: u64 = 2 * IGMP_QUERY_INTERVAL + IGMP_QUERY_RESPONSE_TIME as u64;
+
+
+/// Return `true` if the group is in the local network control block
+/// (224.0.0.0/24), such groups are never reported nor forwarded.
+pub fn is_local_multicast_group(group: Ipv4Addr) -> bool {
+    let [a, b, c, _] = group.octets();
+    (a, b, c) == (224, 0, 0)
+}
+
+/// An IGMP message.
+#[derive(Clone, PartialEq, Eq)]
+pub enum IgmpMessage {
+    /// A membership query, general if the group is unspecified, and
+    /// group-specific otherwise. Sources are only used by IGMPv3.
+    Query {
+        group: Ipv4Addr,
+        /// Maximum delay of the responses, in ticks.
+        max_resp_time: u8,
+        sources: Vec<Ipv4Addr>,
+    },
+    /// An IGMPv2 membership report.
+    V2Report {
+        group: Ipv4Addr,
+    },
+    /// An IGMPv2 leave group message.
+    Leave {
+        group: Ipv4Addr,
+    },
+    /// An IGMPv3 membership report.
+    V3Report {
+        records: Vec<IgmpGroupRecord>,
+    },
+}
+
+impl IgmpMessage {
+
+    /// Size of the message in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            IgmpMessage::Query { sources, .. } if sources.is_empty() => 8,
+            IgmpMessage::Query { sources, .. } => 12 + 4 * sources.len(),
+            IgmpMessage::V2Report { .. } | IgmpMessage::Leave { .. } => 8,
+            IgmpMessage::V3Report { records } => {
+                8 + records.iter().map(|record| 8 + 4 * record.sources.len()).sum::<usize>()
+            }
+        }
+    }
+
+    /// Return `true` if this is a general query.
+    pub fn is_general_query(&self) -> bool {
+        matches!(self, IgmpMessage::Query { group, .. } if group.is_unspecified())
+    }
+
+}
+
+/// A group record of an IGMPv3 report.
+#[derive(Clone, PartialEq, Eq)]
+pub struct IgmpGroupRecord {
+    pub kind: IgmpRecordKind,
+    pub group: Ipv4Addr,
+    pub sources: Vec<Ipv4Addr>,
+}
+
+impl IgmpGroupRecord {
+
+    /// Return `true` if this record means that the sender wants to
+    /// receive traffic of the group, a record including no source
+    /// means the sender left the group.
+    pub fn is_join(&self) -> bool {
+        match self.kind {
+            IgmpRecordKind::ModeIsExclude |
+            IgmpRecordKind::ChangeToExclude => true,
+            IgmpRecordKind::ModeIsInclude |
+            IgmpRecordKind::ChangeToInclude |
+            IgmpRecordKind::AllowNewSources => !self.sources.is_empty(),
+            IgmpRecordKind::BlockOldSources => false,
+        }
+    }
+
+}
+
+/// Kind of an IGMPv3 group record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgmpRecordKind {
+    ModeIsInclude,
+    ModeIsExclude,
+    ChangeToInclude,
+    ChangeToExclude,
+    AllowNewSources,
+    BlockOldSources,
+}
+
+/// IGMP version used by a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IgmpVersion {
+    V2,
+    #[default]
+    V3,
+}
+
+impl fmt::Debug for IgmpMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query { group, max_resp_time, sources } => f.debug_struct("Query")
+                .field("group", &format_args!("{group}"))
+                .field("max_resp_time", max_resp_time)
+                .field("sources", sources)
+                .finish(),
+            Self::V2Report { group } => f.debug_struct("V2Report")
+                .field("group", &format_args!("{group}"))
+                .finish(),
+            Self::Leave { group } => f.debug_struct("Leave")
+                .field("group", &format_args!("{group}"))
+                .finish(),
+            Self::V3Report { records } => f.debug_struct("V3Report")
+                .field("records", records)
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Debug for IgmpGroupRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IgmpGroupRecord")
+            .field("kind", &self.kind)
+            .field("group", &format_args!("{}", self.group))
+            .field("sources", &self.sources)
+            .finish()
+    }
+}
@@ -1,9 +1,11 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::hash::Hash;
+use std::fmt;
 
 
 /// A trait implemented on both IPv4 and IPv6 to allow taking prefix
 /// of an existing address and compare two address.
-pub trait IpAddrExt: Sized + Eq + Copy {
+pub trait IpAddrExt: Sized + Eq + Copy + Hash {
 
     /// The all-zero address.
     const ZERO: Self;
@@ -22,7 +24,7 @@ impl IpAddrExt for Ipv4Addr {
         debug_assert!(prefix_len <= 32);
         let num: u32 = self.into();
         IpPrefix {
-            addr: (num & (u32::MAX << (32 - prefix_len))).into(),
+            addr: (num & u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)).into(),
             prefix_len,
         }
     }
@@ -35,9 +37,10 @@ impl IpAddrExt for Ipv6Addr {
 
     #[inline]
     fn take_prefix(self, prefix_len: u8) -> IpPrefix<Self> {
+        debug_assert!(prefix_len <= 128);
         let num: u128 = self.into();
         IpPrefix {
-            addr: (num & (u128::MAX << (128 - prefix_len))).into(),
+            addr: (num & u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)).into(),
             prefix_len,
         }
     }
@@ -46,7 +49,7 @@ impl IpAddrExt for Ipv6Addr {
 
 
 /// An IP prefix.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct IpPrefix<T> {
     addr: T,
     prefix_len: u8,
@@ -73,3 +76,9 @@ impl<T: IpAddrExt> IpPrefix<T> {
     }
 
 }
+
+impl<T: fmt::Display> fmt::Debug for IpPrefix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
@@ -1,7 +1,13 @@
 pub use std::net::Ipv4Addr;
 use std::fmt;
 
-use super::UdpDatagram;
+use super::{UdpDatagram, TcpSegment, IgmpMessage, Icmpv4Message, OspfPacket, VrrpPacket, GrePacket};
+
+
+/// Length of the IPv4 header, without options, in bytes.
+pub const IPV4_HEADER_LEN: usize = 20;
+/// Minimum MTU that every IPv4 link must support.
+pub const IPV4_MIN_MTU: u16 = 68;
 
 
 #[derive(Clone)]
@@ -27,6 +33,10 @@ pub struct Ipv4Packet {
     pub dst: Ipv4Addr,
     /// Payload.
     pub payload: Ipv4Payload,
+    /// Mark given by the node handling the packet, used to select a
+    /// routing table. It is not transmitted and is cleared when the
+    /// packet is received.
+    pub mark: u32,
 }
 
 impl Ipv4Packet {
@@ -40,7 +50,66 @@ impl Ipv4Packet {
             ttl: 32,
             src,
             dst,
-            payload
+            payload,
+            mark: 0,
+        }
+    }
+
+    /// Size of the packet in bytes, header included.
+    pub fn size(&self) -> usize {
+        IPV4_HEADER_LEN + self.payload.size()
+    }
+
+    /// Return `true` if this packet is a fragment of a bigger packet.
+    pub fn is_fragmented(&self) -> bool {
+        self.is_fragment || self.fragment_offset != 0
+    }
+
+    /// Split this packet in fragments that fit in the given MTU, this also
+    /// works for packets that are already fragments. Return `None` if the
+    /// MTU is too small for any fragment.
+    pub fn fragment(&self, mtu: u16) -> Option<Vec<Ipv4Packet>> {
+
+        // Fragment data length must be a multiple of 8 bytes, except for 
+        // the last fragment.
+        let chunk_len = (mtu as usize).checked_sub(IPV4_HEADER_LEN)? / 8 * 8;
+        if chunk_len == 0 {
+            return None;
+        }
+
+        let (mut original, more_fragments) = match &self.payload {
+            Ipv4Payload::Fragment(fragment) => (fragment.original.clone(), self.is_fragment),
+            payload => (Some(Box::new(payload.clone())), false),
+        };
+
+        let len = self.payload.size();
+        let mut fragments = Vec::new();
+        let mut offset = 0;
+
+        while offset < len {
+            let fragment_len = chunk_len.min(len - offset);
+            let last = offset + fragment_len == len;
+            fragments.push(Ipv4Packet {
+                is_fragment: !last || more_fragments,
+                fragment_offset: self.fragment_offset + (offset / 8) as u16,
+                payload: Ipv4Payload::Fragment(Ipv4Fragment {
+                    len: fragment_len,
+                    original: original.take(),
+                }),
+                ..self.clone_header()
+            });
+            offset += fragment_len;
+        }
+
+        Some(fragments)
+
+    }
+
+    /// Copy the header of this packet, with an empty payload.
+    fn clone_header(&self) -> Ipv4Packet {
+        Ipv4Packet {
+            payload: Ipv4Payload::Custom(Vec::new()),
+            ..*self
         }
     }
 
@@ -51,6 +120,48 @@ impl Ipv4Packet {
 pub enum Ipv4Payload {
     Custom(Vec<u8>),
     Udp(UdpDatagram),
+    Tcp(TcpSegment),
+    Igmp(IgmpMessage),
+    Icmp(Icmpv4Message),
+    Ospf(OspfPacket),
+    Vrrp(VrrpPacket),
+    Gre(GrePacket),
+    /// An IPv4 packet encapsulated in IPv4.
+    Ipip(Box<Ipv4Packet>),
+    /// Data of a fragment.
+    Fragment(Ipv4Fragment),
+}
+
+impl Ipv4Payload {
+
+    /// Size of the payload in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Ipv4Payload::Custom(data) => data.len(),
+            Ipv4Payload::Udp(datagram) => datagram.size(),
+            Ipv4Payload::Tcp(segment) => segment.size(),
+            Ipv4Payload::Igmp(message) => message.size(),
+            Ipv4Payload::Icmp(message) => message.size(),
+            Ipv4Payload::Ospf(packet) => packet.size(),
+            Ipv4Payload::Vrrp(packet) => packet.size(),
+            Ipv4Payload::Gre(packet) => packet.size(),
+            Ipv4Payload::Ipip(packet) => packet.size(),
+            Ipv4Payload::Fragment(fragment) => fragment.len,
+        }
+    }
+
+}
+
+/// Data carried by a fragment. Payloads are not serialized, so the whole
+/// original payload is carried by the first fragment, and other fragments
+/// only carry their length. The original payload is restored when all
+/// fragments are reassembled.
+#[derive(Debug, Clone)]
+pub struct Ipv4Fragment {
+    /// Length of the fragment data in bytes.
+    pub len: usize,
+    /// Original payload, only in the first fragment.
+    pub original: Option<Box<Ipv4Payload>>,
 }
 
 
@@ -65,6 +176,7 @@ impl fmt::Debug for Ipv4Packet {
             .field("src", &format_args!("{}", self.src))
             .field("dst", &format_args!("{}", self.dst))
             .field("payload", &self.payload)
+            .field("mark", &self.mark)
             .finish()
     }
 }
@@ -1 +1,5 @@
 pub use std::net::Ipv6Addr;
+
+
+/// The link-local all-nodes multicast group.
+pub const IPV6_ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xFF02, 0, 0, 0, 0, 0, 0, 1);
@@ -0,0 +1,36 @@
+use super::MacAddr;
+use std::fmt;
+
+
+/// A link aggregation control protocol data unit.
+#[derive(Debug, Clone)]
+pub struct LacpDu {
+    /// Information about the sending port.
+    pub actor: LacpInfo,
+    /// Information about the partner port, as seen by the sender.
+    pub partner: LacpInfo,
+}
+
+/// Information about one end of an aggregated link.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LacpInfo {
+    /// Identifier of the system, usually a MAC address.
+    pub system: MacAddr,
+    /// Aggregation key, ports with the same key can be aggregated.
+    pub key: u16,
+    /// Identifier of the port.
+    pub port: u16,
+    /// True when the port is ready to distribute frames.
+    pub sync: bool,
+}
+
+impl fmt::Debug for LacpInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LacpInfo")
+            .field("system", &format_args!("{}", self.system))
+            .field("key", &self.key)
+            .field("port", &self.port)
+            .field("sync", &self.sync)
+            .finish()
+    }
+}
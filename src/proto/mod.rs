@@ -1,17 +1,41 @@
 // Layer 2 (data link)
 mod eth;
+mod stp;
+mod lacp;
+mod ppp;
 pub use eth::*;
+pub use stp::*;
+pub use lacp::*;
+pub use ppp::*;
 
 // Layer 3 (network)
 mod arp;
 mod ip;
 mod ipv4;
 mod ipv6;
+mod igmp;
+mod icmp;
+mod ospf;
+mod vrrp;
+mod gre;
 pub use arp::*;
 pub use ip::*;
 pub use ipv4::*;
 pub use ipv6::*;
+pub use igmp::*;
+pub use icmp::*;
+pub use ospf::*;
+pub use vrrp::*;
+pub use gre::*;
 
 // Layer 4 (transport)
 mod udp;
+mod tcp;
 pub use udp::*;
+pub use tcp::*;
+
+// Layer 7 (application)
+mod rip;
+mod bgp;
+pub use rip::*;
+pub use bgp::*;
@@ -0,0 +1,169 @@
+use super::Ipv4Addr;
+
+
+/// Group of all OSPF routers, hellos and updates are sent to it.
+pub const OSPF_ALL_ROUTERS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 5);
+/// Group of designated routers.
+pub const OSPF_ALL_DR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 6);
+
+/// Default interval between two hellos, in ticks.
+pub const OSPF_HELLO_INTERVAL: u16 = 10;
+/// Default time after which a neighbor without hello is down, in ticks.
+pub const OSPF_DEAD_INTERVAL: u16 = 40;
+/// Interval between two retransmissions to a neighbor, in ticks.
+pub const OSPF_RXMT_INTERVAL: u64 = 5;
+/// Age after which LSAs are originated again, in ticks.
+pub const OSPF_LS_REFRESH_TIME: u16 = 1800;
+/// Age of LSAs that are removed from databases, in ticks.
+pub const OSPF_MAX_AGE: u16 = 3600;
+
+/// Length of the OSPF header, in bytes.
+const OSPF_HEADER_LEN: usize = 24;
+/// Length of the LSA header, in bytes.
+const OSPF_LSA_HEADER_LEN: usize = 20;
+
+
+/// An OSPFv2 packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OspfPacket {
+    pub router_id: Ipv4Addr,
+    pub area_id: Ipv4Addr,
+    pub message: OspfMessage,
+}
+
+/// Message of an OSPF packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OspfMessage {
+    Hello(OspfHello),
+    /// Headers of the LSAs of the database of the sender. The flag is set
+    /// when the database description of the receiver has been received,
+    /// so it doesn't need to be answered.
+    DatabaseDescription {
+        received: bool,
+        headers: Vec<OspfLsaHeader>,
+    },
+    LinkStateRequest(Vec<OspfLsaKey>),
+    LinkStateUpdate(Vec<OspfLsa>),
+    LinkStateAck(Vec<OspfLsaHeader>),
+}
+
+/// A hello message, used to discover neighbors and elect designated
+/// routers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OspfHello {
+    pub prefix_len: u8,
+    pub hello_interval: u16,
+    pub dead_interval: u16,
+    pub priority: u8,
+    /// Interface address of the designated router, unspecified if none.
+    pub dr: Ipv4Addr,
+    /// Interface address of the backup designated router.
+    pub bdr: Ipv4Addr,
+    /// Router identifiers of neighbors heard on the network.
+    pub neighbors: Vec<Ipv4Addr>,
+}
+
+/// Kind of an LSA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum OspfLsaKind {
+    /// Links of a router.
+    Router,
+    /// Routers attached to a network, originated by its designated router.
+    Network,
+}
+
+/// Identifies an LSA in a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OspfLsaKey {
+    pub kind: OspfLsaKind,
+    /// Router identifier for router LSAs, interface address of the
+    /// designated router for network LSAs.
+    pub id: Ipv4Addr,
+    pub advertising_router: Ipv4Addr,
+}
+
+/// Header of an LSA, identifying an instance of the LSA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OspfLsaHeader {
+    pub key: OspfLsaKey,
+    /// Age in ticks.
+    pub age: u16,
+    pub seq: i32,
+}
+
+impl OspfLsaHeader {
+
+    /// Compare two instances of an LSA, the newer one is greater.
+    pub fn cmp_instance(&self, other: &Self) -> std::cmp::Ordering {
+        self.seq.cmp(&other.seq)
+            .then_with(|| (self.age >= OSPF_MAX_AGE).cmp(&(other.age >= OSPF_MAX_AGE)))
+    }
+
+}
+
+/// A link state advertisement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OspfLsa {
+    pub header: OspfLsaHeader,
+    pub body: OspfLsaBody,
+}
+
+/// Content of an LSA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OspfLsaBody {
+    Router(Vec<OspfRouterLink>),
+    Network {
+        prefix_len: u8,
+        /// Router identifiers of routers attached to the network.
+        routers: Vec<Ipv4Addr>,
+    },
+}
+
+/// A link of a router LSA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OspfRouterLink {
+    pub kind: OspfLinkKind,
+    /// Interface address of the designated router for transit links,
+    /// network address for stub links.
+    pub id: Ipv4Addr,
+    /// Interface address of the router for transit links, prefix length
+    /// as a mask for stub links.
+    pub data: Ipv4Addr,
+    pub metric: u16,
+}
+
+/// Kind of a link of a router LSA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OspfLinkKind {
+    /// A network with a designated router and other routers.
+    Transit,
+    /// A network without other router.
+    Stub,
+}
+
+impl OspfPacket {
+
+    /// Size of the packet in bytes.
+    pub fn size(&self) -> usize {
+        OSPF_HEADER_LEN + match &self.message {
+            OspfMessage::Hello(hello) => 20 + 4 * hello.neighbors.len(),
+            OspfMessage::DatabaseDescription { headers, .. } => 8 + OSPF_LSA_HEADER_LEN * headers.len(),
+            OspfMessage::LinkStateRequest(keys) => 12 * keys.len(),
+            OspfMessage::LinkStateUpdate(lsas) => 4 + lsas.iter().map(OspfLsa::size).sum::<usize>(),
+            OspfMessage::LinkStateAck(headers) => OSPF_LSA_HEADER_LEN * headers.len(),
+        }
+    }
+
+}
+
+impl OspfLsa {
+
+    /// Size of the LSA in bytes.
+    pub fn size(&self) -> usize {
+        OSPF_LSA_HEADER_LEN + match &self.body {
+            OspfLsaBody::Router(links) => 4 + 12 * links.len(),
+            OspfLsaBody::Network { routers, .. } => 4 + 4 * routers.len(),
+        }
+    }
+
+}
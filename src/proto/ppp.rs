@@ -0,0 +1,108 @@
+use super::{Ipv4Packet, Ipv4Addr};
+
+
+/// Length of the PPP header and trailer in HDLC-like framing: flags,
+/// address, control, protocol and frame check sequence, in bytes.
+pub const PPP_HEADER_LEN: usize = 8;
+
+
+/// A PPP frame on a point-to-point link.
+#[derive(Debug, Clone)]
+pub enum PppFrame {
+    /// Link control protocol, negotiating the link itself.
+    Lcp(PppControlPacket<LcpOption>),
+    /// IP control protocol, negotiating the addresses of both ends.
+    Ipcp(PppControlPacket<IpcpOption>),
+    Ipv4(Box<Ipv4Packet>),
+}
+
+/// A packet of a PPP control protocol, such as LCP or IPCP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PppControlPacket<O> {
+    pub code: PppCode,
+    /// Identifier matching replies to requests.
+    pub identifier: u8,
+    /// Configuration options of configure packets, empty for others.
+    pub options: Vec<O>,
+}
+
+/// Code of a PPP control packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PppCode {
+    ConfigureRequest,
+    /// All options of the request are acknowledged.
+    ConfigureAck,
+    /// Some options of the request have unacceptable values, the values
+    /// to use are given.
+    ConfigureNak,
+    /// Some options of the request are not recognized.
+    ConfigureReject,
+    TerminateRequest,
+    TerminateAck,
+    /// Only used by LCP, to check that the peer is alive.
+    EchoRequest,
+    EchoReply,
+}
+
+/// Configuration option of LCP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LcpOption {
+    /// Maximum receive unit, the maximum size of packets accepted by the
+    /// sender.
+    Mru(u16),
+}
+
+/// Configuration option of IPCP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcpOption {
+    /// Address of the sender, unspecified to ask the peer for one.
+    Address(Ipv4Addr),
+}
+
+impl<O> PppControlPacket<O> {
+
+    pub fn new(code: PppCode, identifier: u8, options: Vec<O>) -> Self {
+        Self { code, identifier, options }
+    }
+
+}
+
+impl LcpOption {
+
+    /// Size of the option in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            LcpOption::Mru(_) => 4,
+        }
+    }
+
+}
+
+impl IpcpOption {
+
+    /// Size of the option in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            IpcpOption::Address(_) => 6,
+        }
+    }
+
+}
+
+impl PppFrame {
+
+    /// Size of the payload of the frame in bytes.
+    pub fn payload_size(&self) -> usize {
+        match self {
+            PppFrame::Lcp(packet) => 4 + packet.options.iter().map(LcpOption::size).sum::<usize>(),
+            PppFrame::Ipcp(packet) => 4 + packet.options.iter().map(IpcpOption::size).sum::<usize>(),
+            PppFrame::Ipv4(packet) => packet.size(),
+        }
+    }
+
+    /// Size of the frame in bytes, header and trailer included.
+    pub fn size(&self) -> usize {
+        PPP_HEADER_LEN + self.payload_size()
+    }
+
+}
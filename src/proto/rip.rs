@@ -0,0 +1,113 @@
+use super::{Ipv4Addr, IpAddrExt, IpPrefix};
+
+
+/// UDP port used by RIP.
+pub const RIP_PORT: u16 = 520;
+/// Multicast group where RIPv2 messages are sent.
+pub const RIP_MULTICAST: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 9);
+/// Metric of unreachable routes.
+pub const RIP_INFINITY: u8 = 16;
+/// Maximum number of entries in a message.
+pub const RIP_MAX_ENTRIES: usize = 25;
+
+
+/// A RIPv2 message, carried in UDP datagrams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RipMessage {
+    pub command: RipCommand,
+    pub entries: Vec<RipEntry>,
+}
+
+/// Command of a RIP message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RipCommand {
+    /// Request for the routing table of the receiver, the whole table is
+    /// requested if there is no entry.
+    Request,
+    /// A part of the routing table of the sender.
+    Response,
+}
+
+/// A route of a RIPv2 message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RipEntry {
+    pub prefix: IpPrefix<Ipv4Addr>,
+    /// Next hop, unspecified if the sender is the next hop.
+    pub next_hop: Ipv4Addr,
+    pub metric: u8,
+}
+
+impl RipMessage {
+
+    /// Encode the message as 16-bit words of UDP data.
+    pub fn encode(&self) -> Vec<u16> {
+
+        let command = match self.command {
+            RipCommand::Request => 1,
+            RipCommand::Response => 2,
+        };
+
+        // Version 2.
+        let mut data = vec![command << 8 | 2, 0];
+
+        if self.command == RipCommand::Request && self.entries.is_empty() {
+            // Address family 0 and infinite metric request the whole table.
+            data.extend([0; 9]);
+            data.push(RIP_INFINITY as u16);
+            return data;
+        }
+
+        for entry in &self.entries {
+            let ip = u32::from(entry.prefix.ip());
+            let mask = u32::MAX.checked_shl(32 - entry.prefix.prefix_len() as u32).unwrap_or(0);
+            let next_hop = u32::from(entry.next_hop);
+            // Address family 2 (IP) and no route tag.
+            data.extend([2, 0]);
+            for word in [ip, mask, next_hop, entry.metric as u32] {
+                data.extend([(word >> 16) as u16, word as u16]);
+            }
+        }
+
+        data
+
+    }
+
+    /// Decode a message from UDP data, return `None` if the data is not
+    /// a valid RIPv2 message.
+    pub fn decode(data: &[u16]) -> Option<Self> {
+
+        let (&[header, _], entries) = data.split_first_chunk::<2>()?;
+        if header & 0xFF != 2 || entries.len() % 10 != 0 {
+            return None;
+        }
+
+        let command = match header >> 8 {
+            1 => RipCommand::Request,
+            2 => RipCommand::Response,
+            _ => return None,
+        };
+
+        let mut message = RipMessage { command, entries: Vec::new() };
+        for entry in entries.chunks_exact(10) {
+            let word = |i: usize| (entry[i] as u32) << 16 | entry[i + 1] as u32;
+            match entry[0] {
+                0 if command == RipCommand::Request => continue,
+                2 => {}
+                _ => return None,
+            }
+            let mask = word(4);
+            if mask.leading_ones() + mask.trailing_zeros() != 32 || word(8) > RIP_INFINITY as u32 {
+                return None;
+            }
+            message.entries.push(RipEntry {
+                prefix: Ipv4Addr::from(word(2)).take_prefix(mask.leading_ones() as u8),
+                next_hop: Ipv4Addr::from(word(6)),
+                metric: word(8) as u8,
+            });
+        }
+
+        Some(message)
+
+    }
+
+}
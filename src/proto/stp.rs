@@ -0,0 +1,71 @@
+use super::MacAddr;
+use std::fmt;
+
+
+/// A bridge protocol data unit, used by the spanning tree protocol.
+#[derive(Debug, Clone)]
+pub enum StpBpdu {
+    /// Configuration BPDU, sent by designated ports.
+    Config(StpConfigBpdu),
+    /// Topology change notification, sent toward the root bridge.
+    Tcn,
+}
+
+#[derive(Clone)]
+pub struct StpConfigBpdu {
+    /// Set by the root bridge while a topology change is in progress.
+    pub topology_change: bool,
+    /// Acknowledge a topology change notification.
+    pub topology_change_ack: bool,
+    /// Identifier of the bridge believed to be the root.
+    pub root_id: BridgeId,
+    /// Cost of the path to the root from the sending bridge.
+    pub root_path_cost: u32,
+    /// Identifier of the sending bridge.
+    pub bridge_id: BridgeId,
+    /// Identifier of the sending port.
+    pub port_id: u16,
+    /// Age of the information since it was sent by the root, in ticks.
+    pub message_age: u16,
+    /// Ticks after which the information is discarded.
+    pub max_age: u16,
+    /// Ticks between two configuration BPDUs.
+    pub hello_time: u16,
+    /// Ticks spent in the listening and learning states.
+    pub forward_delay: u16,
+}
+
+/// A bridge identifier, lower is better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BridgeId {
+    pub priority: u16,
+    pub mac: MacAddr,
+}
+
+impl BridgeId {
+
+    pub const fn new(priority: u16, mac: MacAddr) -> Self {
+        Self { priority, mac }
+    }
+
+}
+
+impl fmt::Debug for StpConfigBpdu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StpConfigBpdu")
+            .field("tc", &self.topology_change)
+            .field("tc_ack", &self.topology_change_ack)
+            .field("root_id", &format_args!("{}", self.root_id))
+            .field("root_path_cost", &self.root_path_cost)
+            .field("bridge_id", &format_args!("{}", self.bridge_id))
+            .field("port_id", &format_args!("{:04X}", self.port_id))
+            .field("message_age", &self.message_age)
+            .finish()
+    }
+}
+
+impl fmt::Display for BridgeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.priority, self.mac)
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+
+/// A TCP segment.
+#[derive(Clone)]
+pub struct TcpSegment {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: TcpFlags,
+    pub window: u16,
+    pub data: Vec<u8>,
+}
+
+impl TcpSegment {
+
+    /// Length of the TCP header, without options, in bytes.
+    pub const HEADER_LEN: usize = 20;
+
+    /// Size of the segment in bytes, header included.
+    pub fn size(&self) -> usize {
+        Self::HEADER_LEN + self.data.len()
+    }
+
+}
+
+/// Flags of a TCP segment.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+
+    pub const FIN: Self = Self(0x01);
+    pub const SYN: Self = Self(0x02);
+    pub const RST: Self = Self(0x04);
+    pub const PSH: Self = Self(0x08);
+    pub const ACK: Self = Self(0x10);
+
+    /// Return `true` if all flags of `other` are set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+}
+
+impl std::ops::BitOr for TcpFlags {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Debug for TcpFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [(Self::FIN, "FIN"), (Self::SYN, "SYN"), (Self::RST, "RST"), (Self::PSH, "PSH"), (Self::ACK, "ACK")];
+        let mut list = f.debug_set();
+        for (flag, name) in names {
+            if self.contains(flag) {
+                list.entry(&format_args!("{name}"));
+            }
+        }
+        list.finish()
+    }
+}
+
+impl fmt::Debug for TcpSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpSegment")
+            .field("src_port", &self.src_port)
+            .field("dst_port", &self.dst_port)
+            .field("seq", &self.seq)
+            .field("ack", &self.ack)
+            .field("flags", &self.flags)
+            .field("window", &self.window)
+            .field("data_len", &self.data.len())
+            .finish()
+    }
+}
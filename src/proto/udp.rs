@@ -6,3 +6,12 @@ pub struct UdpDatagram {
     pub dst_port: u16,
     pub data: Vec<u16>,
 }
+
+impl UdpDatagram {
+
+    /// Size of the datagram in bytes, header included.
+    pub fn size(&self) -> usize {
+        8 + self.data.len() * 2
+    }
+
+}
@@ -0,0 +1,42 @@
+use super::{Ipv4Addr, MacAddr};
+
+
+/// Group of VRRP routers, advertisements are sent to it.
+pub const VRRP_MULTICAST: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 18);
+/// Default interval between two advertisements, in ticks.
+pub const VRRP_ADVERT_INTERVAL: u16 = 1;
+/// Default priority of routers that don't own the virtual address.
+pub const VRRP_DEFAULT_PRIORITY: u8 = 100;
+/// Priority of the router owning the virtual address.
+pub const VRRP_OWNER_PRIORITY: u8 = 255;
+
+
+/// A VRRP advertisement, sent by the master of a virtual router.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VrrpPacket {
+    /// Identifier of the virtual router on the network.
+    pub vrid: u8,
+    /// Priority of the sender, 0 when it stops being the master.
+    pub priority: u8,
+    /// Interval between two advertisements, in ticks.
+    pub advert_interval: u16,
+    pub addresses: Vec<Ipv4Addr>,
+}
+
+impl VrrpPacket {
+
+    /// Size of the packet in bytes.
+    pub fn size(&self) -> usize {
+        8 + 4 * self.addresses.len()
+    }
+
+}
+
+impl MacAddr {
+
+    /// Get the virtual MAC address of a virtual router.
+    pub const fn from_vrrp(vrid: u8) -> Self {
+        Self([0x00, 0x00, 0x5E, 0x00, 0x01, vrid])
+    }
+
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr};
+use netcrab::node::{ServerNode, ServerEthIface, ArpState};
+
+use common::{host, send, run};
+
+
+const A_MAC: MacAddr = MacAddr([2, 0, 0, 0, 0, 1]);
+const B_MAC: MacAddr = MacAddr([2, 0, 0, 0, 0, 2]);
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+fn pair() -> (Network, RcNode<ServerNode>, RcNode<ServerNode>) {
+    let a = host(A_MAC.0, A, None);
+    let b = host(B_MAC.0, B, None);
+    let mut net = Network::new();
+    let (ha, hb) = (net.push(a.clone()), net.push(b.clone()));
+    net.link::<EthFrame>(ha, 0, hb, 0);
+    (net, a, b)
+}
+
+fn get_arp(node: &RcNode<ServerNode>, ip: Ipv4Addr) -> Option<ArpState> {
+    node.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().get_arp(ip).map(|entry| entry.state)
+}
+
+#[test]
+fn resolve_and_age() {
+
+    let (mut net, a, b) = pair();
+
+    send(&a, A, B);
+    net.tick();
+    assert_eq!(get_arp(&a, B), Some(ArpState::Incomplete));
+
+    run(&mut net, 3);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+    assert_eq!(get_arp(&a, B), Some(ArpState::Reachable(B_MAC)));
+    // The requester was learned from the request.
+    assert_eq!(get_arp(&b, A), Some(ArpState::Reachable(A_MAC)));
+
+    // Ages count simulated ticks.
+    let age = a.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().get_arp(B).unwrap().age;
+    run(&mut net, 5);
+    assert_eq!(a.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().get_arp(B).unwrap().age, age + 5);
+
+}
+
+#[test]
+fn static_entries() {
+
+    let (mut net, a, b) = pair();
+    a.borrow_mut().get_iface_mut::<ServerEthIface>(0).unwrap().add_static_arp(B, B_MAC);
+
+    // No request is needed to send to a static entry.
+    send(&a, A, B);
+    run(&mut net, 2);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+    assert_eq!(get_arp(&b, A), None);
+
+    // Static entries are not replaced by ARP traffic.
+    send(&b, B, A);
+    run(&mut net, 3);
+    assert!(a.borrow_mut().recv_ipv4().is_some());
+    assert_eq!(get_arp(&b, A), Some(ArpState::Reachable(A_MAC)));
+    assert_eq!(get_arp(&a, B), Some(ArpState::Permanent(B_MAC)));
+
+    // Flushing only removes learned entries.
+    a.borrow_mut().get_iface_mut::<ServerEthIface>(0).unwrap().flush_arp();
+    b.borrow_mut().get_iface_mut::<ServerEthIface>(0).unwrap().flush_arp();
+    assert_eq!(get_arp(&a, B), Some(ArpState::Permanent(B_MAC)));
+    assert_eq!(get_arp(&b, A), None);
+
+    assert!(a.borrow_mut().get_iface_mut::<ServerEthIface>(0).unwrap().remove_arp(B));
+    assert_eq!(a.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().arp_entries().count(), 0);
+
+}
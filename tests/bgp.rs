@@ -0,0 +1,118 @@
+mod common;
+
+use netcrab::net::{Network, RcNode, LinkId};
+use netcrab::proto::{EthFrame, Ipv4Addr, IpAddrExt, IpPrefix};
+use netcrab::node::{ServerNode, Bgp, BgpPeerConf, BgpPeerState, BgpPolicy, BgpPolicyRule, BgpPolicyAction};
+
+use common::{router, ip, run, run_until};
+
+
+fn prefix(a: u8, b: u8, c: u8, d: u8, len: u8) -> IpPrefix<Ipv4Addr> {
+    ip(a, b, c, d).take_prefix(len)
+}
+
+fn bgp_router(id: u8, asn: u16, ifaces: &[(usize, Ipv4Addr)], peers: &[(Ipv4Addr, u16)]) -> RcNode<ServerNode> {
+    let mut node = router(id, ifaces);
+    let mut bgp = Bgp::new(asn, ip(id, id, id, id));
+    for &(peer, peer_asn) in peers {
+        bgp.add_peer(BgpPeerConf::new(peer, peer_asn));
+    }
+    node.set_bgp(Some(bgp));
+    RcNode::new(node)
+}
+
+/// Three autonomous systems in a triangle, C originates a prefix:
+///
+/// ```text
+/// A (100) ---- B (200)
+///      \      /
+///      C (300) - 10.3.0.0/16
+/// ```
+struct BgpTriangle {
+    net: Network,
+    a: RcNode<ServerNode>,
+    b: RcNode<ServerNode>,
+    link_ac: LinkId,
+}
+
+impl BgpTriangle {
+
+    const A_TO_B: Ipv4Addr = Ipv4Addr::new(10, 0, 12, 2);
+    const A_TO_C: Ipv4Addr = Ipv4Addr::new(10, 0, 13, 3);
+    const B_TO_A: Ipv4Addr = Ipv4Addr::new(10, 0, 12, 1);
+
+    fn new() -> Self {
+
+        let a = bgp_router(1, 100, &[(0, ip(10, 0, 12, 1)), (1, ip(10, 0, 13, 1))], &[(Self::A_TO_B, 200), (Self::A_TO_C, 300)]);
+        let b = bgp_router(2, 200, &[(0, ip(10, 0, 12, 2)), (1, ip(10, 0, 23, 2))], &[(Self::B_TO_A, 100), (ip(10, 0, 23, 3), 300)]);
+        let c = bgp_router(3, 300, &[(0, ip(10, 0, 23, 3)), (1, ip(10, 0, 13, 3))], &[(ip(10, 0, 23, 2), 200), (ip(10, 0, 13, 1), 100)]);
+        c.borrow_mut().bgp_mut().unwrap().add_network(Self::prefix());
+
+        let mut net = Network::new();
+        let (ha, hb, hc) = (net.push(a.clone()), net.push(b.clone()), net.push(c));
+        net.link::<EthFrame>(ha, 0, hb, 0);
+        net.link::<EthFrame>(hb, 1, hc, 0);
+        let link_ac = net.link::<EthFrame>(ha, 1, hc, 1);
+        run(&mut net, 20);
+
+        Self { net, a, b, link_ac }
+
+    }
+
+    fn prefix() -> IpPrefix<Ipv4Addr> {
+        prefix(10, 3, 0, 0, 16)
+    }
+
+    /// Get the AS path of the best route of A.
+    fn as_path(&self) -> Option<Vec<u16>> {
+        self.a.borrow_mut().bgp().unwrap().route(Self::prefix()).map(|route| route.attrs.as_path.clone())
+    }
+
+    fn fetch(&self) -> Option<(usize, Ipv4Addr)> {
+        self.a.borrow_mut().get_ipv4_routes().fetch(ip(10, 3, 1, 1))
+    }
+
+}
+
+#[test]
+fn bgp_best_path_selection() {
+
+    let mut bgp = BgpTriangle::new();
+
+    // The shortest AS path is preferred.
+    assert_eq!(bgp.as_path(), Some(vec![300]));
+    assert_eq!(bgp.fetch(), Some((1, BgpTriangle::A_TO_C)));
+
+    // Local preference is preferred over the AS path length.
+    bgp.a.borrow_mut().bgp_mut().unwrap().peer_conf_mut(BgpTriangle::A_TO_B).unwrap().import = BgpPolicy::default()
+        .with_rule(BgpPolicyRule::new(prefix(10, 0, 0, 0, 8), BgpPolicyAction::Permit).with_len(8..=24).with_local_pref(200));
+    run(&mut bgp.net, 2);
+    assert_eq!(bgp.as_path(), Some(vec![200, 300]));
+    assert_eq!(bgp.fetch(), Some((0, BgpTriangle::A_TO_B)));
+
+    // Routes not exported by B are withdrawn.
+    bgp.b.borrow_mut().bgp_mut().unwrap().peer_conf_mut(BgpTriangle::B_TO_A).unwrap().export = BgpPolicy::new(BgpPolicyAction::Deny);
+    run(&mut bgp.net, 5);
+    assert_eq!(bgp.as_path(), Some(vec![300]));
+
+}
+
+#[test]
+fn bgp_failover_after_hold_time() {
+
+    let mut bgp = BgpTriangle::new();
+    assert_eq!(bgp.as_path(), Some(vec![300]));
+
+    bgp.net.set_link_up(bgp.link_ac, false);
+    let a = bgp.a.clone();
+    run_until(&mut bgp.net, 200, || {
+        a.borrow_mut().bgp().unwrap().route(BgpTriangle::prefix()).map(|route| route.attrs.as_path.clone()) == Some(vec![200, 300])
+    });
+    assert_eq!(bgp.fetch(), Some((0, BgpTriangle::A_TO_B)));
+
+    bgp.net.set_link_up(bgp.link_ac, true);
+    run(&mut bgp.net, 100);
+    assert_eq!(bgp.a.borrow_mut().bgp().unwrap().peer_state(BgpTriangle::A_TO_C), Some(BgpPeerState::Established));
+    assert_eq!(bgp.as_path(), Some(vec![300]));
+
+}
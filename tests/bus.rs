@@ -0,0 +1,89 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, EthPayload, MacAddr};
+use netcrab::node::{ServerNode, EthHub, CaptureNode};
+
+use common::{host, ip, send, count_received, run};
+
+
+fn hosts(count: u8) -> Vec<RcNode<ServerNode>> {
+    (1..=count).map(|n| host([2, 0, 0, 0, 0, n], ip(10, 0, 0, n), None)).collect()
+}
+
+/// Every host of a bus sends to every other one at once, transmissions
+/// collide but are all eventually delivered.
+#[test]
+fn csma_cd_collisions() {
+
+    let hosts = hosts(4);
+    let mut net = Network::new();
+    let members = hosts.iter().map(|host| (net.push(host.clone()), 0)).collect::<Vec<_>>();
+    let bus = net.bus::<EthFrame>(&members);
+    net.set_bus_csma_cd(bus, true);
+
+    for (i, src) in hosts.iter().enumerate() {
+        for j in 0..hosts.len() {
+            if i != j {
+                send(src, ip(10, 0, 0, i as u8 + 1), ip(10, 0, 0, j as u8 + 1));
+            }
+        }
+    }
+    run(&mut net, 300);
+
+    assert_eq!(hosts.iter().map(count_received).sum::<usize>(), 12);
+    let stats = net.bus_stats(bus);
+    assert!(stats.collisions > 0);
+    assert_eq!(stats.dropped, 0);
+
+}
+
+/// Without CSMA/CD the medium is ideal.
+#[test]
+fn ideal_bus() {
+
+    let hosts = hosts(3);
+    let mut net = Network::new();
+    let members = hosts.iter().map(|host| (net.push(host.clone()), 0)).collect::<Vec<_>>();
+    let bus = net.bus::<EthFrame>(&members);
+
+    send(&hosts[0], ip(10, 0, 0, 1), ip(10, 0, 0, 3));
+    send(&hosts[1], ip(10, 0, 0, 2), ip(10, 0, 0, 3));
+    run(&mut net, 10);
+
+    assert_eq!(count_received(&hosts[2]), 2);
+    assert_eq!(net.bus_stats(bus).collisions, 0);
+
+}
+
+/// A hub repeats frames on all its other ports, unicast frames are also
+/// seen by a capture on a port that is not their destination.
+#[test]
+fn hub_repeats_frames() {
+
+    let hosts = hosts(3);
+    let capture = RcNode::new(CaptureNode::<EthFrame>::new());
+    let mut net = Network::new();
+    let hub = net.push(EthHub::new());
+    for (port, host) in hosts.iter().enumerate() {
+        let handle = net.push(host.clone());
+        net.link::<EthFrame>(handle, 0, hub, port);
+    }
+    let hc = net.push(capture.clone());
+    net.link::<EthFrame>(hc, 0, hub, 3);
+
+    send(&hosts[0], ip(10, 0, 0, 1), ip(10, 0, 0, 3));
+    send(&hosts[1], ip(10, 0, 0, 2), ip(10, 0, 0, 3));
+    run(&mut net, 10);
+
+    assert_eq!(count_received(&hosts[0]), 0);
+    assert_eq!(count_received(&hosts[2]), 2);
+
+    let dst_mac = MacAddr([2, 0, 0, 0, 0, 3]);
+    let captured = capture.borrow_mut().take();
+    let unicast = captured.iter()
+        .filter(|captured| captured.data.dst == dst_mac && matches!(captured.data.payload, EthPayload::Ipv4(_)))
+        .count();
+    assert_eq!(unicast, 2);
+
+}
@@ -0,0 +1,122 @@
+//! Topologies shared by integration tests.
+
+#![allow(dead_code)]
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, IpAddrExt, Ipv4Packet, Ipv4Payload, Icmpv4Message};
+use netcrab::node::{ServerNode, ServerEthIface, ServerIfaceConf, IpRouteLink};
+
+
+/// Create a host with a single Ethernet interface 0 on a /24 network, and a
+/// default route through the given gateway, or to the link if `None`.
+pub fn host(mac: [u8; 6], ip: Ipv4Addr, gateway: Option<Ipv4Addr>) -> RcNode<ServerNode> {
+    let mut node = ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr(mac)), ServerIfaceConf::with_ipv4(ip, 24));
+    let link = gateway.map_or(IpRouteLink::Direct, IpRouteLink::Indirect);
+    node.get_ipv4_routes_mut().set_default_route(0, link);
+    RcNode::new(node)
+}
+
+/// Create a forwarding router with Ethernet interfaces on the given /24
+/// networks, given as interface index and address.
+pub fn router(id: u8, ifaces: &[(usize, Ipv4Addr)]) -> ServerNode {
+    let mut node = ServerNode::new();
+    for &(iface, ip) in ifaces {
+        node.add_iface_conf(iface, ServerEthIface::new(MacAddr([2, 0, 0, 0, id, iface as u8])), ServerIfaceConf::with_ipv4(ip, 24));
+        node.get_ipv4_routes_mut().add_route(ip.take_prefix(24), iface, IpRouteLink::Direct);
+    }
+    node.set_ipv4_forwarding(true);
+    node
+}
+
+pub fn ip(a: u8, b: u8, c: u8, d: u8) -> Ipv4Addr {
+    Ipv4Addr::new(a, b, c, d)
+}
+
+/// Send a packet with a one-byte payload from the node.
+pub fn send(node: &RcNode<ServerNode>, src: Ipv4Addr, dst: Ipv4Addr) {
+    node.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(src, dst, Ipv4Payload::Custom(vec![1]))));
+}
+
+/// Send a packet with a payload of the given length from the node.
+pub fn send_sized(node: &RcNode<ServerNode>, src: Ipv4Addr, dst: Ipv4Addr, len: usize, allow_fragmentation: bool) {
+    let mut packet = Ipv4Packet::new(src, dst, Ipv4Payload::Custom(vec![1; len]));
+    packet.allow_fragmentation = allow_fragmentation;
+    node.borrow_mut().send_ipv4(Box::new(packet));
+}
+
+/// Return the number of packets received by the node, they are discarded.
+pub fn count_received(node: &RcNode<ServerNode>) -> usize {
+    let mut node = node.borrow_mut();
+    std::iter::from_fn(|| node.recv_ipv4()).count()
+}
+
+pub fn run(net: &mut Network, ticks: usize) {
+    for _ in 0..ticks {
+        net.tick();
+    }
+}
+
+/// Tick the network until the condition holds, return the number of ticks.
+pub fn run_until(net: &mut Network, max_ticks: usize, mut condition: impl FnMut() -> bool) -> usize {
+    for ticks in 0..max_ticks {
+        if condition() {
+            return ticks;
+        }
+        net.tick();
+    }
+    panic!("condition not met after {max_ticks} ticks");
+}
+
+pub fn ping(node: &RcNode<ServerNode>, src: Ipv4Addr, dst: Ipv4Addr) {
+    let request = Icmpv4Message::EchoRequest { id: 1, seq: 1, data: vec![] };
+    node.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(src, dst, Ipv4Payload::Icmp(request))));
+}
+
+/// Return `true` if an echo reply was received, other packets are discarded.
+pub fn recv_echo_reply(node: &RcNode<ServerNode>) -> bool {
+    let mut node = node.borrow_mut();
+    std::iter::from_fn(|| node.recv_ipv4())
+        .any(|packet| matches!(packet.payload, Ipv4Payload::Icmp(Icmpv4Message::EchoReply { .. })))
+}
+
+/// An inside host, a router and an outside host:
+///
+/// ```text
+/// inside 10.0.0.1 -- 10.0.0.254 [0] router [1] 10.0.1.254 -- 10.0.1.1 outside
+/// ```
+pub struct Gateway {
+    pub net: Network,
+    pub inside: RcNode<ServerNode>,
+    pub router: RcNode<ServerNode>,
+    pub outside: RcNode<ServerNode>,
+}
+
+impl Gateway {
+
+    pub const INSIDE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    pub const ROUTER_INSIDE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 254);
+    pub const ROUTER_OUTSIDE: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 254);
+    pub const OUTSIDE: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 1);
+
+    pub fn new() -> Self {
+
+        let inside = host([2, 0, 0, 1, 0, 1], Self::INSIDE, Some(Self::ROUTER_INSIDE));
+        let outside = host([2, 0, 0, 1, 1, 1], Self::OUTSIDE, Some(Self::ROUTER_OUTSIDE));
+        let router = RcNode::new(router(1, &[(0, Self::ROUTER_INSIDE), (1, Self::ROUTER_OUTSIDE)]));
+
+        let mut net = Network::new();
+        let inside_handle = net.push(inside.clone());
+        let outside_handle = net.push(outside.clone());
+        let router_handle = net.push(router.clone());
+        net.link::<EthFrame>(inside_handle, 0, router_handle, 0);
+        net.link::<EthFrame>(outside_handle, 0, router_handle, 1);
+
+        Self { net, inside, router, outside }
+
+    }
+
+    pub fn run(&mut self, ticks: usize) {
+        run(&mut self.net, ticks);
+    }
+
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, IpAddrExt, IpPrefix, Ipv4Packet, Ipv4Payload, UdpDatagram};
+use netcrab::node::{ServerNode, ServerEthIface, ServerIfaceConf, EthSwitch, IpRoutes, IpRouteLink, IpFlow, IpMultipathMode};
+
+use common::{host, ip, run};
+
+
+fn prefix() -> IpPrefix<Ipv4Addr> {
+    ip(10, 9, 0, 0).take_prefix(16)
+}
+
+fn flow(src_port: u16) -> IpFlow<Ipv4Addr> {
+    IpFlow { src: ip(10, 0, 1, 1), dst: ip(10, 9, 0, 1), protocol: 17, src_port, dst_port: 80 }
+}
+
+/// Two paths to the prefix, the second has a weight of 3.
+fn weighted_routes() -> IpRoutes<Ipv4Addr> {
+    let mut routes = IpRoutes::new();
+    routes.add_route(prefix(), 0, IpRouteLink::Indirect(ip(10, 0, 1, 2)));
+    routes.add_route_weighted(prefix(), 1, IpRouteLink::Indirect(ip(10, 0, 2, 2)), 3);
+    routes
+}
+
+#[test]
+fn ecmp_flow_hash() {
+
+    let routes = weighted_routes();
+    let mut counts = [0; 2];
+    for port in 0..1000 {
+        let (iface, _) = routes.fetch_flow(&flow(port)).unwrap();
+        // All packets of a flow take the same path.
+        assert_eq!(routes.fetch_flow(&flow(port)).unwrap().0, iface);
+        counts[iface] += 1;
+    }
+
+    assert!(counts[0] > 150 && counts[0] < 350, "{counts:?}");
+
+}
+
+#[test]
+fn ecmp_round_robin() {
+
+    let mut routes = weighted_routes();
+    routes.set_multipath_mode(IpMultipathMode::RoundRobin);
+    let ifaces = (0..8).map(|_| routes.fetch_flow(&flow(1)).unwrap().0).collect::<Vec<_>>();
+    assert_eq!(ifaces, vec![0, 1, 1, 1, 0, 1, 1, 1]);
+
+    routes.set_next_hop_alive(1, ip(10, 0, 2, 2), false);
+    assert!((0..8).all(|_| routes.fetch_flow(&flow(1)).unwrap().0 == 0));
+
+}
+
+#[test]
+fn ecmp_carrier_loss() {
+
+    let mut a = ServerNode::new();
+    a.add_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 2, 0, 0])), ServerIfaceConf::with_ipv4(ip(10, 0, 1, 1), 24));
+    a.add_iface_conf(1, ServerEthIface::new(MacAddr([2, 0, 0, 2, 0, 1])), ServerIfaceConf::with_ipv4(ip(10, 0, 2, 1), 24));
+    a.get_ipv4_routes_mut().add_route(prefix(), 0, IpRouteLink::Indirect(ip(10, 0, 1, 2)));
+    let a = RcNode::new(a);
+
+    let mut net = Network::new();
+    let (ha, hb) = (net.push(a.clone()), net.push(host([2, 0, 0, 2, 1, 2], ip(10, 0, 1, 2), None)));
+    let link = net.link::<EthFrame>(ha, 0, hb, 0);
+    net.tick();
+    assert!(a.borrow_mut().get_ipv4_routes().fib().any(|(_, iface, _, _)| iface == 0));
+
+    // Carrier loss removes the paths of the interface and its connected route.
+    net.set_link_up(link, false);
+    net.tick();
+    assert!(!a.borrow_mut().get_ipv4_routes().fib().any(|(_, iface, _, _)| iface == 0));
+    assert!(a.borrow_mut().get_ipv4_routes().fetch(ip(10, 9, 0, 1)).is_none());
+
+}
+
+/// A router with two next hops on a switch, the link of the second one
+/// is down.
+#[test]
+fn ecmp_dead_next_hop() {
+
+    let a = RcNode::new(ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 8, 0, 1])), ServerIfaceConf::with_ipv4(ip(10, 0, 0, 1), 24)));
+    a.borrow_mut().get_ipv4_routes_mut().add_route(prefix(), 0, IpRouteLink::Indirect(ip(10, 0, 0, 2)));
+    a.borrow_mut().get_ipv4_routes_mut().add_route(prefix(), 0, IpRouteLink::Indirect(ip(10, 0, 0, 3)));
+
+    let b = host([2, 0, 0, 8, 0, 2], ip(10, 0, 0, 2), None);
+    let c = host([2, 0, 0, 8, 0, 3], ip(10, 0, 0, 3), None);
+    let mut net = Network::new();
+    let (ha, hb, hc, hs) = (net.push(a.clone()), net.push(b), net.push(c), net.push(EthSwitch::new()));
+    net.link::<EthFrame>(ha, 0, hs, 0);
+    net.link::<EthFrame>(hb, 0, hs, 1);
+    let link_c = net.link::<EthFrame>(hc, 0, hs, 2);
+    net.set_link_up(link_c, false);
+
+    for port in 0..20 {
+        let datagram = UdpDatagram { src_port: port, dst_port: 80, data: vec![] };
+        a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(ip(10, 0, 0, 1), ip(10, 9, 0, 1), Ipv4Payload::Udp(datagram))));
+    }
+    run(&mut net, 15);
+
+    // The unanswered next hop is dead, all flows use the other one.
+    assert!(!a.borrow_mut().get_ipv4_routes().is_next_hop_alive(0, ip(10, 0, 0, 3)));
+    for port in 0..100 {
+        assert_eq!(a.borrow_mut().get_ipv4_routes().fetch_flow(&flow(port)), Some((0, ip(10, 0, 0, 2))));
+    }
+
+    // It is probed until it answers.
+    net.set_link_up(link_c, true);
+    run(&mut net, 15);
+    assert!(a.borrow_mut().get_ipv4_routes().is_next_hop_alive(0, ip(10, 0, 0, 3)));
+    let ifaces = (0..100).filter_map(|port| a.borrow_mut().get_ipv4_routes().fetch_flow(&flow(port))).collect::<Vec<_>>();
+    assert!(ifaces.contains(&(0, ip(10, 0, 0, 3))));
+
+}
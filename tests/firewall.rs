@@ -0,0 +1,124 @@
+mod common;
+
+use netcrab::proto::{Ipv4Addr, IpAddrExt, Ipv4Packet, Ipv4Payload, UdpDatagram, TcpSegment, TcpFlags, Icmpv4Message, Icmpv4Unreachable};
+use netcrab::node::{Firewall, FirewallChain, FirewallRule, FirewallAction, FirewallProtocol, ConnState};
+
+use common::Gateway;
+
+
+fn udp(src: Ipv4Addr, dst: Ipv4Addr, src_port: u16, dst_port: u16) -> Box<Ipv4Packet> {
+    Box::new(Ipv4Packet::new(src, dst, Ipv4Payload::Udp(UdpDatagram { src_port, dst_port, data: vec![] })))
+}
+
+/// Only connections opened from the inside are forwarded, other TCP
+/// connections are reset.
+fn gateway_with_firewall() -> Gateway {
+    let gw = Gateway::new();
+    let mut firewall = Firewall::new();
+    firewall.set_policy(FirewallChain::Forward, FirewallAction::Drop);
+    firewall.add_rule(FirewallChain::Forward, FirewallRule::new(FirewallAction::Accept).with_state(ConnState::Established));
+    firewall.add_rule(FirewallChain::Forward, FirewallRule::new(FirewallAction::Accept).with_in_iface(0).with_src(Gateway::INSIDE.take_prefix(24)));
+    firewall.add_rule(FirewallChain::Forward, FirewallRule::new(FirewallAction::Reject).with_protocol(FirewallProtocol::Tcp));
+    firewall.add_rule(FirewallChain::Input, FirewallRule::new(FirewallAction::Reject).with_protocol(FirewallProtocol::Udp).with_dst_ports(22..=22));
+    gw.router.borrow_mut().set_firewall(Some(firewall));
+    gw
+}
+
+#[test]
+fn accept_established() {
+
+    let mut gw = gateway_with_firewall();
+
+    // Not established yet, dropped by the policy.
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, Gateway::INSIDE, 6, 5));
+    gw.run(10);
+    assert!(gw.inside.borrow_mut().recv_ipv4().is_none());
+
+    gw.inside.borrow_mut().send_ipv4(udp(Gateway::INSIDE, Gateway::OUTSIDE, 5, 6));
+    gw.run(10);
+    assert!(gw.outside.borrow_mut().recv_ipv4().is_some());
+
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, Gateway::INSIDE, 6, 5));
+    gw.run(10);
+    assert!(gw.inside.borrow_mut().recv_ipv4().is_some());
+
+    let router = gw.router.borrow_mut();
+    let firewall = router.firewall().unwrap();
+    let hits = firewall.rules(FirewallChain::Forward).map(|(_, hits)| hits.packets).collect::<Vec<_>>();
+    assert_eq!(hits, vec![1, 1, 0]);
+    assert_eq!(firewall.policy_hits(FirewallChain::Forward).packets, 1);
+    assert_eq!(firewall.connection_count(), 1);
+
+}
+
+#[test]
+fn reject_with_reset_and_unreachable() {
+
+    let mut gw = gateway_with_firewall();
+
+    gw.outside.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(Gateway::OUTSIDE, Gateway::INSIDE, Ipv4Payload::Tcp(TcpSegment {
+        src_port: 40000,
+        dst_port: 80,
+        seq: 100,
+        ack: 0,
+        flags: TcpFlags::SYN,
+        window: 1000,
+        data: vec![],
+    }))));
+    gw.run(10);
+
+    let reset = gw.outside.borrow_mut().recv_ipv4().unwrap();
+    assert!(matches!(&reset.payload, Ipv4Payload::Tcp(s) if s.flags.contains(TcpFlags::RST) && s.ack == 101), "{reset:?}");
+    assert!(gw.inside.borrow_mut().recv_ipv4().is_none());
+
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, Gateway::ROUTER_OUTSIDE, 6, 22));
+    gw.run(10);
+
+    let error = gw.outside.borrow_mut().recv_ipv4().unwrap();
+    assert!(matches!(&error.payload, Ipv4Payload::Icmp(Icmpv4Message::DestinationUnreachable { code: Icmpv4Unreachable::Port, .. })));
+
+}
+
+#[test]
+fn filter_multicast_forwarding() {
+
+    const GROUP: Ipv4Addr = Ipv4Addr::new(239, 1, 2, 3);
+
+    // The router is a member of the group, so that packets to it are also
+    // delivered locally and dropped by the input chain.
+    let mut gw = Gateway::new();
+    let mut firewall = Firewall::new();
+    firewall.set_policy(FirewallChain::Input, FirewallAction::Drop);
+    gw.router.borrow_mut().set_firewall(Some(firewall));
+    gw.router.borrow_mut().set_igmp_querier(0, true);
+    gw.router.borrow_mut().join_ipv4_group(1, GROUP);
+    gw.inside.borrow_mut().join_ipv4_group(0, GROUP);
+    gw.run(10);
+
+    // Forwarding is only filtered by the forward chain.
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, GROUP, 5, 6));
+    gw.run(10);
+    assert!(gw.inside.borrow_mut().recv_ipv4().is_some());
+    assert!(gw.router.borrow_mut().recv_ipv4().is_none());
+
+    gw.router.borrow_mut().firewall_mut().unwrap().set_policy(FirewallChain::Forward, FirewallAction::Drop);
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, GROUP, 5, 6));
+    gw.run(10);
+    assert!(gw.inside.borrow_mut().recv_ipv4().is_none());
+
+}
+
+#[test]
+fn filter_daemon_output() {
+
+    // The queries of the IGMP querier go through the output chain.
+    let mut gw = Gateway::new();
+    let mut firewall = Firewall::new();
+    firewall.set_policy(FirewallChain::Output, FirewallAction::Drop);
+    gw.router.borrow_mut().set_firewall(Some(firewall));
+    gw.router.borrow_mut().set_igmp_querier(0, true);
+    gw.run(10);
+
+    assert!(gw.router.borrow_mut().firewall_mut().unwrap().policy_hits(FirewallChain::Output).packets > 0);
+
+}
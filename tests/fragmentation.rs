@@ -0,0 +1,75 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, Ipv4Addr, Ipv4Packet, Ipv4Payload, Icmpv4Message, Icmpv4Unreachable};
+use netcrab::node::ServerNode;
+
+use common::{host, router, run};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const C: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 1);
+
+/// Host A and host C on both sides of a router, the MTU of the router's
+/// interface to C is 576.
+fn routed() -> (Network, RcNode<ServerNode>, RcNode<ServerNode>) {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, Some(Ipv4Addr::new(10, 0, 0, 254)));
+    let c = host([2, 0, 0, 0, 0, 3], C, Some(Ipv4Addr::new(10, 0, 1, 254)));
+    let mut r = router(1, &[(0, Ipv4Addr::new(10, 0, 0, 254)), (1, Ipv4Addr::new(10, 0, 1, 254))]);
+    r.get_iface_conf_mut(1).unwrap().mtu = 576;
+
+    let mut net = Network::new();
+    let (ha, hc, hr) = (net.push(a.clone()), net.push(c.clone()), net.push(r));
+    net.link::<EthFrame>(ha, 0, hr, 0);
+    net.link::<EthFrame>(hc, 0, hr, 1);
+
+    (net, a, c)
+
+}
+
+#[test]
+fn fragmentation_and_reassembly() {
+
+    let (mut net, a, c) = routed();
+    a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, C, Ipv4Payload::Custom(vec![7; 1400]))));
+    run(&mut net, 10);
+
+    let packet = c.borrow_mut().recv_ipv4().unwrap();
+    assert!(matches!(&packet.payload, Ipv4Payload::Custom(data) if data.len() == 1400));
+    assert!(!packet.is_fragmented());
+    assert!(c.borrow_mut().recv_ipv4().is_none());
+
+}
+
+#[test]
+fn fragmentation_needed() {
+
+    let (mut net, a, c) = routed();
+    let mut packet = Ipv4Packet::new(A, C, Ipv4Payload::Custom(vec![7; 1400]));
+    packet.allow_fragmentation = false;
+    a.borrow_mut().send_ipv4(Box::new(packet));
+    run(&mut net, 10);
+
+    assert!(c.borrow_mut().recv_ipv4().is_none());
+    let icmp = a.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(icmp.src, Ipv4Addr::new(10, 0, 0, 254));
+    assert!(matches!(&icmp.payload, Ipv4Payload::Icmp(Icmpv4Message::DestinationUnreachable {
+        code: Icmpv4Unreachable::FragmentationNeeded { mtu: 576 }, ..
+    })));
+
+}
+
+#[test]
+fn fragment_of_fragment() {
+
+    let fragments = Ipv4Packet::new(A, C, Ipv4Payload::Custom(vec![1; 3000])).fragment(1500).unwrap();
+    let fragments: Vec<_> = fragments.iter().flat_map(|fragment| fragment.fragment(576).unwrap()).collect();
+
+    assert!(fragments.iter().all(|fragment| fragment.size() <= 576));
+    assert_eq!(fragments.iter().map(|fragment| fragment.payload.size()).sum::<usize>(), 3000);
+    // Only the very last fragment has no more fragments flag.
+    assert_eq!(fragments.iter().filter(|fragment| !fragment.is_fragment).count(), 1);
+    assert!(!fragments.last().unwrap().is_fragment);
+
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use netcrab::net::{Network, RcNode, LinkId};
+use netcrab::proto::{EthFrame, Ipv4Addr, Ipv4Packet, Ipv4Payload, IgmpVersion};
+use netcrab::node::{EthSwitch, IgmpSnooping, ServerNode};
+
+use common::{host, router, count_received, run};
+
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(239, 1, 1, 1);
+const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 1);
+
+/// Hosts A, B and C behind a snooping switch with the querier router on
+/// port 3, the multicast source is on the other side of the router.
+struct Topology {
+    net: Network,
+    a: RcNode<ServerNode>,
+    b: RcNode<ServerNode>,
+    c: RcNode<ServerNode>,
+    router: RcNode<ServerNode>,
+    source: RcNode<ServerNode>,
+    switch: RcNode<EthSwitch>,
+    /// Links of the switch ports.
+    links: Vec<LinkId>,
+}
+
+impl Topology {
+
+    fn new() -> Self {
+
+        let a = host([2, 0, 0, 0, 0, 1], Ipv4Addr::new(10, 0, 0, 1), None);
+        let b = host([2, 0, 0, 0, 0, 2], Ipv4Addr::new(10, 0, 0, 2), None);
+        let c = host([2, 0, 0, 0, 0, 3], Ipv4Addr::new(10, 0, 0, 3), None);
+        b.borrow_mut().set_igmp_version(IgmpVersion::V2);
+
+        let mut router = router(1, &[(0, Ipv4Addr::new(10, 0, 0, 254)), (1, Ipv4Addr::new(10, 0, 1, 254))]);
+        router.set_igmp_querier(0, true);
+        let router = RcNode::new(router);
+        let source = host([2, 0, 0, 0, 0, 0x20], SOURCE, Some(Ipv4Addr::new(10, 0, 1, 254)));
+
+        let mut switch = EthSwitch::new();
+        switch.set_igmp_snooping(Some(IgmpSnooping::new()));
+        let switch = RcNode::new(switch);
+
+        let mut net = Network::new();
+        let hs = net.push(switch.clone());
+        let handles: Vec<_> = [&a, &b, &c, &router].into_iter().map(|node| net.push(node.clone())).collect();
+        let links = handles.iter().enumerate()
+            .map(|(port, &handle)| net.link::<EthFrame>(handle, 0, hs, port))
+            .collect();
+        let hsrc = net.push(source.clone());
+        net.link::<EthFrame>(hsrc, 0, handles[3], 1);
+
+        Self { net, a, b, c, router, source, switch, links }
+
+    }
+
+    /// Send a packet from the source to the group and return the number of
+    /// packets received by each host.
+    fn send(&mut self) -> (usize, usize, usize) {
+        for node in [&self.a, &self.b, &self.c] {
+            while node.borrow_mut().recv_ipv4().is_some() {}
+        }
+        self.source.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(SOURCE, GROUP, Ipv4Payload::Custom(vec![1]))));
+        run(&mut self.net, 10);
+        (count_received(&self.a), count_received(&self.b), count_received(&self.c))
+    }
+
+}
+
+#[test]
+fn igmp_join_and_snooping() {
+
+    let mut topo = Topology::new();
+    run(&mut topo.net, 3);
+
+    topo.a.borrow_mut().join_ipv4_group(0, GROUP);
+    topo.b.borrow_mut().join_ipv4_group(0, GROUP);
+    run(&mut topo.net, 20);
+
+    assert!(topo.router.borrow_mut().has_ipv4_group_members(0, GROUP));
+    assert_eq!(topo.switch.borrow_mut().igmp_snooping().unwrap().group_ports(1, GROUP).into_iter().collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!(topo.send(), (1, 1, 0));
+
+}
+
+#[test]
+fn igmp_snooping_port_down() {
+
+    let mut topo = Topology::new();
+    run(&mut topo.net, 3);
+    topo.a.borrow_mut().join_ipv4_group(0, GROUP);
+    topo.b.borrow_mut().join_ipv4_group(0, GROUP);
+    run(&mut topo.net, 20);
+
+    // The membership of the port is forgotten as soon as its link is down.
+    topo.net.set_link_up(topo.links[0], false);
+    topo.net.tick();
+    assert_eq!(topo.switch.borrow_mut().igmp_snooping().unwrap().group_ports(1, GROUP).into_iter().collect::<Vec<_>>(), vec![1]);
+    assert_eq!(topo.send(), (0, 1, 0));
+
+}
+
+#[test]
+fn igmp_leave() {
+
+    let mut topo = Topology::new();
+    run(&mut topo.net, 3);
+    topo.a.borrow_mut().join_ipv4_group(0, GROUP);
+    topo.b.borrow_mut().join_ipv4_group(0, GROUP);
+    run(&mut topo.net, 20);
+
+    topo.b.borrow_mut().leave_ipv4_group(0, GROUP);
+    run(&mut topo.net, 10);
+    assert_eq!(topo.send(), (1, 0, 0));
+
+    // Memberships are refreshed by reports to the periodic queries.
+    run(&mut topo.net, 300);
+    assert_eq!(topo.send(), (1, 0, 0));
+
+    topo.a.borrow_mut().leave_ipv4_group(0, GROUP);
+    run(&mut topo.net, 10);
+    assert!(!topo.router.borrow_mut().has_ipv4_group_members(0, GROUP));
+
+}
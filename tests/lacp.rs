@@ -0,0 +1,126 @@
+mod common;
+
+use netcrab::net::{Network, RcNode, LinkId};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, Ipv4Packet, Ipv4Payload};
+use netcrab::node::{EthSwitch, LacpGroup, ServerNode, ServerBondIface, ServerIfaceConf, IpRouteLink};
+
+use common::{host, count_received, run};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+/// Host A on switch port 0, host B bonded to switch ports 1 and 2.
+fn bonded() -> (Network, RcNode<ServerNode>, RcNode<ServerNode>, LinkId) {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, None);
+
+    let mut b = ServerNode::new();
+    b.add_iface_conf(0, ServerBondIface::new(MacAddr([2, 0, 0, 0, 0, 2]), 7, &[0, 1]), ServerIfaceConf::with_ipv4(B, 24));
+    b.get_ipv4_routes_mut().set_default_route(0, IpRouteLink::Direct);
+    let b = RcNode::new(b);
+
+    let mut switch = EthSwitch::new();
+    switch.add_port_group(LacpGroup::new(MacAddr([2, 0, 0, 0, 0, 0x50]), 1, &[1, 2]));
+
+    let mut net = Network::new();
+    let (ha, hb, hs) = (net.push(a.clone()), net.push(b.clone()), net.push(switch));
+    net.link::<EthFrame>(ha, 0, hs, 0);
+    let first = net.link::<EthFrame>(hb, 0, hs, 1);
+    net.link::<EthFrame>(hb, 1, hs, 2);
+
+    (net, a, b, first)
+
+}
+
+fn active_members(node: &RcNode<ServerNode>) -> Vec<usize> {
+    node.borrow_mut().get_iface::<ServerBondIface>(0).unwrap().group().active_members().collect()
+}
+
+#[test]
+fn lacp_aggregates_members() {
+
+    let (mut net, a, b, _) = bonded();
+    run(&mut net, 6);
+    assert_eq!(active_members(&b), vec![0, 1]);
+
+    for i in 0..4 {
+        a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, B, Ipv4Payload::Custom(vec![i]))));
+    }
+    run(&mut net, 10);
+    assert_eq!(count_received(&b), 4);
+
+}
+
+#[test]
+fn lacp_failover() {
+
+    let (mut net, a, b, first) = bonded();
+    run(&mut net, 6);
+
+    net.set_link_up(first, false);
+    run(&mut net, 6);
+    assert_eq!(active_members(&b), vec![1]);
+
+    for i in 0..4 {
+        a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, B, Ipv4Payload::Custom(vec![i]))));
+    }
+    b.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(B, A, Ipv4Payload::Custom(vec![9]))));
+    run(&mut net, 10);
+    assert_eq!(count_received(&b), 4);
+    assert_eq!(count_received(&a), 1);
+
+}
+
+/// Hosts A and B on two switches aggregating their ports 1 and 2.
+#[test]
+fn lacp_between_switches() {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, None);
+    let b = host([2, 0, 0, 0, 0, 2], B, None);
+    let switches = [0x51, 0x52].map(|id| {
+        let mut switch = EthSwitch::new();
+        switch.add_port_group(LacpGroup::new(MacAddr([2, 0, 0, 0, 0, id]), 1, &[1, 2]));
+        RcNode::new(switch)
+    });
+
+    let mut net = Network::new();
+    let (ha, hb) = (net.push(a.clone()), net.push(b.clone()));
+    let hs = switches.clone().map(|switch| net.push(switch));
+    net.link::<EthFrame>(ha, 0, hs[0], 0);
+    net.link::<EthFrame>(hb, 0, hs[1], 0);
+    let first = net.link::<EthFrame>(hs[0], 1, hs[1], 1);
+    net.link::<EthFrame>(hs[0], 2, hs[1], 2);
+    run(&mut net, 6);
+
+    let active = |switch: &RcNode<EthSwitch>| switch.borrow_mut().port_group(1).unwrap().active_members().collect::<Vec<_>>();
+    for switch in &switches {
+        assert_eq!(active(switch), vec![1, 2]);
+    }
+
+    // Broadcast frames are sent on a single member, they don't loop.
+    for i in 0..4 {
+        a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, B, Ipv4Payload::Custom(vec![i]))));
+    }
+    run(&mut net, 10);
+    assert_eq!(count_received(&b), 4);
+
+    net.set_link_up(first, false);
+    run(&mut net, 6);
+    for switch in &switches {
+        assert_eq!(active(switch), vec![2]);
+    }
+
+    for i in 0..4 {
+        b.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(B, A, Ipv4Payload::Custom(vec![i]))));
+    }
+    run(&mut net, 10);
+    assert_eq!(count_received(&a), 4);
+
+}
+
+#[test]
+#[should_panic]
+fn lacp_rejects_large_member() {
+    LacpGroup::new(MacAddr([2, 0, 0, 0, 0, 0x50]), 1, &[1, u16::MAX as usize]);
+}
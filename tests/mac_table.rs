@@ -0,0 +1,81 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr};
+use netcrab::node::{ServerNode, EthSwitch, MacTableOverflow, DEFAULT_MAC_AGING_TIME, DEFAULT_VLAN};
+
+use common::{host, send, run};
+
+
+const A_MAC: MacAddr = MacAddr([2, 0, 0, 0, 0, 1]);
+const B_MAC: MacAddr = MacAddr([2, 0, 0, 0, 0, 2]);
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+/// Two hosts on ports 0 and 1 of a switch.
+fn switched_pair() -> (Network, RcNode<EthSwitch>, RcNode<ServerNode>, RcNode<ServerNode>) {
+    let a = host(A_MAC.0, A, None);
+    let b = host(B_MAC.0, B, None);
+    let switch = RcNode::new(EthSwitch::new());
+    let mut net = Network::new();
+    let (ha, hb, hs) = (net.push(a.clone()), net.push(b.clone()), net.push(switch.clone()));
+    net.link::<EthFrame>(ha, 0, hs, 0);
+    net.link::<EthFrame>(hb, 0, hs, 1);
+    (net, switch, a, b)
+}
+
+fn learned(switch: &RcNode<EthSwitch>) -> Vec<(MacAddr, usize)> {
+    let mut entries = switch.borrow_mut().mac_entries()
+        .filter(|entry| !entry.is_static)
+        .map(|entry| (entry.mac, entry.iface))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|&(_, iface)| iface);
+    entries
+}
+
+#[test]
+fn learn_and_age() {
+
+    let (mut net, switch, a, b) = switched_pair();
+    let static_mac = MacAddr([2, 0, 0, 0, 0, 9]);
+    switch.borrow_mut().add_static_mac(DEFAULT_VLAN, static_mac, 1);
+
+    send(&a, A, B);
+    run(&mut net, 5);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+    assert_eq!(learned(&switch), vec![(A_MAC, 0), (B_MAC, 1)]);
+
+    // Entries age with the default aging time, static entries are kept.
+    run(&mut net, DEFAULT_MAC_AGING_TIME as usize);
+    assert_eq!(learned(&switch), vec![]);
+    assert!(switch.borrow_mut().mac_entries().any(|entry| entry.mac == static_mac && entry.is_static));
+
+    // Without aging, entries are kept forever.
+    switch.borrow_mut().set_mac_aging_time(None);
+    send(&a, A, B);
+    send(&b, B, A);
+    run(&mut net, 5);
+    run(&mut net, 2 * DEFAULT_MAC_AGING_TIME as usize);
+    assert_eq!(learned(&switch), vec![(A_MAC, 0), (B_MAC, 1)]);
+
+}
+
+#[test]
+fn table_limit() {
+
+    let (mut net, switch, a, b) = switched_pair();
+    switch.borrow_mut().set_mac_table_limit(Some(1), MacTableOverflow::Flood);
+
+    // Unknown destinations are flooded, so traffic still flows.
+    send(&a, A, B);
+    run(&mut net, 5);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+    assert_eq!(learned(&switch), vec![(A_MAC, 0)]);
+
+    switch.borrow_mut().set_mac_table_limit(Some(1), MacTableOverflow::EvictOldest);
+    send(&b, B, A);
+    run(&mut net, 5);
+    assert!(a.borrow_mut().recv_ipv4().is_some());
+    assert_eq!(learned(&switch), vec![(B_MAC, 1)]);
+
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, EthPayload, MacAddr, Ipv4Addr, Ipv4Packet, Ipv4Payload, ArpOp};
+use netcrab::node::{EthSwitch, PortMirror, MirrorDirection, CaptureNode};
+
+use common::{host, run};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+/// A sends a packet to B, the traffic of A's port is mirrored to a capture
+/// node on port 5, the returned frames are summarized by payload kind and
+/// source MAC.
+fn capture_a_to_b(direction: MirrorDirection) -> Vec<(&'static str, MacAddr)> {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, None);
+    let b = host([2, 0, 0, 0, 0, 2], B, None);
+    let capture = RcNode::new(CaptureNode::<EthFrame>::new());
+    let mut switch = EthSwitch::new();
+    switch.add_mirror(PortMirror { sources: vec![(0, direction)], destination: 5 });
+
+    let mut net = Network::new();
+    let (ha, hb, hc) = (net.push(a.clone()), net.push(b.clone()), net.push(capture.clone()));
+    let hs = net.push(switch);
+    net.link::<EthFrame>(ha, 0, hs, 0);
+    net.link::<EthFrame>(hb, 0, hs, 1);
+    net.link::<EthFrame>(hc, 0, hs, 5);
+
+    a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, B, Ipv4Payload::Custom(vec![1]))));
+    run(&mut net, 10);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+
+    let captured = capture.borrow_mut().take();
+    captured.into_iter().map(|captured| {
+        let kind = match &captured.data.payload {
+            EthPayload::Arp(arp) if arp.op == ArpOp::Request => "arp request",
+            EthPayload::Arp(_) => "arp reply",
+            EthPayload::Ipv4(_) => "ipv4",
+            _ => "other",
+        };
+        (kind, captured.data.src)
+    }).collect()
+
+}
+
+#[test]
+fn mirror_both_directions() {
+    assert_eq!(capture_a_to_b(MirrorDirection::Both), vec![
+        ("arp request", MacAddr([2, 0, 0, 0, 0, 1])),
+        ("arp reply", MacAddr([2, 0, 0, 0, 0, 2])),
+        ("ipv4", MacAddr([2, 0, 0, 0, 0, 1])),
+    ]);
+}
+
+#[test]
+fn mirror_one_direction() {
+    assert_eq!(capture_a_to_b(MirrorDirection::Rx), vec![
+        ("arp request", MacAddr([2, 0, 0, 0, 0, 1])),
+        ("ipv4", MacAddr([2, 0, 0, 0, 0, 1])),
+    ]);
+    assert_eq!(capture_a_to_b(MirrorDirection::Tx), vec![
+        ("arp reply", MacAddr([2, 0, 0, 0, 0, 2])),
+    ]);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use netcrab::net::{Network, RcNode, LinkDropReason};
+use netcrab::proto::{EthFrame, Ipv4Addr, Ipv4Packet, Ipv4Payload};
+use netcrab::node::{ServerNode, EthSwitch};
+
+use common::{host, router, run};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+const C: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 1);
+
+fn packet(src: Ipv4Addr, dst: Ipv4Addr, len: usize, allow_fragmentation: bool) -> Box<Ipv4Packet> {
+    let mut packet = Ipv4Packet::new(src, dst, Ipv4Payload::Custom(vec![7; len]));
+    packet.allow_fragmentation = allow_fragmentation;
+    Box::new(packet)
+}
+
+/// Host A and host C on both sides of a router, the MTU of the router's
+/// interface to C is 576.
+fn routed() -> (Network, RcNode<ServerNode>, RcNode<ServerNode>) {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, Some(Ipv4Addr::new(10, 0, 0, 254)));
+    let c = host([2, 0, 0, 0, 0, 3], C, Some(Ipv4Addr::new(10, 0, 1, 254)));
+    let mut r = router(1, &[(0, Ipv4Addr::new(10, 0, 0, 254)), (1, Ipv4Addr::new(10, 0, 1, 254))]);
+    r.get_iface_conf_mut(1).unwrap().mtu = 576;
+
+    let mut net = Network::new();
+    let (ha, hc, hr) = (net.push(a.clone()), net.push(c.clone()), net.push(r));
+    net.link::<EthFrame>(ha, 0, hr, 0);
+    net.link::<EthFrame>(hc, 0, hr, 1);
+
+    (net, a, c)
+
+}
+
+#[test]
+fn link_max_size_drops() {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, None);
+    let b = host([2, 0, 0, 0, 0, 2], B, None);
+    let mut net = Network::new();
+    let (ha, hb) = (net.push(a.clone()), net.push(b.clone()));
+    let link = net.link::<EthFrame>(ha, 0, hb, 0);
+    net.set_link_max_size::<EthFrame>(link, Some(1000));
+
+    // The interface MTU is bigger than the link, a black hole.
+    a.borrow_mut().send_ipv4(packet(A, B, 1400, true));
+    run(&mut net, 10);
+    assert!(b.borrow_mut().recv_ipv4().is_none());
+    assert_eq!(net.link_drops(link).len(), 1);
+    assert_eq!(net.link_drops(link)[0].node, ha);
+    assert_eq!(net.link_drops(link)[0].reason, LinkDropReason::Oversized { size: 1434, max_size: 1000 });
+
+    a.borrow_mut().send_ipv4(packet(A, B, 900, true));
+    run(&mut net, 10);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+    assert_eq!(net.take_link_drops(link).len(), 1);
+    assert!(net.link_drops(link).is_empty());
+
+}
+
+#[test]
+fn link_down_drops() {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, None);
+    let b = host([2, 0, 0, 0, 0, 2], B, None);
+    let mut net = Network::new();
+    let (ha, hb, hs) = (net.push(a.clone()), net.push(b.clone()), net.push(EthSwitch::new()));
+    let link_a = net.link::<EthFrame>(ha, 0, hs, 0);
+    let link_b = net.link::<EthFrame>(hb, 0, hs, 1);
+    net.set_link_up(link_b, false);
+
+    // The switch floods the ARP request of A on the down link.
+    a.borrow_mut().send_ipv4(packet(A, B, 10, true));
+    run(&mut net, 10);
+    assert!(net.link_drops(link_a).is_empty());
+    assert!(!net.link_drops(link_b).is_empty());
+    assert!(net.link_drops(link_b).iter().all(|drop| drop.node == hs && drop.reason == LinkDropReason::LinkDown));
+
+}
+
+#[test]
+fn bus_max_size_drops() {
+
+    let a = host([2, 0, 0, 0, 0, 1], A, None);
+    let b = host([2, 0, 0, 0, 0, 2], B, None);
+    let mut net = Network::new();
+    let members = [(net.push(a.clone()), 0), (net.push(b.clone()), 0)];
+    let bus = net.bus::<EthFrame>(&members);
+    net.set_bus_max_size::<EthFrame>(bus, Some(1000));
+
+    a.borrow_mut().send_ipv4(packet(A, B, 1400, true));
+    a.borrow_mut().send_ipv4(packet(A, B, 900, true));
+    run(&mut net, 10);
+    assert_eq!(b.borrow_mut().recv_ipv4().unwrap().size(), 920);
+    assert!(b.borrow_mut().recv_ipv4().is_none());
+    assert_eq!(net.bus_drops(bus).len(), 1);
+    assert_eq!(net.bus_drops(bus)[0].reason, LinkDropReason::Oversized { size: 1434, max_size: 1000 });
+
+}
+
+#[test]
+fn path_mtu_discovery() {
+
+    let (mut net, a, c) = routed();
+    a.borrow_mut().send_ipv4(packet(A, C, 1400, false));
+    run(&mut net, 10);
+    assert_eq!(a.borrow_mut().ipv4_pmtu(C), Some(576));
+    assert_eq!(a.borrow_mut().tcp_mss(C), Some(536));
+
+    // The MSS can't be negative with a tiny MTU.
+    a.borrow_mut().get_iface_conf_mut(0).unwrap().mtu = 20;
+    assert_eq!(a.borrow_mut().tcp_mss(C), Some(0));
+    a.borrow_mut().get_iface_conf_mut(0).unwrap().mtu = 1500;
+
+    // DF packets bigger than the path MTU are now rejected by the host.
+    while a.borrow_mut().recv_ipv4().is_some() {}
+    a.borrow_mut().send_ipv4(packet(A, C, 1400, false));
+    run(&mut net, 10);
+    assert!(c.borrow_mut().recv_ipv4().is_none());
+
+    // Other packets are fragmented by the host.
+    a.borrow_mut().send_ipv4(packet(A, C, 1400, true));
+    run(&mut net, 10);
+    assert!(c.borrow_mut().recv_ipv4().is_some());
+
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr};
+use netcrab::node::{ServerNode, ServerEthIface, ServerIfaceConf, ServerLoopbackIface, EthSwitch, LOOPBACK_IFACE};
+
+use common::{host, ip, send, run};
+
+
+/// Node A with 10.0.0.1/24 and 192.168.5.1/24 on the same interface, B on
+/// 192.168.5.0/24 and C on 10.0.0.0/24, all on a switch.
+fn multihomed() -> (Network, RcNode<ServerNode>, RcNode<ServerNode>, RcNode<ServerNode>) {
+
+    let conf = ServerIfaceConf::with_ipv4(ip(10, 0, 0, 1), 24).with_secondary_ipv4(ip(192, 168, 5, 1), 24);
+    let a = RcNode::new(ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 6, 0, 1])), conf));
+    let b = host([2, 0, 0, 6, 0, 2], ip(192, 168, 5, 2), None);
+    let c = host([2, 0, 0, 6, 0, 3], ip(10, 0, 0, 3), None);
+
+    let mut net = Network::new();
+    let (ha, hb, hc, hs) = (net.push(a.clone()), net.push(b.clone()), net.push(c.clone()), net.push(EthSwitch::new()));
+    net.link::<EthFrame>(ha, 0, hs, 0);
+    net.link::<EthFrame>(hb, 0, hs, 1);
+    net.link::<EthFrame>(hc, 0, hs, 2);
+
+    (net, a, b, c)
+
+}
+
+#[test]
+fn multiaddr_source_selection() {
+
+    let (mut net, a, b, c) = multihomed();
+    net.tick();
+    // Both connected networks and the loopback network.
+    assert_eq!(a.borrow_mut().get_ipv4_routes().fib().count(), 3);
+
+    // The source address is on the network of the destination.
+    send(&a, Ipv4Addr::UNSPECIFIED, ip(192, 168, 5, 2));
+    send(&a, Ipv4Addr::UNSPECIFIED, ip(10, 0, 0, 3));
+    run(&mut net, 5);
+    assert_eq!(b.borrow_mut().recv_ipv4().unwrap().src, ip(192, 168, 5, 1));
+    assert_eq!(c.borrow_mut().recv_ipv4().unwrap().src, ip(10, 0, 0, 1));
+
+    // The primary address is used off the interface networks.
+    let a = a.borrow_mut();
+    let conf = a.get_iface_conf(0).unwrap();
+    assert_eq!(conf.primary_ipv4().unwrap().ip, ip(10, 0, 0, 1));
+    assert_eq!(conf.ipv4_for(ip(172, 16, 0, 1)).unwrap().ip, ip(10, 0, 0, 1));
+
+}
+
+#[test]
+fn multiaddr_secondary() {
+
+    let (mut net, a, b, _) = multihomed();
+
+    // The secondary address answers ARP and receives packets.
+    send(&b, ip(192, 168, 5, 2), ip(192, 168, 5, 1));
+    run(&mut net, 5);
+    let packet = a.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(packet.dst, ip(192, 168, 5, 1));
+
+    // Removing it removes its connected route.
+    assert!(a.borrow_mut().get_iface_conf_mut(0).unwrap().remove_ipv4(ip(192, 168, 5, 1)));
+    assert!(!a.borrow_mut().get_iface_conf_mut(0).unwrap().remove_ipv4(ip(192, 168, 5, 1)));
+    net.tick();
+    assert!(a.borrow_mut().get_ipv4_routes().fetch(ip(192, 168, 5, 2)).is_none());
+
+}
+
+#[test]
+fn multiaddr_loopback() {
+
+    let (mut net, a, _, _) = multihomed();
+    assert!(a.borrow_mut().get_iface::<ServerLoopbackIface>(LOOPBACK_IFACE).is_some());
+
+    // Packets to addresses of the node never leave it.
+    send(&a, Ipv4Addr::UNSPECIFIED, ip(127, 0, 0, 1));
+    send(&a, Ipv4Addr::UNSPECIFIED, ip(10, 0, 0, 1));
+    send(&a, Ipv4Addr::UNSPECIFIED, ip(192, 168, 5, 1));
+    run(&mut net, 5);
+    let mut received = std::iter::from_fn(|| a.borrow_mut().recv_ipv4())
+        .map(|packet| (packet.src, packet.dst))
+        .collect::<Vec<_>>();
+    received.sort();
+    assert_eq!(received, vec![
+        (ip(10, 0, 0, 1), ip(10, 0, 0, 1)),
+        (ip(127, 0, 0, 1), ip(127, 0, 0, 1)),
+        (ip(192, 168, 5, 1), ip(192, 168, 5, 1)),
+    ]);
+
+}
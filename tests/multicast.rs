@@ -0,0 +1,75 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, Ipv4Packet, Ipv4Payload, Ipv6Addr};
+use netcrab::node::{EthHub, ServerNode, ServerEthIface};
+
+use common::{host, run};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const GROUP: Ipv4Addr = Ipv4Addr::new(239, 1, 1, 1);
+
+/// Four hosts on a hub, A sends to the group and then to B.
+fn hub(setup: impl FnOnce(&[RcNode<ServerNode>])) -> Vec<usize> {
+
+    let hosts: Vec<_> = (1..=4).map(|i| host([2, 0, 0, 0, 0, i], Ipv4Addr::new(10, 0, 0, i), None)).collect();
+    setup(&hosts);
+
+    let mut net = Network::new();
+    let hub = net.push(EthHub::new());
+    for (port, node) in hosts.iter().enumerate() {
+        let handle = net.push(node.clone());
+        net.link::<EthFrame>(handle, 0, hub, port);
+    }
+
+    run(&mut net, 3);
+    hosts[0].borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, GROUP, Ipv4Payload::Custom(vec![1]))));
+    hosts[0].borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, Ipv4Addr::new(10, 0, 0, 2), Ipv4Payload::Custom(vec![2]))));
+    run(&mut net, 10);
+
+    hosts[1..].iter().map(|node| {
+        let mut count = 0;
+        while node.borrow_mut().recv_ipv4().is_some() {
+            count += 1;
+        }
+        count
+    }).collect()
+
+}
+
+#[test]
+fn multicast_filter_joined_groups() {
+    // Only B joined the group, C drops it.
+    assert_eq!(hub(|hosts| {
+        hosts[1].borrow_mut().join_ipv4_group(0, GROUP);
+    }), vec![2, 0, 0]);
+}
+
+#[test]
+fn multicast_filter_manual_mac() {
+    // C accepts the frame, but only delivers packets of joined groups.
+    assert_eq!(hub(|hosts| {
+        hosts[2].borrow_mut().get_iface_mut::<ServerEthIface>(0).unwrap().add_multicast_mac(MacAddr::from_multicast_ipv4(GROUP));
+    }), vec![1, 0, 0]);
+}
+
+#[test]
+fn multicast_filter_promiscuous() {
+    // D accepts all frames, but the unicast packet is for B's address and
+    // the group is only joined by C.
+    assert_eq!(hub(|hosts| {
+        hosts[3].borrow_mut().get_iface_mut::<ServerEthIface>(0).unwrap().set_promiscuous(true);
+        hosts[2].borrow_mut().join_ipv4_group(0, GROUP);
+    }), vec![1, 1, 0]);
+}
+
+#[test]
+fn multicast_ipv6_groups() {
+    let mut node = ServerNode::new();
+    let group = "ff02::1:ff02:3".parse::<Ipv6Addr>().unwrap();
+    assert!(node.join_ipv6_group(0, group));
+    assert!(!node.join_ipv6_group(0, "2001:db8::1".parse().unwrap()));
+    assert_eq!(node.ipv6_groups(0).collect::<Vec<_>>(), vec![group]);
+    assert!(node.leave_ipv6_group(0, group));
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use netcrab::proto::{Ipv4Addr, Ipv4Packet, Ipv4Payload, UdpDatagram, Icmpv4Message, Icmpv4Unreachable};
+use netcrab::node::{Nat, NatPortForward, NatProtocol};
+
+use common::Gateway;
+
+
+fn udp(src: Ipv4Addr, dst: Ipv4Addr, src_port: u16, dst_port: u16) -> Box<Ipv4Packet> {
+    Box::new(Ipv4Packet::new(src, dst, Ipv4Payload::Udp(UdpDatagram { src_port, dst_port, data: vec![] })))
+}
+
+fn gateway_with_nat() -> Gateway {
+    let gw = Gateway::new();
+    let mut nat = Nat::new();
+    nat.add_inside(0);
+    nat.add_outside(1);
+    nat.add_port_forward(NatPortForward {
+        protocol: NatProtocol::Udp,
+        outside_port: 8080,
+        inside: Gateway::INSIDE,
+        inside_port: 80,
+    });
+    gw.router.borrow_mut().set_nat(Some(nat));
+    gw
+}
+
+#[test]
+fn translate_outgoing_and_replies() {
+
+    let mut gw = gateway_with_nat();
+
+    gw.inside.borrow_mut().send_ipv4(udp(Gateway::INSIDE, Gateway::OUTSIDE, 5000, 53));
+    gw.run(10);
+
+    let packet = gw.outside.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(packet.src, Gateway::ROUTER_OUTSIDE);
+    let Ipv4Payload::Udp(datagram) = &packet.payload else { panic!("expected udp: {packet:?}") };
+
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, Gateway::ROUTER_OUTSIDE, 53, datagram.src_port));
+    gw.run(10);
+
+    let reply = gw.inside.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!((reply.src, reply.dst), (Gateway::OUTSIDE, Gateway::INSIDE));
+    assert!(matches!(&reply.payload, Ipv4Payload::Udp(d) if d.src_port == 53 && d.dst_port == 5000));
+
+}
+
+#[test]
+fn translate_echo_identifier() {
+
+    let mut gw = gateway_with_nat();
+
+    let request = Icmpv4Message::EchoRequest { id: 7, seq: 1, data: vec![] };
+    gw.inside.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(Gateway::INSIDE, Gateway::OUTSIDE, Ipv4Payload::Icmp(request))));
+    gw.run(10);
+
+    let reply = gw.inside.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(reply.src, Gateway::OUTSIDE);
+    assert!(matches!(&reply.payload, Ipv4Payload::Icmp(Icmpv4Message::EchoReply { id: 7, .. })));
+
+}
+
+#[test]
+fn translate_fragments() {
+
+    let mut gw = gateway_with_nat();
+    gw.inside.borrow_mut().get_iface_conf_mut(0).unwrap().mtu = 576;
+    gw.outside.borrow_mut().get_iface_conf_mut(0).unwrap().mtu = 576;
+
+    let datagram = UdpDatagram { src_port: 5000, dst_port: 53, data: vec![1; 600] };
+    gw.inside.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(Gateway::INSIDE, Gateway::OUTSIDE, Ipv4Payload::Udp(datagram))));
+    gw.run(10);
+
+    let packet = gw.outside.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(packet.src, Gateway::ROUTER_OUTSIDE);
+    let Ipv4Payload::Udp(datagram) = &packet.payload else { panic!("expected udp: {packet:?}") };
+    assert_eq!(datagram.data.len(), 600);
+
+    let reply = UdpDatagram { src_port: 53, dst_port: datagram.src_port, data: vec![2; 600] };
+    gw.outside.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(Gateway::OUTSIDE, Gateway::ROUTER_OUTSIDE, Ipv4Payload::Udp(reply))));
+    gw.run(10);
+
+    let reply = gw.inside.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(reply.dst, Gateway::INSIDE);
+    assert!(matches!(&reply.payload, Ipv4Payload::Udp(d) if d.dst_port == 5000 && d.data.len() == 600), "{reply:?}");
+
+}
+
+#[test]
+fn port_forward() {
+
+    let mut gw = gateway_with_nat();
+
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, Gateway::ROUTER_OUTSIDE, 999, 8080));
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, Gateway::ROUTER_OUTSIDE, 999, 1234));
+    gw.run(10);
+
+    let packet = gw.inside.borrow_mut().recv_ipv4().unwrap();
+    assert!(matches!(&packet.payload, Ipv4Payload::Udp(d) if d.dst_port == 80));
+    assert!(gw.inside.borrow_mut().recv_ipv4().is_none());
+
+}
+
+#[test]
+fn port_forward_only_to_outside_address() {
+
+    let mut gw = gateway_with_nat();
+
+    // Routed through the router to an inside address, the port forwarding
+    // rule doesn't apply.
+    gw.outside.borrow_mut().send_ipv4(udp(Gateway::OUTSIDE, Gateway::INSIDE, 999, 8080));
+    gw.run(10);
+
+    let packet = gw.inside.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(packet.dst, Gateway::INSIDE);
+    assert!(matches!(&packet.payload, Ipv4Payload::Udp(d) if d.dst_port == 8080));
+    assert_eq!(gw.router.borrow_mut().nat().unwrap().translations().count(), 0);
+
+}
+
+#[test]
+fn rewrite_icmp_error() {
+
+    let mut gw = gateway_with_nat();
+
+    gw.inside.borrow_mut().send_ipv4(udp(Gateway::INSIDE, Gateway::OUTSIDE, 5000, 53));
+    gw.run(10);
+
+    // The outside host answers with a port unreachable error.
+    let original = gw.outside.borrow_mut().recv_ipv4().unwrap();
+    let error = Icmpv4Message::DestinationUnreachable { code: Icmpv4Unreachable::Port, original };
+    gw.outside.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(Gateway::OUTSIDE, Gateway::ROUTER_OUTSIDE, Ipv4Payload::Icmp(error))));
+    gw.run(10);
+
+    let error = gw.inside.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!((error.src, error.dst), (Gateway::OUTSIDE, Gateway::INSIDE));
+    let Ipv4Payload::Icmp(Icmpv4Message::DestinationUnreachable { code, original }) = &error.payload else {
+        panic!("expected destination unreachable: {error:?}")
+    };
+    assert_eq!(*code, Icmpv4Unreachable::Port);
+    assert_eq!((original.src, original.dst), (Gateway::INSIDE, Gateway::OUTSIDE));
+    assert!(matches!(&original.payload, Ipv4Payload::Udp(d) if d.src_port == 5000 && d.dst_port == 53));
+
+}
+
+#[test]
+fn expire_translations() {
+
+    let mut gw = gateway_with_nat();
+
+    gw.inside.borrow_mut().send_ipv4(udp(Gateway::INSIDE, Gateway::OUTSIDE, 5000, 53));
+    gw.run(10);
+    assert_eq!(gw.router.borrow_mut().nat().unwrap().translations().count(), 1);
+
+    let timeout = gw.router.borrow_mut().nat().unwrap().timeouts().udp;
+    gw.run(timeout as usize);
+    assert_eq!(gw.router.borrow_mut().nat().unwrap().translations().count(), 0);
+
+}
+
+#[test]
+fn allocate_ports() {
+
+    let mut gw = gateway_with_nat();
+    let recv_src_ports = |gw: &Gateway| {
+        let mut outside = gw.outside.borrow_mut();
+        std::iter::from_fn(|| outside.recv_ipv4())
+            .filter_map(|packet| match &packet.payload {
+                Ipv4Payload::Udp(datagram) => Some(datagram.src_port),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Well-known ports are translated to the first dynamic port.
+    gw.inside.borrow_mut().send_ipv4(udp(Gateway::INSIDE, Gateway::OUTSIDE, 80, 53));
+    gw.run(10);
+    assert_eq!(recv_src_ports(&gw), vec![1024]);
+
+    // Other ports are kept, all ports are now used, up to the last one,
+    // the forwarded port is reserved.
+    for port in (1025..=u16::MAX).filter(|&port| port != 8080) {
+        gw.inside.borrow_mut().send_ipv4(udp(Gateway::INSIDE, Gateway::OUTSIDE, port, 53));
+    }
+    gw.run(5);
+    assert_eq!(recv_src_ports(&gw).len(), 64510);
+
+    // Without a free port, packets are dropped.
+    gw.inside.borrow_mut().send_ipv4(udp(Gateway::INSIDE, Gateway::OUTSIDE, 81, 53));
+    gw.run(5);
+    assert!(recv_src_ports(&gw).is_empty());
+
+}
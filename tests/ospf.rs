@@ -0,0 +1,79 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, Ipv4Addr, IpAddrExt, OSPF_DEAD_INTERVAL};
+use netcrab::node::{ServerNode, EthSwitch, Ospf, OspfIfaceConf};
+
+use common::{router, ip, run, ping, recv_echo_reply};
+
+
+fn ospf_router(id: u8, ifaces: &[(usize, Ipv4Addr)]) -> RcNode<ServerNode> {
+    let mut node = router(id, ifaces);
+    let mut ospf = Ospf::new(ip(id, id, id, id));
+    for &(iface, _) in ifaces {
+        ospf.add_iface(iface, OspfIfaceConf::default());
+    }
+    node.set_ospf(Some(ospf));
+    RcNode::new(node)
+}
+
+/// Three routers on a switched network, and a router D with a stub
+/// network connected to R1 and R3 with point-to-point links:
+///
+/// ```text
+///      switch 10.0.0.0/24
+///     /      |      \
+///   R1      R2      R3
+///     \             /
+///      D -- 10.0.4.0/24
+/// ```
+#[test]
+fn ospf_reconverge_after_link_failure() {
+
+    let r1 = ospf_router(1, &[(0, ip(10, 0, 0, 1)), (1, ip(10, 0, 14, 1))]);
+    let r2 = ospf_router(2, &[(0, ip(10, 0, 0, 2))]);
+    let r3 = ospf_router(3, &[(0, ip(10, 0, 0, 3)), (1, ip(10, 0, 34, 3))]);
+    let d = ospf_router(4, &[(0, ip(10, 0, 14, 4)), (1, ip(10, 0, 34, 4)), (2, ip(10, 0, 4, 4))]);
+
+    let mut net = Network::new();
+    let (h1, h2, h3, hd) = (net.push(r1.clone()), net.push(r2.clone()), net.push(r3.clone()), net.push(d.clone()));
+    let switch = net.push(EthSwitch::new());
+    net.link::<EthFrame>(h1, 0, switch, 1);
+    net.link::<EthFrame>(h2, 0, switch, 2);
+    net.link::<EthFrame>(h3, 0, switch, 3);
+    let link_1d = net.link::<EthFrame>(h1, 1, hd, 0);
+    net.link::<EthFrame>(h3, 1, hd, 1);
+    run(&mut net, 100);
+
+    let stub = ip(10, 0, 4, 0).take_prefix(24);
+    {
+        // The highest router identifier is elected designated router.
+        let node = r2.borrow_mut();
+        let ospf = node.ospf().unwrap();
+        assert_eq!(ospf.dr(0), Some(ip(10, 0, 0, 3)));
+        assert_eq!(ospf.bdr(0), Some(ip(10, 0, 0, 2)));
+        assert!(ospf.routes().iter().any(|route| route.prefix == stub && route.cost == 30));
+    }
+    {
+        let node = r1.borrow_mut();
+        let route = *node.ospf().unwrap().routes().iter().find(|route| route.prefix == stub).unwrap();
+        assert_eq!((route.next_hop, route.cost), (ip(10, 0, 14, 4), 20));
+    }
+
+    ping(&r2, ip(10, 0, 0, 2), ip(10, 0, 4, 4));
+    run(&mut net, 5);
+    assert!(recv_echo_reply(&r2));
+
+    // The routes are recomputed at the latest when the neighbor is dead.
+    let down = net.time();
+    net.set_link_up(link_1d, false);
+    run(&mut net, 100);
+
+    let node = r1.borrow_mut();
+    let change = node.ospf().unwrap().last_route_change().unwrap();
+    assert!(change > down && change <= down + OSPF_DEAD_INTERVAL as u64, "{change}");
+    let route = *node.ospf().unwrap().routes().iter().find(|route| route.prefix == stub).unwrap();
+    assert_eq!((route.next_hop, route.cost), (ip(10, 0, 0, 3), 30));
+    assert_eq!(node.get_ipv4_routes().fetch(ip(10, 0, 4, 1)), Some((0, ip(10, 0, 0, 3))));
+
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use netcrab::net::{Network, RcNode, LinkId, LinkHandle, RawLinkHandle, Links, Node};
+use netcrab::proto::{Ipv4Addr, Ipv4Packet, Ipv4Payload, PppFrame, PppControlPacket, PppCode};
+use netcrab::node::{ServerNode, ServerPppIface, ServerIfaceConf, PppState};
+
+use common::run;
+
+
+const SERVER: Ipv4Addr = Ipv4Addr::new(10, 9, 9, 1);
+const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 9, 9, 2);
+
+/// A server offering an address to a client without configuration.
+fn ppp_link(offered: Option<Ipv4Addr>, server_mtu: u16) -> (Network, LinkId, RcNode<ServerNode>, RcNode<ServerNode>) {
+
+    let mut iface = ServerPppIface::new();
+    iface.set_offered_ipv4(offered);
+    let server = RcNode::new(ServerNode::with_iface_conf(0, iface, ServerIfaceConf::with_ipv4(SERVER, 30).with_mtu(server_mtu)));
+    let client = RcNode::new(ServerNode::with_iface(0, ServerPppIface::new()));
+
+    let mut net = Network::new();
+    let server_handle = net.push(server.clone());
+    let client_handle = net.push(client.clone());
+    let link = net.link::<PppFrame>(server_handle, 0, client_handle, 0);
+
+    (net, link, server, client)
+
+}
+
+#[test]
+fn assign_address_with_ipcp() {
+
+    let (mut net, _, server, client) = ppp_link(Some(CLIENT), 1500);
+    run(&mut net, 10);
+
+    assert!(client.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().is_opened());
+    assert_eq!(client.borrow_mut().get_iface_conf(0).unwrap().primary_ipv4().unwrap().ip, CLIENT);
+    assert_eq!(client.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().peer_ipv4(), Some(SERVER));
+    assert_eq!(server.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().peer_ipv4(), Some(CLIENT));
+
+    // The client has no route configured, the route to its peer is enough.
+    client.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(Ipv4Addr::UNSPECIFIED, SERVER, Ipv4Payload::Custom(vec![1]))));
+    run(&mut net, 3);
+    assert_eq!(server.borrow_mut().recv_ipv4().unwrap().src, CLIENT);
+
+}
+
+#[test]
+fn renegotiate_after_link_failure() {
+
+    let (mut net, link, server, client) = ppp_link(Some(CLIENT), 1500);
+    run(&mut net, 10);
+
+    // Echo requests keep the link open.
+    run(&mut net, 100);
+    assert!(server.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().is_opened());
+
+    net.set_link_up(link, false);
+    net.tick();
+    assert!(client.borrow_mut().get_iface_conf(0).unwrap().primary_ipv4().is_none());
+    assert!(client.borrow_mut().get_ipv4_routes().fetch(SERVER).is_none());
+
+    net.set_link_up(link, true);
+    run(&mut net, 20);
+    assert!(client.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().is_opened());
+    assert!(client.borrow_mut().get_iface_conf(0).unwrap().has_ipv4(CLIENT));
+
+}
+
+#[test]
+fn clamp_mtu_to_peer_mru() {
+
+    let (mut net, link, server, client) = ppp_link(Some(CLIENT), 600);
+    run(&mut net, 10);
+    assert_eq!(client.borrow_mut().get_iface_conf(0).unwrap().mtu, 600);
+
+    client.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(Ipv4Addr::UNSPECIFIED, SERVER, Ipv4Payload::Custom(vec![1; 1000]))));
+    run(&mut net, 3);
+    assert_eq!(server.borrow_mut().recv_ipv4().unwrap().size(), 1020);
+
+    // The configured MTU is restored when the link goes down.
+    net.set_link_up(link, false);
+    net.tick();
+    assert_eq!(client.borrow_mut().get_iface_conf(0).unwrap().mtu, 1500);
+
+}
+
+#[test]
+fn open_without_address() {
+
+    let (mut net, _, server, client) = ppp_link(None, 1500);
+    run(&mut net, 10);
+
+    assert!(server.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().is_opened());
+    assert!(client.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().is_opened());
+    assert!(client.borrow_mut().get_iface_conf(0).unwrap().primary_ipv4().is_none());
+
+}
+
+/// A PPP peer acknowledging every LCP configure request, that only sends
+/// its own request at the given time.
+struct LatePeer {
+    link: Option<LinkHandle<PppFrame>>,
+    start: u64,
+    /// Times of the configure requests received.
+    requests: Vec<u64>,
+    /// Our configure request has been acknowledged.
+    acked: bool,
+}
+
+impl Node for LatePeer {
+
+    fn link(&mut self, _iface: usize, link: RawLinkHandle) -> bool {
+        self.link = link.cast();
+        self.link.is_some()
+    }
+
+    fn tick(&mut self, links: &mut Links) {
+        let Some(handle) = &self.link else { return };
+        let mut link = links.get(handle);
+        let time = link.time();
+        while let Some(frame) = link.recv() {
+            match *frame {
+                PppFrame::Lcp(packet) if packet.code == PppCode::ConfigureRequest => {
+                    self.requests.push(time);
+                    link.send(Box::new(PppFrame::Lcp(PppControlPacket::new(PppCode::ConfigureAck, packet.identifier, packet.options))));
+                }
+                PppFrame::Lcp(packet) if packet.code == PppCode::ConfigureAck => self.acked = true,
+                _ => {}
+            }
+        }
+        if time == self.start {
+            link.send(Box::new(PppFrame::Lcp(PppControlPacket::new(PppCode::ConfigureRequest, 1, Vec::new()))));
+        }
+    }
+
+}
+
+#[test]
+fn wait_for_late_peer() {
+
+    let node = RcNode::new(ServerNode::with_iface(0, ServerPppIface::new()));
+    let peer = RcNode::new(LatePeer { link: None, start: 10, requests: Vec::new(), acked: false });
+
+    let mut net = Network::new();
+    let (hn, hp) = (net.push(node.clone()), net.push(peer.clone()));
+    net.link::<PppFrame>(hn, 0, hp, 0);
+    run(&mut net, 8);
+
+    // Our request is acknowledged, it is only sent again on timeout while
+    // waiting for the request of the peer.
+    assert_eq!(node.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().lcp_state(), PppState::AckReceived);
+    assert!(peer.borrow_mut().requests.windows(2).all(|times| times[1] - times[0] >= 3), "{:?}", peer.borrow_mut().requests);
+
+    run(&mut net, 5);
+    assert_eq!(node.borrow_mut().get_iface::<ServerPppIface>(0).unwrap().lcp_state(), PppState::Opened);
+    assert!(peer.borrow_mut().acked);
+
+}
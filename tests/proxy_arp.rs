@@ -0,0 +1,135 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, Ipv4Payload, Icmpv4Message};
+use netcrab::node::{ServerNode, ServerEthIface, ServerIfaceConf, IpRouteLink, ArpState, ArpSpoofNode, EthSwitch};
+
+use common::{host, router, send, count_received, run};
+
+
+fn recv_echo_request(node: &RcNode<ServerNode>) -> bool {
+    let mut node = node.borrow_mut();
+    std::iter::from_fn(|| node.recv_ipv4())
+        .any(|packet| matches!(packet.payload, Ipv4Payload::Icmp(Icmpv4Message::EchoRequest { .. })))
+}
+
+/// Host A believes that the whole 10.0.0.0/16 network is on its link, the
+/// router answers for hosts of 10.0.1.0/24 on its other interface.
+#[test]
+fn proxy_arp() {
+
+    let a_ip = Ipv4Addr::new(10, 0, 0, 1);
+    let c_ip = Ipv4Addr::new(10, 0, 1, 1);
+
+    let mut a = ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 1, 0, 1])), ServerIfaceConf::with_ipv4(a_ip, 16));
+    a.get_ipv4_routes_mut().set_default_route(0, IpRouteLink::Direct);
+    let a = RcNode::new(a);
+    let c = host([2, 0, 0, 1, 1, 1], c_ip, Some(Ipv4Addr::new(10, 0, 1, 254)));
+
+    let mut r = router(1, &[(0, Ipv4Addr::new(10, 0, 0, 254)), (1, Ipv4Addr::new(10, 0, 1, 254))]);
+    r.get_iface_mut::<ServerEthIface>(0).unwrap().set_proxy_arp(true);
+    r.get_ipv4_routes_mut().set_default_route(1, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 1, 253)));
+    let r = RcNode::new(r);
+    let router_mac = r.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().mac_addr();
+
+    let mut net = Network::new();
+    let (ha, hc, hr) = (net.push(a.clone()), net.push(c.clone()), net.push(r));
+    net.link::<EthFrame>(ha, 0, hr, 0);
+    net.link::<EthFrame>(hc, 0, hr, 1);
+
+    send(&a, a_ip, c_ip);
+    run(&mut net, 10);
+    assert_eq!(count_received(&c), 1);
+    let arp = a.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().get_arp(c_ip).unwrap();
+    assert_eq!(arp.state, ArpState::Reachable(router_mac));
+
+    // Addresses only matched by the default route are not proxied.
+    let other = Ipv4Addr::new(8, 8, 8, 8);
+    send(&a, a_ip, other);
+    run(&mut net, 5);
+    let arp = a.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().get_arp(other).unwrap();
+    assert_eq!(arp.state, ArpState::Incomplete);
+
+}
+
+/// An attacker resolves and poisons two hosts on a switch, and relays
+/// their traffic.
+#[test]
+fn spoof_and_relay() {
+
+    let a_ip = Ipv4Addr::new(10, 0, 0, 1);
+    let b_ip = Ipv4Addr::new(10, 0, 0, 2);
+
+    let a = host([2, 0, 0, 2, 0, 1], a_ip, None);
+    let b = host([2, 0, 0, 2, 0, 2], b_ip, None);
+    let mut attacker = ArpSpoofNode::new(MacAddr([2, 0, 0, 2, 0, 9]), Ipv4Addr::new(10, 0, 0, 9), 24);
+    attacker.add_target(a_ip);
+    attacker.add_target(b_ip);
+    let attacker = RcNode::new(attacker);
+
+    let mut net = Network::new();
+    let (ha, hb, hx) = (net.push(a.clone()), net.push(b.clone()), net.push(attacker.clone()));
+    let switch = net.push(EthSwitch::new());
+    net.link::<EthFrame>(ha, 0, switch, 0);
+    net.link::<EthFrame>(hb, 0, switch, 1);
+    net.link::<EthFrame>(hx, 0, switch, 2);
+    run(&mut net, 10);
+    // Targets are pinged until their MAC address is resolved.
+    assert!(recv_echo_request(&b));
+
+    for _ in 0..3 {
+        send(&a, a_ip, b_ip);
+    }
+    run(&mut net, 10);
+    assert_eq!(count_received(&b), 3);
+    assert_eq!(attacker.borrow_mut().intercepted(), 3);
+
+    // Without relaying the traffic is dropped.
+    attacker.borrow_mut().set_relay(false);
+    send(&a, a_ip, b_ip);
+    run(&mut net, 10);
+    assert_eq!(count_received(&b), 0);
+    assert_eq!(attacker.borrow_mut().intercepted(), 4);
+
+}
+
+/// An attacker poisons a host and its gateway, traffic to other networks
+/// is relayed to the gateway, whatever the order of the targets.
+#[test]
+fn spoof_gateway() {
+
+    let a_ip = Ipv4Addr::new(10, 0, 0, 1);
+    let gateway_ip = Ipv4Addr::new(10, 0, 0, 254);
+    let c_ip = Ipv4Addr::new(10, 0, 1, 1);
+
+    let a = host([2, 0, 0, 3, 0, 1], a_ip, Some(gateway_ip));
+    let c = host([2, 0, 0, 3, 1, 1], c_ip, Some(Ipv4Addr::new(10, 0, 1, 254)));
+    let r = RcNode::new(router(3, &[(0, gateway_ip), (1, Ipv4Addr::new(10, 0, 1, 254))]));
+
+    let mut attacker = ArpSpoofNode::new(MacAddr([2, 0, 0, 3, 0, 9]), Ipv4Addr::new(10, 0, 0, 9), 24);
+    attacker.add_target(a_ip);
+    attacker.add_target(gateway_ip);
+    let attacker = RcNode::new(attacker);
+
+    let mut net = Network::new();
+    let (ha, hc, hr, hx) = (net.push(a.clone()), net.push(c.clone()), net.push(r), net.push(attacker.clone()));
+    let switch = net.push(EthSwitch::new());
+    net.link::<EthFrame>(ha, 0, switch, 0);
+    net.link::<EthFrame>(hr, 0, switch, 1);
+    net.link::<EthFrame>(hx, 0, switch, 2);
+    net.link::<EthFrame>(hc, 0, hr, 1);
+    run(&mut net, 10);
+
+    // Without gateway, traffic to other networks can't be relayed.
+    send(&a, a_ip, c_ip);
+    run(&mut net, 10);
+    assert_eq!(count_received(&c), 0);
+    assert_eq!(attacker.borrow_mut().intercepted(), 1);
+
+    attacker.borrow_mut().set_gateway(Some(gateway_ip));
+    send(&a, a_ip, c_ip);
+    run(&mut net, 10);
+    assert_eq!(count_received(&c), 1);
+    assert_eq!(attacker.borrow_mut().intercepted(), 2);
+
+}
@@ -0,0 +1,116 @@
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, IpAddrExt};
+use netcrab::node::{NoopNode, ServerNode, ServerEthIface, ServerIfaceConf, IpRoutes, IpRouteLink, IpRouteSource};
+
+
+#[test]
+fn rib_admin_distance() {
+
+    let mut routes = IpRoutes::<Ipv4Addr>::new();
+    let prefix = Ipv4Addr::new(10, 1, 0, 0).take_prefix(16);
+    let dst = Ipv4Addr::new(10, 1, 2, 3);
+
+    routes.add_route_from(prefix, 1, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 1, 2)), IpRouteSource::Rip);
+    routes.add_route_from(prefix, 2, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 2, 2)), IpRouteSource::Ospf);
+    assert_eq!(routes.fetch(dst), Some((2, Ipv4Addr::new(10, 0, 2, 2))));
+
+    // Static routes win over dynamic ones by default.
+    routes.add_route(prefix, 3, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 3, 2)));
+    assert_eq!(routes.fetch(dst), Some((3, Ipv4Addr::new(10, 0, 3, 2))));
+    assert_eq!(routes.routes().count(), 3);
+
+    // A floating static route.
+    routes.set_admin_distance(IpRouteSource::Static, 200);
+    assert_eq!(routes.admin_distance(IpRouteSource::Static), 200);
+    assert_eq!(routes.fetch(dst), Some((2, Ipv4Addr::new(10, 0, 2, 2))));
+
+    routes.remove_routes_from(IpRouteSource::Ospf);
+    assert_eq!(routes.fetch(dst), Some((1, Ipv4Addr::new(10, 0, 1, 2))));
+
+}
+
+#[test]
+fn rib_ibgp_distance() {
+
+    let mut routes = IpRoutes::<Ipv4Addr>::new();
+    let prefix = Ipv4Addr::new(10, 1, 0, 0).take_prefix(16);
+    let dst = Ipv4Addr::new(10, 1, 2, 3);
+
+    // Routes from internal BGP peers lose against the IGP.
+    routes.add_route_from(prefix, 1, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 1, 2)), IpRouteSource::Ibgp);
+    routes.add_route_from(prefix, 2, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 2, 2)), IpRouteSource::Ospf);
+    assert_eq!(routes.fetch(dst), Some((2, Ipv4Addr::new(10, 0, 2, 2))));
+    routes.add_route_from(prefix, 3, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 3, 2)), IpRouteSource::Bgp);
+    assert_eq!(routes.fetch(dst), Some((3, Ipv4Addr::new(10, 0, 3, 2))));
+
+    routes.remove_routes_from(IpRouteSource::Bgp);
+    routes.remove_routes_from(IpRouteSource::Ospf);
+    assert_eq!(routes.fetch(dst), Some((1, Ipv4Addr::new(10, 0, 1, 2))));
+
+}
+
+#[test]
+fn rib_many_prefixes() {
+
+    let mut routes = IpRoutes::<Ipv4Addr>::new();
+    for i in 0..1000u32 {
+        let [_, _, a, b] = i.to_be_bytes();
+        let prefix = Ipv4Addr::new(10, a, b, 0).take_prefix(24);
+        routes.add_route_from(prefix, 1, IpRouteLink::Indirect(Ipv4Addr::new(192, 168, 0, 1)), IpRouteSource::Rip);
+        routes.add_route_from(prefix, 2, IpRouteLink::Indirect(Ipv4Addr::new(192, 168, 0, 2)), IpRouteSource::Ospf);
+    }
+    routes.add_route(Ipv4Addr::new(10, 0, 0, 0).take_prefix(8), 0, IpRouteLink::Direct);
+
+    assert_eq!(routes.fib().count(), 1001);
+    assert_eq!(routes.fib().last().unwrap().0.prefix_len(), 8);
+    assert_eq!(routes.fetch(Ipv4Addr::new(10, 3, 231, 1)), Some((2, Ipv4Addr::new(192, 168, 0, 2))));
+    assert_eq!(routes.fetch(Ipv4Addr::new(10, 3, 232, 1)), Some((0, Ipv4Addr::new(10, 3, 232, 1))));
+
+}
+
+#[test]
+fn rib_longest_prefix_first() {
+
+    let mut routes = IpRoutes::<Ipv4Addr>::new();
+    routes.set_default_route(0, IpRouteLink::Indirect(Ipv4Addr::new(10, 0, 0, 254)));
+    routes.add_route_from(Ipv4Addr::new(10, 1, 0, 0).take_prefix(16), 1, IpRouteLink::Direct, IpRouteSource::Bgp);
+    routes.add_route(Ipv4Addr::new(10, 1, 2, 0).take_prefix(24), 2, IpRouteLink::Direct);
+
+    let lens = routes.fib().map(|(prefix, ..)| prefix.prefix_len()).collect::<Vec<_>>();
+    assert_eq!(lens, vec![24, 16, 0]);
+    assert_eq!(routes.fetch(Ipv4Addr::new(10, 1, 2, 3)).unwrap().0, 2);
+    assert_eq!(routes.fetch(Ipv4Addr::new(10, 1, 3, 3)).unwrap().0, 1);
+    assert_eq!(routes.fetch(Ipv4Addr::new(192, 0, 2, 1)), Some((0, Ipv4Addr::new(10, 0, 0, 254))));
+    assert_eq!(routes.fetch_specific(Ipv4Addr::new(192, 0, 2, 1)), None);
+
+}
+
+#[test]
+fn rib_connected_routes() {
+
+    let inside = Ipv4Addr::new(10, 0, 0, 254);
+    let outside = Ipv4Addr::new(10, 0, 1, 1);
+    let mut router = ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 0, 1, 0])), ServerIfaceConf::with_ipv4(inside, 24));
+    router.add_iface_conf(1, ServerEthIface::new(MacAddr([2, 0, 0, 0, 1, 1])), ServerIfaceConf::with_ipv4(Ipv4Addr::new(10, 0, 1, 254), 24));
+    assert_eq!(router.get_ipv4_routes().fetch(outside), Some((1, outside)));
+
+    // A static route loses against the connected one, until the connected
+    // distance is raised.
+    router.get_ipv4_routes_mut().add_route(outside.take_prefix(24), 0, IpRouteLink::Direct);
+    assert_eq!(router.get_ipv4_routes().fetch(outside), Some((1, outside)));
+    router.get_ipv4_routes_mut().set_admin_distance(IpRouteSource::Connected, 5);
+    assert_eq!(router.get_ipv4_routes().fetch(outside), Some((0, outside)));
+
+    // Connected routes follow the interface configuration.
+    router.get_ipv4_routes_mut().set_admin_distance(IpRouteSource::Connected, 0);
+    router.get_iface_conf_mut(1).unwrap().ipv4.clear();
+    let router = RcNode::new(router);
+    let mut net = Network::new();
+    let (hr, hn) = (net.push(router.clone()), net.push(NoopNode::<EthFrame>::new()));
+    net.link::<EthFrame>(hr, 0, hn, 0);
+    net.link::<EthFrame>(hr, 1, hn, 1);
+    net.tick();
+    assert_eq!(router.borrow_mut().get_ipv4_routes().fetch(outside), Some((0, outside)));
+    assert_eq!(router.borrow_mut().get_ipv4_routes().fetch(inside), Some((0, inside)));
+
+}
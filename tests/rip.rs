@@ -0,0 +1,121 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{
+    EthFrame, EthPayload, Ipv4Addr, IpAddrExt, IpPrefix, Ipv4Payload,
+    RipMessage, RipCommand, RIP_PORT, RIP_INFINITY,
+};
+use netcrab::node::{ServerNode, EthHub, CaptureNode, Rip, RipSplitHorizon};
+
+use common::{host, router, ip, run, run_until, ping, recv_echo_reply};
+
+
+fn prefix(a: u8, b: u8, c: u8, d: u8, len: u8) -> IpPrefix<Ipv4Addr> {
+    ip(a, b, c, d).take_prefix(len)
+}
+
+fn rip_router(id: u8, ifaces: &[(usize, Ipv4Addr)]) -> RcNode<ServerNode> {
+    let mut node = router(id, ifaces);
+    let mut rip = Rip::new();
+    for &(iface, _) in ifaces {
+        rip.add_iface(iface);
+    }
+    node.set_rip(Some(rip));
+    RcNode::new(node)
+}
+
+/// Triangle of routers, with a stub network on A and C:
+///
+/// ```text
+/// 10.0.1.0/24 - A ---- B
+///                \    /
+///                  C - 10.0.3.0/24
+/// ```
+#[test]
+fn rip_reconverge_after_link_failure() {
+
+    let a = rip_router(1, &[(0, ip(10, 0, 1, 1)), (1, ip(10, 0, 12, 1)), (2, ip(10, 0, 13, 1))]);
+    let b = rip_router(2, &[(0, ip(10, 0, 12, 2)), (1, ip(10, 0, 23, 2))]);
+    let c = rip_router(3, &[(0, ip(10, 0, 23, 3)), (1, ip(10, 0, 3, 3)), (2, ip(10, 0, 13, 3))]);
+
+    let mut net = Network::new();
+    let (ha, hb, hc) = (net.push(a.clone()), net.push(b.clone()), net.push(c.clone()));
+    net.link::<EthFrame>(ha, 1, hb, 0);
+    net.link::<EthFrame>(hb, 1, hc, 0);
+    let link_ac = net.link::<EthFrame>(ha, 2, hc, 2);
+    run(&mut net, 10);
+
+    let stub = prefix(10, 0, 3, 0, 24);
+    let route = a.borrow_mut().rip().unwrap().route(stub).unwrap();
+    assert_eq!((route.metric, route.next_hop), (2, ip(10, 0, 13, 3)));
+
+    ping(&a, ip(10, 0, 13, 1), ip(10, 0, 3, 3));
+    run(&mut net, 5);
+    assert!(recv_echo_reply(&a));
+
+    // Routes through the failed link time out and are replaced.
+    net.set_link_up(link_ac, false);
+    run_until(&mut net, 300, || a.borrow_mut().rip().unwrap().route(stub).unwrap().metric == 3);
+    let route = a.borrow_mut().rip().unwrap().route(stub).unwrap();
+    assert_eq!(route.next_hop, ip(10, 0, 12, 2));
+
+}
+
+/// Get the metrics advertised for a prefix in the RIP responses captured.
+fn captured_rip_metrics(capture: &RcNode<CaptureNode<EthFrame>>, src: Ipv4Addr, prefix: IpPrefix<Ipv4Addr>) -> Vec<u8> {
+    capture.borrow_mut().take().into_iter()
+        .filter_map(|captured| match captured.data.payload {
+            EthPayload::Ipv4(packet) if packet.src == src => match packet.payload {
+                Ipv4Payload::Udp(datagram) if datagram.dst_port == RIP_PORT => RipMessage::decode(&datagram.data),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|message| message.command == RipCommand::Response)
+        .flat_map(|message| message.entries)
+        .filter(|entry| entry.prefix == prefix)
+        .map(|entry| entry.metric)
+        .collect()
+}
+
+/// Routers A and B share a hub with a capture, B has a stub network that A
+/// learns from it.
+#[test]
+fn rip_split_horizon() {
+
+    let a = rip_router(1, &[(0, ip(10, 0, 12, 1))]);
+    let b = rip_router(2, &[(0, ip(10, 0, 12, 2)), (1, ip(10, 0, 2, 2))]);
+    let stub_host = host([2, 0, 0, 9, 0, 1], ip(10, 0, 2, 1), None);
+    let capture = RcNode::new(CaptureNode::<EthFrame>::new());
+
+    let mut net = Network::new();
+    let (ha, hb, hh) = (net.push(a.clone()), net.push(b.clone()), net.push(stub_host));
+    let (hub, hcap) = (net.push(EthHub::new()), net.push(capture.clone()));
+    net.link::<EthFrame>(ha, 0, hub, 0);
+    net.link::<EthFrame>(hb, 0, hub, 1);
+    net.link::<EthFrame>(hcap, 0, hub, 2);
+    net.link::<EthFrame>(hb, 1, hh, 0);
+
+    let stub = prefix(10, 0, 2, 0, 24);
+    let update = Rip::new().timers().update as usize;
+    run(&mut net, 10);
+    assert_eq!(a.borrow_mut().rip().unwrap().route(stub).unwrap().iface, 0);
+
+    // The route is advertised back as unreachable by default.
+    capture.borrow_mut().take();
+    run(&mut net, update);
+    let metrics = captured_rip_metrics(&capture, ip(10, 0, 12, 1), stub);
+    assert!(!metrics.is_empty());
+    assert!(metrics.iter().all(|&metric| metric == RIP_INFINITY));
+
+    a.borrow_mut().rip_mut().unwrap().set_split_horizon(RipSplitHorizon::Simple);
+    run(&mut net, update);
+    assert!(captured_rip_metrics(&capture, ip(10, 0, 12, 1), stub).is_empty());
+
+    a.borrow_mut().rip_mut().unwrap().set_split_horizon(RipSplitHorizon::Disabled);
+    run(&mut net, update);
+    let metrics = captured_rip_metrics(&capture, ip(10, 0, 12, 1), stub);
+    assert!(!metrics.is_empty());
+    assert!(metrics.iter().all(|&metric| metric == 2));
+
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use netcrab::net::{Network, RcNode, LinkId};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, Ipv4Packet, Ipv4Payload, BridgeId};
+use netcrab::node::{ServerNode, EthSwitch, StpBridge, StpPortRole, StpPortState, MAX_PORT_NUMBER};
+
+use common::{host, run, run_until};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+fn stp_switch(id: u8) -> RcNode<EthSwitch> {
+    let mut stp = StpBridge::new(BridgeId::new(32768, MacAddr([2, 0, 0, 0, 1, id])));
+    stp.set_timers(2, 20, 4);
+    let mut switch = EthSwitch::new();
+    switch.set_stp(Some(stp));
+    RcNode::new(switch)
+}
+
+/// Three switches in a loop, with host A on switch 1 and B on switch 2:
+///
+/// ```text
+///        [1] switch 0 [3]
+///        /              \
+///      [1]              [3]
+/// A [0] switch 1 [2] -- [2] switch 2 [0] B
+/// ```
+fn triangle() -> (Network, [RcNode<EthSwitch>; 3], RcNode<ServerNode>, RcNode<ServerNode>, LinkId) {
+
+    let switches = [stp_switch(0), stp_switch(1), stp_switch(2)];
+    let a = host([2, 0, 0, 0, 0, 1], A, None);
+    let b = host([2, 0, 0, 0, 0, 2], B, None);
+
+    let mut net = Network::new();
+    let hs = switches.clone().map(|switch| net.push(switch));
+    net.link::<EthFrame>(hs[0], 1, hs[1], 1);
+    net.link::<EthFrame>(hs[1], 2, hs[2], 2);
+    let link_20 = net.link::<EthFrame>(hs[2], 3, hs[0], 3);
+    let (ha, hb) = (net.push(a.clone()), net.push(b.clone()));
+    net.link::<EthFrame>(ha, 0, hs[1], 0);
+    net.link::<EthFrame>(hb, 0, hs[2], 0);
+
+    (net, switches, a, b, link_20)
+
+}
+
+#[test]
+fn converge_and_block_loop() {
+
+    let (mut net, switches, a, b, _) = triangle();
+    run(&mut net, 40);
+
+    let root_id = switches[0].borrow_mut().stp().unwrap().bridge_id();
+    for switch in &switches {
+        assert_eq!(switch.borrow_mut().stp().unwrap().root_id(), root_id);
+    }
+    assert!(switches[0].borrow_mut().stp().unwrap().is_root());
+    assert_eq!(switches[1].borrow_mut().stp().unwrap().root_port(), Some(1));
+    assert_eq!(switches[2].borrow_mut().stp().unwrap().root_port(), Some(3));
+
+    // The segment between switches 1 and 2 is designated by the lowest
+    // bridge, switch 2 blocks its end.
+    let port = switches[1].borrow_mut().stp().unwrap().port_info(2).unwrap();
+    assert_eq!((port.role, port.state), (StpPortRole::Designated, StpPortState::Forwarding));
+    let port = switches[2].borrow_mut().stp().unwrap().port_info(2).unwrap();
+    assert_eq!((port.role, port.state), (StpPortRole::Alternate, StpPortState::Blocking));
+
+    // Flooded frames don't loop, the packet is received once.
+    a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, B, Ipv4Payload::Custom(vec![1]))));
+    run(&mut net, 20);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+    assert!(b.borrow_mut().recv_ipv4().is_none());
+
+}
+
+#[test]
+fn reconverge_on_link_failure() {
+
+    let (mut net, switches, a, b, link_20) = triangle();
+    run(&mut net, 40);
+
+    a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, B, Ipv4Payload::Custom(vec![1]))));
+    run(&mut net, 20);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+    assert!(switches[0].borrow_mut().mac_entries().any(|entry| entry.iface == 3));
+
+    // The root information expires on switch 2, which unblocks the
+    // segment toward switch 1.
+    net.set_link_up(link_20, false);
+    run_until(&mut net, 60, || switches[2].borrow_mut().stp().unwrap().root_port() == Some(2));
+    run_until(&mut net, 20, || switches[2].borrow_mut().stp().unwrap().port_state(2) == StpPortState::Forwarding);
+    run(&mut net, 10);
+
+    // The topology change flushed the addresses learned through the
+    // failed link.
+    for switch in &switches {
+        assert!(!switch.borrow_mut().mac_entries().any(|entry| entry.iface == 3));
+    }
+
+    // A still knows the MAC address of B, the unicast frame is flooded
+    // through the new path.
+    a.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(A, B, Ipv4Payload::Custom(vec![2]))));
+    run(&mut net, 5);
+    assert!(b.borrow_mut().recv_ipv4().is_some());
+
+}
+
+#[test]
+fn reject_ports_above_max_number() {
+
+    let switch = stp_switch(0);
+    let mut net = Network::new();
+    let hs = net.push(switch.clone());
+    let (ha, hb) = (net.push(host([2, 0, 0, 0, 0, 1], A, None)), net.push(host([2, 0, 0, 0, 0, 2], B, None)));
+    net.link::<EthFrame>(hs, MAX_PORT_NUMBER, ha, 0);
+
+    // The switch refuses the link, the network doesn't create it.
+    let linked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        net.link::<EthFrame>(hs, MAX_PORT_NUMBER + 1, hb, 0);
+    }));
+    assert!(linked.is_err());
+    assert!(switch.borrow_mut().stp().unwrap().port_info(MAX_PORT_NUMBER).is_some());
+    assert!(switch.borrow_mut().stp().unwrap().port_info(MAX_PORT_NUMBER + 1).is_none());
+
+}
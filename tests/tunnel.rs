@@ -0,0 +1,143 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, IpAddrExt, Ipv4Packet, Ipv4Payload, GrePacket, GrePayload};
+use netcrab::node::{ServerNode, ServerEthIface, ServerIfaceConf, ServerTunnelIface, TunnelMode, IpRouteLink, IpFlow, LOOPBACK_IFACE};
+
+use common::{ip, send_sized, run};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+/// Nodes A and B on a link, with a GRE tunnel on 172.16.0.0/30 and an
+/// IP-in-IP tunnel on 172.17.0.0/30 between them.
+fn tunnels(conf_a: ServerIfaceConf) -> (Network, RcNode<ServerNode>, RcNode<ServerNode>) {
+
+    let a = RcNode::new(ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 7, 0, 1])), conf_a));
+    let b = RcNode::new(ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 7, 0, 2])), ServerIfaceConf::with_ipv4(B, 24)));
+    a.borrow_mut().add_tunnel(1, ServerTunnelIface::gre(B).with_local(A), ServerIfaceConf::with_ipv4(ip(172, 16, 0, 1), 30));
+    b.borrow_mut().add_tunnel(1, ServerTunnelIface::gre(A), ServerIfaceConf::with_ipv4(ip(172, 16, 0, 2), 30));
+    a.borrow_mut().add_tunnel(2, ServerTunnelIface::ipip(B), ServerIfaceConf::with_ipv4(ip(172, 17, 0, 1), 30));
+    b.borrow_mut().add_tunnel(2, ServerTunnelIface::ipip(A), ServerIfaceConf::with_ipv4(ip(172, 17, 0, 2), 30));
+
+    let mut net = Network::new();
+    let (ha, hb) = (net.push(a.clone()), net.push(b.clone()));
+    net.link::<EthFrame>(ha, 0, hb, 0);
+    net.tick();
+
+    (net, a, b)
+
+}
+
+#[test]
+fn tunnel_encapsulation() {
+
+    let (mut net, a, b) = tunnels(ServerIfaceConf::with_ipv4(A, 24));
+
+    // The GRE packet is fragmented to fit the link once encapsulated.
+    send_sized(&a, Ipv4Addr::UNSPECIFIED, ip(172, 16, 0, 2), 3000, true);
+    send_sized(&a, Ipv4Addr::UNSPECIFIED, ip(172, 17, 0, 2), 10, true);
+    run(&mut net, 10);
+
+    let mut received = std::iter::from_fn(|| b.borrow_mut().recv_ipv4())
+        .map(|packet| (packet.src, packet.dst, packet.size()))
+        .collect::<Vec<_>>();
+    received.sort();
+    assert_eq!(received, vec![
+        (ip(172, 16, 0, 1), ip(172, 16, 0, 2), 3020),
+        (ip(172, 17, 0, 1), ip(172, 17, 0, 2), 30),
+    ]);
+
+}
+
+#[test]
+fn tunnel_nesting() {
+
+    let (mut net, a, b) = tunnels(ServerIfaceConf::with_ipv4(A, 24));
+
+    // The remote endpoint is routed through its own tunnel.
+    a.borrow_mut().get_ipv4_routes_mut().add_route(B.take_prefix(32), 2, IpRouteLink::Direct);
+    send_sized(&a, Ipv4Addr::UNSPECIFIED, ip(172, 17, 0, 2), 10, true);
+    run(&mut net, 10);
+
+    assert_eq!(a.borrow_mut().get_iface::<ServerTunnelIface>(2).unwrap().nesting_drops(), 1);
+    assert!(b.borrow_mut().recv_ipv4().is_none());
+
+}
+
+#[test]
+#[should_panic]
+fn tunnel_ambiguous() {
+    let mut a = ServerNode::new();
+    a.add_tunnel(1, ServerTunnelIface::gre(B), ServerIfaceConf::with_ipv4(ip(172, 16, 0, 1), 30));
+    a.add_tunnel(2, ServerTunnelIface::gre(B).with_local(A), ServerIfaceConf::with_ipv4(ip(172, 16, 1, 1), 30));
+}
+
+#[test]
+fn tunnel_mtu_in_vrf() {
+
+    // The endpoints are in a VRF, with a small MTU.
+    let (mut net, a, b) = tunnels(ServerIfaceConf::with_ipv4(A, 24).with_vrf("red").with_mtu(1000));
+
+    // Inner packets must fit once encapsulated, the GRE overhead is 24.
+    send_sized(&a, ip(172, 16, 0, 1), ip(172, 16, 0, 2), 970, false);
+    run(&mut net, 10);
+    assert_eq!(a.borrow_mut().ipv4_pmtu(ip(172, 16, 0, 2)), Some(976));
+    assert!(b.borrow_mut().recv_ipv4().is_none());
+
+    send_sized(&a, ip(172, 16, 0, 1), ip(172, 16, 0, 2), 950, false);
+    run(&mut net, 10);
+    assert_eq!(b.borrow_mut().recv_ipv4().unwrap().size(), 970);
+
+}
+
+/// Node A reaches the remote endpoint of B through two equal-cost paths,
+/// the second one with a smaller MTU.
+#[test]
+fn tunnel_mtu_multipath() {
+
+    let remote = ip(192, 168, 0, 2);
+    let mut a = ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 7, 1, 0])), ServerIfaceConf::with_ipv4(A, 24));
+    a.add_iface_conf(1, ServerEthIface::new(MacAddr([2, 0, 0, 7, 1, 1])), ServerIfaceConf::with_ipv4(ip(10, 0, 1, 1), 24).with_mtu(1000));
+    a.get_ipv4_routes_mut().add_route(remote.take_prefix(32), 0, IpRouteLink::Indirect(B));
+    a.get_ipv4_routes_mut().add_route(remote.take_prefix(32), 1, IpRouteLink::Indirect(ip(10, 0, 1, 2)));
+    let mut b = ServerNode::with_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 7, 2, 0])), ServerIfaceConf::with_ipv4(B, 24));
+    b.add_iface_conf(1, ServerEthIface::new(MacAddr([2, 0, 0, 7, 2, 1])), ServerIfaceConf::with_ipv4(ip(10, 0, 1, 2), 24));
+    b.get_iface_conf_mut(LOOPBACK_IFACE).unwrap().add_ipv4(remote, 32);
+
+    // Find a GRE key for each path.
+    let path = |key| {
+        let inner = Ipv4Packet::new(Ipv4Addr::UNSPECIFIED, Ipv4Addr::UNSPECIFIED, Ipv4Payload::Custom(Vec::new()));
+        let probe = Ipv4Packet::new(A, remote, Ipv4Payload::Gre(GrePacket { key: Some(key), payload: GrePayload::Ipv4(Box::new(inner)) }));
+        a.get_ipv4_routes().fetch_flow(&IpFlow::from_ipv4(&probe)).unwrap().0
+    };
+    let keys = [0, 1].map(|iface| (1..).find(|&key| path(key) == iface).unwrap());
+
+    for (n, key) in keys.into_iter().enumerate() {
+        let mode = TunnelMode::Gre { key: Some(key) };
+        a.add_tunnel(2 + n, ServerTunnelIface::new(mode, remote).with_local(A), ServerIfaceConf::with_ipv4(ip(172, 16, n as u8, 1), 30));
+        b.add_tunnel(2 + n, ServerTunnelIface::new(mode, A).with_local(remote), ServerIfaceConf::with_ipv4(ip(172, 16, n as u8, 2), 30));
+    }
+
+    let (a, b) = (RcNode::new(a), RcNode::new(b));
+    let mut net = Network::new();
+    let (ha, hb) = (net.push(a.clone()), net.push(b.clone()));
+    net.link::<EthFrame>(ha, 0, hb, 0);
+    net.link::<EthFrame>(ha, 1, hb, 1);
+    net.tick();
+
+    // The inner packet only fits the first path once encapsulated, the GRE
+    // overhead with a key is 28.
+    send_sized(&a, ip(172, 16, 0, 1), ip(172, 16, 0, 2), 960, false);
+    send_sized(&a, ip(172, 16, 1, 1), ip(172, 16, 1, 2), 960, false);
+    run(&mut net, 10);
+    assert_eq!(a.borrow_mut().ipv4_pmtu(ip(172, 16, 0, 2)), None);
+    assert_eq!(a.borrow_mut().ipv4_pmtu(ip(172, 16, 1, 2)), Some(972));
+
+    let received = std::iter::from_fn(|| b.borrow_mut().recv_ipv4())
+        .map(|packet| (packet.dst, packet.size()))
+        .collect::<Vec<_>>();
+    assert_eq!(received, vec![(ip(172, 16, 0, 2), 980)]);
+
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, Ipv4Addr};
+use netcrab::node::{ServerNode, EthSwitch, VlanPortMode};
+
+use common::{host, send, count_received, run};
+
+
+const A: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const B: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+const C: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 3);
+const D: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 4);
+
+/// Two switches joined by a trunk on port 9, A and C are on the default
+/// VLAN 1, B and D are on VLAN 20:
+///
+/// ```text
+/// A [0] switch 1 [9] -- [9] switch 2 [0] C
+/// B [1]                              [1] D
+/// ```
+fn trunked(allowed: Option<Vec<u16>>) -> (Network, [RcNode<ServerNode>; 4]) {
+
+    let hosts = [
+        host([2, 0, 0, 0, 0, 1], A, None),
+        host([2, 0, 0, 0, 0, 2], B, None),
+        host([2, 0, 0, 0, 0, 3], C, None),
+        host([2, 0, 0, 0, 0, 4], D, None),
+    ];
+
+    let mut switch_1 = EthSwitch::new();
+    let mut switch_2 = EthSwitch::new();
+    switch_1.set_port_mode(1, VlanPortMode::Access(20));
+    switch_2.set_port_mode(1, VlanPortMode::Access(20));
+    switch_1.set_port_mode(9, VlanPortMode::Trunk { allowed: None, native: None });
+    switch_2.set_port_mode(9, VlanPortMode::Trunk { allowed, native: None });
+
+    let mut net = Network::new();
+    let handles = hosts.clone().map(|host| net.push(host));
+    let (s1, s2) = (net.push(switch_1), net.push(switch_2));
+    net.link::<EthFrame>(handles[0], 0, s1, 0);
+    net.link::<EthFrame>(handles[1], 0, s1, 1);
+    net.link::<EthFrame>(handles[2], 0, s2, 0);
+    net.link::<EthFrame>(handles[3], 0, s2, 1);
+    net.link::<EthFrame>(s1, 9, s2, 9);
+
+    (net, hosts)
+
+}
+
+#[test]
+fn isolate_vlans_across_trunk() {
+
+    let (mut net, [a, b, c, d]) = trunked(None);
+
+    send(&a, A, C);
+    send(&a, A, D);
+    send(&b, B, D);
+    run(&mut net, 20);
+
+    assert_eq!(count_received(&c), 1);
+    // D only receives the packet from its own VLAN.
+    assert_eq!(count_received(&d), 1);
+
+}
+
+#[test]
+fn prune_trunk_vlans() {
+
+    let (mut net, [a, b, c, d]) = trunked(Some(vec![1]));
+
+    send(&a, A, C);
+    send(&b, B, D);
+    run(&mut net, 20);
+
+    assert_eq!(count_received(&c), 1);
+    assert_eq!(count_received(&d), 0);
+
+}
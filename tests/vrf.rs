@@ -0,0 +1,144 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, MacAddr, Ipv4Addr, Ipv4Packet, Ipv4Payload};
+use netcrab::node::{ServerNode, ServerEthIface, ServerIfaceConf, RoutingRule};
+
+use common::{host, ip, send, count_received, run, ping, recv_echo_reply};
+
+
+/// A router with the same two networks 10.0.0.0/24 and 10.0.1.0/24 in the
+/// VRFs red, on interfaces 0 and 1, and blue, on interfaces 2 and 3, with a
+/// host on each interface.
+fn overlapping() -> (Network, RcNode<ServerNode>, Vec<RcNode<ServerNode>>) {
+
+    let mut r = ServerNode::new();
+    for (iface, vrf) in [(0, "red"), (1, "red"), (2, "blue"), (3, "blue")] {
+        let conf = ServerIfaceConf::with_ipv4(ip(10, 0, iface as u8 % 2, 254), 24).with_vrf(vrf);
+        r.add_iface_conf(iface, ServerEthIface::new(MacAddr([2, 0, 0, 4, 0, iface as u8])), conf);
+    }
+    r.set_ipv4_forwarding(true);
+    let r = RcNode::new(r);
+
+    let mut net = Network::new();
+    let hr = net.push(r.clone());
+    let mut hosts = Vec::new();
+    for iface in 0..4 {
+        let subnet = iface as u8 % 2;
+        let h = host([2, 0, 0, 5, subnet, iface as u8], ip(10, 0, subnet, 1), Some(ip(10, 0, subnet, 254)));
+        let hh = net.push(h.clone());
+        net.link::<EthFrame>(hh, 0, hr, iface);
+        hosts.push(h);
+    }
+
+    (net, r, hosts)
+
+}
+
+#[test]
+fn vrf_isolation() {
+
+    let (mut net, r, hosts) = overlapping();
+    net.tick();
+    assert_eq!(r.borrow_mut().get_ipv4_table("red").unwrap().fib().count(), 2);
+    assert_eq!(r.borrow_mut().get_ipv4_table("blue").unwrap().fib().count(), 2);
+    assert!(r.borrow_mut().ipv4_tables().eq(["main", "blue", "red"]));
+
+    // Tables of VRFs in use are not removed.
+    r.borrow_mut().add_ipv4_table("green");
+    assert!(!r.borrow_mut().remove_ipv4_table("red"));
+    assert!(r.borrow_mut().remove_ipv4_table("green"));
+    assert!(!r.borrow_mut().remove_ipv4_table("green"));
+    assert!(r.borrow_mut().ipv4_tables().eq(["main", "blue", "red"]));
+
+    send(&hosts[0], ip(10, 0, 0, 1), ip(10, 0, 1, 1));
+    send(&hosts[2], ip(10, 0, 0, 1), ip(10, 0, 1, 1));
+    send(&hosts[2], ip(10, 0, 0, 1), ip(10, 0, 1, 1));
+    run(&mut net, 10);
+    assert_eq!(count_received(&hosts[1]), 1);
+    assert_eq!(count_received(&hosts[3]), 2);
+
+    // Echo requests are answered in the VRF they came from.
+    ping(&hosts[2], ip(10, 0, 0, 1), ip(10, 0, 0, 254));
+    run(&mut net, 10);
+    assert!(recv_echo_reply(&hosts[2]));
+    assert!(!recv_echo_reply(&hosts[0]));
+
+    // Once its interfaces leave the VRF, the table can be removed.
+    for iface in [0, 1] {
+        r.borrow_mut().get_iface_conf_mut(iface).unwrap().vrf = None;
+    }
+    assert!(r.borrow_mut().remove_ipv4_table("red"));
+    net.tick();
+    assert!(r.borrow_mut().ipv4_tables().eq(["main", "blue"]));
+
+}
+
+#[test]
+fn vrf_rules() {
+
+    let (mut net, r, hosts) = overlapping();
+
+    // Traffic received on interface 0 leaks into the blue table.
+    r.borrow_mut().add_ipv4_rule(RoutingRule::new("blue").with_in_iface(0));
+    send(&hosts[0], ip(10, 0, 0, 1), ip(10, 0, 1, 1));
+    run(&mut net, 10);
+    assert_eq!(count_received(&hosts[1]), 0);
+    assert_eq!(count_received(&hosts[3]), 1);
+
+    r.borrow_mut().remove_ipv4_rules("blue");
+    assert!(r.borrow_mut().ipv4_rules().is_empty());
+    send(&hosts[0], ip(10, 0, 0, 1), ip(10, 0, 1, 1));
+    run(&mut net, 10);
+    assert_eq!(count_received(&hosts[1]), 1);
+
+    // Rules to missing tables are skipped.
+    r.borrow_mut().add_ipv4_rule(RoutingRule::new("green").with_in_iface(0));
+    send(&hosts[0], ip(10, 0, 0, 1), ip(10, 0, 1, 1));
+    run(&mut net, 10);
+    assert_eq!(count_received(&hosts[1]), 1);
+
+}
+
+/// Red has 10.0.0.254/24 on interface 0 and 172.16.0.1/24 on interface 1,
+/// blue has 172.16.0.5/24 on interface 2, also the address of the host on
+/// interface 1.
+#[test]
+fn vrf_overlapping_addresses() {
+
+    let mut r = ServerNode::new();
+    r.add_iface_conf(0, ServerEthIface::new(MacAddr([2, 0, 0, 9, 0, 0])), ServerIfaceConf::with_ipv4(ip(10, 0, 0, 254), 24).with_vrf("red"));
+    r.add_iface_conf(1, ServerEthIface::new(MacAddr([2, 0, 0, 9, 0, 1])), ServerIfaceConf::with_ipv4(ip(172, 16, 0, 1), 24).with_vrf("red"));
+    r.add_iface_conf(2, ServerEthIface::new(MacAddr([2, 0, 0, 9, 0, 2])), ServerIfaceConf::with_ipv4(ip(172, 16, 0, 5), 24).with_vrf("blue"));
+    r.get_iface_conf_mut(1).unwrap().mtu = 1000;
+    r.set_ipv4_forwarding(true);
+    let r = RcNode::new(r);
+
+    let h1 = host([2, 0, 0, 9, 1, 1], ip(10, 0, 0, 1), Some(ip(10, 0, 0, 254)));
+    let h2 = host([2, 0, 0, 9, 1, 2], ip(172, 16, 0, 5), Some(ip(172, 16, 0, 1)));
+    let h3 = host([2, 0, 0, 9, 1, 3], ip(172, 16, 0, 9), None);
+    let mut net = Network::new();
+    let (hr, a, b, c) = (net.push(r.clone()), net.push(h1.clone()), net.push(h2.clone()), net.push(h3.clone()));
+    net.link::<EthFrame>(a, 0, hr, 0);
+    net.link::<EthFrame>(b, 0, hr, 1);
+    net.link::<EthFrame>(c, 0, hr, 2);
+
+    // The address of blue is not local to red, the packet is forwarded.
+    send(&h1, ip(10, 0, 0, 1), ip(172, 16, 0, 5));
+    run(&mut net, 10);
+    assert_eq!(count_received(&h2), 1);
+    assert_eq!(count_received(&r), 0);
+
+    // Packets sent by the node are routed in the given VRF.
+    r.borrow_mut().send_ipv4_in("blue", Box::new(Ipv4Packet::new(Ipv4Addr::UNSPECIFIED, ip(172, 16, 0, 9), Ipv4Payload::Custom(vec![2]))));
+    run(&mut net, 10);
+    let packet = h3.borrow_mut().recv_ipv4().unwrap();
+    assert_eq!(packet.src, ip(172, 16, 0, 5));
+
+    // So is the TCP MSS.
+    assert_eq!(r.borrow_mut().tcp_mss_in("red", ip(172, 16, 0, 9)), Some(960));
+    assert_eq!(r.borrow_mut().tcp_mss_in("blue", ip(172, 16, 0, 9)), Some(1460));
+    assert_eq!(r.borrow_mut().tcp_mss_in("blue", ip(10, 0, 0, 1)), None);
+    assert_eq!(r.borrow_mut().tcp_mss(ip(10, 0, 0, 1)), None);
+
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use netcrab::net::{Network, RcNode};
+use netcrab::proto::{EthFrame, EthPayload, MacAddr, Ipv4Packet, Ipv4Payload, ArpOp};
+use netcrab::node::{ServerEthIface, EthSwitch, CaptureNode, Vrrp, VrrpGroupConf, VrrpState};
+
+use common::{host, router, ip, run, run_until};
+
+
+/// Two VRRP routers between a LAN and a server network, R1 has the highest
+/// priority:
+///
+/// ```text
+/// host -- switch -- R1, R2 -- switch -- server
+///             |
+///          capture
+/// ```
+#[test]
+fn vrrp_failover_and_preemption() {
+
+    let vip = ip(10, 0, 0, 1);
+    let vrrp_router = |id: u8, priority: Option<u8>| {
+        let mut node = router(id, &[(0, ip(10, 0, 0, id)), (1, ip(10, 0, 1, id))]);
+        let mut conf = VrrpGroupConf::new(7, vip);
+        if let Some(priority) = priority {
+            conf = conf.with_priority(priority);
+        }
+        let mut vrrp = Vrrp::new();
+        vrrp.add_group(0, conf);
+        node.set_vrrp(Some(vrrp));
+        RcNode::new(node)
+    };
+
+    let r1 = vrrp_router(11, Some(200));
+    let r2 = vrrp_router(12, None);
+    let lan_host = host([2, 0, 0, 9, 1, 50], ip(10, 0, 0, 50), Some(vip));
+    let server = host([2, 0, 0, 9, 2, 5], ip(10, 0, 1, 5), None);
+
+    let mut net = Network::new();
+    let (h1, h2) = (net.push(r1.clone()), net.push(r2.clone()));
+    let (hh, hs) = (net.push(lan_host.clone()), net.push(server.clone()));
+    let capture = RcNode::new(CaptureNode::<EthFrame>::new());
+    let hc = net.push(capture.clone());
+    let (lan, servers) = (net.push(EthSwitch::new()), net.push(EthSwitch::new()));
+    let link_r1 = net.link::<EthFrame>(h1, 0, lan, 0);
+    net.link::<EthFrame>(h2, 0, lan, 1);
+    net.link::<EthFrame>(hh, 0, lan, 2);
+    net.link::<EthFrame>(hc, 0, lan, 3);
+    net.link::<EthFrame>(h1, 1, servers, 0);
+    net.link::<EthFrame>(h2, 1, servers, 1);
+    net.link::<EthFrame>(hs, 0, servers, 2);
+    run(&mut net, 10);
+
+    assert_eq!(r1.borrow_mut().vrrp().unwrap().state(0, 7), Some(VrrpState::Master));
+    assert_eq!(r2.borrow_mut().vrrp().unwrap().state(0, 7), Some(VrrpState::Backup));
+    assert_eq!(r2.borrow_mut().vrrp().unwrap().master(0, 7), Some(ip(10, 0, 0, 11)));
+
+    let send = |data: u8| {
+        lan_host.borrow_mut().send_ipv4(Box::new(Ipv4Packet::new(ip(10, 0, 0, 50), ip(10, 0, 1, 5), Ipv4Payload::Custom(vec![data]))));
+    };
+    let received = || matches!(server.borrow_mut().recv_ipv4().map(|packet| packet.payload), Some(Ipv4Payload::Custom(data)) if data.len() == 1);
+
+    send(1);
+    run(&mut net, 5);
+    assert!(received());
+    let arp = lan_host.borrow_mut().get_iface::<ServerEthIface>(0).unwrap().get_arp(vip).unwrap().state.mac();
+    assert_eq!(arp, Some(MacAddr::from_vrrp(7)));
+
+    // The backup takes over the virtual address when the master is lost,
+    // and announces it with a gratuitous ARP.
+    capture.borrow_mut().take();
+    net.set_link_up(link_r1, false);
+    run_until(&mut net, 10, || r2.borrow_mut().vrrp().unwrap().state(0, 7) == Some(VrrpState::Master));
+    run(&mut net, 2);
+    let announces = capture.borrow_mut().take().into_iter()
+        .filter(|captured| captured.data.dst == MacAddr::BROADCAST)
+        .filter(|captured| matches!(&captured.data.payload, EthPayload::Arp(arp)
+            if arp.op == ArpOp::Request && arp.sender_mac == MacAddr::from_vrrp(7) && arp.sender_ip == vip && arp.target_ip == vip))
+        .count();
+    assert_eq!(announces, 1);
+    send(2);
+    run(&mut net, 5);
+    assert!(received());
+
+    // The master preempts the backup when it comes back.
+    net.set_link_up(link_r1, true);
+    run(&mut net, 8);
+    assert_eq!(r1.borrow_mut().vrrp().unwrap().state(0, 7), Some(VrrpState::Master));
+    assert_eq!(r2.borrow_mut().vrrp().unwrap().state(0, 7), Some(VrrpState::Backup));
+    send(3);
+    run(&mut net, 5);
+    assert!(received());
+
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use netcrab::net::{Network, PathLoss};
+use netcrab::proto::EthFrame;
+use netcrab::node::{EthAccessPoint, EthSwitch};
+
+use common::{host, ip, send, count_received, run};
+
+
+#[test]
+fn range() {
+
+    let a = host([2, 0, 0, 0, 0, 1], ip(10, 0, 0, 1), None);
+    let b = host([2, 0, 0, 0, 0, 2], ip(10, 0, 0, 2), None);
+    let mut net = Network::new();
+    let (ha, hb) = (net.push(a.clone()), net.push(b.clone()));
+    let air = net.wireless::<EthFrame>(PathLoss::UnitDisk { range: 12.0 }, &[
+        (ha, 0, [0.0, 0.0, 0.0]),
+        (hb, 0, [20.0, 0.0, 0.0]),
+    ]);
+
+    send(&a, ip(10, 0, 0, 1), ip(10, 0, 0, 2));
+    run(&mut net, 10);
+    assert_eq!(count_received(&b), 0);
+    assert_eq!(net.wireless_stats(air).delivered, 0);
+
+    // Once in range, the ARP request is retried and the packet goes through.
+    net.set_wireless_position(air, hb, [10.0, 0.0, 0.0]);
+    send(&a, ip(10, 0, 0, 1), ip(10, 0, 0, 2));
+    run(&mut net, 20);
+    assert_eq!(count_received(&b), 1);
+
+}
+
+/// Stations A and C can't sense each other but both reach the access
+/// point between them, their transmissions collide at the access point
+/// and are retried:
+///
+/// ```text
+/// A (0) ..... AP (10) ..... C (20)
+///             |
+///           switch -- W
+/// ```
+#[test]
+fn hidden_terminals() {
+
+    let a = host([2, 0, 0, 0, 0, 1], ip(10, 0, 0, 1), None);
+    let c = host([2, 0, 0, 0, 0, 3], ip(10, 0, 0, 3), None);
+    let w = host([2, 0, 0, 0, 0, 5], ip(10, 0, 0, 5), None);
+
+    let mut net = Network::new();
+    let (ha, hc, hw) = (net.push(a.clone()), net.push(c.clone()), net.push(w.clone()));
+    let ap = net.push(EthAccessPoint::new());
+    let switch = net.push(EthSwitch::new());
+    net.link::<EthFrame>(ap, 0, switch, 0);
+    net.link::<EthFrame>(hw, 0, switch, 1);
+    let air = net.wireless::<EthFrame>(PathLoss::UnitDisk { range: 12.0 }, &[
+        (ha, 0, [0.0, 0.0, 0.0]),
+        (hc, 0, [20.0, 0.0, 0.0]),
+        (ap, 1, [10.0, 0.0, 0.0]),
+    ]);
+
+    for _ in 0..10 {
+        send(&a, ip(10, 0, 0, 1), ip(10, 0, 0, 5));
+        send(&c, ip(10, 0, 0, 3), ip(10, 0, 0, 5));
+    }
+    run(&mut net, 500);
+
+    let stats = net.wireless_stats(air);
+    assert!(stats.collisions > 0);
+    assert_eq!(stats.dropped, 0);
+    assert_eq!(count_received(&w), 20);
+
+}